@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS audit_log(
+    id VARCHAR(200) PRIMARY KEY,
+    event TEXT,
+    created_at TIMESTAMP
+);
@@ -0,0 +1,6 @@
+-- roles used to be a json array of role ids; turn each entry into an unbounded assignment
+UPDATE subjects SET roles = (
+    SELECT json_group_array(json_object('role_id', value, 'valid_from', NULL, 'valid_until', NULL))
+    FROM json_each(subjects.roles)
+)
+WHERE json_type(roles, '$[0]') = 'text';
@@ -0,0 +1 @@
+ALTER TABLE roles ADD COLUMN approvers VARCHAR(200) DEFAULT '[]';
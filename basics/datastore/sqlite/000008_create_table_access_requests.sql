@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS access_requests(
+    id VARCHAR(200) PRIMARY KEY,
+    subject_id VARCHAR(200),
+    role_id VARCHAR(200),
+    state VARCHAR(200),
+    valid_until TIMESTAMP,
+    decided_by VARCHAR(200),
+    decided_at TIMESTAMP,
+    expires_at TIMESTAMP,
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP
+);
@@ -0,0 +1,99 @@
+-- sqlite cannot change a primary key in place, so every table is rebuilt
+-- with a (tenant_id, id) key; existing rows move into the 'default' tenant
+
+CREATE TABLE permissions_scoped(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    name VARCHAR(200),
+    operation VARCHAR(200),
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
+INSERT INTO permissions_scoped
+SELECT 'default', id, name, json_set(operation, '$.Invoke.tenant_id', 'default'), created_at, updated_at
+FROM permissions;
+DROP TABLE permissions;
+ALTER TABLE permissions_scoped RENAME TO permissions;
+
+CREATE TABLE subjects_scoped(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    version INTEGER,
+    name VARCHAR(200),
+    roles VARCHAR(200),
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    deleted_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
+INSERT INTO subjects_scoped
+SELECT 'default', id, version, name, roles, created_at, updated_at, deleted_at
+FROM subjects;
+DROP TABLE subjects;
+ALTER TABLE subjects_scoped RENAME TO subjects;
+
+CREATE TABLE roles_scoped(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    name VARCHAR(200),
+    permissions VARCHAR(200),
+    approvers VARCHAR(200) DEFAULT '[]',
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
+INSERT INTO roles_scoped
+SELECT 'default', id, name, permissions, approvers, created_at, updated_at
+FROM roles;
+DROP TABLE roles;
+ALTER TABLE roles_scoped RENAME TO roles;
+
+CREATE TABLE groups_scoped(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    name VARCHAR(200),
+    subjects VARCHAR(200),
+    roles VARCHAR(200),
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
+INSERT INTO groups_scoped
+SELECT 'default', id, name, subjects, roles, created_at, updated_at
+FROM groups;
+DROP TABLE groups;
+ALTER TABLE groups_scoped RENAME TO groups;
+
+CREATE TABLE audit_log_scoped(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    event TEXT,
+    created_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
+INSERT INTO audit_log_scoped
+SELECT 'default', id, event, created_at
+FROM audit_log;
+DROP TABLE audit_log;
+ALTER TABLE audit_log_scoped RENAME TO audit_log;
+
+CREATE TABLE access_requests_scoped(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    subject_id VARCHAR(200),
+    role_id VARCHAR(200),
+    state VARCHAR(200),
+    valid_until TIMESTAMP,
+    decided_by VARCHAR(200),
+    decided_at TIMESTAMP,
+    expires_at TIMESTAMP,
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
+INSERT INTO access_requests_scoped
+SELECT 'default', id, subject_id, role_id, state, valid_until, decided_by, decided_at, expires_at, created_at, updated_at
+FROM access_requests;
+DROP TABLE access_requests;
+ALTER TABLE access_requests_scoped RENAME TO access_requests;
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS resources(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    name VARCHAR(200) NOT NULL,
+    PRIMARY KEY (tenant_id, id),
+    UNIQUE (tenant_id, name)
+);
+
+-- register the resources already embedded in permissions, keeping the first id seen per name
+INSERT OR IGNORE INTO resources
+SELECT tenant_id, json_extract(operation, '$.Invoke.id'), json_extract(operation, '$.Invoke.name')
+FROM permissions
+ORDER BY created_at;
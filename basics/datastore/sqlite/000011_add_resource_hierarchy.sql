@@ -0,0 +1,2 @@
+ALTER TABLE resources ADD COLUMN parent_id VARCHAR(200);
+ALTER TABLE permissions ADD COLUMN effect VARCHAR(200) DEFAULT 'allow';
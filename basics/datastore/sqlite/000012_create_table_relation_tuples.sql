@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS relation_tuples(
+    tenant_id VARCHAR(200) NOT NULL,
+    object VARCHAR(200) NOT NULL,
+    relation VARCHAR(200) NOT NULL,
+    subject VARCHAR(200) NOT NULL,
+    PRIMARY KEY (tenant_id, object, relation, subject)
+);
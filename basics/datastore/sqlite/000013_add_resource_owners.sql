@@ -0,0 +1,2 @@
+ALTER TABLE resources ADD COLUMN owner_id VARCHAR(200);
+ALTER TABLE permissions ADD COLUMN grantee VARCHAR(200) DEFAULT 'role_holders';
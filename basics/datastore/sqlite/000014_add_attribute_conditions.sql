@@ -0,0 +1,3 @@
+ALTER TABLE subjects ADD COLUMN attributes TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE resources ADD COLUMN attributes TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE permissions ADD COLUMN condition TEXT;
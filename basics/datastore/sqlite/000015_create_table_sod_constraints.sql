@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS sod_constraints(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    name VARCHAR(200),
+    kind VARCHAR(200),
+    roles TEXT,
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS sessions(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    subject_id VARCHAR(200),
+    active_roles TEXT,
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS delegations(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    delegator_id VARCHAR(200),
+    delegate_id VARCHAR(200),
+    permissions TEXT,
+    valid_until TIMESTAMP,
+    revoked_by VARCHAR(200),
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
+CREATE INDEX IF NOT EXISTS delegations_by_delegate ON delegations (tenant_id, delegate_id, valid_until);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS outbox(
+    position INTEGER PRIMARY KEY AUTOINCREMENT,
+    tenant_id VARCHAR(200) NOT NULL,
+    event TEXT,
+    created_at TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS outbox_by_tenant ON outbox (tenant_id, position);
+CREATE TABLE IF NOT EXISTS outbox_offsets(
+    tenant_id VARCHAR(200) NOT NULL,
+    consumer VARCHAR(200) NOT NULL,
+    position INTEGER NOT NULL,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, consumer)
+);
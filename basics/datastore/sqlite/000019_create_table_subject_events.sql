@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS subject_events(
+    tenant_id VARCHAR(200) NOT NULL,
+    subject_id VARCHAR(200) NOT NULL,
+    version INTEGER NOT NULL,
+    event TEXT,
+    occurred_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, subject_id, version)
+);
+CREATE TABLE IF NOT EXISTS subject_snapshots(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    version INTEGER NOT NULL,
+    name VARCHAR(200),
+    roles VARCHAR(200),
+    attributes TEXT NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    deleted_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id, version)
+);
+-- subjects stored before their events were kept start their stream from a snapshot of what they are now
+INSERT INTO subject_snapshots (tenant_id, id, version, name, roles, attributes, created_at, updated_at, deleted_at)
+SELECT tenant_id, id, COALESCE(version, 0), name, roles, attributes, created_at, updated_at, deleted_at
+FROM subjects;
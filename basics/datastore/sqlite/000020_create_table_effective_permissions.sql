@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS effective_permission_subjects(
+    tenant_id VARCHAR(200) NOT NULL,
+    subject_id VARCHAR(200) NOT NULL,
+    roles TEXT,
+    groups TEXT,
+    indexable BOOLEAN,
+    valid_until TIMESTAMP,
+    computed_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, subject_id)
+);
+CREATE TABLE IF NOT EXISTS effective_permissions(
+    tenant_id VARCHAR(200) NOT NULL,
+    subject_id VARCHAR(200) NOT NULL,
+    operation VARCHAR(200) NOT NULL,
+    resource_id VARCHAR(200) NOT NULL,
+    allowed BOOLEAN,
+    PRIMARY KEY (tenant_id, subject_id, operation, resource_id)
+);
+CREATE TABLE IF NOT EXISTS effective_permission_positions(
+    tenant_id VARCHAR(200) NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (tenant_id)
+);
@@ -0,0 +1,19 @@
+-- subjects stored before there were service accounts are all people
+ALTER TABLE subjects ADD COLUMN kind VARCHAR(200) NOT NULL DEFAULT 'person';
+ALTER TABLE subject_snapshots ADD COLUMN kind VARCHAR(200) NOT NULL DEFAULT 'person';
+CREATE TABLE IF NOT EXISTS api_keys(
+    tenant_id VARCHAR(200) NOT NULL,
+    id VARCHAR(200) NOT NULL,
+    subject_id VARCHAR(200),
+    name VARCHAR(200),
+    prefix VARCHAR(200) NOT NULL,
+    hash VARCHAR(200) NOT NULL,
+    expires_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, id)
+);
+CREATE UNIQUE INDEX IF NOT EXISTS api_keys_by_prefix ON api_keys (prefix);
+CREATE INDEX IF NOT EXISTS api_keys_by_subject ON api_keys (tenant_id, subject_id);
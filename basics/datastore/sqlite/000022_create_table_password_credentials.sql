@@ -0,0 +1,17 @@
+-- sessions started before signing in existed carry no token and never expire
+ALTER TABLE sessions ADD COLUMN token_hash VARCHAR(200);
+ALTER TABLE sessions ADD COLUMN expires_at TIMESTAMP;
+ALTER TABLE sessions ADD COLUMN revoked_at TIMESTAMP;
+CREATE INDEX IF NOT EXISTS sessions_by_subject ON sessions (tenant_id, subject_id);
+CREATE TABLE IF NOT EXISTS password_credentials(
+    tenant_id VARCHAR(200) NOT NULL,
+    subject_id VARCHAR(200) NOT NULL,
+    hash VARCHAR(200) NOT NULL,
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    locked_until TIMESTAMP,
+    last_login_at TIMESTAMP,
+    changed_at TIMESTAMP,
+    created_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (tenant_id, subject_id)
+);
@@ -0,0 +1,17 @@
+-- groups stored before groups could nest have no member groups
+ALTER TABLE groups ADD COLUMN groups VARCHAR(200) NOT NULL DEFAULT '[]';
+
+-- the direct members of every group, kept in step with the json lists, so that membership can be
+-- followed both ways through an index, and through nested groups with a recursive query
+CREATE TABLE IF NOT EXISTS group_members(
+    tenant_id VARCHAR(200) NOT NULL,
+    group_id VARCHAR(200) NOT NULL,
+    member_kind VARCHAR(200) NOT NULL,
+    member_id VARCHAR(200) NOT NULL,
+    PRIMARY KEY (tenant_id, group_id, member_kind, member_id)
+);
+CREATE INDEX IF NOT EXISTS group_members_by_member ON group_members (tenant_id, member_kind, member_id);
+
+INSERT OR IGNORE INTO group_members
+SELECT groups.tenant_id, groups.id, 'subject', json_each.value
+FROM groups, json_each(groups.subjects);
@@ -0,0 +1,12 @@
+-- people used to sign in with their subject's name, which need not be unique. each credential now
+-- carries its own login; namesakes sign in with their subject id until they are given one
+ALTER TABLE password_credentials ADD COLUMN login VARCHAR(200);
+UPDATE password_credentials SET login = (
+    SELECT name FROM subjects
+    WHERE subjects.tenant_id = password_credentials.tenant_id AND subjects.id = password_credentials.subject_id
+);
+UPDATE password_credentials SET login = subject_id
+WHERE login IS NULL OR (tenant_id, login) IN (
+    SELECT tenant_id, login FROM password_credentials GROUP BY tenant_id, login HAVING COUNT(*) > 1
+);
+CREATE UNIQUE INDEX IF NOT EXISTS password_credentials_by_login ON password_credentials (tenant_id, login);
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::application::effective_permissions::EffectivePermissionIndexer;
+use crate::domain::conditions::RequestContext;
+use crate::domain::subjects::SubjectId;
+use crate::infrastructure::sqlite::effective_permission::SqliteEffectivePermissionIndex;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::outbox::SqliteChangeFeed;
+use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use crate::infrastructure::sqlite::resource::SqliteResourceRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::metrics::{self, Metrics, CACHE_LOOKUPS};
+use crate::test_support::{access_checker, resource_id, seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: reports
+    permissions:
+      - name: read reports
+        resource: reports
+    roles:
+      - name: analyst
+        permissions: [read reports]
+    subjects:
+      - name: alec leamas
+        roles: [analyst]
+      - name: george smiley
+";
+
+#[derive(Debug, Clone, Default)]
+struct RecordedSpan {
+    name: String,
+    fields: HashMap<String, String>,
+}
+
+impl Visit for RecordedSpan {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.fields.insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+// keeps every span opened while it is the default subscriber, with the fields recorded on it so far
+#[derive(Clone, Default)]
+struct SpanRecorder {
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+}
+
+// the position of a span in the recorder, kept in the span's extensions
+struct RecordedIndex(usize);
+
+impl<S> Layer<S> for SpanRecorder
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+        let mut span = RecordedSpan {
+            name: attributes.metadata().name().to_string(),
+            ..Default::default()
+        };
+        attributes.record(&mut span);
+
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span);
+        if let Some(span_ref) = context.span(id) {
+            span_ref.extensions_mut().insert(RecordedIndex(spans.len() - 1));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, context: Context<'_, S>) {
+        if let Some(span_ref) = context.span(id) {
+            if let Some(RecordedIndex(index)) = span_ref.extensions().get::<RecordedIndex>() {
+                values.record(&mut self.spans.lock().unwrap()[*index]);
+            }
+        }
+    }
+}
+
+impl SpanRecorder {
+    fn find(&self, name: &str) -> Vec<RecordedSpan> {
+        self.spans.lock().unwrap()
+            .iter()
+            .filter(|span| span.name == name)
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_std::test]
+async fn test_checks_record_their_decision_in_a_span() {
+    let connection_pool = seeded_database(SEED).await;
+    let access_checker = access_checker(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let george_smiley_id = subject_id(&connection_pool, "george smiley").await;
+    let reports_id = resource_id(&connection_pool, "reports").await;
+
+    let recorder = SpanRecorder::default();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+    access_checker.check_in_context(alec_leamas_id.clone(), reports_id.clone(), &RequestContext::default()).await.unwrap();
+    access_checker.check_in_context(george_smiley_id.clone(), reports_id.clone(), &RequestContext::default()).await.unwrap();
+    access_checker.check_in_context(SubjectId::default(), reports_id.clone(), &RequestContext::default()).await.unwrap_err();
+
+    let checks = recorder.find("access_check");
+    assert_eq!(checks.len(), 3);
+    let expected = [(alec_leamas_id, "allow"), (george_smiley_id, "deny")];
+    for (check, (subject_id, decision)) in checks.iter().zip(expected) {
+        assert_eq!(check.fields["subject_id"], String::from(subject_id));
+        assert_eq!(check.fields["resource_id"], String::from(reports_id.clone()));
+        assert_eq!(check.fields["decision"], decision);
+        assert_eq!(check.fields["source"], "evaluated");
+        assert!(check.fields["latency_ms"].parse::<f64>().unwrap() >= 0.0);
+    }
+    assert_eq!(checks[2].fields["decision"], "error");
+    assert!(!checks[2].fields.contains_key("source"));
+}
+
+#[async_std::test]
+async fn test_repository_calls_are_recorded_in_spans() {
+    let connection_pool = seeded_database(SEED).await;
+    let access_checker = access_checker(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let reports_id = resource_id(&connection_pool, "reports").await;
+
+    let recorder = SpanRecorder::default();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+    access_checker.check_in_context(alec_leamas_id, reports_id, &RequestContext::default()).await.unwrap();
+
+    let calls = recorder.find("repository");
+    let subject_lookup = calls.iter()
+        .find(|span| span.fields["repository"] == "subject" && span.fields["operation"] == "get_by_id")
+        .expect("subject lookup was not recorded");
+    assert_eq!(subject_lookup.fields["outcome"], "ok");
+    assert!(subject_lookup.fields["latency_ms"].parse::<f64>().unwrap() >= 0.0);
+    assert!(calls.iter().any(|span| span.fields["repository"] == "permission"));
+    assert!(calls.iter().all(|span| span.fields.contains_key("latency_ms")));
+}
+
+#[async_std::test]
+async fn test_indexed_checks_record_the_index_as_their_source() {
+    let connection_pool = seeded_database(SEED).await;
+    let indexer = EffectivePermissionIndexer::new(
+        tenant_id(),
+        Box::new(SqliteEffectivePermissionIndex::new(connection_pool.clone(), tenant_id())),
+        access_checker(&connection_pool),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+    );
+    indexer.rebuild(&SqliteChangeFeed::new(connection_pool.clone(), tenant_id())).await.unwrap();
+    let access_checker = access_checker(&connection_pool)
+        .with_index(Box::new(SqliteEffectivePermissionIndex::new(connection_pool.clone(), tenant_id())));
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let reports_id = resource_id(&connection_pool, "reports").await;
+
+    let hits = [("cache", "effective_permission_index"), ("result", "hit")];
+    let hits_before = metrics::global().get_counter(CACHE_LOOKUPS, &hits);
+    let recorder = SpanRecorder::default();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+    access_checker.check_in_context(alec_leamas_id, reports_id, &RequestContext::default()).await.unwrap();
+
+    let checks = recorder.find("access_check");
+    assert_eq!(checks.len(), 1);
+    assert_eq!(checks[0].fields["decision"], "allow");
+    assert_eq!(checks[0].fields["source"], "index");
+    assert!(recorder.find("repository").iter().all(|span| span.fields["repository"] != "subject"));
+    assert!(metrics::global().get_counter(CACHE_LOOKUPS, &hits) > hits_before);
+}
+
+#[async_std::test]
+async fn test_metrics_render_in_prometheus_text_format() {
+    let metrics = Metrics::new();
+    metrics.increment_counter(metrics::ACCESS_DECISIONS, &[("outcome", "allow")]);
+    metrics.increment_counter(metrics::ACCESS_DECISIONS, &[("outcome", "allow")]);
+    metrics.increment_counter(metrics::ACCESS_DECISIONS, &[("outcome", "deny")]);
+    metrics.observe(metrics::REPOSITORY_DURATION, &[("repository", "subject")], Duration::from_millis(3));
+    metrics.observe(metrics::REPOSITORY_DURATION, &[("repository", "subject")], Duration::from_millis(300));
+
+    let text = metrics.render();
+    assert!(text.contains("# TYPE basics_access_decisions_total counter\n"));
+    assert!(text.contains("basics_access_decisions_total{outcome=\"allow\"} 2\n"));
+    assert!(text.contains("basics_access_decisions_total{outcome=\"deny\"} 1\n"));
+    assert!(text.contains("# TYPE basics_repository_duration_seconds histogram\n"));
+    assert!(text.contains("basics_repository_duration_seconds_bucket{repository=\"subject\",le=\"0.001\"} 0\n"));
+    assert!(text.contains("basics_repository_duration_seconds_bucket{repository=\"subject\",le=\"0.005\"} 1\n"));
+    assert!(text.contains("basics_repository_duration_seconds_bucket{repository=\"subject\",le=\"+Inf\"} 2\n"));
+    assert!(text.contains("basics_repository_duration_seconds_count{repository=\"subject\"} 2\n"));
+}
@@ -1,60 +1,423 @@
-use crate::domain::permissions::{PermissionId, Permission};
-use crate::domain::repositories::Repository;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::Instant;
+
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+use tracing::{field, info_span, Instrument, Span};
+
+use crate::application::delegations::delegable_permissions;
+use crate::application::resources::resolve_ancestry;
+use crate::domain::conditions::{Attributes, RequestContext};
+use crate::domain::delegations::DelegationId;
+use crate::domain::permissions::{Effect, Grantee, Permission};
+use crate::domain::repositories::{
+    DelegationRepository, EffectivePermissionIndex, Error, GroupRepository, PermissionRepository, Repository,
+    ResourceRepository, SodConstraintRepository,
+};
 use crate::domain::resources::ResourceId;
 use crate::domain::roles::{RoleId, Role};
+use crate::domain::separation_of_duties::SeparationOfDuties;
+use crate::domain::sessions::Session;
 use crate::domain::subjects::{Subject, SubjectId};
+use crate::domain::tenants::TenantId;
 use crate::domain::operations::Operation::Invoke;
+use crate::metrics::{self, ACCESS_CHECK_DURATION, ACCESS_DECISIONS, CACHE_LOOKUPS};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessDecision {
+    pub allowed: bool,
+    // set when the subject got in through a delegation rather than its own roles or ownership
+    pub delegation_id: Option<DelegationId>,
+    pub delegator_id: Option<SubjectId>,
+}
 
+impl AccessDecision {
+    pub(crate) fn of(allowed: bool) -> AccessDecision {
+        AccessDecision {
+            allowed,
+            delegation_id: None,
+            delegator_id: None,
+        }
+    }
+}
+
+// a checker is bound to a single tenant; repositories handed to it must be scoped to the same tenant
 pub struct AccessChecker {
-    subject_repository: Box<dyn Repository<SubjectId, Subject>>,
-    role_repository: Box<dyn Repository<RoleId, Role>>,
-    permission_repository: Box<dyn Repository<PermissionId, Permission>>,
+    tenant_id: TenantId,
+    subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+    role_repository: Box<dyn Repository<RoleId, Role> + Send + Sync>,
+    permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+    resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+    sod_constraint_repository: Box<dyn SodConstraintRepository + Send + Sync>,
+    delegation_repository: Box<dyn DelegationRepository + Send + Sync>,
+    effective_permission_index: Option<Box<dyn EffectivePermissionIndex + Send + Sync>>,
 }
 
 impl AccessChecker {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        subject_repository: Box<dyn Repository<SubjectId, Subject>>,
-        role_repository: Box<dyn Repository<RoleId, Role>>,
-        permission_repository: Box<dyn Repository<PermissionId, Permission>>,
+        tenant_id: TenantId,
+        subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+        role_repository: Box<dyn Repository<RoleId, Role> + Send + Sync>,
+        permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+        sod_constraint_repository: Box<dyn SodConstraintRepository + Send + Sync>,
+        delegation_repository: Box<dyn DelegationRepository + Send + Sync>,
     ) -> AccessChecker {
         Self {
-            subject_repository: subject_repository,
-            role_repository: role_repository,
-            permission_repository: permission_repository
+            tenant_id,
+            subject_repository,
+            group_repository,
+            role_repository,
+            permission_repository,
+            resource_repository,
+            sod_constraint_repository,
+            delegation_repository,
+            effective_permission_index: None,
+        }
+    }
+
+    // checks without a session look the subject's own decision up in the index first, and only
+    // evaluate it when the index cannot answer
+    pub fn with_index(mut self, effective_permission_index: Box<dyn EffectivePermissionIndex + Send + Sync>) -> AccessChecker {
+        self.effective_permission_index = Some(effective_permission_index);
+        self
+    }
+
+    pub async fn can_invoke_by_name(&self, subject_id: SubjectId, resource_name: &str) -> Result<bool, Error> {
+        self.can_invoke_by_name_in_context(subject_id, resource_name, &RequestContext::default()).await
+    }
+
+    // resources that were never registered cannot be invoked by anyone
+    pub async fn can_invoke_by_name_in_context(
+        &self,
+        subject_id: SubjectId,
+        resource_name: &str,
+        context: &RequestContext,
+    ) -> Result<bool, Error> {
+        match self.resource_repository.get_by_name(resource_name).await? {
+            Some(resource) => self.can_invoke_in_context(subject_id, resource.get_id(), context).await,
+            None => Ok(false),
+        }
+    }
+
+    // like `can_invoke_by_name_in_context`, but tells how the subject got in
+    pub async fn check_by_name_in_context(
+        &self,
+        subject_id: SubjectId,
+        resource_name: &str,
+        context: &RequestContext,
+    ) -> Result<AccessDecision, Error> {
+        match self.resource_repository.get_by_name(resource_name).await? {
+            Some(resource) => self.check_in_context(subject_id, resource.get_id(), context).await,
+            None => Ok(AccessDecision::of(false)),
+        }
+    }
+
+    // a condition that cannot be evaluated (e.g. a missing attribute) never lets an allow through but always lets a deny through
+    pub(crate) fn applies(permission: &Permission, subject_attributes: &Attributes, resource_attributes: &Attributes, context: &RequestContext) -> bool {
+        match permission.get_condition() {
+            None => true,
+            Some(condition) => condition.evaluate(subject_attributes, resource_attributes, context)
+                .unwrap_or(permission.get_effect() == Effect::Deny),
+        }
+    }
+
+    fn add_grant(&self, grants: &mut HashMap<ResourceId, HashSet<Effect>>, permission: &Permission) {
+        match permission.get_operation() {
+            Invoke(resource) => {
+                if resource.get_tenant_id() == self.tenant_id {
+                    grants.entry(resource.get_id()).or_default().insert(permission.get_effect());
+                }
+            },
+        }
+    }
+
+    // the nearest resource in the hierarchy that carries a grant decides; on that resource deny wins over allow.
+    // owner permissions count as grants when the subject owns the invoked resource
+    pub async fn can_invoke(&self, subject_id: SubjectId, resource_id: ResourceId) -> Result<bool, Error> {
+        self.can_invoke_in_context(subject_id, resource_id, &RequestContext::default()).await
+    }
+
+    // conditions see the subject's attributes, the invoked resource's attributes and the request context
+    pub async fn can_invoke_in_context(
+        &self,
+        subject_id: SubjectId,
+        resource_id: ResourceId,
+        context: &RequestContext,
+    ) -> Result<bool, Error> {
+        Ok(self.check_in_context(subject_id, resource_id, context).await?.allowed)
+    }
+
+    // like `can_invoke_in_context`, but tells how the subject got in
+    pub async fn check_in_context(
+        &self,
+        subject_id: SubjectId,
+        resource_id: ResourceId,
+        context: &RequestContext,
+    ) -> Result<AccessDecision, Error> {
+        observed_check(&subject_id, &resource_id, async {
+            // indexed decisions never depend on the request context: subjects whose do are not indexed
+            if let Some(index) = &self.effective_permission_index {
+                let indexed = index.lookup(subject_id.clone(), resource_id.clone(), Utc::now()).await?;
+                metrics::global().increment_counter(CACHE_LOOKUPS, &[
+                    ("cache", "effective_permission_index"),
+                    ("result", if indexed.is_some() { "hit" } else { "miss" }),
+                ]);
+                let answered = match indexed {
+                    Some(true) => Some(true),
+                    Some(false) if self.delegation_repository
+                        .find_active_for_delegate(subject_id.clone(), Utc::now())
+                        .await?
+                        .is_empty() => Some(false),
+                    _ => None,
+                };
+                if let Some(allowed) = answered {
+                    Span::current().record("source", "index");
+                    return Ok(AccessDecision::of(allowed));
+                }
+            }
+
+            let subject = self.subject_repository.get_by_id(subject_id.clone())
+                .await?
+                .ok_or_else(|| Error::Simple(format!("subject {} not found", String::from(subject_id.clone()))))?;
+
+            if subject.get_tenant_id() != self.tenant_id {
+                return Ok(AccessDecision::of(false));
+            }
+
+            self.decide_with_delegations(&subject, resource_id.clone(), context, None).await
+        }).await
+    }
+
+    // within a session only the roles it activated grant anything, while every role the subject holds
+    // still denies. roles whose assignment ended since they were activated no longer count, and a session
+    // whose active roles break a dynamic separation of duties constraint is refused outright, since
+    // constraints may have been added after the roles were activated. sessions that expired or were
+    // revoked grant nothing
+    pub async fn can_invoke_in_session(
+        &self,
+        session: &Session,
+        resource_id: ResourceId,
+        context: &RequestContext,
+    ) -> Result<bool, Error> {
+        Ok(self.check_in_session(session, resource_id, context).await?.allowed)
+    }
+
+    pub async fn check_in_session(
+        &self,
+        session: &Session,
+        resource_id: ResourceId,
+        context: &RequestContext,
+    ) -> Result<AccessDecision, Error> {
+        let subject_id = session.get_subject_id();
+        observed_check(&subject_id, &resource_id, async {
+            if session.get_tenant_id() != self.tenant_id || !session.is_active_at(Utc::now()) {
+                return Ok(AccessDecision::of(false));
+            }
+
+            let subject = self.subject_repository.get_by_id(session.get_subject_id())
+                .await?
+                .ok_or_else(|| Error::Simple(format!("subject {} not found", String::from(session.get_subject_id()))))?;
+            if subject.get_tenant_id() != self.tenant_id || !subject.is_active() {
+                return Ok(AccessDecision::of(false));
+            }
+
+            let active_roles: HashSet<RoleId> = session.get_active_roles()
+                .intersection(&subject.get_active_roles(Utc::now()))
+                .cloned()
+                .collect();
+
+            let constraints = self.sod_constraint_repository.find_all().await?;
+            let mut roles = Vec::new();
+            for role_id in constraints.iter().flat_map(|constraint| constraint.get_roles()).collect::<HashSet<_>>() {
+                roles.extend(self.role_repository.get_by_id(role_id).await?);
+            }
+            SeparationOfDuties::new(constraints, &roles, &[], &[])
+                .ensure_session_roles(&subject.get_name(), &active_roles)?;
+
+            self.decide_with_delegations(&subject, resource_id.clone(), context, Some(&active_roles)).await
+        }).await
+    }
+
+    // delegations are only looked at when the subject cannot get in on its own. a delegation lends
+    // the permissions its delegator still holds at the time of the check, and only lets the delegate
+    // in where the delegator could go itself; the delegate's own denies keep applying
+    async fn decide_with_delegations(
+        &self,
+        subject: &Subject,
+        resource_id: ResourceId,
+        context: &RequestContext,
+        granting_roles: Option<&HashSet<RoleId>>,
+    ) -> Result<AccessDecision, Error> {
+        Span::current().record("source", "evaluated");
+        if self.decide(subject, resource_id.clone(), context, granting_roles, &[]).await? {
+            return Ok(AccessDecision::of(true));
         }
+
+        let now = Utc::now();
+        for delegation in self.delegation_repository.find_active_for_delegate(subject.get_id(), now).await? {
+            let delegator = match self.subject_repository.get_by_id(delegation.get_delegator_id()).await? {
+                Some(delegator) if delegator.get_tenant_id() == self.tenant_id => delegator,
+                _ => continue,
+            };
+            let held = delegable_permissions(
+                self.role_repository.as_ref(),
+                self.permission_repository.as_ref(),
+                &delegator,
+                now,
+            ).await?;
+
+            let mut delegated = Vec::new();
+            for permission_id in delegation.get_permissions().intersection(&held) {
+                delegated.extend(self.permission_repository.get_by_id(permission_id.clone()).await?);
+            }
+            if delegated.is_empty() {
+                continue;
+            }
+
+            if self.decide(subject, resource_id.clone(), context, granting_roles, &delegated).await?
+                && self.decide(&delegator, resource_id.clone(), context, None, &[]).await?
+            {
+                Span::current().record("source", "delegation");
+                return Ok(AccessDecision {
+                    allowed: true,
+                    delegation_id: Some(delegation.get_id()),
+                    delegator_id: Some(delegator.get_id()),
+                });
+            }
+        }
+
+        Ok(AccessDecision::of(false))
     }
 
-    pub async fn can_invoke(&self, subject_id: SubjectId, resource_id: ResourceId) -> Result<bool, ()> {
-        let subject = self.subject_repository.get_by_id(subject_id)
-            .await
-            .expect("failed to fetch subject")
-            .expect("subject not found");
+    // the subject's own decision without a request context, delegations or a session: what the
+    // effective permission index holds
+    pub(crate) async fn evaluate(&self, subject: &Subject, resource_id: ResourceId) -> Result<bool, Error> {
+        self.decide(subject, resource_id, &RequestContext::default(), None, &[]).await
+    }
 
-        let mut can_invoke_resource = false;
+    // the subject's decision with delegations, evaluated every time and kept out of the metrics:
+    // what a simulation compares
+    pub(crate) async fn evaluate_with_delegations(
+        &self,
+        subject: &Subject,
+        resource_id: ResourceId,
+        context: &RequestContext,
+    ) -> Result<AccessDecision, Error> {
+        self.decide_with_delegations(subject, resource_id, context, None).await
+    }
 
-        for role_id in subject.get_roles() {
-            let role = self.role_repository.get_by_id(role_id)
-                .await
-                .expect("failed to fetch role")
-                .expect("role not found");
+    // subjects that are not active hold nothing. roles held through groups, including the groups that
+    // contain those groups, count like roles held directly, except that they cannot be activated in a session.
+    // `granting_roles` limits which of the subject's roles may allow; `None` lets all of them.
+    // `delegated` are permissions lent to the subject on top of its roles. a role or permission that is
+    // referred to but missing fails the check rather than being passed over, as it may have been a deny
+    async fn decide(
+        &self,
+        subject: &Subject,
+        resource_id: ResourceId,
+        context: &RequestContext,
+        granting_roles: Option<&HashSet<RoleId>>,
+        delegated: &[Permission],
+    ) -> Result<bool, Error> {
+        if !subject.is_active() {
+            return Ok(false);
+        }
+
+        let ancestry = resolve_ancestry(self.resource_repository.as_ref(), resource_id.clone()).await?;
+
+        let subject_attributes = subject.get_attributes();
+        let resource_attributes = ancestry.first()
+            .map(|resource| resource.get_attributes())
+            .unwrap_or_default();
+        let applies = |permission: &Permission| {
+            Self::applies(permission, &subject_attributes, &resource_attributes, context)
+        };
+
+        let mut grants: HashMap<ResourceId, HashSet<Effect>> = HashMap::new();
+
+        let mut roles = subject.get_active_roles(Utc::now());
+        let groups = self.group_repository.find_by_subject_transitively(subject.get_id()).await?;
+        roles.extend(groups.iter().flat_map(|group| group.get_roles()));
+
+        for role_id in roles {
+            let grants_allow = granting_roles.is_none_or(|roles| roles.contains(&role_id));
+            let role = self.role_repository.get_by_id(role_id.clone())
+                .await?
+                .ok_or_else(|| Error::Simple(format!("role {} not found", String::from(role_id))))?;
 
             for permission_id in role.get_permissions() {
-                let permission = self.permission_repository.get_by_id(permission_id)
-                    .await
-                    .expect("failed to fetch permission")
-                    .expect("permission not found");
-
-                match permission.get_operation() {
-                    Invoke(resource) => {
-                        if resource.get_id() == resource_id {
-                            can_invoke_resource = true;
-                            break
-                        }
-                    },
+                let permission = self.permission_repository.get_by_id(permission_id.clone())
+                    .await?
+                    .ok_or_else(|| Error::Simple(format!("permission {} not found", String::from(permission_id))))?;
+
+                let counts = grants_allow || permission.get_effect() == Effect::Deny;
+                // owner permissions only ever apply through ownership, even when a role includes them
+                if counts && permission.get_grantee() == Grantee::RoleHolders && applies(&permission) {
+                    self.add_grant(&mut grants, &permission);
                 }
             }
         }
 
-        Ok(can_invoke_resource)
+        for permission in delegated.iter().filter(|permission| applies(permission)) {
+            self.add_grant(&mut grants, permission);
+        }
+
+        if ancestry.first().is_some_and(|resource| resource.is_owned_by(&subject.get_id())) {
+            let owner_permissions = self.permission_repository.find_owner_permissions().await?;
+            for permission in owner_permissions.iter().filter(|permission| applies(permission)) {
+                self.add_grant(&mut grants, permission);
+            }
+        }
+
+        let mut lineage: Vec<ResourceId> = ancestry.iter()
+            .map(|resource| resource.get_id())
+            .collect();
+        if lineage.is_empty() {
+            // unregistered resources have no ancestors but may still be granted directly
+            lineage.push(resource_id);
+        }
+
+        for resource_id in lineage {
+            if let Some(effects) = grants.get(&resource_id) {
+                return Ok(!effects.contains(&Effect::Deny));
+            }
+        }
+
+        Ok(false)
     }
-}
\ No newline at end of file
+}
+
+// runs an access check inside an `access_check` span and records its decision and latency, both in the
+// span and in the metrics. the check itself records what decided it in the span's `source` field
+async fn observed_check<F>(subject_id: &SubjectId, resource_id: &ResourceId, check: F) -> Result<AccessDecision, Error>
+where
+    F: Future<Output = Result<AccessDecision, Error>>,
+{
+    let span = info_span!(
+        "access_check",
+        subject_id = %String::from(subject_id.clone()),
+        resource_id = %String::from(resource_id.clone()),
+        decision = field::Empty,
+        source = field::Empty,
+        latency_ms = field::Empty,
+    );
+    let started = Instant::now();
+    let result = check.instrument(span.clone()).await;
+    let elapsed = started.elapsed();
+
+    let decision = match &result {
+        Ok(decision) if decision.allowed => "allow",
+        Ok(_) => "deny",
+        Err(_) => "error",
+    };
+    span.record("decision", decision);
+    span.record("latency_ms", elapsed.as_secs_f64() * 1000.0);
+    metrics::global().increment_counter(ACCESS_DECISIONS, &[("outcome", decision)]);
+    metrics::global().observe(ACCESS_CHECK_DURATION, &[], elapsed);
+    result
+}
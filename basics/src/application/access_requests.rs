@@ -0,0 +1,214 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::application::separation_of_duties::DutiesLoader;
+use crate::domain::access_requests::{AccessRequest, AccessRequestId, AccessRequestState};
+use crate::domain::repositories::{AccessRequestRepository, EntityChange, Error, Repository, UnitOfWork};
+use crate::domain::roles::{Role, RoleId};
+use crate::domain::subjects::{Subject, SubjectId};
+use crate::domain::tenants::TenantId;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestRoleRequest {
+    pub subject_id: SubjectId,
+    pub role_id: RoleId,
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestRoleResponse {
+    pub access_request_id: AccessRequestId,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecideAccessRequestRequest {
+    pub access_request_id: AccessRequestId,
+    pub approver_id: SubjectId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecideAccessRequestResponse {
+    pub state: AccessRequestState,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExpireAccessRequestsRequest {
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExpireAccessRequestsResponse {
+    pub access_request_ids: Vec<AccessRequestId>,
+}
+
+#[async_trait]
+pub trait AccessRequestService {
+    async fn request_role(&self, req: RequestRoleRequest) -> Result<RequestRoleResponse, Error>;
+    async fn approve(&self, req: DecideAccessRequestRequest) -> Result<DecideAccessRequestResponse, Error>;
+    async fn reject(&self, req: DecideAccessRequestRequest) -> Result<DecideAccessRequestResponse, Error>;
+    async fn expire(&self, req: ExpireAccessRequestsRequest) -> Result<ExpireAccessRequestsResponse, Error>;
+}
+
+pub struct AccessRequestServiceImpl {
+    tenant_id: TenantId,
+    access_request_repository: Box<dyn AccessRequestRepository + Send + Sync>,
+    subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+    role_repository: Box<dyn Repository<RoleId, Role> + Send + Sync>,
+    unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+    duties_loader: DutiesLoader,
+    pending_for: Duration,
+}
+
+impl AccessRequestServiceImpl {
+    // `pending_for` is how long a request stays open before it expires undecided
+    pub fn new(
+        tenant_id: TenantId,
+        access_request_repository: Box<dyn AccessRequestRepository + Send + Sync>,
+        subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+        role_repository: Box<dyn Repository<RoleId, Role> + Send + Sync>,
+        unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+        duties_loader: DutiesLoader,
+        pending_for: Duration,
+    ) -> Self {
+        AccessRequestServiceImpl {
+            tenant_id,
+            access_request_repository,
+            subject_repository,
+            role_repository,
+            unit_of_work,
+            duties_loader,
+            pending_for,
+        }
+    }
+
+    async fn get_access_request(&self, access_request_id: AccessRequestId) -> Result<AccessRequest, Error> {
+        self.access_request_repository.get_by_id(access_request_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("access request {} not found", String::from(access_request_id))))
+    }
+
+    async fn get_subject(&self, subject_id: SubjectId) -> Result<Subject, Error> {
+        let subject = self.subject_repository.get_by_id(subject_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("subject {} not found", String::from(subject_id.clone()))))?;
+
+        if subject.get_deleted_at().is_some() {
+            return Err(Error::Simple(format!("subject {} is deleted", String::from(subject_id))));
+        }
+        Ok(subject)
+    }
+
+    async fn get_role(&self, role_id: RoleId) -> Result<Role, Error> {
+        self.role_repository.get_by_id(role_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("role {} not found", String::from(role_id))))
+    }
+
+    async fn ensure_approver(&self, access_request: &AccessRequest, approver_id: &SubjectId) -> Result<(), Error> {
+        let role = self.get_role(access_request.get_role_id()).await?;
+        if !role.is_approver(approver_id) {
+            return Err(Error::Simple(format!(
+                "subject {} is not an approver for role {}",
+                String::from(approver_id.clone()),
+                role.get_name(),
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AccessRequestService for AccessRequestServiceImpl {
+    async fn request_role(&self, req: RequestRoleRequest) -> Result<RequestRoleResponse, Error> {
+        self.get_subject(req.subject_id.clone()).await?;
+
+        let now = Utc::now();
+        if let Some(valid_until) = req.valid_until.filter(|valid_until| *valid_until <= now) {
+            return Err(Error::Simple(format!("the role would have expired already, at {}", valid_until)));
+        }
+
+        let role = self.get_role(req.role_id.clone()).await?;
+        if role.get_approvers().is_empty() {
+            return Err(Error::Simple(format!("role {} has no designated approvers", role.get_name())));
+        }
+
+        let access_request = AccessRequest::new(
+            self.tenant_id.clone(),
+            req.subject_id,
+            req.role_id,
+            req.valid_until,
+            now + self.pending_for,
+        );
+        self.access_request_repository.save(access_request.clone()).await?;
+
+        Ok(RequestRoleResponse {
+            access_request_id: access_request.get_id(),
+            expires_at: access_request.get_expires_at(),
+        })
+    }
+
+    async fn approve(&self, req: DecideAccessRequestRequest) -> Result<DecideAccessRequestResponse, Error> {
+        let mut access_request = self.get_access_request(req.access_request_id).await?;
+        self.ensure_approver(&access_request, &req.approver_id).await?;
+
+        let now = Utc::now();
+        let requested = access_request.approve(req.approver_id, now)?;
+
+        // an approval only ever widens what the subject holds: a role it already holds for longer, or for
+        // good, is left as it is, and one it holds for part of the time is extended to cover both
+        let mut subject = self.get_subject(access_request.get_subject_id()).await?;
+        let held = subject.get_role_assignment(&requested.get_role_id()).filter(|held| !held.is_expired_at(now));
+        let assignment = match &held {
+            Some(held) => match held.spanning(&requested) {
+                Some(assignment) => assignment,
+                None => return Err(Error::Simple(format!(
+                    "subject {} is granted role {} only after the requested grant would end",
+                    subject.get_name(),
+                    self.get_role(requested.get_role_id()).await?.get_name(),
+                ))),
+            },
+            None => requested,
+        };
+
+        // an approval cannot override separation of duties; the request stays pending. the grant and the
+        // decision are committed together, so that a granted request never stays pending
+        let mut changes = Vec::new();
+        if held.as_ref() != Some(&assignment) {
+            let duties = self.duties_loader.load().await?;
+            subject.add_role_assignment(assignment, &duties)?;
+            changes.push(EntityChange::SaveSubject(subject));
+        }
+        changes.push(EntityChange::SaveAccessRequest(access_request.clone()));
+        self.unit_of_work.commit(changes).await?;
+
+        Ok(DecideAccessRequestResponse {
+            state: access_request.get_state(),
+        })
+    }
+
+    async fn reject(&self, req: DecideAccessRequestRequest) -> Result<DecideAccessRequestResponse, Error> {
+        let mut access_request = self.get_access_request(req.access_request_id).await?;
+        self.ensure_approver(&access_request, &req.approver_id).await?;
+
+        access_request.reject(req.approver_id, Utc::now())?;
+        self.access_request_repository.save(access_request.clone()).await?;
+
+        Ok(DecideAccessRequestResponse {
+            state: access_request.get_state(),
+        })
+    }
+
+    async fn expire(&self, req: ExpireAccessRequestsRequest) -> Result<ExpireAccessRequestsResponse, Error> {
+        let mut access_request_ids = Vec::new();
+
+        for mut access_request in self.access_request_repository.find_expired_pending(req.at).await? {
+            access_request.expire(req.at)?;
+            self.access_request_repository.save(access_request.clone()).await?;
+            access_request_ids.push(access_request.get_id());
+        }
+
+        Ok(ExpireAccessRequestsResponse { access_request_ids })
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::access_requests::{
+    AccessRequestService, AccessRequestServiceImpl, DecideAccessRequestRequest, RequestRoleRequest,
+};
+use crate::application::separation_of_duties::DutiesLoader;
+use crate::domain::access_requests::{AccessRequest, AccessRequestId, AccessRequestState};
+use crate::domain::repositories::{Repository, RoleRepository, SubjectRepository};
+use crate::domain::roles::RoleId;
+use crate::domain::separation_of_duties::SeparationOfDuties;
+use crate::domain::subjects::{RoleAssignment, Subject};
+use crate::infrastructure::sqlite::access_request::SqliteAccessRequestRepository;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+use crate::test_support::{seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    roles:
+      - name: on call
+        approvers: [george smiley]
+    subjects:
+      - name: alec leamas
+      - name: george smiley
+";
+
+fn access_request_service(connection_pool: &Pool<Sqlite>) -> AccessRequestServiceImpl {
+    AccessRequestServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteAccessRequestRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())),
+        DutiesLoader::new(
+            Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+            Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+            Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+            Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        ),
+        Duration::days(1),
+    )
+}
+
+async fn on_call(connection_pool: &Pool<Sqlite>) -> RoleId {
+    SqliteRoleRepository::new(connection_pool.clone(), tenant_id()).get_by_name("on call").await.unwrap().unwrap().get_id()
+}
+
+async fn alec_leamas(connection_pool: &Pool<Sqlite>) -> Subject {
+    SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).get_by_name("alec leamas").await.unwrap().unwrap()
+}
+
+// alec leamas holds the role as given before asking for it again
+async fn holding(connection_pool: &Pool<Sqlite>, assignment: RoleAssignment) {
+    let mut alec_leamas = alec_leamas(connection_pool).await;
+    alec_leamas.add_role_assignment(assignment, &SeparationOfDuties::default()).unwrap();
+    SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).save(alec_leamas).await.unwrap();
+}
+
+async fn requested_until(connection_pool: &Pool<Sqlite>, valid_until: DateTime<Utc>) -> AccessRequestId {
+    access_request_service(connection_pool).request_role(RequestRoleRequest {
+        subject_id: subject_id(connection_pool, "alec leamas").await,
+        role_id: on_call(connection_pool).await,
+        valid_until: Some(valid_until),
+    }).await.unwrap().access_request_id
+}
+
+async fn approve(connection_pool: &Pool<Sqlite>, access_request_id: AccessRequestId) -> Result<AccessRequestState, String> {
+    access_request_service(connection_pool).approve(DecideAccessRequestRequest {
+        access_request_id,
+        approver_id: subject_id(connection_pool, "george smiley").await,
+    }).await.map(|response| response.state).map_err(|error| error.to_string())
+}
+
+#[async_std::test]
+async fn test_a_grant_that_ended_while_pending_is_not_approved() {
+    let connection_pool = seeded_database(SEED).await;
+    let access_request_repository = SqliteAccessRequestRepository::new(connection_pool.clone(), tenant_id());
+    let now = Utc::now();
+    // asked for until a moment that has passed while it waited for a decision
+    let access_request = AccessRequest::new(
+        tenant_id(),
+        subject_id(&connection_pool, "alec leamas").await,
+        on_call(&connection_pool).await,
+        Some(now - Duration::minutes(1)),
+        now + Duration::days(1),
+    );
+    access_request_repository.save(access_request.clone()).await.unwrap();
+
+    let error = approve(&connection_pool, access_request.get_id()).await.unwrap_err();
+    assert!(error.ends_with("which has passed"), "{}", error);
+    let stored = access_request_repository.get_by_id(access_request.get_id()).await.unwrap().unwrap();
+    assert_eq!(stored.get_state(), AccessRequestState::Pending);
+    assert!(alec_leamas(&connection_pool).await.get_role_assignment(&on_call(&connection_pool).await).is_none());
+}
+
+#[async_std::test]
+async fn test_approvals_never_cut_a_held_role_short() {
+    let connection_pool = seeded_database(SEED).await;
+    let on_call = on_call(&connection_pool).await;
+    holding(&connection_pool, RoleAssignment::new(on_call.clone())).await;
+
+    let access_request_id = requested_until(&connection_pool, Utc::now() + Duration::hours(1)).await;
+    assert_eq!(approve(&connection_pool, access_request_id).await, Ok(AccessRequestState::Approved));
+    assert_eq!(alec_leamas(&connection_pool).await.get_role_assignment(&on_call), Some(RoleAssignment::new(on_call)));
+}
+
+#[async_std::test]
+async fn test_approvals_extend_a_role_held_for_part_of_the_time() {
+    let connection_pool = seeded_database(SEED).await;
+    let on_call = on_call(&connection_pool).await;
+    let now = Utc::now();
+    let started = now - Duration::days(1);
+    holding(&connection_pool, RoleAssignment::bounded(on_call.clone(), Some(started), Some(now + Duration::hours(1)))).await;
+
+    let valid_until = now + Duration::days(2);
+    let access_request_id = requested_until(&connection_pool, valid_until).await;
+    assert_eq!(approve(&connection_pool, access_request_id).await, Ok(AccessRequestState::Approved));
+    let held = alec_leamas(&connection_pool).await.get_role_assignment(&on_call).unwrap();
+    assert_eq!(held.get_valid_from().map(|at| at.timestamp_millis()), Some(started.timestamp_millis()));
+    assert_eq!(held.get_valid_until().map(|at| at.timestamp_millis()), Some(valid_until.timestamp_millis()));
+
+}
+
+#[async_std::test]
+async fn test_approvals_are_refused_when_the_held_role_only_starts_later() {
+    let connection_pool = seeded_database(SEED).await;
+    let now = Utc::now();
+    holding(&connection_pool, RoleAssignment::bounded(on_call(&connection_pool).await, Some(now + Duration::days(3)), None)).await;
+
+    // the two grants could not be joined without a gap between them
+    let access_request_id = requested_until(&connection_pool, now + Duration::days(1)).await;
+    assert_eq!(
+        approve(&connection_pool, access_request_id).await,
+        Err("subject alec leamas is granted role on call only after the requested grant would end".to_string()),
+    );
+}
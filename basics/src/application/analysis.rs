@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use crate::domain::groups::{members_of, Group, GroupId};
+use crate::domain::operations::Operation;
+use crate::domain::permissions::{Effect, Grantee, Permission, PermissionId};
+use crate::domain::repositories::{
+    Error, GroupRepository, PermissionRepository, ResourceRepository, RoleRepository, SubjectRepository,
+};
+use crate::domain::resources::{Resource, ResourceId};
+use crate::domain::roles::{Role, RoleId};
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+// resources that change who may do what; invoking them is as good as holding every permission
+pub const DEFAULT_PRIVILEGED_RESOURCES: [&str; 4] = ["roles", "permissions", "groups", "subjects"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    UnheldRole,
+    UnreferencedPermission,
+    EmptyGroup,
+    OverlappingGrant,
+    PrivilegeEscalation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub severity: Severity,
+    // the kind of entity the finding is about, e.g. `role`
+    pub entity: String,
+    pub id: String,
+    pub name: String,
+    pub message: String,
+    // the chain of entities that leads to the problem, e.g. `role admin`, `permission edit roles`, `resource roles`
+    pub path: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisReport {
+    pub tenant_id: TenantId,
+    pub findings: Vec<Finding>,
+    pub counts: BTreeMap<FindingKind, usize>,
+}
+
+impl AnalysisReport {
+    pub fn count_at_least(&self, severity: Severity) -> usize {
+        self.findings.iter().filter(|finding| finding.severity >= severity).count()
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|error| Error::Simple(format!("unable to write report: {}", error)))
+    }
+}
+
+// a subject holds a role directly or through a group; expired assignments and deleted subjects don't count.
+// the analysis is static: conditions are assumed to hold and deny grants are not subtracted
+pub struct PolicyAnalyzer {
+    tenant_id: TenantId,
+    resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+    permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+    role_repository: Box<dyn RoleRepository + Send + Sync>,
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+    privileged_resources: Vec<String>,
+}
+
+impl PolicyAnalyzer {
+    pub fn new(
+        tenant_id: TenantId,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+        permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+        role_repository: Box<dyn RoleRepository + Send + Sync>,
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+        privileged_resources: Vec<String>,
+    ) -> PolicyAnalyzer {
+        PolicyAnalyzer {
+            tenant_id,
+            resource_repository,
+            permission_repository,
+            role_repository,
+            subject_repository,
+            group_repository,
+            privileged_resources,
+        }
+    }
+
+    pub async fn analyze(&self) -> Result<AnalysisReport, Error> {
+        let resources = self.resource_repository.find_all().await?;
+        let permissions: HashMap<PermissionId, Permission> = self.permission_repository.find_all().await?
+            .into_iter()
+            .map(|permission| (permission.get_id(), permission))
+            .collect();
+        let roles: HashMap<RoleId, Role> = self.role_repository.find_all().await?
+            .into_iter()
+            .map(|role| (role.get_id(), role))
+            .collect();
+        let subjects: Vec<_> = self.subject_repository.find_all().await?
+            .into_iter()
+            .filter(|subject| subject.get_deleted_at().is_none())
+            .collect();
+        let groups = self.group_repository.find_all().await?;
+
+        let mut findings = Vec::new();
+
+        // every way each live subject holds a role, as the path leading to it, in the order of `subjects`
+        let now = Utc::now();
+        let mut holdings: Vec<Vec<(RoleId, Vec<String>)>> = Vec::new();
+        for subject in &subjects {
+            let subject_hop = format!("subject {}", subject.get_name());
+            let mut held = Vec::new();
+            for assignment in subject.get_role_assignments().iter().filter(|assignment| !assignment.is_expired_at(now)) {
+                held.push((assignment.get_role_id(), vec![subject_hop.clone()]));
+            }
+            for (group, hops) in group_paths(&groups, &subject.get_id()) {
+                for role_id in group.get_roles() {
+                    held.push((role_id, [subject_hop.clone()].into_iter().chain(hops.iter().cloned()).collect()));
+                }
+            }
+            holdings.push(held);
+        }
+        let mut holders: HashMap<RoleId, Vec<Vec<String>>> = HashMap::new();
+        for held in &holdings {
+            for (role_id, path) in held {
+                holders.entry(role_id.clone()).or_default().push(path.clone());
+            }
+        }
+
+        for role in roles.values().filter(|role| !holders.contains_key(&role.get_id())) {
+            findings.push(Finding {
+                kind: FindingKind::UnheldRole,
+                severity: Severity::Warning,
+                entity: "role".to_string(),
+                id: role.get_id().into(),
+                name: role.get_name(),
+                message: format!("no subject holds role {}, directly or through a group", role.get_name()),
+                path: vec![format!("role {}", role.get_name())],
+            });
+        }
+
+        // owner permissions apply through ownership and need no role
+        let referenced: HashSet<PermissionId> = roles.values().flat_map(Role::get_permissions).collect();
+        for permission in permissions.values() {
+            if permission.get_grantee() == Grantee::RoleHolders && !referenced.contains(&permission.get_id()) {
+                findings.push(Finding {
+                    kind: FindingKind::UnreferencedPermission,
+                    severity: Severity::Warning,
+                    entity: "permission".to_string(),
+                    id: permission.get_id().into(),
+                    name: permission.get_name(),
+                    message: format!("no role includes permission {}, so it grants nothing", permission.get_name()),
+                    path: vec![format!("permission {}", permission.get_name())],
+                });
+            }
+        }
+
+        let live: HashSet<_> = subjects.iter().map(|subject| subject.get_id()).collect();
+        for group in &groups {
+            if !members_of(&groups, group).iter().any(|subject_id| live.contains(subject_id)) {
+                findings.push(Finding {
+                    kind: FindingKind::EmptyGroup,
+                    severity: Severity::Warning,
+                    entity: "group".to_string(),
+                    id: group.get_id().into(),
+                    name: group.get_name(),
+                    message: format!("group {} has no members that are not deleted", group.get_name()),
+                    path: vec![format!("group {}", group.get_name())],
+                });
+            }
+        }
+
+        // the same resource granted to a subject by more than one permission is redundant at best and
+        // contradictory when the effects differ
+        let resource_names: HashMap<ResourceId, String> = resources.iter().map(|resource| (resource.get_id(), resource.get_name())).collect();
+        for (subject, held) in subjects.iter().zip(&holdings) {
+            let mut grants: BTreeMap<String, Vec<(Permission, String)>> = BTreeMap::new();
+            for (role_id, _) in held {
+                let Some(role) = roles.get(role_id) else { continue };
+                for permission in role.get_permissions().iter().filter_map(|permission_id| permissions.get(permission_id)) {
+                    if permission.get_grantee() != Grantee::RoleHolders {
+                        continue;
+                    }
+                    let entries = grants.entry(String::from(resource_of(permission).get_id())).or_default();
+                    if !entries.iter().any(|(granted, _)| granted.get_id() == permission.get_id()) {
+                        entries.push((permission.clone(), role.get_name()));
+                    }
+                }
+            }
+
+            for (resource_id, entries) in grants.into_iter().filter(|(_, entries)| entries.len() > 1) {
+                let resource_name = resource_names.get(&ResourceId::from(resource_id.clone()))
+                    .cloned()
+                    .unwrap_or_else(|| resource_of(&entries[0].0).get_name());
+                let effects: HashSet<Effect> = entries.iter().map(|(permission, _)| permission.get_effect()).collect();
+                let (severity, message) = if effects.len() > 1 {
+                    (Severity::Warning, format!("{} is both allowed and denied on {}", subject.get_name(), resource_name))
+                } else {
+                    (Severity::Info, format!("{} is granted {} more than once", subject.get_name(), resource_name))
+                };
+                findings.push(Finding {
+                    kind: FindingKind::OverlappingGrant,
+                    severity,
+                    entity: "subject".to_string(),
+                    id: subject.get_id().into(),
+                    name: subject.get_name(),
+                    message,
+                    path: entries.iter()
+                        .map(|(permission, role)| format!("role {} / permission {}", role, permission.get_name()))
+                        .collect(),
+                });
+            }
+        }
+
+        // a grant covers the resource it names and everything below it
+        let privileged = self.covering_privileged(&resources);
+        for permission in permissions.values().filter(|permission| permission.get_effect() == Effect::Allow) {
+            let resource = resource_of(permission);
+            if !privileged.contains(&resource.get_id()) && !self.is_privileged_name(&resource.get_name()) {
+                continue;
+            }
+            let resource_hop = format!("resource {}", resource.get_name());
+
+            if permission.get_grantee() == Grantee::Owner {
+                findings.push(Finding {
+                    kind: FindingKind::PrivilegeEscalation,
+                    severity: Severity::Error,
+                    entity: "permission".to_string(),
+                    id: permission.get_id().into(),
+                    name: permission.get_name(),
+                    message: format!("whoever owns {} can change authorization through it", resource.get_name()),
+                    path: vec![format!("permission {}", permission.get_name()), resource_hop],
+                });
+                continue;
+            }
+
+            let mut granting: Vec<&Role> = roles.values().filter(|role| role.get_permissions().contains(&permission.get_id())).collect();
+            granting.sort_by_key(|role| role.get_name());
+            for role in granting {
+                let held_by: Vec<String> = holders.get(&role.get_id())
+                    .map(|paths| paths.iter().map(|path| path.join(" -> ")).collect())
+                    .unwrap_or_default();
+                let message = if held_by.is_empty() {
+                    format!("holders of role {} could change authorization through {}; no one holds it yet", role.get_name(), resource.get_name())
+                } else {
+                    format!("holders of role {} can change authorization through {}: {}", role.get_name(), resource.get_name(), held_by.join(", "))
+                };
+                findings.push(Finding {
+                    kind: FindingKind::PrivilegeEscalation,
+                    severity: Severity::Error,
+                    entity: "role".to_string(),
+                    id: role.get_id().into(),
+                    name: role.get_name(),
+                    message,
+                    path: vec![format!("role {}", role.get_name()), format!("permission {}", permission.get_name()), resource_hop.clone()],
+                });
+            }
+        }
+
+        findings.sort_by(|left, right| (left.kind, &left.name, &left.message).cmp(&(right.kind, &right.name, &right.message)));
+        let mut counts = BTreeMap::new();
+        for finding in &findings {
+            *counts.entry(finding.kind).or_insert(0) += 1;
+        }
+
+        Ok(AnalysisReport {
+            tenant_id: self.tenant_id.clone(),
+            findings,
+            counts,
+        })
+    }
+
+    fn is_privileged_name(&self, name: &str) -> bool {
+        self.privileged_resources.iter().any(|privileged| {
+            name == privileged || name.strip_prefix(privileged.as_str()).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    // privileged resources along with every ancestor of one, since grants are inherited downwards
+    fn covering_privileged(&self, resources: &[Resource]) -> HashSet<ResourceId> {
+        let parents: HashMap<ResourceId, Option<ResourceId>> = resources.iter()
+            .map(|resource| (resource.get_id(), resource.get_parent_id()))
+            .collect();
+        let mut covering = HashSet::new();
+        for resource in resources.iter().filter(|resource| self.is_privileged_name(&resource.get_name())) {
+            let mut current = Some(resource.get_id());
+            while let Some(id) = current {
+                if !covering.insert(id.clone()) {
+                    break;
+                }
+                current = parents.get(&id).cloned().flatten();
+            }
+        }
+        covering
+    }
+}
+
+fn resource_of(permission: &Permission) -> Resource {
+    match permission.get_operation() {
+        Operation::Invoke(resource) => resource,
+    }
+}
+
+// the groups the subject belongs to, each with the groups that lead to it from one the subject is a
+// direct member of, that one first
+fn group_paths<'a>(groups: &'a [Group], subject_id: &SubjectId) -> Vec<(&'a Group, Vec<String>)> {
+    let mut paths: Vec<(&Group, Vec<String>)> = groups.iter()
+        .filter(|group| group.get_subjects().contains(subject_id))
+        .map(|group| (group, vec![format!("group {}", group.get_name())]))
+        .collect();
+    let mut reached: HashSet<GroupId> = paths.iter().map(|(group, _)| group.get_id()).collect();
+    let mut next = 0;
+    while next < paths.len() {
+        let (member, hops) = paths[next].clone();
+        for group in groups.iter().filter(|group| group.get_groups().contains(&member.get_id())) {
+            if reached.insert(group.get_id()) {
+                paths.push((group, hops.iter().cloned().chain([format!("group {}", group.get_name())]).collect()));
+            }
+        }
+        next += 1;
+    }
+    paths
+}
@@ -0,0 +1,133 @@
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::analysis::{AnalysisReport, FindingKind, PolicyAnalyzer, Severity, DEFAULT_PRIVILEGED_RESOURCES};
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use crate::infrastructure::sqlite::resource::SqliteResourceRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::test_support::{seeded_database, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: console
+      - name: roles
+        parent: console
+      - name: reports
+      - name: reports/karla
+        parent: reports
+        owner: alec leamas
+    permissions:
+      - name: read reports
+        resource: reports
+      - name: view reports
+        resource: reports
+      - name: shred reports
+        resource: reports
+        effect: deny
+      - name: use console
+        resource: console
+      - name: edit roles
+        resource: roles
+        grantee: owner
+      - name: stray
+        resource: reports
+    roles:
+      - name: analyst
+        permissions: [read reports, view reports]
+      - name: shredder
+        permissions: [shred reports]
+      - name: operator
+        permissions: [use console]
+      - name: auditor
+    subjects:
+      - name: alec leamas
+        roles: [analyst, shredder]
+      - name: bill haydon
+    groups:
+      - name: station
+        groups: [the circus]
+        roles: [operator]
+      - name: the circus
+        subjects: [bill haydon]
+      - name: moscow centre
+";
+
+async fn analyze(connection_pool: &Pool<Sqlite>) -> AnalysisReport {
+    PolicyAnalyzer::new(
+        tenant_id(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        DEFAULT_PRIVILEGED_RESOURCES.iter().map(|name| name.to_string()).collect(),
+    ).analyze().await.unwrap()
+}
+
+#[async_std::test]
+async fn test_analysis_finds_every_kind_of_problem() {
+    let connection_pool = seeded_database(SEED).await;
+    let report = analyze(&connection_pool).await;
+
+    let findings: Vec<(FindingKind, Severity, &str, &str)> = report.findings.iter()
+        .map(|finding| (finding.kind, finding.severity, finding.name.as_str(), finding.message.as_str()))
+        .collect();
+    assert_eq!(findings, vec![
+        (FindingKind::UnheldRole, Severity::Warning, "auditor", "no subject holds role auditor, directly or through a group"),
+        (FindingKind::UnreferencedPermission, Severity::Warning, "stray", "no role includes permission stray, so it grants nothing"),
+        (FindingKind::EmptyGroup, Severity::Warning, "moscow centre", "group moscow centre has no members that are not deleted"),
+        (FindingKind::OverlappingGrant, Severity::Warning, "alec leamas", "alec leamas is both allowed and denied on reports"),
+        (FindingKind::PrivilegeEscalation, Severity::Error, "edit roles", "whoever owns roles can change authorization through it"),
+        (
+            FindingKind::PrivilegeEscalation,
+            Severity::Error,
+            "operator",
+            "holders of role operator can change authorization through console: subject bill haydon -> group the circus -> group station",
+        ),
+    ]);
+    assert_eq!(report.findings[5].path, vec!["role operator", "permission use console", "resource console"]);
+    assert_eq!(report.count_at_least(Severity::Error), 2);
+    assert_eq!(report.count_at_least(Severity::Warning), 6);
+}
+
+#[async_std::test]
+async fn test_the_report_reads_as_json() {
+    let connection_pool = seeded_database(SEED).await;
+    let report: serde_json::Value = serde_json::from_str(&analyze(&connection_pool).await.to_json().unwrap()).unwrap();
+
+    assert_eq!(report["tenant_id"], "circus");
+    assert_eq!(report["counts"], serde_json::json!({
+        "unheld_role": 1,
+        "unreferenced_permission": 1,
+        "empty_group": 1,
+        "overlapping_grant": 1,
+        "privilege_escalation": 2,
+    }));
+    assert_eq!(report["findings"][0]["kind"], "unheld_role");
+    assert_eq!(report["findings"][0]["severity"], "warning");
+}
+
+#[async_std::test]
+async fn test_a_clean_store_has_no_findings() {
+    let connection_pool = seeded_database("
+        resources:
+          - name: reports
+        permissions:
+          - name: read reports
+            resource: reports
+        roles:
+          - name: analyst
+            permissions: [read reports]
+        subjects:
+          - name: alec leamas
+        groups:
+          - name: the circus
+            subjects: [alec leamas]
+            roles: [analyst]
+    ").await;
+    let report = analyze(&connection_pool).await;
+    assert!(report.findings.is_empty(), "{:?}", report.findings);
+    assert!(report.counts.is_empty());
+}
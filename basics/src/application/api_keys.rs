@@ -0,0 +1,189 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::domain::api_keys::{parse_api_key, ApiKey, ApiKeyId};
+use crate::domain::repositories::{ApiKeyRepository, Error, Repository};
+use crate::domain::subjects::{Subject, SubjectId, SubjectKind};
+use crate::domain::tenants::TenantId;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssueApiKeyRequest {
+    pub subject_id: SubjectId,
+    pub name: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+// `api_key` is the only time the key is ever shown
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssueApiKeyResponse {
+    pub api_key_id: ApiKeyId,
+    pub api_key: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+// the replaced key keeps working for `grace_period_seconds`, so that callers can switch over; without
+// one it is revoked right away
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateApiKeyRequest {
+    pub api_key_id: ApiKeyId,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub grace_period_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeApiKeyRequest {
+    pub api_key_id: ApiKeyId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeApiKeyResponse {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListApiKeysRequest {
+    pub subject_id: SubjectId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyDescription {
+    pub api_key_id: ApiKeyId,
+    pub name: String,
+    pub prefix: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListApiKeysResponse {
+    pub api_keys: Vec<ApiKeyDescription>,
+}
+
+#[async_trait]
+pub trait ApiKeyService {
+    async fn issue_api_key(&self, req: IssueApiKeyRequest) -> Result<IssueApiKeyResponse, Error>;
+    async fn rotate_api_key(&self, req: RotateApiKeyRequest) -> Result<IssueApiKeyResponse, Error>;
+    async fn revoke_api_key(&self, req: RevokeApiKeyRequest) -> Result<RevokeApiKeyResponse, Error>;
+    async fn list_api_keys(&self, req: ListApiKeysRequest) -> Result<ListApiKeysResponse, Error>;
+    // the service account a key belongs to, ready to be handed to `AccessChecker`. malformed, unknown,
+    // expired and revoked keys and keys of subjects that are not active are all refused alike
+    async fn authenticate(&self, api_key: &str) -> Result<SubjectId, Error>;
+}
+
+pub struct ApiKeyServiceImpl {
+    tenant_id: TenantId,
+    api_key_repository: Box<dyn ApiKeyRepository + Send + Sync>,
+    subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+}
+
+impl ApiKeyServiceImpl {
+    pub fn new(
+        tenant_id: TenantId,
+        api_key_repository: Box<dyn ApiKeyRepository + Send + Sync>,
+        subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+    ) -> Self {
+        ApiKeyServiceImpl {
+            tenant_id,
+            api_key_repository,
+            subject_repository,
+        }
+    }
+
+    async fn get_api_key(&self, api_key_id: ApiKeyId) -> Result<ApiKey, Error> {
+        self.api_key_repository.get_by_id(api_key_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("api key {} not found", String::from(api_key_id))))
+    }
+
+    async fn get_subject(&self, subject_id: SubjectId) -> Result<Subject, Error> {
+        self.subject_repository.get_by_id(subject_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("subject {} not found", String::from(subject_id))))
+    }
+
+    async fn issue(&self, subject: &Subject, name: &str, expires_at: Option<DateTime<Utc>>) -> Result<IssueApiKeyResponse, Error> {
+        let (api_key, plaintext) = ApiKey::issue(subject, name, expires_at)?;
+        self.api_key_repository.save(api_key.clone()).await?;
+
+        Ok(IssueApiKeyResponse {
+            api_key_id: api_key.get_id(),
+            api_key: plaintext,
+            expires_at: api_key.get_expires_at(),
+        })
+    }
+}
+
+#[async_trait]
+impl ApiKeyService for ApiKeyServiceImpl {
+    async fn issue_api_key(&self, req: IssueApiKeyRequest) -> Result<IssueApiKeyResponse, Error> {
+        let subject = self.get_subject(req.subject_id).await?;
+        self.issue(&subject, &req.name, req.expires_at).await
+    }
+
+    async fn rotate_api_key(&self, req: RotateApiKeyRequest) -> Result<IssueApiKeyResponse, Error> {
+        let replaced = self.get_api_key(req.api_key_id).await?;
+        let now = Utc::now();
+        if !replaced.is_usable_at(now) {
+            return Err(Error::Simple(format!("api key {} is revoked or expired", replaced.get_name())));
+        }
+
+        let subject = self.get_subject(replaced.get_subject_id()).await?;
+        let (api_key, plaintext) = ApiKey::issue(&subject, &replaced.get_name(), req.expires_at)?;
+        let grace_until = req.grace_period_seconds
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| now + Duration::seconds(seconds));
+        self.api_key_repository.rotate(replaced.get_id(), api_key.clone(), grace_until, now).await?;
+
+        Ok(IssueApiKeyResponse {
+            api_key_id: api_key.get_id(),
+            api_key: plaintext,
+            expires_at: api_key.get_expires_at(),
+        })
+    }
+
+    async fn revoke_api_key(&self, req: RevokeApiKeyRequest) -> Result<RevokeApiKeyResponse, Error> {
+        let mut api_key = self.get_api_key(req.api_key_id).await?;
+        api_key.revoke(Utc::now())?;
+        self.api_key_repository.save(api_key).await?;
+        Ok(RevokeApiKeyResponse {})
+    }
+
+    async fn list_api_keys(&self, req: ListApiKeysRequest) -> Result<ListApiKeysResponse, Error> {
+        let api_keys = self.api_key_repository.find_by_subject(req.subject_id)
+            .await?
+            .into_iter()
+            .map(|api_key| ApiKeyDescription {
+                api_key_id: api_key.get_id(),
+                name: api_key.get_name(),
+                prefix: api_key.get_prefix(),
+                expires_at: api_key.get_expires_at(),
+                last_used_at: api_key.get_last_used_at(),
+                revoked_at: api_key.get_revoked_at(),
+                created_at: api_key.get_created_at(),
+            })
+            .collect();
+        Ok(ListApiKeysResponse { api_keys })
+    }
+
+    async fn authenticate(&self, api_key: &str) -> Result<SubjectId, Error> {
+        let refused = || Error::Simple("invalid api key".to_string());
+        let (prefix, secret) = parse_api_key(api_key).ok_or_else(refused)?;
+        let stored = self.api_key_repository.get_by_prefix(&prefix).await?.ok_or_else(refused)?;
+        let now = Utc::now();
+        if !stored.verify(&secret) || !stored.is_usable_at(now) {
+            return Err(refused());
+        }
+
+        let subject = self.subject_repository.get_by_id(stored.get_subject_id()).await?.ok_or_else(refused)?;
+        if subject.get_tenant_id() != self.tenant_id
+            || subject.get_kind() != SubjectKind::ServiceAccount
+            || !subject.is_active()
+        {
+            return Err(refused());
+        }
+
+        self.api_key_repository.record_use(stored.get_id(), now).await?;
+        Ok(subject.get_id())
+    }
+}
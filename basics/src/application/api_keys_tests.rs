@@ -0,0 +1,181 @@
+use chrono::{Duration, Utc};
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::api_keys::{
+    ApiKeyService, ApiKeyServiceImpl, IssueApiKeyRequest, IssueApiKeyResponse, RevokeApiKeyRequest, RotateApiKeyRequest,
+};
+use crate::domain::api_keys::{parse_api_key, ApiKey};
+use crate::domain::repositories::{ApiKeyRepository, Repository};
+use crate::domain::secrets::hash_secret;
+use crate::domain::subjects::{Subject, SubjectId};
+use crate::infrastructure::sqlite::api_key::SqliteApiKeyRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::test_support::{seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    subjects:
+      - name: alec leamas
+";
+
+fn api_key_service(connection_pool: &Pool<Sqlite>) -> ApiKeyServiceImpl {
+    ApiKeyServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteApiKeyRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+async fn service_account(connection_pool: &Pool<Sqlite>) -> SubjectId {
+    let reporting_bot = Subject::service_account(tenant_id(), "reporting bot");
+    SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).save(reporting_bot.clone()).await.unwrap();
+    reporting_bot.get_id()
+}
+
+async fn issued(api_key_service: &ApiKeyServiceImpl, subject_id: SubjectId) -> IssueApiKeyResponse {
+    api_key_service.issue_api_key(IssueApiKeyRequest {
+        subject_id,
+        name: "reports".to_string(),
+        expires_at: None,
+    }).await.unwrap()
+}
+
+#[async_std::test]
+async fn test_only_a_hash_of_the_key_is_kept() {
+    let connection_pool = seeded_database(SEED).await;
+    let api_key_service = api_key_service(&connection_pool);
+    let reporting_bot_id = service_account(&connection_pool).await;
+    let issued = issued(&api_key_service, reporting_bot_id.clone()).await;
+
+    let (prefix, secret) = parse_api_key(&issued.api_key).unwrap();
+    assert!(issued.api_key.starts_with(&format!("bsk_{}_", prefix)));
+    let stored = SqliteApiKeyRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_prefix(&prefix)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.get_id(), issued.api_key_id);
+    assert_eq!(stored.get_hash(), hash_secret(&secret));
+    assert!(!stored.get_hash().contains(&secret));
+
+    assert_eq!(api_key_service.authenticate(&issued.api_key).await.unwrap(), reporting_bot_id);
+    let tampered = format!("bsk_{}_{}", prefix, hash_secret(&secret));
+    for api_key in [tampered.as_str(), "bsk_nothing_here", "not a key"] {
+        let error = api_key_service.authenticate(api_key).await.unwrap_err();
+        assert_eq!(error.to_string(), "invalid api key");
+    }
+}
+
+#[async_std::test]
+async fn test_people_are_not_issued_keys() {
+    let connection_pool = seeded_database(SEED).await;
+    let error = api_key_service(&connection_pool).issue_api_key(IssueApiKeyRequest {
+        subject_id: subject_id(&connection_pool, "alec leamas").await,
+        name: "reports".to_string(),
+        expires_at: None,
+    }).await.unwrap_err();
+    assert_eq!(error.to_string(), "subject alec leamas is not a service account");
+}
+
+#[async_std::test]
+async fn test_rotated_keys_work_for_the_grace_period_only() {
+    let connection_pool = seeded_database(SEED).await;
+    let api_key_service = api_key_service(&connection_pool);
+    let reporting_bot_id = service_account(&connection_pool).await;
+    let first = issued(&api_key_service, reporting_bot_id.clone()).await;
+
+    // with a grace period both keys work until it is over
+    let second = api_key_service.rotate_api_key(RotateApiKeyRequest {
+        api_key_id: first.api_key_id.clone(),
+        expires_at: None,
+        grace_period_seconds: Some(3600),
+    }).await.unwrap();
+    assert_ne!(second.api_key, first.api_key);
+    assert_eq!(api_key_service.authenticate(&first.api_key).await.unwrap(), reporting_bot_id);
+    assert_eq!(api_key_service.authenticate(&second.api_key).await.unwrap(), reporting_bot_id);
+    let replaced = SqliteApiKeyRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_id(first.api_key_id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(replaced.get_expires_at().is_some());
+    assert!(replaced.get_revoked_at().is_none());
+
+    // without one the replaced key stops working right away
+    let third = api_key_service.rotate_api_key(RotateApiKeyRequest {
+        api_key_id: second.api_key_id.clone(),
+        expires_at: None,
+        grace_period_seconds: None,
+    }).await.unwrap();
+    api_key_service.authenticate(&second.api_key).await.unwrap_err();
+    assert_eq!(api_key_service.authenticate(&third.api_key).await.unwrap(), reporting_bot_id);
+
+    let error = api_key_service.rotate_api_key(RotateApiKeyRequest {
+        api_key_id: second.api_key_id,
+        expires_at: None,
+        grace_period_seconds: None,
+    }).await.unwrap_err();
+    assert_eq!(error.to_string(), "api key reports is revoked or expired");
+}
+
+#[async_std::test]
+async fn test_revoked_keys_are_refused() {
+    let connection_pool = seeded_database(SEED).await;
+    let api_key_service = api_key_service(&connection_pool);
+    let issued = issued(&api_key_service, service_account(&connection_pool).await).await;
+
+    api_key_service.revoke_api_key(RevokeApiKeyRequest { api_key_id: issued.api_key_id }).await.unwrap();
+    let error = api_key_service.authenticate(&issued.api_key).await.unwrap_err();
+    assert_eq!(error.to_string(), "invalid api key");
+}
+
+#[async_std::test]
+async fn test_a_stale_copy_neither_revives_a_key_nor_extends_it() {
+    let connection_pool = seeded_database(SEED).await;
+    let api_key_service = api_key_service(&connection_pool);
+    let api_key_repository = SqliteApiKeyRepository::new(connection_pool.clone(), tenant_id());
+    let issued = issued(&api_key_service, service_account(&connection_pool).await).await;
+    let mut stale = api_key_repository.get_by_id(issued.api_key_id.clone()).await.unwrap().unwrap();
+
+    api_key_service.revoke_api_key(RevokeApiKeyRequest { api_key_id: issued.api_key_id.clone() }).await.unwrap();
+    api_key_repository.save(stale.clone()).await.unwrap();
+    let stored = api_key_repository.get_by_id(issued.api_key_id.clone()).await.unwrap().unwrap();
+    assert!(stored.get_revoked_at().is_some());
+    api_key_service.authenticate(&issued.api_key).await.unwrap_err();
+
+    let soon = Utc::now() + Duration::hours(1);
+    let mut expiring = stored.clone();
+    expiring.expire(soon);
+    api_key_repository.save(expiring).await.unwrap();
+    stale.expire(soon + Duration::days(1));
+    api_key_repository.save(stale).await.unwrap();
+    let stored = api_key_repository.get_by_id(issued.api_key_id).await.unwrap().unwrap();
+    assert_eq!(stored.get_expires_at().map(|at| at.timestamp_millis()), Some(soon.timestamp_millis()));
+}
+
+#[async_std::test]
+async fn test_a_rotation_judges_the_replaced_key_as_stored() {
+    let connection_pool = seeded_database(SEED).await;
+    let api_key_service = api_key_service(&connection_pool);
+    let api_key_repository = SqliteApiKeyRepository::new(connection_pool.clone(), tenant_id());
+    let reporting_bot_id = service_account(&connection_pool).await;
+    let first = issued(&api_key_service, reporting_bot_id.clone()).await;
+
+    // stands in for a rotation that read the key just before it was revoked
+    let reporting_bot = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_id(reporting_bot_id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    let (replacement, _) = ApiKey::issue(&reporting_bot, "reports", None).unwrap();
+    api_key_service.revoke_api_key(RevokeApiKeyRequest { api_key_id: first.api_key_id.clone() }).await.unwrap();
+    let error = api_key_repository
+        .rotate(first.api_key_id.clone(), replacement.clone(), Some(Utc::now() + Duration::hours(1)), Utc::now())
+        .await
+        .unwrap_err();
+    assert_eq!(error.to_string(), "api key reports is revoked or expired");
+    assert!(api_key_repository.get_by_id(replacement.get_id()).await.unwrap().is_none());
+    let keys = api_key_repository.find_by_subject(reporting_bot_id).await.unwrap();
+    assert_eq!(keys.len(), 1);
+    assert!(keys[0].get_revoked_at().is_some());
+}
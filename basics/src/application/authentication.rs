@@ -0,0 +1,241 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::domain::credentials::{verify_no_password, LockoutPolicy, PasswordCredential};
+use crate::domain::repositories::{CredentialRepository, Error, SessionRepository, SubjectRepository};
+use crate::domain::sessions::{parse_session_token, Session, SessionId};
+use crate::domain::subjects::{Subject, SubjectId, SubjectKind};
+use crate::domain::tenants::TenantId;
+
+// setting a password signs the subject out everywhere. without a login, a subject's first password goes
+// with its name and later ones keep the login they had
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetPasswordRequest {
+    pub subject_id: SubjectId,
+    #[serde(default)]
+    pub login: Option<String>,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetPasswordResponse {
+    pub revoked_sessions: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub login: String,
+    pub password: String,
+}
+
+// `token` is the only time the token is ever shown
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginResponse {
+    pub session_id: SessionId,
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogoutRequest {
+    pub session_id: SessionId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogoutResponse {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogoutEverywhereRequest {
+    pub subject_id: SubjectId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogoutEverywhereResponse {
+    pub revoked_sessions: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListSessionsRequest {
+    pub subject_id: SubjectId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionDescription {
+    pub session_id: SessionId,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListSessionsResponse {
+    pub sessions: Vec<SessionDescription>,
+}
+
+#[async_trait]
+pub trait AuthenticationService {
+    async fn set_password(&self, req: SetPasswordRequest) -> Result<SetPasswordResponse, Error>;
+    // unknown logins, wrong passwords and subjects that are locked out are all refused alike. a subject
+    // that is locked out is refused without its password being looked at
+    async fn login(&self, req: LoginRequest) -> Result<LoginResponse, Error>;
+    async fn logout(&self, req: LogoutRequest) -> Result<LogoutResponse, Error>;
+    async fn logout_everywhere(&self, req: LogoutEverywhereRequest) -> Result<LogoutEverywhereResponse, Error>;
+    // sessions that have not ended yet
+    async fn list_sessions(&self, req: ListSessionsRequest) -> Result<ListSessionsResponse, Error>;
+    // the session a token stands for, ready to be handed to `AccessChecker`. malformed and unknown
+    // tokens, sessions that expired or were revoked and sessions of subjects that are not active are all refused alike
+    async fn authenticate(&self, token: &str) -> Result<Session, Error>;
+}
+
+pub struct AuthenticationServiceImpl {
+    tenant_id: TenantId,
+    credential_repository: Box<dyn CredentialRepository + Send + Sync>,
+    session_repository: Box<dyn SessionRepository + Send + Sync>,
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    lockout_policy: LockoutPolicy,
+    session_lifetime: Duration,
+}
+
+impl AuthenticationServiceImpl {
+    pub fn new(
+        tenant_id: TenantId,
+        credential_repository: Box<dyn CredentialRepository + Send + Sync>,
+        session_repository: Box<dyn SessionRepository + Send + Sync>,
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        lockout_policy: LockoutPolicy,
+        session_lifetime: Duration,
+    ) -> Self {
+        AuthenticationServiceImpl {
+            tenant_id,
+            credential_repository,
+            session_repository,
+            subject_repository,
+            lockout_policy,
+            session_lifetime,
+        }
+    }
+
+    async fn get_subject(&self, subject_id: SubjectId) -> Result<Subject, Error> {
+        self.subject_repository.get_by_id(subject_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("subject {} not found", String::from(subject_id))))
+    }
+
+    // the person signing in with the login, if it is still active
+    async fn get_credential(&self, login: &str) -> Result<Option<(Subject, PasswordCredential)>, Error> {
+        let credential = match self.credential_repository.get_by_login(login).await? {
+            Some(credential) => credential,
+            None => return Ok(None),
+        };
+        match self.subject_repository.get_by_id(credential.get_subject_id()).await? {
+            Some(subject) if subject.get_kind() == SubjectKind::Person && subject.is_active() => Ok(Some((subject, credential))),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthenticationService for AuthenticationServiceImpl {
+    async fn set_password(&self, req: SetPasswordRequest) -> Result<SetPasswordResponse, Error> {
+        let subject = self.get_subject(req.subject_id).await?;
+        let credential = match self.credential_repository.get_by_id(subject.get_id()).await? {
+            Some(mut credential) => {
+                credential.change_password(&subject, &req.password)?;
+                if let Some(login) = &req.login {
+                    credential.change_login(login)?;
+                }
+                credential
+            },
+            None => PasswordCredential::new(&subject, req.login.as_deref().unwrap_or(&subject.get_name()), &req.password)?,
+        };
+        self.credential_repository.save(credential).await?;
+
+        let revoked_sessions = self.session_repository.revoke_all_by_subject(subject.get_id(), Utc::now()).await?;
+        Ok(SetPasswordResponse { revoked_sessions })
+    }
+
+    async fn login(&self, req: LoginRequest) -> Result<LoginResponse, Error> {
+        let refused = || Error::Simple("invalid login or password".to_string());
+        let (subject, credential) = match self.get_credential(&req.login).await? {
+            Some(found) => found,
+            None => {
+                verify_no_password(&req.password);
+                return Err(refused());
+            },
+        };
+
+        let now = Utc::now();
+        // telling the caller about the lockout would tell them the login exists
+        if let Some(locked_until) = credential.get_locked_until().filter(|_| credential.is_locked_at(now)) {
+            verify_no_password(&req.password);
+            tracing::info!(subject_id = String::from(subject.get_id()), %locked_until, "login refused during lockout");
+            return Err(refused());
+        }
+        if !credential.verify(&req.password) {
+            self.credential_repository.record_failed_login(subject.get_id(), &self.lockout_policy, now).await?;
+            tracing::info!(subject_id = String::from(subject.get_id()), "failed login");
+            return Err(refused());
+        }
+
+        // the lockout checked above was read before the password was; the store has the last word
+        if !self.credential_repository.record_successful_login(subject.get_id(), now).await? {
+            tracing::info!(subject_id = String::from(subject.get_id()), "login refused during lockout");
+            return Err(refused());
+        }
+
+        let expires_at = now + self.session_lifetime;
+        let (session, token) = Session::sign_in(&subject, expires_at)?;
+        self.session_repository.save(session.clone()).await?;
+        Ok(LoginResponse {
+            session_id: session.get_id(),
+            token,
+            expires_at,
+        })
+    }
+
+    async fn logout(&self, req: LogoutRequest) -> Result<LogoutResponse, Error> {
+        let mut session = self.session_repository.get_by_id(req.session_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("session {} not found", String::from(req.session_id))))?;
+        // logging out of a session that is over already changes nothing
+        if session.get_revoked_at().is_none() {
+            session.revoke(Utc::now())?;
+            self.session_repository.save(session).await?;
+        }
+        Ok(LogoutResponse {})
+    }
+
+    async fn logout_everywhere(&self, req: LogoutEverywhereRequest) -> Result<LogoutEverywhereResponse, Error> {
+        let subject = self.get_subject(req.subject_id).await?;
+        let revoked_sessions = self.session_repository.revoke_all_by_subject(subject.get_id(), Utc::now()).await?;
+        Ok(LogoutEverywhereResponse { revoked_sessions })
+    }
+
+    async fn list_sessions(&self, req: ListSessionsRequest) -> Result<ListSessionsResponse, Error> {
+        let sessions = self.session_repository.find_active_by_subject(req.subject_id, Utc::now())
+            .await?
+            .into_iter()
+            .map(|session| SessionDescription {
+                session_id: session.get_id(),
+                expires_at: session.get_expires_at(),
+                created_at: session.get_created_at(),
+            })
+            .collect();
+        Ok(ListSessionsResponse { sessions })
+    }
+
+    async fn authenticate(&self, token: &str) -> Result<Session, Error> {
+        let refused = || Error::Simple("invalid session token".to_string());
+        let (session_id, secret) = parse_session_token(token).ok_or_else(refused)?;
+        let session = self.session_repository.get_by_id(session_id).await?.ok_or_else(refused)?;
+        if !session.verify(&secret) || !session.is_active_at(Utc::now()) {
+            return Err(refused());
+        }
+
+        let subject = self.subject_repository.get_by_id(session.get_subject_id()).await?.ok_or_else(refused)?;
+        if subject.get_tenant_id() != self.tenant_id || !subject.is_active() {
+            return Err(refused());
+        }
+        Ok(session)
+    }
+}
@@ -0,0 +1,227 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::authentication::{AuthenticationService, AuthenticationServiceImpl, LoginRequest, SetPasswordRequest};
+use crate::domain::credentials::{LockoutPolicy, PasswordCredential};
+use crate::domain::repositories::{CredentialRepository, Error, Repository, SessionRepository};
+use crate::domain::subjects::{Subject, SubjectId};
+use crate::infrastructure::sqlite::credential::SqliteCredentialRepository;
+use crate::infrastructure::sqlite::session::SqliteSessionRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::test_support::{seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    subjects:
+      - name: alec leamas
+      - name: george smiley
+";
+
+const PASSWORD: &str = "the spy who came in";
+
+fn authentication_service(connection_pool: &Pool<Sqlite>) -> AuthenticationServiceImpl {
+    AuthenticationServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteCredentialRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSessionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        LockoutPolicy::new(3, Duration::minutes(15)),
+        Duration::hours(8),
+    )
+}
+
+// hands out the credential as it was read before guesses made in parallel locked the subject out
+struct ReadBeforeTheLockout {
+    credential_repository: SqliteCredentialRepository,
+    read: PasswordCredential,
+}
+
+#[async_trait]
+impl Repository<SubjectId, PasswordCredential> for ReadBeforeTheLockout {
+    async fn get_by_id(&self, id: SubjectId) -> Result<Option<PasswordCredential>, Error> {
+        self.credential_repository.get_by_id(id).await
+    }
+
+    async fn save(&self, entity: PasswordCredential) -> Result<(), Error> {
+        self.credential_repository.save(entity).await
+    }
+}
+
+#[async_trait]
+impl CredentialRepository for ReadBeforeTheLockout {
+    async fn get_by_login(&self, _login: &str) -> Result<Option<PasswordCredential>, Error> {
+        Ok(Some(self.read.clone()))
+    }
+
+    async fn record_failed_login(&self, subject_id: SubjectId, policy: &LockoutPolicy, at: DateTime<Utc>) -> Result<(), Error> {
+        self.credential_repository.record_failed_login(subject_id, policy, at).await
+    }
+
+    async fn record_successful_login(&self, subject_id: SubjectId, at: DateTime<Utc>) -> Result<bool, Error> {
+        self.credential_repository.record_successful_login(subject_id, at).await
+    }
+}
+
+fn login(login: &str, password: &str) -> LoginRequest {
+    LoginRequest {
+        login: login.to_string(),
+        password: password.to_string(),
+    }
+}
+
+#[async_std::test]
+async fn test_passwords_are_kept_as_argon2_hashes() {
+    let connection_pool = seeded_database(SEED).await;
+    let authentication_service = authentication_service(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    authentication_service.set_password(SetPasswordRequest {
+        subject_id: alec_leamas_id.clone(),
+        login: None,
+        password: PASSWORD.to_string(),
+    }).await.unwrap();
+
+    let credential = SqliteCredentialRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_id(alec_leamas_id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(credential.get_hash().starts_with("$argon2id$"), "{}", credential.get_hash());
+    assert!(!credential.get_hash().contains(PASSWORD));
+    assert_eq!(credential.get_login(), "alec leamas");
+
+    let signed_in = authentication_service.login(login("alec leamas", PASSWORD)).await.unwrap();
+    let session = authentication_service.authenticate(&signed_in.token).await.unwrap();
+    assert_eq!(session.get_subject_id(), alec_leamas_id);
+}
+
+#[async_std::test]
+async fn test_unknown_logins_are_refused_like_wrong_passwords() {
+    let connection_pool = seeded_database(SEED).await;
+    let authentication_service = authentication_service(&connection_pool);
+    authentication_service.set_password(SetPasswordRequest {
+        subject_id: subject_id(&connection_pool, "alec leamas").await,
+        login: None,
+        password: PASSWORD.to_string(),
+    }).await.unwrap();
+
+    let wrong_password = authentication_service.login(login("alec leamas", "control")).await.unwrap_err();
+    // george smiley exists, he just has no password
+    let no_password = authentication_service.login(login("george smiley", PASSWORD)).await.unwrap_err();
+    let unknown = authentication_service.login(login("karla", PASSWORD)).await.unwrap_err();
+    assert_eq!(wrong_password.to_string(), "invalid login or password");
+    assert_eq!(no_password.to_string(), wrong_password.to_string());
+    assert_eq!(unknown.to_string(), wrong_password.to_string());
+}
+
+#[async_std::test]
+async fn test_wrong_passwords_lock_the_subject_out_without_saying_so() {
+    let connection_pool = seeded_database(SEED).await;
+    let authentication_service = authentication_service(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    authentication_service.set_password(SetPasswordRequest {
+        subject_id: alec_leamas_id.clone(),
+        login: None,
+        password: PASSWORD.to_string(),
+    }).await.unwrap();
+
+    for password in ["control", "karla", "smiley"] {
+        authentication_service.login(login("alec leamas", password)).await.unwrap_err();
+    }
+    let credential = SqliteCredentialRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_id(alec_leamas_id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(credential.get_locked_until().is_some());
+
+    // the right password does not get past the lockout, and the refusal reads like any other
+    let error = authentication_service.login(login("alec leamas", PASSWORD)).await.unwrap_err();
+    assert_eq!(error.to_string(), "invalid login or password");
+
+    // a new password lifts it
+    authentication_service.set_password(SetPasswordRequest {
+        subject_id: alec_leamas_id,
+        login: None,
+        password: "the looking glass war".to_string(),
+    }).await.unwrap();
+    authentication_service.login(login("alec leamas", "the looking glass war")).await.unwrap();
+}
+
+#[async_std::test]
+async fn test_a_lockout_reached_in_parallel_stops_logins_already_past_the_check() {
+    let connection_pool = seeded_database(SEED).await;
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    authentication_service(&connection_pool).set_password(SetPasswordRequest {
+        subject_id: alec_leamas_id.clone(),
+        login: None,
+        password: PASSWORD.to_string(),
+    }).await.unwrap();
+    let credential_repository = SqliteCredentialRepository::new(connection_pool.clone(), tenant_id());
+    let read = credential_repository.get_by_id(alec_leamas_id.clone()).await.unwrap().unwrap();
+
+    let policy = LockoutPolicy::new(3, Duration::minutes(15));
+    for _ in 0..3 {
+        credential_repository.record_failed_login(alec_leamas_id.clone(), &policy, Utc::now()).await.unwrap();
+    }
+    let authentication_service = AuthenticationServiceImpl::new(
+        tenant_id(),
+        Box::new(ReadBeforeTheLockout { credential_repository, read }),
+        Box::new(SqliteSessionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        policy,
+        Duration::hours(8),
+    );
+
+    let error = authentication_service.login(login("alec leamas", PASSWORD)).await.unwrap_err();
+    assert_eq!(error.to_string(), "invalid login or password");
+    let sessions = SqliteSessionRepository::new(connection_pool.clone(), tenant_id())
+        .find_active_by_subject(alec_leamas_id.clone(), Utc::now())
+        .await
+        .unwrap();
+    assert!(sessions.is_empty());
+    // nor do guesses made during the lockout count towards the next one
+    authentication_service.login(login("alec leamas", "control")).await.unwrap_err();
+    let credential = SqliteCredentialRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_id(alec_leamas_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(credential.get_locked_until().is_some());
+    assert_eq!(credential.get_failed_attempts(), 0);
+}
+
+#[async_std::test]
+async fn test_namesakes_sign_in_with_logins_of_their_own() {
+    let connection_pool = seeded_database(SEED).await;
+    let authentication_service = authentication_service(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let namesake = Subject::new(tenant_id(), "alec leamas");
+    SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).save(namesake.clone()).await.unwrap();
+
+    authentication_service.set_password(SetPasswordRequest {
+        subject_id: alec_leamas_id.clone(),
+        login: None,
+        password: PASSWORD.to_string(),
+    }).await.unwrap();
+    let error = authentication_service.set_password(SetPasswordRequest {
+        subject_id: namesake.get_id(),
+        login: None,
+        password: "call for the dead".to_string(),
+    }).await.unwrap_err();
+    assert_eq!(error.to_string(), "login alec leamas is taken");
+    authentication_service.set_password(SetPasswordRequest {
+        subject_id: namesake.get_id(),
+        login: Some("leamas".to_string()),
+        password: "call for the dead".to_string(),
+    }).await.unwrap();
+
+    let signed_in = authentication_service.login(login("alec leamas", PASSWORD)).await.unwrap();
+    assert_eq!(authentication_service.authenticate(&signed_in.token).await.unwrap().get_subject_id(), alec_leamas_id);
+    let signed_in = authentication_service.login(login("leamas", "call for the dead")).await.unwrap();
+    assert_eq!(authentication_service.authenticate(&signed_in.token).await.unwrap().get_subject_id(), namesake.get_id());
+    authentication_service.login(login("alec leamas", "call for the dead")).await.unwrap_err();
+
+    let credential_repository = SqliteCredentialRepository::new(connection_pool.clone(), tenant_id());
+    assert_eq!(credential_repository.get_by_login("leamas").await.unwrap().unwrap().get_subject_id(), namesake.get_id());
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::domain::outbox::{ChangeEvent, OutboxEntry};
+use crate::domain::repositories::{ChangeFeed, Error};
+use crate::domain::resources::ResourceId;
+use crate::domain::subjects::SubjectId;
+use crate::metrics::{self, CACHE_LOOKUPS};
+
+const BATCH_SIZE: u32 = 100;
+
+#[async_trait]
+pub trait ChangeSubscriber {
+    async fn handle(&self, entry: &OutboxEntry) -> Result<(), Error>;
+}
+
+// streams the change feed to a subscriber under a consumer name. the offset is committed once
+// entries are handled, so delivery is at least once: a crash between handling and committing
+// replays those entries to the next consumer with the same name
+pub struct ChangeFeedConsumer {
+    name: String,
+    change_feed: Box<dyn ChangeFeed + Send + Sync>,
+}
+
+impl ChangeFeedConsumer {
+    pub fn new(name: &str, change_feed: Box<dyn ChangeFeed + Send + Sync>) -> ChangeFeedConsumer {
+        ChangeFeedConsumer {
+            name: name.to_string(),
+            change_feed,
+        }
+    }
+
+    // hands every entry past the committed offset to the subscriber, oldest first, and returns how
+    // many it handled. a failing entry stops the poll; the entries before it stay committed
+    pub async fn poll<S>(&self, subscriber: &S) -> Result<usize, Error>
+    where
+        S: ChangeSubscriber + Sync + ?Sized,
+    {
+        let mut handled = 0;
+        let mut position = self.change_feed.get_offset(&self.name).await?;
+        loop {
+            let entries = self.change_feed.read_after(position, BATCH_SIZE).await?;
+            if entries.is_empty() {
+                return Ok(handled);
+            }
+
+            for entry in entries {
+                if let Err(err) = subscriber.handle(&entry).await {
+                    self.change_feed.commit_offset(&self.name, position).await?;
+                    return Err(err);
+                }
+                position = entry.get_position();
+                handled += 1;
+            }
+            self.change_feed.commit_offset(&self.name, position).await?;
+        }
+    }
+}
+
+// remembers access decisions of `AccessChecker::can_invoke`. a change to a subject only affects its
+// own decisions; any role, group, permission or resource change may affect everyone's, so it drops them all
+#[derive(Default)]
+pub struct DecisionCache {
+    decisions: Mutex<HashMap<(SubjectId, ResourceId), bool>>,
+}
+
+impl DecisionCache {
+    pub fn new() -> DecisionCache {
+        DecisionCache::default()
+    }
+
+    pub fn get(&self, subject_id: &SubjectId, resource_id: &ResourceId) -> Option<bool> {
+        let allowed = self.decisions.lock().unwrap()
+            .get(&(subject_id.clone(), resource_id.clone()))
+            .copied();
+        metrics::global().increment_counter(CACHE_LOOKUPS, &[
+            ("cache", "decision_cache"),
+            ("result", if allowed.is_some() { "hit" } else { "miss" }),
+        ]);
+        allowed
+    }
+
+    pub fn put(&self, subject_id: SubjectId, resource_id: ResourceId, allowed: bool) {
+        self.decisions.lock().unwrap().insert((subject_id, resource_id), allowed);
+    }
+
+    pub fn len(&self) -> usize {
+        self.decisions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl ChangeSubscriber for DecisionCache {
+    async fn handle(&self, entry: &OutboxEntry) -> Result<(), Error> {
+        let mut decisions = self.decisions.lock().unwrap();
+        match entry.get_event() {
+            ChangeEvent::SubjectSaved { subject_id } | ChangeEvent::SubjectDeleted { subject_id } => {
+                decisions.retain(|(cached_subject_id, _), _| *cached_subject_id != subject_id);
+            },
+            _ => decisions.clear(),
+        }
+        Ok(())
+    }
+}
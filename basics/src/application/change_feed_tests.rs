@@ -0,0 +1,144 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::application::change_feed::{ChangeFeedConsumer, ChangeSubscriber, DecisionCache};
+use crate::domain::outbox::{ChangeEvent, OutboxEntry};
+use crate::domain::repositories::{ChangeFeed, Error, Repository};
+use crate::domain::resources::ResourceId;
+use crate::domain::roles::Role;
+use crate::domain::subjects::Subject;
+use crate::infrastructure::sqlite::outbox::SqliteChangeFeed;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::test_support::{empty_database, subject_id, tenant_id};
+
+// keeps every entry handed to it and fails on the entry at `failing_at`
+#[derive(Default)]
+struct RecordingSubscriber {
+    handled: Mutex<Vec<OutboxEntry>>,
+    failing_at: Option<i64>,
+}
+
+impl RecordingSubscriber {
+    fn failing_at(position: i64) -> RecordingSubscriber {
+        RecordingSubscriber {
+            failing_at: Some(position),
+            ..Default::default()
+        }
+    }
+
+    fn positions(&self) -> Vec<i64> {
+        self.handled.lock().unwrap().iter().map(OutboxEntry::get_position).collect()
+    }
+}
+
+#[async_trait]
+impl ChangeSubscriber for RecordingSubscriber {
+    async fn handle(&self, entry: &OutboxEntry) -> Result<(), Error> {
+        if self.failing_at == Some(entry.get_position()) {
+            return Err(Error::Simple(format!("entry {} failed", entry.get_position())));
+        }
+        self.handled.lock().unwrap().push(entry.clone());
+        Ok(())
+    }
+}
+
+#[async_std::test]
+async fn test_the_feed_lists_changes_in_the_order_they_were_saved() {
+    let connection_pool = empty_database().await;
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id());
+    let role_repository = SqliteRoleRepository::new(connection_pool.clone(), tenant_id());
+    let alec_leamas = Subject::new(tenant_id(), "alec leamas");
+    let analyst = Role::new(tenant_id(), "analyst");
+    let george_smiley = Subject::new(tenant_id(), "george smiley");
+    subject_repository.save(alec_leamas.clone()).await.unwrap();
+    role_repository.save(analyst.clone()).await.unwrap();
+    subject_repository.save(george_smiley.clone()).await.unwrap();
+
+    let change_feed = SqliteChangeFeed::new(connection_pool.clone(), tenant_id());
+    let entries = change_feed.read_after(0, 10).await.unwrap();
+    let events: Vec<ChangeEvent> = entries.iter().map(OutboxEntry::get_event).collect();
+    assert_eq!(events, vec![
+        ChangeEvent::SubjectSaved { subject_id: alec_leamas.get_id() },
+        ChangeEvent::RoleSaved { role_id: analyst.get_id() },
+        ChangeEvent::SubjectSaved { subject_id: george_smiley.get_id() },
+    ]);
+    assert!(entries.windows(2).all(|pair| pair[0].get_position() < pair[1].get_position()));
+    assert_eq!(change_feed.get_head_position().await.unwrap(), entries[2].get_position());
+
+    let rest = change_feed.read_after(entries[0].get_position(), 1).await.unwrap();
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].get_position(), entries[1].get_position());
+}
+
+#[async_std::test]
+async fn test_consumers_resume_from_their_own_offsets() {
+    let connection_pool = empty_database().await;
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id());
+    subject_repository.save(Subject::new(tenant_id(), "alec leamas")).await.unwrap();
+    subject_repository.save(Subject::new(tenant_id(), "george smiley")).await.unwrap();
+    let change_feed = || Box::new(SqliteChangeFeed::new(connection_pool.clone(), tenant_id()));
+
+    let subscriber = RecordingSubscriber::default();
+    let consumer = ChangeFeedConsumer::new("index", change_feed());
+    assert_eq!(consumer.poll(&subscriber).await.unwrap(), 2);
+    assert_eq!(consumer.poll(&subscriber).await.unwrap(), 0);
+
+    subject_repository.save(Subject::new(tenant_id(), "connie sachs")).await.unwrap();
+    // a consumer of the same name picks up where the first left off
+    assert_eq!(ChangeFeedConsumer::new("index", change_feed()).poll(&subscriber).await.unwrap(), 1);
+    assert_eq!(subscriber.positions().len(), 3);
+
+    let other = RecordingSubscriber::default();
+    assert_eq!(ChangeFeedConsumer::new("cache", change_feed()).poll(&other).await.unwrap(), 3);
+    assert_eq!(other.positions(), subscriber.positions());
+}
+
+#[async_std::test]
+async fn test_a_failing_entry_is_handed_out_again_on_the_next_poll() {
+    let connection_pool = empty_database().await;
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id());
+    for name in ["alec leamas", "george smiley", "connie sachs"] {
+        subject_repository.save(Subject::new(tenant_id(), name)).await.unwrap();
+    }
+    let change_feed = SqliteChangeFeed::new(connection_pool.clone(), tenant_id());
+    let positions: Vec<i64> = change_feed.read_after(0, 10).await.unwrap().iter().map(OutboxEntry::get_position).collect();
+
+    let consumer = ChangeFeedConsumer::new("index", Box::new(SqliteChangeFeed::new(connection_pool.clone(), tenant_id())));
+    let failing = RecordingSubscriber::failing_at(positions[1]);
+    assert!(consumer.poll(&failing).await.is_err());
+    assert_eq!(failing.positions(), positions[..1]);
+    assert_eq!(change_feed.get_offset("index").await.unwrap(), positions[0]);
+
+    let recovered = RecordingSubscriber::default();
+    assert_eq!(consumer.poll(&recovered).await.unwrap(), 2);
+    assert_eq!(recovered.positions(), positions[1..]);
+}
+
+#[async_std::test]
+async fn test_the_decision_cache_drops_what_a_change_may_affect() {
+    let connection_pool = empty_database().await;
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id());
+    subject_repository.save(Subject::new(tenant_id(), "alec leamas")).await.unwrap();
+    subject_repository.save(Subject::new(tenant_id(), "george smiley")).await.unwrap();
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let george_smiley_id = subject_id(&connection_pool, "george smiley").await;
+    let reports_id = ResourceId::for_name(&tenant_id(), "reports");
+
+    let cache = DecisionCache::new();
+    let consumer = ChangeFeedConsumer::new("cache", Box::new(SqliteChangeFeed::new(connection_pool.clone(), tenant_id())));
+    consumer.poll(&cache).await.unwrap();
+    cache.put(alec_leamas_id.clone(), reports_id.clone(), true);
+    cache.put(george_smiley_id.clone(), reports_id.clone(), false);
+
+    let alec_leamas = subject_repository.get_by_id(alec_leamas_id.clone()).await.unwrap().unwrap();
+    subject_repository.save(alec_leamas).await.unwrap();
+    consumer.poll(&cache).await.unwrap();
+    assert_eq!(cache.get(&alec_leamas_id, &reports_id), None);
+    assert_eq!(cache.get(&george_smiley_id, &reports_id), Some(false));
+
+    SqliteRoleRepository::new(connection_pool.clone(), tenant_id()).save(Role::new(tenant_id(), "analyst")).await.unwrap();
+    consumer.poll(&cache).await.unwrap();
+    assert!(cache.is_empty());
+}
@@ -0,0 +1,176 @@
+use std::collections::{BTreeSet, HashSet};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::domain::delegations::{Delegation, DelegationId};
+use crate::domain::permissions::{Effect, Grantee, PermissionId, Permission};
+use crate::domain::repositories::{DelegationRepository, Error, Repository};
+use crate::domain::roles::{Role, RoleId};
+use crate::domain::subjects::{Subject, SubjectId};
+use crate::domain::tenants::TenantId;
+
+// what a subject may hand on: the allow permissions of the roles it holds at `at`. denies are not
+// worth delegating, and owner permissions follow ownership rather than the subject
+pub async fn delegable_permissions<R, P>(
+    role_repository: &R,
+    permission_repository: &P,
+    subject: &Subject,
+    at: DateTime<Utc>,
+) -> Result<HashSet<PermissionId>, Error>
+where
+    R: Repository<RoleId, Role> + ?Sized,
+    P: Repository<PermissionId, Permission> + ?Sized,
+{
+    let mut delegable = HashSet::new();
+    if subject.get_deleted_at().is_some() {
+        return Ok(delegable);
+    }
+
+    for role_id in subject.get_active_roles(at) {
+        let role = match role_repository.get_by_id(role_id).await? {
+            Some(role) => role,
+            None => continue,
+        };
+        for permission_id in role.get_permissions() {
+            let permission = match permission_repository.get_by_id(permission_id).await? {
+                Some(permission) => permission,
+                None => continue,
+            };
+            if permission.get_effect() == Effect::Allow && permission.get_grantee() == Grantee::RoleHolders {
+                delegable.insert(permission.get_id());
+            }
+        }
+    }
+    Ok(delegable)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DelegateRequest {
+    pub delegator_id: SubjectId,
+    pub delegate_id: SubjectId,
+    pub permission_ids: HashSet<PermissionId>,
+    pub valid_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DelegateResponse {
+    pub delegation_id: DelegationId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeDelegationRequest {
+    pub delegation_id: DelegationId,
+    pub revoked_by: SubjectId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeDelegationResponse {}
+
+#[async_trait]
+pub trait DelegationService {
+    async fn delegate(&self, req: DelegateRequest) -> Result<DelegateResponse, Error>;
+    async fn revoke(&self, req: RevokeDelegationRequest) -> Result<RevokeDelegationResponse, Error>;
+}
+
+pub struct DelegationServiceImpl {
+    tenant_id: TenantId,
+    delegation_repository: Box<dyn DelegationRepository + Send + Sync>,
+    subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+    role_repository: Box<dyn Repository<RoleId, Role> + Send + Sync>,
+    permission_repository: Box<dyn Repository<PermissionId, Permission> + Send + Sync>,
+}
+
+impl DelegationServiceImpl {
+    pub fn new(
+        tenant_id: TenantId,
+        delegation_repository: Box<dyn DelegationRepository + Send + Sync>,
+        subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+        role_repository: Box<dyn Repository<RoleId, Role> + Send + Sync>,
+        permission_repository: Box<dyn Repository<PermissionId, Permission> + Send + Sync>,
+    ) -> Self {
+        DelegationServiceImpl {
+            tenant_id,
+            delegation_repository,
+            subject_repository,
+            role_repository,
+            permission_repository,
+        }
+    }
+
+    async fn get_subject(&self, subject_id: SubjectId) -> Result<Subject, Error> {
+        let subject = self.subject_repository.get_by_id(subject_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("subject {} not found", String::from(subject_id.clone()))))?;
+
+        if subject.get_deleted_at().is_some() {
+            return Err(Error::Simple(format!("subject {} is deleted", String::from(subject_id))));
+        }
+        Ok(subject)
+    }
+}
+
+#[async_trait]
+impl DelegationService for DelegationServiceImpl {
+    // a delegator can only hand on what it holds itself. delegates cannot delegate further, since
+    // delegated permissions never count as held
+    async fn delegate(&self, req: DelegateRequest) -> Result<DelegateResponse, Error> {
+        let delegator = self.get_subject(req.delegator_id.clone()).await?;
+        let delegate = self.get_subject(req.delegate_id.clone()).await?;
+
+        let delegable = delegable_permissions(
+            self.role_repository.as_ref(),
+            self.permission_repository.as_ref(),
+            &delegator,
+            Utc::now(),
+        ).await?;
+        let mut missing = BTreeSet::new();
+        for permission_id in req.permission_ids.difference(&delegable) {
+            let name = self.permission_repository.get_by_id(permission_id.clone())
+                .await?
+                .map_or_else(|| String::from(permission_id.clone()), |permission| permission.get_name());
+            missing.insert(name);
+        }
+        if !missing.is_empty() {
+            return Err(Error::Simple(format!(
+                "subject {} cannot delegate permissions it does not hold: {}",
+                delegator.get_name(),
+                missing.into_iter().collect::<Vec<_>>().join(", "),
+            )));
+        }
+
+        let delegation = Delegation::new(
+            self.tenant_id.clone(),
+            delegator.get_id(),
+            delegate.get_id(),
+            req.permission_ids,
+            req.valid_until,
+        )?;
+        self.delegation_repository.save(delegation.clone()).await?;
+
+        Ok(DelegateResponse {
+            delegation_id: delegation.get_id(),
+        })
+    }
+
+    // only the delegator or the delegate can end a delegation early
+    async fn revoke(&self, req: RevokeDelegationRequest) -> Result<RevokeDelegationResponse, Error> {
+        let mut delegation = self.delegation_repository.get_by_id(req.delegation_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("delegation {} not found", String::from(req.delegation_id.clone()))))?;
+
+        if req.revoked_by != delegation.get_delegator_id() && req.revoked_by != delegation.get_delegate_id() {
+            return Err(Error::Simple(format!(
+                "subject {} is neither the delegator nor the delegate of delegation {}",
+                String::from(req.revoked_by),
+                String::from(req.delegation_id),
+            )));
+        }
+
+        delegation.revoke(req.revoked_by, Utc::now())?;
+        self.delegation_repository.save(delegation).await?;
+
+        Ok(RevokeDelegationResponse {})
+    }
+}
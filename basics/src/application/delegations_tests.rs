@@ -0,0 +1,123 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, Utc};
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::delegations::{DelegateRequest, DelegationService, DelegationServiceImpl};
+use crate::domain::conditions::RequestContext;
+use crate::domain::permissions::PermissionId;
+use crate::domain::repositories::{PermissionRepository, Repository, RoleRepository};
+use crate::domain::separation_of_duties::SeparationOfDuties;
+use crate::infrastructure::sqlite::delegation::SqliteDelegationRepository;
+use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::test_support::{access_checker, resource_id, seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: reports
+      - name: payroll
+    permissions:
+      - name: read reports
+        resource: reports
+      - name: shred reports
+        resource: reports
+        effect: deny
+      - name: read payroll
+        resource: payroll
+    roles:
+      - name: analyst
+        permissions: [read reports, shred reports]
+      - name: paymaster
+        permissions: [read payroll]
+    subjects:
+      - name: george smiley
+        roles: [analyst, paymaster]
+      - name: alec leamas
+        roles: [analyst]
+      - name: peter guillam
+";
+
+fn delegation_service(connection_pool: &Pool<Sqlite>) -> DelegationServiceImpl {
+    DelegationServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteDelegationRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+async fn permission_id(connection_pool: &Pool<Sqlite>, name: &str) -> PermissionId {
+    SqlitePermissionRepository::new(connection_pool.clone(), tenant_id()).get_by_name(name).await.unwrap().unwrap().get_id()
+}
+
+async fn delegate(connection_pool: &Pool<Sqlite>, delegator: &str, delegate: &str, permissions: &[&str]) -> DelegateRequest {
+    let mut permission_ids = HashSet::new();
+    for name in permissions {
+        permission_ids.insert(permission_id(connection_pool, name).await);
+    }
+    DelegateRequest {
+        delegator_id: subject_id(connection_pool, delegator).await,
+        delegate_id: subject_id(connection_pool, delegate).await,
+        permission_ids,
+        valid_until: Utc::now() + Duration::days(1),
+    }
+}
+
+#[async_std::test]
+async fn test_a_subject_cannot_delegate_more_than_it_holds() {
+    let connection_pool = seeded_database(SEED).await;
+    let delegation_service = delegation_service(&connection_pool);
+
+    let request = delegate(&connection_pool, "alec leamas", "peter guillam", &["read reports", "read payroll"]).await;
+    let error = delegation_service.delegate(request).await.unwrap_err();
+    assert_eq!(error.to_string(), "subject alec leamas cannot delegate permissions it does not hold: read payroll");
+
+    // denies are held but not worth handing on
+    let request = delegate(&connection_pool, "george smiley", "peter guillam", &["shred reports"]).await;
+    let error = delegation_service.delegate(request).await.unwrap_err();
+    assert_eq!(error.to_string(), "subject george smiley cannot delegate permissions it does not hold: shred reports");
+
+    // what was delegated does not count as held, so it cannot be passed on
+    let request = delegate(&connection_pool, "george smiley", "peter guillam", &["read payroll"]).await;
+    delegation_service.delegate(request).await.unwrap();
+    let request = delegate(&connection_pool, "peter guillam", "alec leamas", &["read payroll"]).await;
+    let error = delegation_service.delegate(request).await.unwrap_err();
+    assert_eq!(error.to_string(), "subject peter guillam cannot delegate permissions it does not hold: read payroll");
+}
+
+#[async_std::test]
+async fn test_a_delegation_lends_only_what_the_delegator_still_holds() {
+    let connection_pool = seeded_database(SEED).await;
+    let access_checker = access_checker(&connection_pool);
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id());
+    let role_repository = SqliteRoleRepository::new(connection_pool.clone(), tenant_id());
+    let george_smiley = subject_id(&connection_pool, "george smiley").await;
+    let peter_guillam = subject_id(&connection_pool, "peter guillam").await;
+    let payroll = resource_id(&connection_pool, "payroll").await;
+
+    let request = delegate(&connection_pool, "george smiley", "peter guillam", &["read payroll"]).await;
+    let delegation_id = delegation_service(&connection_pool).delegate(request).await.unwrap().delegation_id;
+    let decision = access_checker.check_in_context(peter_guillam.clone(), payroll.clone(), &RequestContext::default()).await.unwrap();
+    assert!(decision.allowed);
+    assert_eq!(decision.delegation_id, Some(delegation_id));
+    assert_eq!(decision.delegator_id, Some(george_smiley.clone()));
+
+    // the delegation stays, but lends nothing while george no longer pays
+    let paymaster = role_repository.get_by_name("paymaster").await.unwrap().unwrap().get_id();
+    let mut george = subject_repository.get_by_id(george_smiley.clone()).await.unwrap().unwrap();
+    george.remove_role(&paymaster);
+    subject_repository.save(george).await.unwrap();
+    let decision = access_checker.check_in_context(peter_guillam.clone(), payroll.clone(), &RequestContext::default()).await.unwrap();
+    assert!(!decision.allowed);
+    assert_eq!(decision.delegation_id, None);
+
+    // and lends it again once he does
+    let mut george = subject_repository.get_by_id(george_smiley).await.unwrap().unwrap();
+    george.add_role(paymaster, &SeparationOfDuties::default()).unwrap();
+    subject_repository.save(george).await.unwrap();
+    assert!(access_checker.check_in_context(peter_guillam, payroll, &RequestContext::default()).await.unwrap().allowed);
+}
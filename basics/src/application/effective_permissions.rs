@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use crate::application::access_checker::AccessChecker;
+use crate::application::change_feed::ChangeSubscriber;
+use crate::domain::effective_permissions::EffectivePermissions;
+use crate::domain::outbox::{ChangeEvent, OutboxEntry};
+use crate::domain::permissions::Grantee;
+use crate::domain::repositories::{
+    ChangeFeed, EffectivePermissionIndex, Error, GroupRepository, PermissionRepository, ResourceRepository,
+    RoleRepository, SubjectRepository,
+};
+use crate::domain::resources::{Resource, ResourceId};
+use crate::domain::subjects::{Subject, SubjectId};
+use crate::domain::tenants::TenantId;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexMismatch {
+    pub subject_id: SubjectId,
+    // unset when the whole entry of the subject is off
+    pub resource_id: Option<ResourceId>,
+    pub indexed: Option<bool>,
+    pub live: Option<bool>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    pub tenant_id: TenantId,
+    pub checked_subjects: usize,
+    pub mismatches: Vec<IndexMismatch>,
+}
+
+impl ConsistencyReport {
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|error| Error::Simple(format!("unable to write report: {}", error)))
+    }
+}
+
+// keeps the effective permission index in line with the store. as a change feed subscriber it
+// recomputes the subjects a change concerns; `rebuild` recomputes every subject and `check` compares
+// the index with what the access checker decides right now
+pub struct EffectivePermissionIndexer {
+    tenant_id: TenantId,
+    effective_permission_index: Box<dyn EffectivePermissionIndex + Send + Sync>,
+    // must not use the index itself, so that its decisions are always evaluated
+    access_checker: AccessChecker,
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+    role_repository: Box<dyn RoleRepository + Send + Sync>,
+    permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+    resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+}
+
+impl EffectivePermissionIndexer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        tenant_id: TenantId,
+        effective_permission_index: Box<dyn EffectivePermissionIndex + Send + Sync>,
+        access_checker: AccessChecker,
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+        role_repository: Box<dyn RoleRepository + Send + Sync>,
+        permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+    ) -> Self {
+        EffectivePermissionIndexer {
+            tenant_id,
+            effective_permission_index,
+            access_checker,
+            subject_repository,
+            group_repository,
+            role_repository,
+            permission_repository,
+            resource_repository,
+        }
+    }
+
+    // a subject whose decisions involve a condition is stored without decisions, since they depend
+    // on the request. the entry goes stale at the next start or end of one of its role assignments
+    async fn compute(&self, subject: &Subject, resources: &[Resource]) -> Result<EffectivePermissions, Error> {
+        let now = Utc::now();
+        let groups = self.group_repository.find_by_subject_transitively(subject.get_id()).await?;
+
+        let mut granting_roles = subject.get_active_roles(now);
+        granting_roles.extend(groups.iter().flat_map(|group| group.get_roles()));
+        let mut indexable = true;
+        for role_id in &granting_roles {
+            let role = match self.role_repository.get_by_id(role_id.clone()).await? {
+                Some(role) => role,
+                None => continue,
+            };
+            for permission_id in role.get_permissions() {
+                if let Some(permission) = self.permission_repository.get_by_id(permission_id).await? {
+                    indexable &= permission.get_condition().is_none();
+                }
+            }
+        }
+        if resources.iter().any(|resource| resource.is_owned_by(&subject.get_id())) {
+            indexable &= self.permission_repository.find_owner_permissions()
+                .await?
+                .iter()
+                .all(|permission| permission.get_condition().is_none());
+        }
+
+        let valid_until = subject.get_role_assignments()
+            .iter()
+            .flat_map(|assignment| [assignment.get_valid_from(), assignment.get_valid_until()])
+            .flatten()
+            .filter(|at| *at > now)
+            .min();
+
+        let mut decisions = HashMap::new();
+        if indexable {
+            for resource in resources {
+                decisions.insert(resource.get_id(), self.access_checker.evaluate(subject, resource.get_id()).await?);
+            }
+        }
+
+        let mut roles = subject.get_roles();
+        roles.extend(groups.iter().flat_map(|group| group.get_roles()));
+        Ok(EffectivePermissions::builder()
+            .tenant_id(subject.get_tenant_id())
+            .subject_id(subject.get_id())
+            .roles(roles)
+            .groups(groups.iter().map(|group| group.get_id()).collect())
+            .decisions(decisions)
+            .indexable(indexable)
+            .valid_until(valid_until)
+            .computed_at(now)
+            .build())
+    }
+
+    async fn index_subjects(&self, subject_ids: HashSet<SubjectId>) -> Result<(), Error> {
+        let resources = self.resource_repository.find_all().await?;
+        for subject_id in subject_ids {
+            match self.subject_repository.get_by_id(subject_id.clone()).await? {
+                Some(subject) => self.effective_permission_index.store(self.compute(&subject, &resources).await?).await?,
+                None => self.effective_permission_index.remove(subject_id).await?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn index_all(&self) -> Result<usize, Error> {
+        let subject_ids: HashSet<SubjectId> = self.subject_repository.find_all()
+            .await?
+            .iter()
+            .map(Subject::get_id)
+            .collect();
+        let count = subject_ids.len();
+        self.index_subjects(subject_ids).await?;
+        Ok(count)
+    }
+
+    // the position is read first, so that changes made while rebuilding are caught up on afterwards
+    pub async fn rebuild(&self, change_feed: &(dyn ChangeFeed + Send + Sync)) -> Result<usize, Error> {
+        let position = change_feed.get_head_position().await?;
+        self.effective_permission_index.clear().await?;
+        let count = self.index_all().await?;
+        self.effective_permission_index.advance_to(position).await?;
+        Ok(count)
+    }
+
+    // stale entries and those of subjects with conditions are left out: the index does not answer from them
+    pub async fn check(&self) -> Result<ConsistencyReport, Error> {
+        let resources = self.resource_repository.find_all().await?;
+        let mut indexed: HashMap<SubjectId, EffectivePermissions> = self.effective_permission_index.find_all()
+            .await?
+            .into_iter()
+            .map(|entry| (entry.get_subject_id(), entry))
+            .collect();
+
+        let now = Utc::now();
+        let subjects = self.subject_repository.find_all().await?;
+        let mut mismatches = Vec::new();
+        for subject in &subjects {
+            let entry = match indexed.remove(&subject.get_id()) {
+                Some(entry) if entry.is_current_at(now) => entry,
+                Some(_) => continue,
+                None => {
+                    mismatches.push(IndexMismatch {
+                        subject_id: subject.get_id(),
+                        resource_id: None,
+                        indexed: None,
+                        live: None,
+                        message: format!("subject {} is not indexed", subject.get_name()),
+                    });
+                    continue;
+                },
+            };
+
+            let live = self.compute(subject, &resources).await?;
+            if !live.is_indexable() {
+                mismatches.push(IndexMismatch {
+                    subject_id: subject.get_id(),
+                    resource_id: None,
+                    indexed: None,
+                    live: None,
+                    message: format!("subject {} is indexed but its decisions depend on conditions", subject.get_name()),
+                });
+                continue;
+            }
+
+            let indexed_decisions = entry.get_decisions();
+            for (resource_id, allowed) in live.get_decisions() {
+                let indexed_allowed = indexed_decisions.get(&resource_id).copied();
+                if indexed_allowed != Some(allowed) {
+                    mismatches.push(IndexMismatch {
+                        subject_id: subject.get_id(),
+                        resource_id: Some(resource_id.clone()),
+                        indexed: indexed_allowed,
+                        live: Some(allowed),
+                        message: format!(
+                            "subject {} {} invoke resource {} but the index says otherwise",
+                            subject.get_name(),
+                            if allowed { "may" } else { "may not" },
+                            String::from(resource_id),
+                        ),
+                    });
+                }
+            }
+        }
+
+        for subject_id in indexed.into_keys() {
+            mismatches.push(IndexMismatch {
+                subject_id: subject_id.clone(),
+                resource_id: None,
+                indexed: None,
+                live: None,
+                message: format!("subject {} is indexed but does not exist", String::from(subject_id)),
+            });
+        }
+
+        Ok(ConsistencyReport {
+            tenant_id: self.tenant_id.clone(),
+            checked_subjects: subjects.len(),
+            mismatches,
+        })
+    }
+}
+
+#[async_trait]
+impl ChangeSubscriber for EffectivePermissionIndexer {
+    // a group concerns the subjects it had as well as those it has now, through member groups included,
+    // a role those it was held by.
+    // owner permissions and resources may concern anyone, so they recompute every subject
+    async fn handle(&self, entry: &OutboxEntry) -> Result<(), Error> {
+        match entry.get_event() {
+            ChangeEvent::SubjectSaved { subject_id } | ChangeEvent::SubjectDeleted { subject_id } => {
+                self.index_subjects([subject_id].into_iter().collect()).await?;
+            },
+            ChangeEvent::GroupSaved { group_id } | ChangeEvent::GroupDeleted { group_id } => {
+                let mut subject_ids: HashSet<SubjectId> = self.effective_permission_index.find_subjects_in_group(group_id.clone())
+                    .await?
+                    .into_iter()
+                    .collect();
+                subject_ids.extend(self.group_repository.find_members(group_id).await?);
+                self.index_subjects(subject_ids).await?;
+            },
+            ChangeEvent::RoleSaved { role_id } | ChangeEvent::RoleDeleted { role_id } => {
+                let subject_ids = self.effective_permission_index.find_subjects_with_role(role_id).await?;
+                self.index_subjects(subject_ids.into_iter().collect()).await?;
+            },
+            ChangeEvent::PermissionSaved { permission_id } | ChangeEvent::PermissionDeleted { permission_id } => {
+                match self.permission_repository.get_by_id(permission_id.clone()).await? {
+                    Some(permission) if permission.get_grantee() == Grantee::RoleHolders => {
+                        let mut subject_ids = HashSet::new();
+                        for role in self.role_repository.find_all().await? {
+                            if role.get_permissions().contains(&permission_id) {
+                                subject_ids.extend(self.effective_permission_index.find_subjects_with_role(role.get_id()).await?);
+                            }
+                        }
+                        self.index_subjects(subject_ids).await?;
+                    },
+                    _ => { self.index_all().await?; },
+                }
+            },
+            ChangeEvent::ResourceSaved { .. } | ChangeEvent::ResourceDeleted { .. } => {
+                self.index_all().await?;
+            },
+        }
+        self.effective_permission_index.advance_to(entry.get_position()).await
+    }
+}
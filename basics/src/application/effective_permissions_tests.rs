@@ -0,0 +1,191 @@
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::change_feed::ChangeFeedConsumer;
+use crate::application::effective_permissions::EffectivePermissionIndexer;
+use crate::domain::groups::Group;
+use crate::domain::repositories::{
+    EffectivePermissionIndex, EntityChange, GroupRepository, PermissionRepository, Repository, ResourceRepository,
+    RoleRepository, SubjectRepository, UnitOfWork,
+};
+use crate::infrastructure::sqlite::effective_permission::SqliteEffectivePermissionIndex;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::outbox::SqliteChangeFeed;
+use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use crate::infrastructure::sqlite::resource::SqliteResourceRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+use crate::test_support::{access_checker, resource_id, seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: archive
+      - name: reports
+        parent: archive
+      - name: ledgers
+    permissions:
+      - name: read archive
+        resource: archive
+      - name: read ledgers
+        resource: ledgers
+    roles:
+      - name: analyst
+        permissions: [read archive]
+    subjects:
+      - name: alec leamas
+        roles: [analyst]
+      - name: george smiley
+      - name: connie sachs
+    groups:
+      - name: the circus
+        subjects: [george smiley]
+        roles: [analyst]
+";
+
+fn indexer(connection_pool: &Pool<Sqlite>) -> EffectivePermissionIndexer {
+    EffectivePermissionIndexer::new(
+        tenant_id(),
+        Box::new(SqliteEffectivePermissionIndex::new(connection_pool.clone(), tenant_id())),
+        access_checker(connection_pool),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+fn change_feed(connection_pool: &Pool<Sqlite>) -> SqliteChangeFeed {
+    SqliteChangeFeed::new(connection_pool.clone(), tenant_id())
+}
+
+async fn group(connection_pool: &Pool<Sqlite>, name: &str) -> Group {
+    SqliteGroupRepository::new(connection_pool.clone(), tenant_id())
+        .find_all()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|group| group.get_name() == name)
+        .unwrap()
+}
+
+// every subject and resource, with what the index says next to what evaluating the checks decides
+async fn lookups_and_decisions(connection_pool: &Pool<Sqlite>) -> Vec<(String, Option<bool>, bool)> {
+    let index = SqliteEffectivePermissionIndex::new(connection_pool.clone(), tenant_id());
+    let access_checker = access_checker(connection_pool);
+    let resources = SqliteResourceRepository::new(connection_pool.clone(), tenant_id()).find_all().await.unwrap();
+    let subjects = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).find_all().await.unwrap();
+
+    let mut pairs = Vec::new();
+    for subject in &subjects {
+        for resource in &resources {
+            let indexed = index.lookup(subject.get_id(), resource.get_id(), chrono::Utc::now()).await.unwrap();
+            let decided = access_checker.evaluate(subject, resource.get_id()).await.unwrap();
+            pairs.push((format!("{} on {}", subject.get_name(), resource.get_name()), indexed, decided));
+        }
+    }
+    pairs
+}
+
+async fn assert_index_answers_as_decided(connection_pool: &Pool<Sqlite>) {
+    for (pair, indexed, decided) in lookups_and_decisions(connection_pool).await {
+        assert_eq!(indexed, Some(decided), "{}", pair);
+    }
+}
+
+async fn assert_index_cannot_answer(connection_pool: &Pool<Sqlite>) {
+    for (pair, indexed, _) in lookups_and_decisions(connection_pool).await {
+        assert_eq!(indexed, None, "{}", pair);
+    }
+}
+
+async fn can_invoke(connection_pool: &Pool<Sqlite>, subject_name: &str, resource_name: &str) -> bool {
+    let access_checker = access_checker(connection_pool)
+        .with_index(Box::new(SqliteEffectivePermissionIndex::new(connection_pool.clone(), tenant_id())));
+    access_checker.can_invoke(subject_id(connection_pool, subject_name).await, resource_id(connection_pool, resource_name).await)
+        .await
+        .unwrap()
+}
+
+#[async_std::test]
+async fn test_changes_from_the_feed_keep_the_index_in_line_with_decisions() {
+    let connection_pool = seeded_database(SEED).await;
+    let indexer = indexer(&connection_pool);
+    let consumer = ChangeFeedConsumer::new("effective permissions", Box::new(change_feed(&connection_pool)));
+    consumer.poll(&indexer).await.unwrap();
+    assert_index_answers_as_decided(&connection_pool).await;
+    assert!(!can_invoke(&connection_pool, "alec leamas", "ledgers").await);
+
+    let role_repository = SqliteRoleRepository::new(connection_pool.clone(), tenant_id());
+    let mut analyst = role_repository.get_by_name("analyst").await.unwrap().unwrap();
+    let read_ledgers = SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_name("read ledgers")
+        .await
+        .unwrap()
+        .unwrap();
+    analyst.add_permission(read_ledgers.get_id());
+    role_repository.save(analyst).await.unwrap();
+    assert!(consumer.poll(&indexer).await.unwrap() > 0);
+    assert_index_answers_as_decided(&connection_pool).await;
+    assert!(can_invoke(&connection_pool, "alec leamas", "ledgers").await);
+    assert!(can_invoke(&connection_pool, "george smiley", "ledgers").await);
+
+    let mut the_circus = group(&connection_pool, "the circus").await;
+    the_circus.remove_subject(&subject_id(&connection_pool, "george smiley").await);
+    SqliteGroupRepository::new(connection_pool.clone(), tenant_id()).save(the_circus).await.unwrap();
+    assert!(consumer.poll(&indexer).await.unwrap() > 0);
+    assert_index_answers_as_decided(&connection_pool).await;
+    assert!(!can_invoke(&connection_pool, "george smiley", "reports").await);
+
+    let resource_repository = SqliteResourceRepository::new(connection_pool.clone(), tenant_id());
+    let mut reports = resource_repository.get_by_name("reports").await.unwrap().unwrap();
+    reports.move_to(Some(resource_id(&connection_pool, "ledgers").await));
+    resource_repository.save(reports).await.unwrap();
+    assert!(consumer.poll(&indexer).await.unwrap() > 0);
+    assert_index_answers_as_decided(&connection_pool).await;
+    assert!(can_invoke(&connection_pool, "alec leamas", "reports").await);
+    assert!(!can_invoke(&connection_pool, "connie sachs", "reports").await);
+}
+
+#[async_std::test]
+async fn test_a_rebuild_catches_up_on_changes_the_index_missed() {
+    let connection_pool = seeded_database(SEED).await;
+    let indexer = indexer(&connection_pool);
+    assert_eq!(indexer.rebuild(&change_feed(&connection_pool)).await.unwrap(), 3);
+    assert_index_answers_as_decided(&connection_pool).await;
+
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id());
+    let mut the_circus = group(&connection_pool, "the circus").await;
+    the_circus.remove_subject(&subject_id(&connection_pool, "george smiley").await);
+    SqliteGroupRepository::new(connection_pool.clone(), tenant_id()).save(the_circus).await.unwrap();
+    let alec_leamas = subject_repository.get_by_name("alec leamas").await.unwrap().unwrap();
+    SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())
+        .commit(vec![EntityChange::DeleteSubject(alec_leamas.get_id())])
+        .await
+        .unwrap();
+
+    assert_eq!(indexer.rebuild(&change_feed(&connection_pool)).await.unwrap(), 2);
+    assert_index_answers_as_decided(&connection_pool).await;
+    assert!(!can_invoke(&connection_pool, "george smiley", "archive").await);
+    let indexed = SqliteEffectivePermissionIndex::new(connection_pool.clone(), tenant_id()).find_all().await.unwrap();
+    assert!(indexed.iter().all(|entry| entry.get_subject_id() != alec_leamas.get_id()));
+    assert!(indexer.check().await.unwrap().mismatches.is_empty());
+}
+
+#[async_std::test]
+async fn test_an_index_behind_the_feed_falls_back_to_evaluating() {
+    let connection_pool = seeded_database(SEED).await;
+    let indexer = indexer(&connection_pool);
+    indexer.rebuild(&change_feed(&connection_pool)).await.unwrap();
+    assert!(can_invoke(&connection_pool, "george smiley", "reports").await);
+
+    let mut the_circus = group(&connection_pool, "the circus").await;
+    the_circus.remove_subject(&subject_id(&connection_pool, "george smiley").await);
+    SqliteGroupRepository::new(connection_pool.clone(), tenant_id()).save(the_circus).await.unwrap();
+
+    // the entry of george smiley still allows the reports, but it is older than the feed
+    assert_index_cannot_answer(&connection_pool).await;
+    assert!(!can_invoke(&connection_pool, "george smiley", "reports").await);
+    assert!(can_invoke(&connection_pool, "alec leamas", "reports").await);
+}
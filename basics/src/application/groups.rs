@@ -0,0 +1,165 @@
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+
+use crate::application::separation_of_duties::DutiesLoader;
+use crate::domain::groups::{Group, GroupId};
+use crate::domain::repositories::{Error, GroupRepository};
+use crate::domain::roles::RoleId;
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateGroupRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateGroupResponse {
+    pub group_id: GroupId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddGroupSubjectRequest {
+    pub group_id: GroupId,
+    pub subject_id: SubjectId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddGroupRoleRequest {
+    pub group_id: GroupId,
+    pub role_id: RoleId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupMemberGroupRequest {
+    pub group_id: GroupId,
+    pub member_group_id: GroupId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateGroupResponse {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListGroupMembersRequest {
+    pub group_id: GroupId,
+}
+
+// the subjects of the group and of every group nested in it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListGroupMembersResponse {
+    pub subject_ids: Vec<SubjectId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListSubjectGroupsRequest {
+    pub subject_id: SubjectId,
+}
+
+// the groups the subject is a direct member of and the groups that contain them
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListSubjectGroupsResponse {
+    pub group_ids: Vec<GroupId>,
+}
+
+#[async_trait]
+pub trait GroupService {
+    async fn create_group(&self, req: CreateGroupRequest) -> Result<CreateGroupResponse, Error>;
+    async fn add_subject(&self, req: AddGroupSubjectRequest) -> Result<UpdateGroupResponse, Error>;
+    async fn add_role(&self, req: AddGroupRoleRequest) -> Result<UpdateGroupResponse, Error>;
+    async fn add_group(&self, req: GroupMemberGroupRequest) -> Result<UpdateGroupResponse, Error>;
+    async fn remove_group(&self, req: GroupMemberGroupRequest) -> Result<UpdateGroupResponse, Error>;
+    async fn list_members(&self, req: ListGroupMembersRequest) -> Result<ListGroupMembersResponse, Error>;
+    async fn list_subject_groups(&self, req: ListSubjectGroupsRequest) -> Result<ListSubjectGroupsResponse, Error>;
+}
+
+pub struct GroupServiceImpl {
+    tenant_id: TenantId,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+    duties_loader: DutiesLoader,
+}
+
+impl GroupServiceImpl {
+    pub fn new(
+        tenant_id: TenantId,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+        duties_loader: DutiesLoader,
+    ) -> Self {
+        GroupServiceImpl {
+            tenant_id,
+            group_repository,
+            duties_loader,
+        }
+    }
+
+    async fn get_group(&self, group_id: GroupId) -> Result<Group, Error> {
+        self.group_repository.get_by_id(group_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("group {} not found", String::from(group_id))))
+    }
+}
+
+#[async_trait]
+impl GroupService for GroupServiceImpl {
+    async fn create_group(&self, req: CreateGroupRequest) -> Result<CreateGroupResponse, Error> {
+        let group = Group::new(self.tenant_id.clone(), &req.name);
+        self.group_repository.save(group.clone()).await?;
+
+        Ok(CreateGroupResponse {
+            group_id: group.get_id(),
+        })
+    }
+
+    async fn add_subject(&self, req: AddGroupSubjectRequest) -> Result<UpdateGroupResponse, Error> {
+        let mut group = self.get_group(req.group_id).await?;
+        let duties = self.duties_loader.load().await?;
+        group.add_subject(req.subject_id, &duties)?;
+        self.group_repository.save(group).await?;
+
+        Ok(UpdateGroupResponse {})
+    }
+
+    async fn add_role(&self, req: AddGroupRoleRequest) -> Result<UpdateGroupResponse, Error> {
+        let mut group = self.get_group(req.group_id).await?;
+        let duties = self.duties_loader.load().await?;
+        group.add_role(req.role_id, &duties)?;
+        self.group_repository.save(group).await?;
+
+        Ok(UpdateGroupResponse {})
+    }
+
+    async fn add_group(&self, req: GroupMemberGroupRequest) -> Result<UpdateGroupResponse, Error> {
+        let mut group = self.get_group(req.group_id).await?;
+        let member_group = self.get_group(req.member_group_id).await?;
+        let ancestors = self.group_repository.find_ancestors(group.get_id()).await?;
+        let duties = self.duties_loader.load().await?;
+        group.add_group(member_group.get_id(), &ancestors, &duties)?;
+        self.group_repository.save(group).await?;
+
+        Ok(UpdateGroupResponse {})
+    }
+
+    async fn remove_group(&self, req: GroupMemberGroupRequest) -> Result<UpdateGroupResponse, Error> {
+        let mut group = self.get_group(req.group_id).await?;
+        group.remove_group(&req.member_group_id);
+        self.group_repository.save(group).await?;
+
+        Ok(UpdateGroupResponse {})
+    }
+
+    async fn list_members(&self, req: ListGroupMembersRequest) -> Result<ListGroupMembersResponse, Error> {
+        let group = self.get_group(req.group_id).await?;
+        let subject_ids = self.group_repository.find_members(group.get_id()).await?;
+
+        Ok(ListGroupMembersResponse {
+            subject_ids,
+        })
+    }
+
+    async fn list_subject_groups(&self, req: ListSubjectGroupsRequest) -> Result<ListSubjectGroupsResponse, Error> {
+        let groups = self.group_repository.find_by_subject_transitively(req.subject_id).await?;
+
+        Ok(ListSubjectGroupsResponse {
+            group_ids: groups.iter().map(Group::get_id).collect(),
+        })
+    }
+}
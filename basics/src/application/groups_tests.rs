@@ -0,0 +1,166 @@
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::groups::{GroupMemberGroupRequest, GroupService, GroupServiceImpl, ListGroupMembersRequest, ListSubjectGroupsRequest};
+use crate::application::separation_of_duties::DutiesLoader;
+use crate::domain::groups::{Group, GroupId};
+use crate::domain::repositories::{EntityChange, GroupRepository, Repository, UnitOfWork};
+use crate::domain::separation_of_duties::SeparationOfDuties;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+use crate::test_support::{seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    roles:
+      - name: engineer
+    subjects:
+      - name: bill haydon
+      - name: roy bland
+    groups:
+      - name: employees
+        subjects: [roy bland]
+        groups: [london station]
+      - name: london station
+        groups: [the circus]
+      - name: the circus
+        subjects: [bill haydon]
+        roles: [engineer]
+";
+
+async fn group(connection_pool: &Pool<Sqlite>, name: &str) -> Group {
+    SqliteGroupRepository::new(connection_pool.clone(), tenant_id())
+        .find_all()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|group| group.get_name() == name)
+        .unwrap()
+}
+
+fn group_service(connection_pool: &Pool<Sqlite>) -> GroupServiceImpl {
+    GroupServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        DutiesLoader::new(
+            Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+            Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+            Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+            Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        ),
+    )
+}
+
+// stands in for a change that checked the groups above it before another change committed
+fn containing_unchecked(mut group: Group, group_id: GroupId) -> Group {
+    group.add_group(group_id, &[], &SeparationOfDuties::default()).unwrap();
+    group
+}
+
+#[async_std::test]
+async fn test_the_store_refuses_cycles_the_domain_could_not_see() {
+    let connection_pool = seeded_database(SEED).await;
+    let group_repository = SqliteGroupRepository::new(connection_pool.clone(), tenant_id());
+    let employees = group(&connection_pool, "employees").await;
+    let the_circus = containing_unchecked(group(&connection_pool, "the circus").await, employees.get_id());
+
+    let error = group_repository.save(the_circus.clone()).await.unwrap_err();
+    assert_eq!(error.to_string(), "group the circus would end up containing itself through its member groups");
+
+    // a batch that closes the circle, as an import of a snapshot with one could
+    let error = SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())
+        .commit(vec![EntityChange::SaveGroup(the_circus)])
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("containing itself"), "{}", error);
+    assert!(group(&connection_pool, "the circus").await.get_groups().is_empty());
+}
+
+#[async_std::test]
+async fn test_deleted_groups_leave_the_groups_that_contained_them() {
+    let connection_pool = seeded_database(SEED).await;
+    let group_repository = SqliteGroupRepository::new(connection_pool.clone(), tenant_id());
+    let london_station = group(&connection_pool, "london station").await;
+    let bill_haydon_id = subject_id(&connection_pool, "bill haydon").await;
+
+    SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())
+        .commit(vec![EntityChange::DeleteGroup(london_station.get_id())])
+        .await
+        .unwrap();
+
+    let employees = group(&connection_pool, "employees").await;
+    assert!(employees.get_groups().is_empty());
+    assert!(group_repository.find_ancestors(group(&connection_pool, "the circus").await.get_id()).await.unwrap().is_empty());
+    let bill_haydon_groups: Vec<String> = group_repository.find_by_subject_transitively(bill_haydon_id).await.unwrap()
+        .iter()
+        .map(Group::get_name)
+        .collect();
+    assert_eq!(bill_haydon_groups, ["the circus"]);
+    // the group can be saved again without the one that is gone
+    group_repository.save(employees).await.unwrap();
+}
+
+#[async_std::test]
+async fn test_a_group_cannot_contain_itself_or_a_group_above_it() {
+    let connection_pool = seeded_database(SEED).await;
+    let group_service = group_service(&connection_pool);
+    let the_circus = group(&connection_pool, "the circus").await.get_id();
+
+    let error = group_service.add_group(GroupMemberGroupRequest {
+        group_id: the_circus.clone(),
+        member_group_id: the_circus.clone(),
+    }).await.unwrap_err();
+    assert_eq!(error.to_string(), "group the circus cannot contain itself");
+
+    // london station contains the circus directly, employees through london station
+    for ancestor in ["london station", "employees"] {
+        let error = group_service.add_group(GroupMemberGroupRequest {
+            group_id: the_circus.clone(),
+            member_group_id: group(&connection_pool, ancestor).await.get_id(),
+        }).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("group the circus cannot contain group {}: it is already a member of it", ancestor),
+        );
+    }
+    assert!(group(&connection_pool, "the circus").await.get_groups().is_empty());
+}
+
+#[async_std::test]
+async fn test_membership_reaches_through_every_level() {
+    let connection_pool = seeded_database(SEED).await;
+    let group_service = group_service(&connection_pool);
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id());
+
+    let mut members = Vec::new();
+    for subject_id in group_service.list_members(ListGroupMembersRequest {
+        group_id: group(&connection_pool, "employees").await.get_id(),
+    }).await.unwrap().subject_ids {
+        members.push(subject_repository.get_by_id(subject_id).await.unwrap().unwrap().get_name());
+    }
+    members.sort();
+    assert_eq!(members, ["bill haydon", "roy bland"]);
+
+    let group_repository = SqliteGroupRepository::new(connection_pool.clone(), tenant_id());
+    let london_station_members = group_repository.find_members(group(&connection_pool, "london station").await.get_id()).await.unwrap();
+    assert_eq!(london_station_members, [subject_id(&connection_pool, "bill haydon").await]);
+
+    let mut bill_haydon_groups = Vec::new();
+    for group_id in group_service.list_subject_groups(ListSubjectGroupsRequest {
+        subject_id: subject_id(&connection_pool, "bill haydon").await,
+    }).await.unwrap().group_ids {
+        bill_haydon_groups.push(group_repository.get_by_id(group_id).await.unwrap().unwrap().get_name());
+    }
+    bill_haydon_groups.sort();
+    assert_eq!(bill_haydon_groups, ["employees", "london station", "the circus"]);
+
+    let roy_bland_groups: Vec<String> = group_repository.find_by_subject_transitively(subject_id(&connection_pool, "roy bland").await)
+        .await
+        .unwrap()
+        .iter()
+        .map(Group::get_name)
+        .collect();
+    assert_eq!(roy_bland_groups, ["employees"]);
+}
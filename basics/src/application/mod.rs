@@ -1,2 +1,56 @@
 pub mod access_checker;
-pub mod subjects;
\ No newline at end of file
+#[cfg(test)]
+mod access_check_observability_tests;
+pub mod access_requests;
+#[cfg(test)]
+mod access_requests_tests;
+pub mod api_keys;
+#[cfg(test)]
+mod api_keys_tests;
+pub mod authentication;
+#[cfg(test)]
+mod authentication_tests;
+pub mod analysis;
+#[cfg(test)]
+mod analysis_tests;
+pub mod change_feed;
+#[cfg(test)]
+mod change_feed_tests;
+pub mod delegations;
+#[cfg(test)]
+mod delegations_tests;
+pub mod effective_permissions;
+#[cfg(test)]
+mod effective_permissions_tests;
+pub mod groups;
+#[cfg(test)]
+mod groups_tests;
+pub mod overlay;
+pub mod policies;
+pub mod relationships;
+#[cfg(test)]
+mod relationships_tests;
+pub mod resources;
+#[cfg(test)]
+mod resources_tests;
+pub mod role_assignments;
+#[cfg(test)]
+mod role_assignments_tests;
+pub mod scim;
+pub mod seeds;
+#[cfg(test)]
+mod seeds_tests;
+pub mod separation_of_duties;
+#[cfg(test)]
+mod separation_of_duties_tests;
+pub mod sessions;
+pub mod simulation;
+#[cfg(test)]
+mod simulation_tests;
+pub mod snapshots;
+#[cfg(test)]
+mod snapshots_tests;
+pub mod subjects;
+pub mod tokens;
+#[cfg(test)]
+mod tokens_tests;
@@ -0,0 +1,421 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::domain::delegations::{Delegation, DelegationId};
+use crate::domain::groups::{containing, members_of, Group, GroupId};
+use crate::domain::permissions::{Grantee, Permission, PermissionId};
+use crate::domain::repositories::{
+    DelegationRepository, EntityChange, Error, GroupRepository, PermissionRepository, Repository, ResourceRepository,
+    RoleRepository, SodConstraintRepository, SubjectRepository,
+};
+use crate::domain::resources::{Resource, ResourceId};
+use crate::domain::roles::{Role, RoleId};
+use crate::domain::separation_of_duties::{SodConstraint, SodConstraintId};
+use crate::domain::subjects::{Subject, SubjectEventEnvelope, SubjectId};
+
+// changes laid over the stored entities without writing them: the overlay repositories answer as if
+// the changes had been committed, and whatever is saved through them only ever lands in the overlay
+#[derive(Debug, Default)]
+pub struct Overlay {
+    resources: Layer<ResourceId, Resource>,
+    permissions: Layer<PermissionId, Permission>,
+    roles: Layer<RoleId, Role>,
+    subjects: Layer<SubjectId, Subject>,
+    groups: Layer<GroupId, Group>,
+}
+
+impl Overlay {
+    pub fn new(changes: Vec<EntityChange>) -> Overlay {
+        let mut overlay = Overlay::default();
+        for change in changes {
+            overlay.apply(change);
+        }
+        overlay
+    }
+
+    // a later change to the same entity replaces an earlier one, as it would in a unit of work
+    pub fn apply(&mut self, change: EntityChange) {
+        match change {
+            EntityChange::SaveResource(resource) => self.resources.save(resource.get_id(), resource),
+            EntityChange::SavePermission(permission) => self.permissions.save(permission.get_id(), permission),
+            EntityChange::SaveRole(role) => self.roles.save(role.get_id(), role),
+            EntityChange::SaveSubject(subject) | EntityChange::RestoreSubject(subject) => self.subjects.save(subject.get_id(), subject),
+            EntityChange::SaveGroup(group) => self.groups.save(group.get_id(), group),
+            EntityChange::DeleteResource(resource_id) => self.resources.delete(resource_id),
+            EntityChange::DeletePermission(permission_id) => self.permissions.delete(permission_id),
+            EntityChange::DeleteRole(role_id) => self.roles.delete(role_id),
+            EntityChange::DeleteSubject(subject_id) => self.subjects.delete(subject_id),
+            EntityChange::DeleteGroup(group_id) => self.groups.delete(group_id),
+            // access checks read neither
+            EntityChange::SaveAccessRequest(_) | EntityChange::AppendAuditRecord(_) => {},
+        }
+    }
+}
+
+// `None` stands for an entity the overlay deletes
+#[derive(Debug)]
+struct Layer<Id, Entity> {
+    changed: HashMap<Id, Option<Entity>>,
+}
+
+impl<Id, Entity> Default for Layer<Id, Entity> {
+    fn default() -> Self {
+        Layer {
+            changed: HashMap::new(),
+        }
+    }
+}
+
+impl<Id: Eq + Hash, Entity: Clone> Layer<Id, Entity> {
+    fn save(&mut self, id: Id, entity: Entity) {
+        self.changed.insert(id, Some(entity));
+    }
+
+    fn delete(&mut self, id: Id) {
+        self.changed.insert(id, None);
+    }
+
+    // `None` when the overlay leaves the stored entity as it is
+    fn get(&self, id: &Id) -> Option<Option<Entity>> {
+        self.changed.get(id).cloned()
+    }
+
+    // the stored entities the overlay does not touch, then the ones it saves
+    fn merge(&self, stored: Vec<Entity>, id_of: impl Fn(&Entity) -> Id) -> Vec<Entity> {
+        stored.into_iter()
+            .filter(|entity| !self.changed.contains_key(&id_of(entity)))
+            .chain(self.changed.values().flatten().cloned())
+            .collect()
+    }
+}
+
+pub struct OverlayResourceRepository {
+    store: Arc<dyn ResourceRepository + Send + Sync>,
+    overlay: Arc<RwLock<Overlay>>,
+}
+
+impl OverlayResourceRepository {
+    pub fn new(store: Arc<dyn ResourceRepository + Send + Sync>, overlay: Arc<RwLock<Overlay>>) -> Self {
+        OverlayResourceRepository { store, overlay }
+    }
+}
+
+#[async_trait]
+impl Repository<ResourceId, Resource> for OverlayResourceRepository {
+    async fn get_by_id(&self, id: ResourceId) -> Result<Option<Resource>, Error> {
+        let changed = self.overlay.read().unwrap().resources.get(&id);
+        match changed {
+            Some(resource) => Ok(resource),
+            None => self.store.get_by_id(id).await,
+        }
+    }
+
+    async fn save(&self, entity: Resource) -> Result<(), Error> {
+        self.overlay.write().unwrap().resources.save(entity.get_id(), entity);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ResourceRepository for OverlayResourceRepository {
+    async fn get_by_name(&self, name: &str) -> Result<Option<Resource>, Error> {
+        Ok(self.find_all().await?.into_iter().find(|resource| resource.get_name() == name))
+    }
+
+    async fn register(&self, resource: Resource) -> Result<Resource, Error> {
+        if let Some(registered) = self.get_by_name(&resource.get_name()).await? {
+            return Ok(registered);
+        }
+        self.save(resource.clone()).await?;
+        Ok(resource)
+    }
+
+    async fn find_all(&self) -> Result<Vec<Resource>, Error> {
+        let stored = self.store.find_all().await?;
+        Ok(self.overlay.read().unwrap().resources.merge(stored, Resource::get_id))
+    }
+}
+
+pub struct OverlayPermissionRepository {
+    store: Arc<dyn PermissionRepository + Send + Sync>,
+    overlay: Arc<RwLock<Overlay>>,
+}
+
+impl OverlayPermissionRepository {
+    pub fn new(store: Arc<dyn PermissionRepository + Send + Sync>, overlay: Arc<RwLock<Overlay>>) -> Self {
+        OverlayPermissionRepository { store, overlay }
+    }
+}
+
+#[async_trait]
+impl Repository<PermissionId, Permission> for OverlayPermissionRepository {
+    async fn get_by_id(&self, id: PermissionId) -> Result<Option<Permission>, Error> {
+        let changed = self.overlay.read().unwrap().permissions.get(&id);
+        match changed {
+            Some(permission) => Ok(permission),
+            None => self.store.get_by_id(id).await,
+        }
+    }
+
+    async fn save(&self, entity: Permission) -> Result<(), Error> {
+        self.overlay.write().unwrap().permissions.save(entity.get_id(), entity);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PermissionRepository for OverlayPermissionRepository {
+    async fn find_owner_permissions(&self) -> Result<Vec<Permission>, Error> {
+        Ok(self.find_all().await?
+            .into_iter()
+            .filter(|permission| permission.get_grantee() == Grantee::Owner)
+            .collect())
+    }
+
+    async fn get_by_name(&self, name: &str) -> Result<Option<Permission>, Error> {
+        Ok(self.find_all().await?
+            .into_iter()
+            .filter(|permission| permission.get_name() == name)
+            .min_by_key(Permission::get_created_at))
+    }
+
+    async fn find_all(&self) -> Result<Vec<Permission>, Error> {
+        let stored = self.store.find_all().await?;
+        Ok(self.overlay.read().unwrap().permissions.merge(stored, Permission::get_id))
+    }
+}
+
+pub struct OverlayRoleRepository {
+    store: Arc<dyn RoleRepository + Send + Sync>,
+    overlay: Arc<RwLock<Overlay>>,
+}
+
+impl OverlayRoleRepository {
+    pub fn new(store: Arc<dyn RoleRepository + Send + Sync>, overlay: Arc<RwLock<Overlay>>) -> Self {
+        OverlayRoleRepository { store, overlay }
+    }
+}
+
+#[async_trait]
+impl Repository<RoleId, Role> for OverlayRoleRepository {
+    async fn get_by_id(&self, id: RoleId) -> Result<Option<Role>, Error> {
+        let changed = self.overlay.read().unwrap().roles.get(&id);
+        match changed {
+            Some(role) => Ok(role),
+            None => self.store.get_by_id(id).await,
+        }
+    }
+
+    async fn save(&self, entity: Role) -> Result<(), Error> {
+        self.overlay.write().unwrap().roles.save(entity.get_id(), entity);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RoleRepository for OverlayRoleRepository {
+    async fn get_by_name(&self, name: &str) -> Result<Option<Role>, Error> {
+        Ok(self.find_all().await?
+            .into_iter()
+            .filter(|role| role.get_name() == name)
+            .min_by_key(Role::get_created_at))
+    }
+
+    async fn find_all(&self) -> Result<Vec<Role>, Error> {
+        let stored = self.store.find_all().await?;
+        Ok(self.overlay.read().unwrap().roles.merge(stored, Role::get_id))
+    }
+}
+
+pub struct OverlaySubjectRepository {
+    store: Arc<dyn SubjectRepository + Send + Sync>,
+    overlay: Arc<RwLock<Overlay>>,
+}
+
+impl OverlaySubjectRepository {
+    pub fn new(store: Arc<dyn SubjectRepository + Send + Sync>, overlay: Arc<RwLock<Overlay>>) -> Self {
+        OverlaySubjectRepository { store, overlay }
+    }
+}
+
+#[async_trait]
+impl Repository<SubjectId, Subject> for OverlaySubjectRepository {
+    async fn get_by_id(&self, id: SubjectId) -> Result<Option<Subject>, Error> {
+        let changed = self.overlay.read().unwrap().subjects.get(&id);
+        match changed {
+            Some(subject) => Ok(subject),
+            None => self.store.get_by_id(id).await,
+        }
+    }
+
+    async fn save(&self, entity: Subject) -> Result<(), Error> {
+        self.overlay.write().unwrap().subjects.save(entity.get_id(), entity);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SubjectRepository for OverlaySubjectRepository {
+    // the overlay keeps no events: a subject it changes has the history that is stored
+    async fn get_history(&self, id: SubjectId) -> Result<Vec<SubjectEventEnvelope>, Error> {
+        self.store.get_history(id).await
+    }
+
+    async fn find_with_expired_roles(&self, at: DateTime<Utc>) -> Result<Vec<Subject>, Error> {
+        Ok(self.find_all().await?
+            .into_iter()
+            .filter(|subject| subject.get_role_assignments().iter().any(|assignment| assignment.is_expired_at(at)))
+            .collect())
+    }
+
+    async fn get_by_name(&self, name: &str) -> Result<Option<Subject>, Error> {
+        Ok(self.find_all().await?
+            .into_iter()
+            .filter(|subject| subject.get_name() == name && subject.get_deleted_at().is_none())
+            .min_by_key(Subject::get_created_at))
+    }
+
+    async fn find_all(&self) -> Result<Vec<Subject>, Error> {
+        let stored = self.store.find_all().await?;
+        Ok(self.overlay.read().unwrap().subjects.merge(stored, Subject::get_id))
+    }
+}
+
+// the nested group queries walk the merged groups in memory rather than in the store
+pub struct OverlayGroupRepository {
+    store: Arc<dyn GroupRepository + Send + Sync>,
+    overlay: Arc<RwLock<Overlay>>,
+}
+
+impl OverlayGroupRepository {
+    pub fn new(store: Arc<dyn GroupRepository + Send + Sync>, overlay: Arc<RwLock<Overlay>>) -> Self {
+        OverlayGroupRepository { store, overlay }
+    }
+
+    async fn find_containing(&self, is_member: impl Fn(&Group) -> bool + Send) -> Result<Vec<Group>, Error> {
+        let groups = self.find_all().await?;
+        let direct: HashSet<GroupId> = groups.iter()
+            .filter(|group| is_member(group))
+            .map(Group::get_id)
+            .collect();
+        let reached = containing(&groups, direct);
+        Ok(groups.into_iter().filter(|group| reached.contains(&group.get_id())).collect())
+    }
+}
+
+#[async_trait]
+impl Repository<GroupId, Group> for OverlayGroupRepository {
+    async fn get_by_id(&self, id: GroupId) -> Result<Option<Group>, Error> {
+        let changed = self.overlay.read().unwrap().groups.get(&id);
+        match changed {
+            Some(group) => Ok(group),
+            None => self.store.get_by_id(id).await,
+        }
+    }
+
+    async fn save(&self, entity: Group) -> Result<(), Error> {
+        self.overlay.write().unwrap().groups.save(entity.get_id(), entity);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GroupRepository for OverlayGroupRepository {
+    async fn find_all(&self) -> Result<Vec<Group>, Error> {
+        let stored = self.store.find_all().await?;
+        let mut groups = self.overlay.read().unwrap().groups.merge(stored, Group::get_id);
+        groups.sort_by_key(Group::get_created_at);
+        Ok(groups)
+    }
+
+    async fn find_by_subject(&self, subject_id: SubjectId) -> Result<Vec<Group>, Error> {
+        Ok(self.find_all().await?
+            .into_iter()
+            .filter(|group| group.get_subjects().contains(&subject_id))
+            .collect())
+    }
+
+    async fn find_by_subject_transitively(&self, subject_id: SubjectId) -> Result<Vec<Group>, Error> {
+        self.find_containing(|group| group.get_subjects().contains(&subject_id)).await
+    }
+
+    async fn find_ancestors(&self, group_id: GroupId) -> Result<Vec<Group>, Error> {
+        self.find_containing(|group| group.get_groups().contains(&group_id)).await
+    }
+
+    async fn find_members(&self, group_id: GroupId) -> Result<Vec<SubjectId>, Error> {
+        let groups = self.find_all().await?;
+        let Some(group) = groups.iter().find(|group| group.get_id() == group_id) else {
+            return Ok(Vec::new());
+        };
+        let mut subject_ids: Vec<SubjectId> = members_of(&groups, group).into_iter().collect();
+        subject_ids.sort_by_key(|subject_id| String::from(subject_id.clone()));
+        Ok(subject_ids)
+    }
+}
+
+// constraints and delegations are not among the changes an overlay takes: these read the store, and
+// what is saved through them goes nowhere
+pub struct OverlaySodConstraintRepository {
+    store: Arc<dyn SodConstraintRepository + Send + Sync>,
+}
+
+impl OverlaySodConstraintRepository {
+    pub fn new(store: Arc<dyn SodConstraintRepository + Send + Sync>) -> Self {
+        OverlaySodConstraintRepository { store }
+    }
+}
+
+#[async_trait]
+impl Repository<SodConstraintId, SodConstraint> for OverlaySodConstraintRepository {
+    async fn get_by_id(&self, id: SodConstraintId) -> Result<Option<SodConstraint>, Error> {
+        self.store.get_by_id(id).await
+    }
+
+    async fn save(&self, _entity: SodConstraint) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SodConstraintRepository for OverlaySodConstraintRepository {
+    async fn get_by_name(&self, name: &str) -> Result<Option<SodConstraint>, Error> {
+        self.store.get_by_name(name).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<SodConstraint>, Error> {
+        self.store.find_all().await
+    }
+}
+
+pub struct OverlayDelegationRepository {
+    store: Arc<dyn DelegationRepository + Send + Sync>,
+}
+
+impl OverlayDelegationRepository {
+    pub fn new(store: Arc<dyn DelegationRepository + Send + Sync>) -> Self {
+        OverlayDelegationRepository { store }
+    }
+}
+
+#[async_trait]
+impl Repository<DelegationId, Delegation> for OverlayDelegationRepository {
+    async fn get_by_id(&self, id: DelegationId) -> Result<Option<Delegation>, Error> {
+        self.store.get_by_id(id).await
+    }
+
+    async fn save(&self, _entity: Delegation) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DelegationRepository for OverlayDelegationRepository {
+    async fn find_active_for_delegate(&self, delegate_id: SubjectId, at: DateTime<Utc>) -> Result<Vec<Delegation>, Error> {
+        self.store.find_active_for_delegate(delegate_id, at).await
+    }
+}
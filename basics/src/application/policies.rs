@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::domain::policies::{Policy, Principal};
+use crate::domain::repositories::{Error, PermissionRepository, ResourceRepository, RoleRepository};
+use crate::domain::resources::Resource;
+use crate::domain::roles::Role;
+use crate::domain::tenants::TenantId;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LoadPolicyResponse {
+    pub resources_registered: usize,
+    pub roles_created: usize,
+    pub permissions_created: usize,
+}
+
+// loading is additive and idempotent: statements already loaded map onto the same permissions, and
+// nothing the policy no longer mentions is removed
+pub struct PolicyLoader {
+    tenant_id: TenantId,
+    resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+    role_repository: Box<dyn RoleRepository + Send + Sync>,
+    permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+}
+
+impl PolicyLoader {
+    pub fn new(
+        tenant_id: TenantId,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+        role_repository: Box<dyn RoleRepository + Send + Sync>,
+        permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+    ) -> PolicyLoader {
+        PolicyLoader {
+            tenant_id,
+            resource_repository,
+            role_repository,
+            permission_repository,
+        }
+    }
+
+    pub async fn load_file(&self, path: impl AsRef<Path>) -> Result<LoadPolicyResponse, Error> {
+        let path = path.as_ref();
+        let source = async_std::fs::read_to_string(path)
+            .await
+            .map_err(|error| Error::Simple(format!("unable to read policy {}: {}", path.display(), error)))?;
+        self.load(&source).await
+    }
+
+    pub async fn load(&self, source: &str) -> Result<LoadPolicyResponse, Error> {
+        let policy = Policy::parse(source)?;
+        let mut response = LoadPolicyResponse::default();
+
+        let mut resources = HashMap::new();
+        for name in policy.get_resource_names() {
+            if self.resource_repository.get_by_name(&name).await?.is_none() {
+                response.resources_registered += 1;
+            }
+            let resource = self.resource_repository.register(Resource::new(self.tenant_id.clone(), &name)).await?;
+            resources.insert(name, resource);
+        }
+
+        let mut roles: HashMap<String, Role> = HashMap::new();
+        for grant in policy.compile(&self.tenant_id, &resources)? {
+            let permission_id = grant.permission.get_id();
+            if self.permission_repository.get_by_id(permission_id.clone()).await?.is_none() {
+                self.permission_repository.save(grant.permission).await?;
+                response.permissions_created += 1;
+            }
+
+            if let Principal::Role(name) = grant.principal {
+                if !roles.contains_key(&name) {
+                    let role = match self.role_repository.get_by_name(&name).await? {
+                        Some(role) => role,
+                        None => {
+                            response.roles_created += 1;
+                            Role::new(self.tenant_id.clone(), &name)
+                        },
+                    };
+                    roles.insert(name.clone(), role);
+                }
+                roles.get_mut(&name).unwrap().add_permission(permission_id);
+            }
+        }
+
+        for role in roles.into_values() {
+            self.role_repository.save(role).await?;
+        }
+
+        Ok(response)
+    }
+}
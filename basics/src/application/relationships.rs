@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+
+use crate::domain::namespaces::{NamespaceConfig, Rewrite};
+use crate::domain::relationships::{ObjectRef, RelationTuple, TupleSubject, OBJECT_ITSELF};
+use crate::domain::repositories::{Error, RelationTupleRepository};
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum UsersetTree {
+    Subjects(Vec<SubjectId>),
+    Userset { object: ObjectRef, relation: String, tree: Box<UsersetTree> },
+    Union(Vec<UsersetTree>),
+    Intersection(Box<UsersetTree>, Box<UsersetTree>),
+    Exclusion(Box<UsersetTree>, Box<UsersetTree>),
+}
+
+impl UsersetTree {
+    // the subjects the tree stands for once every rewrite is applied
+    pub fn resolve(&self) -> HashSet<SubjectId> {
+        match self {
+            UsersetTree::Subjects(subjects) => subjects.iter().cloned().collect(),
+            UsersetTree::Userset { tree, .. } => tree.resolve(),
+            UsersetTree::Union(trees) => trees.iter().flat_map(UsersetTree::resolve).collect(),
+            UsersetTree::Intersection(left, right) => {
+                let right = right.resolve();
+                left.resolve().into_iter().filter(|subject_id| right.contains(subject_id)).collect()
+            },
+            UsersetTree::Exclusion(left, right) => {
+                let right = right.resolve();
+                left.resolve().into_iter().filter(|subject_id| !right.contains(subject_id)).collect()
+            },
+        }
+    }
+}
+
+// every hop into another object#relation costs one level of depth; running out is an error rather than a denial
+// so that callers can tell a deep or cyclic graph apart from a missing relationship
+pub struct RelationshipChecker {
+    namespace_config: NamespaceConfig,
+    relation_tuple_repository: Box<dyn RelationTupleRepository + Send + Sync>,
+    max_depth: usize,
+}
+
+impl RelationshipChecker {
+    pub fn new(
+        namespace_config: NamespaceConfig,
+        relation_tuple_repository: Box<dyn RelationTupleRepository + Send + Sync>,
+        max_depth: usize,
+    ) -> RelationshipChecker {
+        RelationshipChecker {
+            namespace_config,
+            relation_tuple_repository,
+            max_depth,
+        }
+    }
+
+    pub async fn check(&self, object: ObjectRef, relation: &str, subject_id: SubjectId) -> Result<bool, Error> {
+        self.check_relation(object, relation.to_string(), subject_id, self.max_depth).await
+    }
+
+    pub async fn expand(&self, object: ObjectRef, relation: &str) -> Result<UsersetTree, Error> {
+        self.expand_relation(object, relation.to_string(), self.max_depth).await
+    }
+
+    fn depth_exceeded(object: &ObjectRef, relation: &str) -> Error {
+        Error::Simple(format!("depth limit exceeded while resolving {}#{}", object, relation))
+    }
+
+    fn check_relation(&self, object: ObjectRef, relation: String, subject_id: SubjectId, depth: usize) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(async move {
+            if depth == 0 {
+                return Err(Self::depth_exceeded(&object, &relation));
+            }
+            let rewrite = self.namespace_config.get_rewrite(&object.get_namespace(), &relation)?.clone();
+            self.check_rewrite(object, relation, rewrite, subject_id, depth - 1).await
+        })
+    }
+
+    fn check_rewrite(&self, object: ObjectRef, relation: String, rewrite: Rewrite, subject_id: SubjectId, depth: usize) -> BoxFuture<'_, Result<bool, Error>> {
+        Box::pin(async move {
+            match rewrite {
+                Rewrite::This => {
+                    let tuples = self.relation_tuple_repository.find(object, &relation).await?;
+                    // direct grants are settled before descending into subject sets
+                    if tuples.iter().any(|tuple| tuple.get_subject() == TupleSubject::Subject(subject_id.clone())) {
+                        return Ok(true);
+                    }
+                    for tuple in tuples {
+                        if let TupleSubject::SubjectSet { object, relation } = tuple.get_subject() {
+                            if relation != OBJECT_ITSELF && self.check_relation(object, relation, subject_id.clone(), depth).await? {
+                                return Ok(true);
+                            }
+                        }
+                    }
+                    Ok(false)
+                },
+                Rewrite::ComputedUserset(computed) => self.check_relation(object, computed, subject_id, depth).await,
+                Rewrite::TupleToUserset { tupleset, computed_userset } => {
+                    for tuple in self.relation_tuple_repository.find(object, &tupleset).await? {
+                        if let TupleSubject::SubjectSet { object, .. } = tuple.get_subject() {
+                            if self.check_relation(object, computed_userset.clone(), subject_id.clone(), depth).await? {
+                                return Ok(true);
+                            }
+                        }
+                    }
+                    Ok(false)
+                },
+                Rewrite::Union(left, right) => {
+                    Ok(self.check_rewrite(object.clone(), relation.clone(), *left, subject_id.clone(), depth).await?
+                        || self.check_rewrite(object, relation, *right, subject_id, depth).await?)
+                },
+                Rewrite::Intersection(left, right) => {
+                    Ok(self.check_rewrite(object.clone(), relation.clone(), *left, subject_id.clone(), depth).await?
+                        && self.check_rewrite(object, relation, *right, subject_id, depth).await?)
+                },
+                Rewrite::Exclusion(left, right) => {
+                    Ok(self.check_rewrite(object.clone(), relation.clone(), *left, subject_id.clone(), depth).await?
+                        && !self.check_rewrite(object, relation, *right, subject_id, depth).await?)
+                },
+            }
+        })
+    }
+
+    fn expand_relation(&self, object: ObjectRef, relation: String, depth: usize) -> BoxFuture<'_, Result<UsersetTree, Error>> {
+        Box::pin(async move {
+            if depth == 0 {
+                return Err(Self::depth_exceeded(&object, &relation));
+            }
+            let rewrite = self.namespace_config.get_rewrite(&object.get_namespace(), &relation)?.clone();
+            let tree = self.expand_rewrite(object.clone(), relation.clone(), rewrite, depth - 1).await?;
+            Ok(UsersetTree::Userset { object, relation, tree: Box::new(tree) })
+        })
+    }
+
+    fn expand_rewrite(&self, object: ObjectRef, relation: String, rewrite: Rewrite, depth: usize) -> BoxFuture<'_, Result<UsersetTree, Error>> {
+        Box::pin(async move {
+            match rewrite {
+                Rewrite::This => {
+                    let mut subjects = Vec::new();
+                    let mut trees = Vec::new();
+                    for tuple in self.relation_tuple_repository.find(object, &relation).await? {
+                        match tuple.get_subject() {
+                            TupleSubject::Subject(subject_id) => subjects.push(subject_id),
+                            TupleSubject::SubjectSet { relation, .. } if relation == OBJECT_ITSELF => {},
+                            TupleSubject::SubjectSet { object, relation } => {
+                                trees.push(self.expand_relation(object, relation, depth).await?);
+                            },
+                        }
+                    }
+                    trees.insert(0, UsersetTree::Subjects(subjects));
+                    Ok(UsersetTree::Union(trees))
+                },
+                Rewrite::ComputedUserset(computed) => self.expand_relation(object, computed, depth).await,
+                Rewrite::TupleToUserset { tupleset, computed_userset } => {
+                    let mut trees = Vec::new();
+                    for tuple in self.relation_tuple_repository.find(object, &tupleset).await? {
+                        if let TupleSubject::SubjectSet { object, .. } = tuple.get_subject() {
+                            trees.push(self.expand_relation(object, computed_userset.clone(), depth).await?);
+                        }
+                    }
+                    Ok(UsersetTree::Union(trees))
+                },
+                Rewrite::Union(left, right) => Ok(UsersetTree::Union(vec![
+                    self.expand_rewrite(object.clone(), relation.clone(), *left, depth).await?,
+                    self.expand_rewrite(object, relation, *right, depth).await?,
+                ])),
+                Rewrite::Intersection(left, right) => Ok(UsersetTree::Intersection(
+                    Box::new(self.expand_rewrite(object.clone(), relation.clone(), *left, depth).await?),
+                    Box::new(self.expand_rewrite(object, relation, *right, depth).await?),
+                )),
+                Rewrite::Exclusion(left, right) => Ok(UsersetTree::Exclusion(
+                    Box::new(self.expand_rewrite(object.clone(), relation.clone(), *left, depth).await?),
+                    Box::new(self.expand_rewrite(object, relation, *right, depth).await?),
+                )),
+            }
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RelationTupleRequest {
+    // `object#relation@subject`
+    pub tuple: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RelationTupleResponse {}
+
+#[async_trait]
+pub trait RelationshipService {
+    async fn write_tuple(&self, req: RelationTupleRequest) -> Result<RelationTupleResponse, Error>;
+    async fn delete_tuple(&self, req: RelationTupleRequest) -> Result<RelationTupleResponse, Error>;
+}
+
+pub struct RelationshipServiceImpl {
+    tenant_id: TenantId,
+    namespace_config: NamespaceConfig,
+    relation_tuple_repository: Box<dyn RelationTupleRepository + Send + Sync>,
+}
+
+impl RelationshipServiceImpl {
+    pub fn new(
+        tenant_id: TenantId,
+        namespace_config: NamespaceConfig,
+        relation_tuple_repository: Box<dyn RelationTupleRepository + Send + Sync>,
+    ) -> Self {
+        RelationshipServiceImpl {
+            tenant_id,
+            namespace_config,
+            relation_tuple_repository,
+        }
+    }
+
+    // only tuples the namespace config knows how to interpret are accepted
+    fn parse_tuple(&self, tuple: &str) -> Result<RelationTuple, Error> {
+        let tuple = RelationTuple::parse(self.tenant_id.clone(), tuple)?;
+        self.namespace_config.get_rewrite(&tuple.get_object().get_namespace(), &tuple.get_relation())?;
+
+        if let TupleSubject::SubjectSet { object, relation } = tuple.get_subject() {
+            if relation == OBJECT_ITSELF {
+                self.namespace_config.get_namespace(&object.get_namespace())
+                    .ok_or_else(|| Error::Simple(format!("unknown namespace {}", object.get_namespace())))?;
+            } else {
+                self.namespace_config.get_rewrite(&object.get_namespace(), &relation)?;
+            }
+        }
+        Ok(tuple)
+    }
+}
+
+#[async_trait]
+impl RelationshipService for RelationshipServiceImpl {
+    async fn write_tuple(&self, req: RelationTupleRequest) -> Result<RelationTupleResponse, Error> {
+        let tuple = self.parse_tuple(&req.tuple)?;
+        self.relation_tuple_repository.write(tuple).await?;
+        Ok(RelationTupleResponse {})
+    }
+
+    async fn delete_tuple(&self, req: RelationTupleRequest) -> Result<RelationTupleResponse, Error> {
+        let tuple = self.parse_tuple(&req.tuple)?;
+        self.relation_tuple_repository.delete(tuple).await?;
+        Ok(RelationTupleResponse {})
+    }
+}
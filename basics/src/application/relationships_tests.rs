@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::relationships::{
+    RelationTupleRequest, RelationshipChecker, RelationshipService, RelationshipServiceImpl, UsersetTree,
+};
+use crate::domain::namespaces::NamespaceConfig;
+use crate::domain::relationships::ObjectRef;
+use crate::domain::subjects::SubjectId;
+use crate::infrastructure::sqlite::relation_tuple::SqliteRelationTupleRepository;
+use crate::test_support::{empty_database, tenant_id};
+
+const NAMESPACE_CONFIG: &str = "
+    namespace team {
+        relation member
+    }
+    namespace folder {
+        relation owner
+        relation viewer = this | owner
+    }
+    namespace document {
+        relation parent
+        relation owner
+        relation cleared
+        relation banned
+        relation editor = this | owner | parent->owner
+        relation reader = this & cleared
+        relation viewer = (this | editor | parent->viewer) - banned
+    }
+";
+
+const TUPLES: [&str; 10] = [
+    "team:circus#member@control",
+    "team:circus#member@smiley",
+    "folder:operations#owner@team:circus#member",
+    "folder:operations#viewer@guillam",
+    "document:windfall#parent@folder:operations#...",
+    "document:windfall#owner@haydon",
+    "document:windfall#banned@smiley",
+    "document:windfall#reader@guillam",
+    "document:windfall#reader@haydon",
+    "document:windfall#cleared@haydon",
+];
+
+async fn database(tuples: &[&str]) -> Pool<Sqlite> {
+    let connection_pool = empty_database().await;
+    let relationship_service = RelationshipServiceImpl::new(
+        tenant_id(),
+        NamespaceConfig::parse(NAMESPACE_CONFIG).unwrap(),
+        Box::new(SqliteRelationTupleRepository::new(connection_pool.clone(), tenant_id())),
+    );
+    for tuple in tuples {
+        relationship_service.write_tuple(RelationTupleRequest { tuple: tuple.to_string() }).await.unwrap();
+    }
+    connection_pool
+}
+
+fn relationship_checker(connection_pool: &Pool<Sqlite>, max_depth: usize) -> RelationshipChecker {
+    RelationshipChecker::new(
+        NamespaceConfig::parse(NAMESPACE_CONFIG).unwrap(),
+        Box::new(SqliteRelationTupleRepository::new(connection_pool.clone(), tenant_id())),
+        max_depth,
+    )
+}
+
+fn subject(name: &str) -> SubjectId {
+    SubjectId::from(name.to_string())
+}
+
+fn windfall() -> ObjectRef {
+    ObjectRef::new("document", "windfall")
+}
+
+#[async_std::test]
+async fn test_check_follows_unions_through_tuplesets() {
+    let connection_pool = database(&TUPLES).await;
+    let relationship_checker = relationship_checker(&connection_pool, 16);
+
+    // directly, and as members of the team that owns the parent folder
+    for (name, expected) in [("haydon", true), ("control", true), ("smiley", true), ("guillam", false)] {
+        let is_editor = relationship_checker.check(windfall(), "editor", subject(name)).await.unwrap();
+        assert_eq!(is_editor, expected, "{}", name);
+    }
+}
+
+#[async_std::test]
+async fn test_check_needs_both_sides_of_an_intersection() {
+    let connection_pool = database(&TUPLES).await;
+    let relationship_checker = relationship_checker(&connection_pool, 16);
+
+    assert!(relationship_checker.check(windfall(), "reader", subject("haydon")).await.unwrap());
+    assert!(!relationship_checker.check(windfall(), "reader", subject("guillam")).await.unwrap());
+    assert!(!relationship_checker.check(windfall(), "reader", subject("control")).await.unwrap());
+}
+
+#[async_std::test]
+async fn test_check_and_expand_leave_out_the_excluded() {
+    let connection_pool = database(&TUPLES).await;
+    let relationship_checker = relationship_checker(&connection_pool, 16);
+
+    for (name, expected) in [("haydon", true), ("control", true), ("guillam", true), ("smiley", false)] {
+        let is_viewer = relationship_checker.check(windfall(), "viewer", subject(name)).await.unwrap();
+        assert_eq!(is_viewer, expected, "{}", name);
+    }
+
+    let tree = relationship_checker.expand(windfall(), "viewer").await.unwrap();
+    let expected: HashSet<SubjectId> = ["haydon", "control", "guillam"].into_iter().map(subject).collect();
+    assert_eq!(tree.resolve(), expected);
+    match tree {
+        UsersetTree::Userset { object, relation, tree } => {
+            assert_eq!((object, relation.as_str()), (windfall(), "viewer"));
+            assert!(matches!(*tree, UsersetTree::Exclusion(_, _)), "{:?}", tree);
+        },
+        tree => panic!("expected the viewer userset, got {:?}", tree),
+    }
+
+    let tree = relationship_checker.expand(windfall(), "reader").await.unwrap();
+    assert_eq!(tree.resolve(), [subject("haydon")].into_iter().collect());
+}
+
+#[async_std::test]
+async fn test_running_out_of_depth_is_an_error() {
+    let connection_pool = database(&[
+        "team:circus#member@team:london#member",
+        "team:london#member@team:circus#member",
+        "team:berlin#member@team:bonn#member",
+        "team:bonn#member@leamas",
+    ]).await;
+    let checker = relationship_checker(&connection_pool, 16);
+
+    // teams that contain each other never settle whether someone who is in neither is a member
+    let error = checker.check(ObjectRef::new("team", "circus"), "member", subject("karla")).await.unwrap_err();
+    assert!(error.to_string().starts_with("depth limit exceeded while resolving team:"), "{}", error);
+    let error = checker.expand(ObjectRef::new("team", "circus"), "member").await.unwrap_err();
+    assert!(error.to_string().starts_with("depth limit exceeded while resolving team:"), "{}", error);
+
+    // two hops fit in a depth of two, not in a depth of one
+    let berlin = ObjectRef::new("team", "berlin");
+    assert!(relationship_checker(&connection_pool, 2).check(berlin.clone(), "member", subject("leamas")).await.unwrap());
+    let error = relationship_checker(&connection_pool, 1).check(berlin, "member", subject("leamas")).await.unwrap_err();
+    assert_eq!(error.to_string(), "depth limit exceeded while resolving team:bonn#member");
+}
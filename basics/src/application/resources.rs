@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+
+use crate::domain::audit::{AuditEvent, AuditRecord, AuditRecordId};
+use crate::domain::repositories::{Error, Repository, ResourceRepository};
+use crate::domain::resources::{Resource, ResourceId};
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+// returns the resource followed by its ancestors, nearest first
+pub async fn resolve_ancestry<R>(resource_repository: &R, resource_id: ResourceId) -> Result<Vec<Resource>, Error>
+where
+    R: ResourceRepository + ?Sized,
+{
+    let mut ancestry = Vec::new();
+    let mut visited = HashSet::new();
+    let mut next = Some(resource_id);
+
+    while let Some(resource_id) = next {
+        if !visited.insert(resource_id.clone()) {
+            return Err(Error::Simple(format!("resource {} is part of a cycle", String::from(resource_id))));
+        }
+
+        let resource = match resource_repository.get_by_id(resource_id).await? {
+            Some(resource) => resource,
+            None => break,
+        };
+        next = resource.get_parent_id();
+        ancestry.push(resource);
+    }
+
+    Ok(ancestry)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterResourceRequest {
+    pub name: String,
+    pub parent_id: Option<ResourceId>,
+    pub owner_id: Option<SubjectId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterResourceResponse {
+    pub resource_id: ResourceId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveResourceRequest {
+    pub resource_id: ResourceId,
+    pub parent_id: Option<ResourceId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveResourceResponse {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferOwnershipRequest {
+    pub resource_id: ResourceId,
+    pub owner_id: SubjectId,
+    pub transferred_by: SubjectId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferOwnershipResponse {
+    pub audit_record_id: AuditRecordId,
+}
+
+#[async_trait]
+pub trait ResourceService {
+    async fn register_resource(&self, req: RegisterResourceRequest) -> Result<RegisterResourceResponse, Error>;
+    async fn move_resource(&self, req: MoveResourceRequest) -> Result<MoveResourceResponse, Error>;
+    async fn transfer_ownership(&self, req: TransferOwnershipRequest) -> Result<TransferOwnershipResponse, Error>;
+}
+
+pub struct ResourceServiceImpl {
+    tenant_id: TenantId,
+    resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+    audit_repository: Box<dyn Repository<AuditRecordId, AuditRecord> + Send + Sync>,
+}
+
+impl ResourceServiceImpl {
+    pub fn new(
+        tenant_id: TenantId,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+        audit_repository: Box<dyn Repository<AuditRecordId, AuditRecord> + Send + Sync>,
+    ) -> Self {
+        ResourceServiceImpl {
+            tenant_id,
+            resource_repository,
+            audit_repository,
+        }
+    }
+
+    async fn ensure_exists(&self, resource_id: ResourceId) -> Result<Resource, Error> {
+        self.resource_repository.get_by_id(resource_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("resource {} not found", String::from(resource_id))))
+    }
+}
+
+#[async_trait]
+impl ResourceService for ResourceServiceImpl {
+    async fn register_resource(&self, req: RegisterResourceRequest) -> Result<RegisterResourceResponse, Error> {
+        let mut resource = match req.parent_id {
+            Some(parent_id) => {
+                self.ensure_exists(parent_id.clone()).await?;
+                Resource::with_parent(self.tenant_id.clone(), &req.name, parent_id)
+            },
+            None => Resource::new(self.tenant_id.clone(), &req.name),
+        };
+        // the registering owner only sticks if the name was not registered yet
+        if let Some(owner_id) = req.owner_id {
+            resource.transfer_ownership(owner_id);
+        }
+
+        let resource = self.resource_repository.register(resource).await?;
+
+        Ok(RegisterResourceResponse {
+            resource_id: resource.get_id(),
+        })
+    }
+
+    // access is resolved by walking the live hierarchy, so moving a resource is all it takes
+    // for its descendants to pick up the new ancestors' grants
+    async fn move_resource(&self, req: MoveResourceRequest) -> Result<MoveResourceResponse, Error> {
+        let mut resource = self.ensure_exists(req.resource_id.clone()).await?;
+
+        if let Some(parent_id) = req.parent_id.clone() {
+            let ancestry = resolve_ancestry(self.resource_repository.as_ref(), parent_id.clone()).await?;
+            if ancestry.is_empty() {
+                return Err(Error::Simple(format!("resource {} not found", String::from(parent_id))));
+            }
+            if ancestry.iter().any(|ancestor| ancestor.get_id() == req.resource_id) {
+                return Err(Error::Simple(format!(
+                    "cannot move resource {} under its own descendant {}",
+                    resource.get_name(),
+                    String::from(parent_id),
+                )));
+            }
+        }
+
+        resource.move_to(req.parent_id);
+        self.resource_repository.save(resource).await?;
+
+        Ok(MoveResourceResponse {})
+    }
+
+    async fn transfer_ownership(&self, req: TransferOwnershipRequest) -> Result<TransferOwnershipResponse, Error> {
+        let mut resource = self.ensure_exists(req.resource_id.clone()).await?;
+
+        if !resource.is_owned_by(&req.transferred_by) {
+            return Err(Error::Simple(format!(
+                "subject {} does not own resource {}",
+                String::from(req.transferred_by),
+                resource.get_name(),
+            )));
+        }
+
+        let previous_owner_id = resource.transfer_ownership(req.owner_id.clone());
+        self.resource_repository.save(resource).await?;
+
+        let record = AuditRecord::new(self.tenant_id.clone(), AuditEvent::ResourceOwnershipTransferred {
+            resource_id: req.resource_id,
+            previous_owner_id,
+            owner_id: req.owner_id,
+            transferred_by: req.transferred_by,
+        });
+        self.audit_repository.save(record.clone()).await?;
+
+        Ok(TransferOwnershipResponse {
+            audit_record_id: record.get_id(),
+        })
+    }
+}
@@ -0,0 +1,244 @@
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::resources::{
+    MoveResourceRequest, RegisterResourceRequest, ResourceService, ResourceServiceImpl, TransferOwnershipRequest,
+};
+use crate::domain::audit::AuditEvent;
+use crate::domain::repositories::{Repository, ResourceRepository};
+use crate::domain::resources::ResourceId;
+use crate::domain::tenants::TenantId;
+use crate::infrastructure::sqlite::audit::SqliteAuditRepository;
+use crate::infrastructure::sqlite::resource::SqliteResourceRepository;
+use crate::test_support::{access_checker, empty_database, resource_id, seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: archive
+    subjects:
+      - name: alec leamas
+      - name: george smiley
+";
+
+const HIERARCHY_SEED: &str = "
+    resources:
+      - name: archive
+      - name: reports
+        parent: archive
+      - name: quarterly reports
+        parent: reports
+    permissions:
+      - name: read archive
+        resource: archive
+      - name: keep out of reports
+        resource: reports
+        effect: deny
+      - name: read quarterly reports
+        resource: quarterly reports
+      - name: read reports
+        resource: reports
+    roles:
+      - name: analyst
+        permissions: [read archive]
+      - name: auditor
+        permissions: [keep out of reports]
+      - name: treasurer
+        permissions: [read quarterly reports]
+      - name: torn
+        permissions: [read reports, keep out of reports]
+    subjects:
+      - name: alec leamas
+        roles: [analyst]
+      - name: george smiley
+        roles: [analyst, auditor]
+      - name: connie sachs
+        roles: [analyst, auditor, treasurer]
+      - name: peter guillam
+        roles: [torn]
+";
+
+const OWNERSHIP_SEED: &str = "
+    resources:
+      - name: dossiers
+      - name: the karla dossier
+        parent: dossiers
+        owner: alec leamas
+    permissions:
+      - name: keep own dossiers
+        resource: dossiers
+        grantee: owner
+    subjects:
+      - name: alec leamas
+      - name: george smiley
+";
+
+fn resource_service(connection_pool: &Pool<Sqlite>) -> ResourceServiceImpl {
+    ResourceServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteAuditRepository::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+fn registering(name: &str, parent_id: Option<ResourceId>) -> RegisterResourceRequest {
+    RegisterResourceRequest {
+        name: name.to_string(),
+        parent_id,
+        owner_id: None,
+    }
+}
+
+async fn can_invoke(connection_pool: &Pool<Sqlite>, subject_name: &str, resource_name: &str) -> bool {
+    access_checker(connection_pool)
+        .can_invoke(subject_id(connection_pool, subject_name).await, resource_id(connection_pool, resource_name).await)
+        .await
+        .unwrap()
+}
+
+#[async_std::test]
+async fn test_resource_ids_derive_from_the_tenant_and_the_name() {
+    let connection_pool = empty_database().await;
+    let registered = resource_service(&connection_pool).register_resource(registering("reports", None)).await.unwrap();
+    assert_eq!(registered.resource_id, ResourceId::for_name(&tenant_id(), "reports"));
+
+    // another store agrees on the id without ever having seen the resource
+    let other_pool = empty_database().await;
+    let registered_elsewhere = resource_service(&other_pool).register_resource(registering("reports", None)).await.unwrap();
+    assert_eq!(registered_elsewhere.resource_id, registered.resource_id);
+
+    assert_ne!(ResourceId::for_name(&TenantId::from("moscow centre"), "reports"), registered.resource_id);
+    assert_ne!(ResourceId::for_name(&tenant_id(), "ledgers"), registered.resource_id);
+}
+
+#[async_std::test]
+async fn test_registering_a_name_again_returns_the_resource_registered_first() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = resource_service(&connection_pool);
+    let archive_id = ResourceId::for_name(&tenant_id(), "archive");
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let george_smiley_id = subject_id(&connection_pool, "george smiley").await;
+
+    let first = service.register_resource(RegisterResourceRequest {
+        name: "reports".to_string(),
+        parent_id: Some(archive_id),
+        owner_id: Some(alec_leamas_id.clone()),
+    }).await.unwrap();
+    let second = service.register_resource(RegisterResourceRequest {
+        name: "reports".to_string(),
+        parent_id: None,
+        owner_id: Some(george_smiley_id),
+    }).await.unwrap();
+    assert_eq!(second.resource_id, first.resource_id);
+
+    let resource_repository = SqliteResourceRepository::new(connection_pool.clone(), tenant_id());
+    let reports = resource_repository.get_by_name("reports").await.unwrap().unwrap();
+    assert!(reports.is_owned_by(&alec_leamas_id));
+    assert_eq!(reports.get_parent_id(), Some(ResourceId::for_name(&tenant_id(), "archive")));
+    assert_eq!(resource_repository.find_all().await.unwrap().len(), 2);
+}
+
+#[async_std::test]
+async fn test_resources_cannot_be_registered_under_an_unknown_parent() {
+    let connection_pool = empty_database().await;
+    let unknown = ResourceId::for_name(&tenant_id(), "archive");
+    let error = resource_service(&connection_pool).register_resource(registering("reports", Some(unknown))).await.unwrap_err();
+    assert!(error.to_string().contains("not found"), "{}", error);
+}
+
+#[async_std::test]
+async fn test_grants_are_inherited_down_the_hierarchy() {
+    let connection_pool = seeded_database(HIERARCHY_SEED).await;
+    assert!(can_invoke(&connection_pool, "alec leamas", "archive").await);
+    assert!(can_invoke(&connection_pool, "alec leamas", "reports").await);
+    assert!(can_invoke(&connection_pool, "alec leamas", "quarterly reports").await);
+}
+
+#[async_std::test]
+async fn test_the_grant_nearest_the_resource_wins_and_deny_wins_on_the_same_resource() {
+    let connection_pool = seeded_database(HIERARCHY_SEED).await;
+    assert!(can_invoke(&connection_pool, "george smiley", "archive").await);
+    assert!(!can_invoke(&connection_pool, "george smiley", "reports").await);
+    assert!(!can_invoke(&connection_pool, "george smiley", "quarterly reports").await);
+
+    assert!(!can_invoke(&connection_pool, "connie sachs", "reports").await);
+    assert!(can_invoke(&connection_pool, "connie sachs", "quarterly reports").await);
+
+    assert!(!can_invoke(&connection_pool, "peter guillam", "reports").await);
+    assert!(!can_invoke(&connection_pool, "peter guillam", "quarterly reports").await);
+}
+
+#[async_std::test]
+async fn test_moved_resources_inherit_from_their_new_ancestors() {
+    let connection_pool = seeded_database(HIERARCHY_SEED).await;
+    let service = resource_service(&connection_pool);
+    service.move_resource(MoveResourceRequest {
+        resource_id: resource_id(&connection_pool, "quarterly reports").await,
+        parent_id: Some(resource_id(&connection_pool, "archive").await),
+    }).await.unwrap();
+    assert!(can_invoke(&connection_pool, "george smiley", "quarterly reports").await);
+
+    service.move_resource(MoveResourceRequest {
+        resource_id: resource_id(&connection_pool, "quarterly reports").await,
+        parent_id: None,
+    }).await.unwrap();
+    assert!(!can_invoke(&connection_pool, "alec leamas", "quarterly reports").await);
+}
+
+#[async_std::test]
+async fn test_resources_cannot_move_under_their_own_descendants() {
+    let connection_pool = seeded_database(HIERARCHY_SEED).await;
+    let error = resource_service(&connection_pool).move_resource(MoveResourceRequest {
+        resource_id: resource_id(&connection_pool, "archive").await,
+        parent_id: Some(resource_id(&connection_pool, "quarterly reports").await),
+    }).await.unwrap_err();
+    assert!(error.to_string().contains("under its own descendant"), "{}", error);
+    assert!(can_invoke(&connection_pool, "alec leamas", "quarterly reports").await);
+}
+
+#[async_std::test]
+async fn test_owner_permissions_follow_a_transfer_and_the_transfer_is_audited() {
+    let connection_pool = seeded_database(OWNERSHIP_SEED).await;
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let george_smiley_id = subject_id(&connection_pool, "george smiley").await;
+    let dossier_id = resource_id(&connection_pool, "the karla dossier").await;
+    assert!(can_invoke(&connection_pool, "alec leamas", "the karla dossier").await);
+    assert!(!can_invoke(&connection_pool, "alec leamas", "dossiers").await);
+    assert!(!can_invoke(&connection_pool, "george smiley", "the karla dossier").await);
+
+    let transferred = resource_service(&connection_pool).transfer_ownership(TransferOwnershipRequest {
+        resource_id: dossier_id.clone(),
+        owner_id: george_smiley_id.clone(),
+        transferred_by: alec_leamas_id.clone(),
+    }).await.unwrap();
+    assert!(can_invoke(&connection_pool, "george smiley", "the karla dossier").await);
+    assert!(!can_invoke(&connection_pool, "alec leamas", "the karla dossier").await);
+
+    let record = SqliteAuditRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_id(transferred.audit_record_id)
+        .await
+        .unwrap()
+        .unwrap();
+    match record.get_event() {
+        AuditEvent::ResourceOwnershipTransferred { resource_id, previous_owner_id, owner_id, transferred_by } => {
+            assert_eq!(resource_id, dossier_id);
+            assert_eq!(previous_owner_id, Some(alec_leamas_id.clone()));
+            assert_eq!(owner_id, george_smiley_id);
+            assert_eq!(transferred_by, alec_leamas_id);
+        },
+        event => panic!("unexpected audit event {:?}", event),
+    }
+}
+
+#[async_std::test]
+async fn test_only_the_owner_may_transfer_a_resource() {
+    let connection_pool = seeded_database(OWNERSHIP_SEED).await;
+    let george_smiley_id = subject_id(&connection_pool, "george smiley").await;
+
+    let error = resource_service(&connection_pool).transfer_ownership(TransferOwnershipRequest {
+        resource_id: resource_id(&connection_pool, "the karla dossier").await,
+        owner_id: george_smiley_id.clone(),
+        transferred_by: george_smiley_id,
+    }).await.unwrap_err();
+    assert!(error.to_string().contains("does not own resource the karla dossier"), "{}", error);
+    assert!(can_invoke(&connection_pool, "alec leamas", "the karla dossier").await);
+}
@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::application::separation_of_duties::DutiesLoader;
+use crate::domain::audit::{AuditEvent, AuditRecord};
+use crate::domain::repositories::{EntityChange, Error, Repository, SubjectRepository, UnitOfWork};
+use crate::domain::roles::{Role, RoleId};
+use crate::domain::subjects::{RoleAssignment, SubjectId};
+
+pub struct RoleAssignmentSweeper {
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+}
+
+impl RoleAssignmentSweeper {
+    pub fn new(
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+    ) -> RoleAssignmentSweeper {
+        Self {
+            subject_repository,
+            unit_of_work,
+        }
+    }
+
+    // removes every assignment that expired at or before `at`, returning one audit record per removal.
+    // a subject is saved in the same commit as its records, so no removal goes unrecorded
+    pub async fn sweep(&self, at: DateTime<Utc>) -> Result<Vec<AuditRecord>, Error> {
+        let mut records = Vec::new();
+
+        for mut subject in self.subject_repository.find_with_expired_roles(at).await? {
+            let expired = subject.remove_expired_roles(at);
+            if expired.is_empty() {
+                continue;
+            }
+
+            let subject_records: Vec<AuditRecord> = expired.iter()
+                .map(|assignment| AuditRecord::new(subject.get_tenant_id(), AuditEvent::RoleAssignmentExpired {
+                    subject_id: subject.get_id(),
+                    role_id: assignment.get_role_id(),
+                    valid_until: assignment.get_valid_until(),
+                }))
+                .collect();
+            let mut changes = vec![EntityChange::SaveSubject(subject)];
+            changes.extend(subject_records.iter().cloned().map(EntityChange::AppendAuditRecord));
+            self.unit_of_work.commit(changes).await?;
+            records.extend(subject_records);
+        }
+
+        Ok(records)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignRoleRequest {
+    pub subject_id: SubjectId,
+    pub role_id: RoleId,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignRoleResponse {}
+
+#[async_trait]
+pub trait RoleAssignmentService {
+    async fn assign_role(&self, req: AssignRoleRequest) -> Result<AssignRoleResponse, Error>;
+}
+
+pub struct RoleAssignmentServiceImpl {
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    role_repository: Box<dyn Repository<RoleId, Role> + Send + Sync>,
+    duties_loader: DutiesLoader,
+}
+
+impl RoleAssignmentServiceImpl {
+    pub fn new(
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        role_repository: Box<dyn Repository<RoleId, Role> + Send + Sync>,
+        duties_loader: DutiesLoader,
+    ) -> Self {
+        Self {
+            subject_repository,
+            role_repository,
+            duties_loader,
+        }
+    }
+}
+
+#[async_trait]
+impl RoleAssignmentService for RoleAssignmentServiceImpl {
+    async fn assign_role(&self, req: AssignRoleRequest) -> Result<AssignRoleResponse, Error> {
+        let mut subject = self.subject_repository.get_by_id(req.subject_id.clone())
+            .await?
+            .filter(|subject| subject.get_deleted_at().is_none())
+            .ok_or_else(|| Error::Simple(format!("subject {} not found", String::from(req.subject_id))))?;
+        self.role_repository.get_by_id(req.role_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("role {} not found", String::from(req.role_id.clone()))))?;
+
+        let duties = self.duties_loader.load().await?;
+        subject.add_role_assignment(RoleAssignment::bounded(req.role_id, req.valid_from, req.valid_until), &duties)?;
+        self.subject_repository.save(subject).await?;
+
+        Ok(AssignRoleResponse {})
+    }
+}
@@ -0,0 +1,116 @@
+use chrono::{Duration, Utc};
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::role_assignments::{
+    AssignRoleRequest, RoleAssignmentService, RoleAssignmentServiceImpl, RoleAssignmentSweeper,
+};
+use crate::application::separation_of_duties::DutiesLoader;
+use crate::domain::audit::AuditEvent;
+use crate::domain::repositories::{Repository, RoleRepository};
+use crate::domain::roles::RoleId;
+use crate::infrastructure::sqlite::audit::SqliteAuditRepository;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+use crate::test_support::{access_checker, resource_id, seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: reports
+    permissions:
+      - name: read reports
+        resource: reports
+    roles:
+      - name: analyst
+        permissions: [read reports]
+      - name: auditor
+    subjects:
+      - name: alec leamas
+        roles: [auditor]
+";
+
+fn role_assignment_service(connection_pool: &Pool<Sqlite>) -> RoleAssignmentServiceImpl {
+    RoleAssignmentServiceImpl::new(
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        DutiesLoader::new(
+            Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+            Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+            Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+            Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        ),
+    )
+}
+
+fn sweeper(connection_pool: &Pool<Sqlite>) -> RoleAssignmentSweeper {
+    RoleAssignmentSweeper::new(
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+async fn role_id(connection_pool: &Pool<Sqlite>, name: &str) -> RoleId {
+    SqliteRoleRepository::new(connection_pool.clone(), tenant_id()).get_by_name(name).await.unwrap().unwrap().get_id()
+}
+
+#[async_std::test]
+async fn test_assignments_grant_only_within_their_window() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = role_assignment_service(&connection_pool);
+    let access_checker = access_checker(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let reports_id = resource_id(&connection_pool, "reports").await;
+
+    service.assign_role(AssignRoleRequest {
+        subject_id: alec_leamas_id.clone(),
+        role_id: role_id(&connection_pool, "analyst").await,
+        valid_from: Some(Utc::now() + Duration::hours(1)),
+        valid_until: Some(Utc::now() + Duration::hours(2)),
+    }).await.unwrap();
+    assert!(!access_checker.can_invoke(alec_leamas_id.clone(), reports_id.clone()).await.unwrap());
+
+    service.assign_role(AssignRoleRequest {
+        subject_id: alec_leamas_id.clone(),
+        role_id: role_id(&connection_pool, "analyst").await,
+        valid_from: Some(Utc::now() - Duration::hours(1)),
+        valid_until: Some(Utc::now() + Duration::hours(1)),
+    }).await.unwrap();
+    assert!(access_checker.can_invoke(alec_leamas_id, reports_id).await.unwrap());
+}
+
+#[async_std::test]
+async fn test_the_sweeper_removes_expired_assignments_and_audits_each_removal() {
+    let connection_pool = seeded_database(SEED).await;
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let analyst_id = role_id(&connection_pool, "analyst").await;
+    let valid_until = Utc::now() + Duration::hours(1);
+    role_assignment_service(&connection_pool).assign_role(AssignRoleRequest {
+        subject_id: alec_leamas_id.clone(),
+        role_id: analyst_id.clone(),
+        valid_from: None,
+        valid_until: Some(valid_until),
+    }).await.unwrap();
+
+    assert!(sweeper(&connection_pool).sweep(Utc::now()).await.unwrap().is_empty());
+
+    let records = sweeper(&connection_pool).sweep(valid_until + Duration::minutes(1)).await.unwrap();
+    assert_eq!(records.len(), 1);
+    match records[0].get_event() {
+        AuditEvent::RoleAssignmentExpired { subject_id, role_id, valid_until: expired_at } => {
+            assert_eq!(subject_id, alec_leamas_id);
+            assert_eq!(role_id, analyst_id);
+            assert_eq!(expired_at.map(|at| at.timestamp_millis()), Some(valid_until.timestamp_millis()));
+        },
+        event => panic!("unexpected audit event {:?}", event),
+    }
+    let stored = SqliteAuditRepository::new(connection_pool.clone(), tenant_id()).get_by_id(records[0].get_id()).await.unwrap();
+    assert!(stored.is_some());
+
+    let alec_leamas = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).get_by_id(alec_leamas_id).await.unwrap().unwrap();
+    assert!(!alec_leamas.get_roles().contains(&analyst_id));
+    assert!(alec_leamas.get_roles().contains(&role_id(&connection_pool, "auditor").await));
+    assert!(sweeper(&connection_pool).sweep(valid_until + Duration::minutes(1)).await.unwrap().is_empty());
+}
@@ -0,0 +1,833 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::application::separation_of_duties::DutiesLoader;
+use crate::domain::conditions::AttributeValue;
+use crate::domain::groups::{Group, GroupId};
+use crate::domain::repositories::{EntityChange, Error, GroupRepository, SubjectRepository, UnitOfWork};
+use crate::domain::scim_filters::{attribute_key, get_attribute, PatchPath, ScimFilter};
+use crate::domain::subjects::{Subject, SubjectId, SubjectKind, ACTIVE_ATTRIBUTE};
+use crate::domain::tenants::TenantId;
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
+// the subject attribute that keeps the identity provider's own id of a user
+pub const EXTERNAL_ID_ATTRIBUTE: &str = "external_id";
+
+// the most resources a list returns, whatever count it asks for
+pub const MAX_RESULTS: usize = 200;
+
+// an error as SCIM clients expect it, e.g. `{"status": "409", "scimType": "uniqueness", ...}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScimError {
+    pub schemas: Vec<String>,
+    // a string, as SCIM has it
+    pub status: String,
+    #[serde(rename = "scimType", default, skip_serializing_if = "Option::is_none")]
+    pub scim_type: Option<String>,
+    pub detail: String,
+}
+
+impl ScimError {
+    fn new(status: u16, scim_type: Option<&str>, detail: String) -> ScimError {
+        ScimError {
+            schemas: vec![ERROR_SCHEMA.to_string()],
+            status: status.to_string(),
+            scim_type: scim_type.map(str::to_string),
+            detail,
+        }
+    }
+
+    pub fn get_status(&self) -> u16 {
+        self.status.parse().unwrap_or(500)
+    }
+
+    pub fn invalid_syntax(detail: String) -> ScimError {
+        ScimError::new(400, Some("invalidSyntax"), detail)
+    }
+
+    pub fn invalid_filter(detail: String) -> ScimError {
+        ScimError::new(400, Some("invalidFilter"), detail)
+    }
+
+    pub fn invalid_path(detail: String) -> ScimError {
+        ScimError::new(400, Some("invalidPath"), detail)
+    }
+
+    pub fn invalid_value(detail: String) -> ScimError {
+        ScimError::new(400, Some("invalidValue"), detail)
+    }
+
+    pub fn no_target(detail: String) -> ScimError {
+        ScimError::new(400, Some("noTarget"), detail)
+    }
+
+    pub fn mutability(detail: String) -> ScimError {
+        ScimError::new(400, Some("mutability"), detail)
+    }
+
+    pub fn not_found(detail: String) -> ScimError {
+        ScimError::new(404, None, detail)
+    }
+
+    pub fn uniqueness(detail: String) -> ScimError {
+        ScimError::new(409, Some("uniqueness"), detail)
+    }
+
+    pub fn precondition_failed(detail: String) -> ScimError {
+        ScimError::new(412, None, detail)
+    }
+
+    // a rule of the domain that refused the change, e.g. a separation of duties constraint
+    fn refused(error: Error) -> ScimError {
+        let Error::Simple(cause) = error;
+        ScimError::invalid_value(cause)
+    }
+}
+
+// errors of the store are logged rather than handed to the identity provider
+impl From<Error> for ScimError {
+    fn from(error: Error) -> Self {
+        let Error::Simple(cause) = error;
+        tracing::error!(cause, "unable to handle scim request");
+        ScimError::new(500, None, "the request could not be handled".to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    pub resource_type: String,
+    pub created: DateTime<Utc>,
+    pub last_modified: DateTime<Utc>,
+    // the ETag of the resource
+    pub version: String,
+    pub location: String,
+}
+
+// a member of a group, or a group of a user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScimReference {
+    pub value: String,
+    pub display: String,
+    #[serde(rename = "$ref")]
+    pub reference: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+// a person. `active` and `externalId` are kept in the subject's attributes; `groups` is read only:
+// memberships change through the groups
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    pub schemas: Vec<String>,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    pub user_name: String,
+    pub active: bool,
+    pub groups: Vec<ScimReference>,
+    pub meta: ScimMeta,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroup {
+    pub schemas: Vec<String>,
+    pub id: String,
+    pub display_name: String,
+    pub members: Vec<ScimReference>,
+    pub meta: ScimMeta,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListResponse<T> {
+    pub schemas: Vec<String>,
+    pub total_results: usize,
+    pub start_index: usize,
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScimListRequest {
+    pub filter: Option<String>,
+    // 1-based
+    pub start_index: Option<i64>,
+    pub count: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScimGetRequest {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScimCreateRequest {
+    pub resource: Value,
+}
+
+// `if_match` holds the ETags the resource must still have, as the If-Match header lists them
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScimReplaceRequest {
+    pub id: String,
+    pub resource: Value,
+    pub if_match: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScimPatchRequest {
+    pub id: String,
+    pub patch: Value,
+    pub if_match: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScimDeleteRequest {
+    pub id: String,
+    pub if_match: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScimDeleteResponse {}
+
+// provisioning by an identity provider over SCIM 2.0: users are the people among the subjects and
+// groups are groups. a user's ETag is the version of its subject
+#[async_trait]
+pub trait ScimService {
+    async fn list_users(&self, req: ScimListRequest) -> Result<ScimListResponse<ScimUser>, ScimError>;
+    async fn get_user(&self, req: ScimGetRequest) -> Result<ScimUser, ScimError>;
+    async fn create_user(&self, req: ScimCreateRequest) -> Result<ScimUser, ScimError>;
+    async fn replace_user(&self, req: ScimReplaceRequest) -> Result<ScimUser, ScimError>;
+    async fn patch_user(&self, req: ScimPatchRequest) -> Result<ScimUser, ScimError>;
+    async fn delete_user(&self, req: ScimDeleteRequest) -> Result<ScimDeleteResponse, ScimError>;
+    async fn list_groups(&self, req: ScimListRequest) -> Result<ScimListResponse<ScimGroup>, ScimError>;
+    async fn get_group(&self, req: ScimGetRequest) -> Result<ScimGroup, ScimError>;
+    async fn create_group(&self, req: ScimCreateRequest) -> Result<ScimGroup, ScimError>;
+    async fn replace_group(&self, req: ScimReplaceRequest) -> Result<ScimGroup, ScimError>;
+    async fn patch_group(&self, req: ScimPatchRequest) -> Result<ScimGroup, ScimError>;
+    async fn delete_group(&self, req: ScimDeleteRequest) -> Result<ScimDeleteResponse, ScimError>;
+}
+
+struct UserInput {
+    user_name: String,
+    external_id: Option<String>,
+    active: bool,
+}
+
+impl UserInput {
+    // attributes the service does not keep, e.g. `name` or `emails`, are ignored
+    fn parse(resource: &Value) -> Result<UserInput, ScimError> {
+        let resource = resource.as_object()
+            .ok_or_else(|| ScimError::invalid_syntax("a user is a json object".to_string()))?;
+        let user_name = match get_attribute(resource, "userName") {
+            Some(Value::String(user_name)) if !user_name.trim().is_empty() => user_name.trim().to_string(),
+            _ => return Err(ScimError::invalid_value("userName is required".to_string())),
+        };
+        let external_id = match get_attribute(resource, "externalId") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(external_id)) => Some(external_id.clone()),
+            Some(_) => return Err(ScimError::invalid_value("externalId is a string".to_string())),
+        };
+        let active = match get_attribute(resource, "active") {
+            None | Some(Value::Null) => true,
+            Some(active) => boolean(active).ok_or_else(|| ScimError::invalid_value("active is a boolean".to_string()))?,
+        };
+        Ok(UserInput {
+            user_name,
+            external_id,
+            active,
+        })
+    }
+
+    // records only what changes, so that a replace that changes nothing keeps the version
+    fn apply(&self, subject: &mut Subject) {
+        if subject.get_name() != self.user_name {
+            subject.rename(&self.user_name);
+        }
+        let attributes = subject.get_attributes();
+        match (&self.external_id, attributes.get(EXTERNAL_ID_ATTRIBUTE)) {
+            (Some(external_id), Some(AttributeValue::String(current))) if current == external_id => {},
+            (Some(external_id), _) => subject.set_attribute(EXTERNAL_ID_ATTRIBUTE, AttributeValue::from(external_id.clone())),
+            (None, Some(_)) => subject.remove_attribute(EXTERNAL_ID_ATTRIBUTE),
+            (None, None) => {},
+        }
+        let suspended = attributes.get(ACTIVE_ATTRIBUTE) == Some(&AttributeValue::Bool(false));
+        if self.active && suspended {
+            subject.remove_attribute(ACTIVE_ATTRIBUTE);
+        } else if !self.active && !suspended {
+            subject.set_attribute(ACTIVE_ATTRIBUTE, AttributeValue::Bool(false));
+        }
+    }
+}
+
+struct GroupInput {
+    display_name: String,
+    members: HashSet<SubjectId>,
+}
+
+impl GroupInput {
+    fn parse(resource: &Value) -> Result<GroupInput, ScimError> {
+        let resource = resource.as_object()
+            .ok_or_else(|| ScimError::invalid_syntax("a group is a json object".to_string()))?;
+        let display_name = match get_attribute(resource, "displayName") {
+            Some(Value::String(display_name)) if !display_name.trim().is_empty() => display_name.trim().to_string(),
+            _ => return Err(ScimError::invalid_value("displayName is required".to_string())),
+        };
+        let members = match get_attribute(resource, "members") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(members)) => members.clone(),
+            Some(_) => return Err(ScimError::invalid_value("members is a list".to_string())),
+        };
+
+        let mut subject_ids = HashSet::new();
+        for member in members {
+            let member = member.as_object()
+                .ok_or_else(|| ScimError::invalid_value("a member is a json object".to_string()))?;
+            if get_attribute(member, "type").and_then(Value::as_str).is_some_and(|kind| !kind.eq_ignore_ascii_case("User")) {
+                return Err(ScimError::invalid_value("only users can be members of a group".to_string()));
+            }
+            match get_attribute(member, "value") {
+                Some(Value::String(value)) => subject_ids.insert(SubjectId::from(value.clone())),
+                _ => return Err(ScimError::invalid_value("a member needs a value".to_string())),
+            };
+        }
+        Ok(GroupInput {
+            display_name,
+            members: subject_ids,
+        })
+    }
+}
+
+// the booleans some identity providers send as strings, e.g. `"False"`, count too
+fn boolean(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(value) => Some(*value),
+        Value::String(value) if value.eq_ignore_ascii_case("true") => Some(true),
+        Value::String(value) if value.eq_ignore_ascii_case("false") => Some(false),
+        _ => None,
+    }
+}
+
+fn user_etag(subject: &Subject) -> String {
+    format!("W/\"{}\"", subject.get_version())
+}
+
+// groups have no version: their ETag is a digest of what they hold instead
+fn group_etag(group: &Group) -> String {
+    let mut subject_ids: Vec<String> = group.get_subjects().iter().cloned().map(String::from).collect();
+    subject_ids.sort();
+    let mut group_ids: Vec<String> = group.get_groups().iter().cloned().map(String::from).collect();
+    group_ids.sort();
+    let mut role_ids: Vec<String> = group.get_roles().into_iter().map(String::from).collect();
+    role_ids.sort();
+    let digest = Sha256::digest(to_json(&(group.get_name(), subject_ids, group_ids, role_ids)).to_string());
+    format!("W/\"{}\"", hex::encode(&digest[..8]))
+}
+
+// weak and strong ETags compare alike; `*` matches whatever the resource is at
+fn ensure_matches(if_match: &Option<String>, etag: &str) -> Result<(), ScimError> {
+    let if_match = match if_match {
+        Some(if_match) => if_match,
+        None => return Ok(()),
+    };
+    let strip = |etag: &str| etag.trim().trim_start_matches("W/").to_string();
+    if if_match.split(',').any(|candidate| candidate.trim() == "*" || strip(candidate) == strip(etag)) {
+        return Ok(());
+    }
+    Err(ScimError::precondition_failed(format!("the resource changed, it is at {}", etag)))
+}
+
+// the page of resources `start_index` and `count` ask for
+fn page<T>(resources: Vec<T>, start_index: Option<i64>, count: Option<i64>) -> ScimListResponse<T> {
+    let start_index = start_index.unwrap_or(1).max(1) as usize;
+    let count = count.map_or(MAX_RESULTS, |count| count.clamp(0, MAX_RESULTS as i64) as usize);
+    let total_results = resources.len();
+    let resources: Vec<T> = resources.into_iter().skip(start_index - 1).take(count).collect();
+    ScimListResponse {
+        schemas: vec![LIST_RESPONSE_SCHEMA.to_string()],
+        total_results,
+        start_index,
+        items_per_page: resources.len(),
+        resources,
+    }
+}
+
+fn parse_filter(filter: &Option<String>) -> Result<Option<ScimFilter>, ScimError> {
+    filter.as_deref()
+        .map(ScimFilter::parse)
+        .transpose()
+        .map_err(|Error::Simple(cause)| ScimError::invalid_filter(cause))
+}
+
+fn to_json<T: Serialize>(resource: &T) -> Value {
+    serde_json::to_value(resource).expect("scim resources always serialize")
+}
+
+// applies the operations of a PatchOp message to a resource, e.g.
+// `{"op": "remove", "path": "members[value eq \"2819c223\"]"}`. op names are matched without regard to
+// case. attributes the resource does not have at all are ones the service does not keep: operations
+// on their values are ignored
+fn apply_patch(resource: &Value, patch: &Value) -> Result<Value, ScimError> {
+    let operations = patch.as_object()
+        .and_then(|patch| get_attribute(patch, "Operations"))
+        .and_then(Value::as_array)
+        .ok_or_else(|| ScimError::invalid_syntax("a patch needs a list of Operations".to_string()))?;
+
+    let mut patched = resource.as_object().cloned().unwrap_or_default();
+    for operation in operations {
+        let operation = operation.as_object()
+            .ok_or_else(|| ScimError::invalid_syntax("an operation is a json object".to_string()))?;
+        let op = get_attribute(operation, "op")
+            .and_then(Value::as_str)
+            .map(str::to_ascii_lowercase)
+            .ok_or_else(|| ScimError::invalid_syntax("an operation needs an op".to_string()))?;
+        let path = get_attribute(operation, "path")
+            .and_then(Value::as_str)
+            .map(PatchPath::parse)
+            .transpose()
+            .map_err(|Error::Simple(cause)| ScimError::invalid_path(cause))?;
+        let value = get_attribute(operation, "value").cloned();
+
+        match (op.as_str(), path, value) {
+            ("add" | "replace", Some(path), Some(value)) => set(&mut patched, &path, value, op == "add")?,
+            // without a path the value holds the attributes to set
+            ("add" | "replace", None, Some(Value::Object(values))) => {
+                for (name, value) in values {
+                    let path = PatchPath::parse(&name).map_err(|Error::Simple(cause)| ScimError::invalid_path(cause))?;
+                    set(&mut patched, &path, value, op == "add")?;
+                }
+            },
+            ("add" | "replace", _, _) => return Err(ScimError::invalid_syntax(format!("{} needs a value", op))),
+            ("remove", Some(path), value) => remove(&mut patched, &path, value),
+            ("remove", None, _) => return Err(ScimError::no_target("remove needs a path".to_string())),
+            (op, _, _) => return Err(ScimError::invalid_syntax(format!("unknown op {}", op))),
+        }
+    }
+    Ok(Value::Object(patched))
+}
+
+fn set(resource: &mut Map<String, Value>, path: &PatchPath, value: Value, add: bool) -> Result<(), ScimError> {
+    let attribute = path.get_attribute();
+    let key = attribute_key(resource, attribute.get_name()).unwrap_or_else(|| attribute.get_name().to_string());
+
+    if let Some(filter) = path.get_filter() {
+        let values = match resource.get_mut(&key) {
+            Some(Value::Array(values)) => values,
+            _ => return Ok(()),
+        };
+        let mut matched = false;
+        for current in values.iter_mut().filter(|current| filter.matches(current)) {
+            matched = true;
+            match (attribute.get_sub_attribute(), current.as_object_mut()) {
+                (Some(sub_attribute), Some(current)) => set_attribute(current, sub_attribute, value.clone()),
+                _ => *current = value.clone(),
+            }
+        }
+        if !matched {
+            return Err(ScimError::no_target(format!("no value of {} matches the filter", attribute.get_name())));
+        }
+        return Ok(());
+    }
+
+    match (attribute.get_sub_attribute(), resource.get_mut(&key)) {
+        (Some(sub_attribute), Some(Value::Object(current))) => set_attribute(current, sub_attribute, value),
+        (Some(sub_attribute), _) => {
+            let mut current = Map::new();
+            current.insert(sub_attribute.to_string(), value);
+            resource.insert(key, Value::Object(current));
+        },
+        // adding to a multi-valued attribute keeps the values it has
+        (None, Some(Value::Array(current))) if add => match value {
+            Value::Array(values) => current.extend(values.into_iter().filter(|value| !current.contains(value)).collect::<Vec<_>>()),
+            value if !current.contains(&value) => current.push(value),
+            _ => {},
+        },
+        (None, _) => { resource.insert(key, value); },
+    }
+    Ok(())
+}
+
+fn set_attribute(object: &mut Map<String, Value>, name: &str, value: Value) {
+    let key = attribute_key(object, name).unwrap_or_else(|| name.to_string());
+    object.insert(key, value);
+}
+
+fn remove(resource: &mut Map<String, Value>, path: &PatchPath, value: Option<Value>) {
+    let attribute = path.get_attribute();
+    let key = match attribute_key(resource, attribute.get_name()) {
+        Some(key) => key,
+        None => return,
+    };
+    let remove_sub_attribute = |current: &mut Value| {
+        if let (Some(sub_attribute), Some(current)) = (attribute.get_sub_attribute(), current.as_object_mut()) {
+            if let Some(key) = attribute_key(current, sub_attribute) {
+                current.remove(&key);
+            }
+        }
+    };
+
+    match (path.get_filter(), resource.get_mut(&key), attribute.get_sub_attribute()) {
+        (Some(filter), Some(Value::Array(values)), None) => values.retain(|current| !filter.matches(current)),
+        (Some(filter), Some(Value::Array(values)), Some(_)) => {
+            values.iter_mut().filter(|current| filter.matches(current)).for_each(remove_sub_attribute);
+        },
+        (Some(_), _, _) => {},
+        // some identity providers name the values to remove of a multi-valued attribute in the value,
+        // e.g. `{"op": "remove", "path": "members", "value": [{"value": "2819c223"}]}`
+        (None, Some(Value::Array(values)), None) if value.is_some() => {
+            let removed: Vec<Value> = match value {
+                Some(Value::Array(removed)) => removed,
+                Some(removed) => vec![removed],
+                None => Vec::new(),
+            };
+            let identifier = |value: &Value| value.as_object().and_then(|value| get_attribute(value, "value")).cloned();
+            values.retain(|current| !removed.iter().any(|removed| removed == current || identifier(removed).is_some_and(|id| identifier(current) == Some(id))));
+        },
+        (None, Some(current), Some(_)) => remove_sub_attribute(current),
+        (None, _, _) => { resource.remove(&key); },
+    }
+}
+
+// a patch may not change what the service provider alone decides
+fn ensure_unchanged(original: &Value, patched: &Value, read_only: &[&str]) -> Result<(), ScimError> {
+    for name in read_only {
+        let before = original.as_object().and_then(|original| get_attribute(original, name));
+        let after = patched.as_object().and_then(|patched| get_attribute(patched, name));
+        if before != after {
+            return Err(ScimError::mutability(format!("{} is read only", name)));
+        }
+    }
+    Ok(())
+}
+
+pub struct ScimServiceImpl {
+    tenant_id: TenantId,
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+    unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+    duties_loader: DutiesLoader,
+    base_url: String,
+}
+
+impl ScimServiceImpl {
+    // `base_url` is where the endpoints are served, e.g. `https://basics.example.com/scim/v2`; the
+    // locations of resources start with it
+    pub fn new(
+        tenant_id: TenantId,
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+        unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+        duties_loader: DutiesLoader,
+        base_url: &str,
+    ) -> Self {
+        ScimServiceImpl {
+            tenant_id,
+            subject_repository,
+            group_repository,
+            unit_of_work,
+            duties_loader,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn is_user(&self, subject: &Subject) -> bool {
+        subject.get_tenant_id() == self.tenant_id
+            && subject.get_kind() == SubjectKind::Person
+            && subject.get_deleted_at().is_none()
+    }
+
+    async fn find_user(&self, id: &str) -> Result<Subject, ScimError> {
+        self.subject_repository.get_by_id(SubjectId::from(id.to_string()))
+            .await?
+            .filter(|subject| self.is_user(subject))
+            .ok_or_else(|| ScimError::not_found(format!("user {} not found", id)))
+    }
+
+    async fn find_group(&self, id: &str) -> Result<Group, ScimError> {
+        self.group_repository.get_by_id(GroupId::from(id.to_string()))
+            .await?
+            .filter(|group| group.get_tenant_id() == self.tenant_id)
+            .ok_or_else(|| ScimError::not_found(format!("group {} not found", id)))
+    }
+
+    fn location(&self, resource_type: &str, id: &str) -> String {
+        format!("{}/{}/{}", self.base_url, resource_type, id)
+    }
+
+    // groups the user belongs to through a member group are "indirect"
+    async fn to_user(&self, subject: &Subject) -> Result<ScimUser, ScimError> {
+        let id = String::from(subject.get_id());
+        let groups = self.group_repository.find_by_subject_transitively(subject.get_id())
+            .await?
+            .into_iter()
+            .map(|group| {
+                let group_id = String::from(group.get_id());
+                let kind = if group.get_subjects().contains(&subject.get_id()) { "direct" } else { "indirect" };
+                ScimReference {
+                    reference: self.location("Groups", &group_id),
+                    value: group_id,
+                    display: group.get_name(),
+                    kind: kind.to_string(),
+                }
+            })
+            .collect();
+        let external_id = match subject.get_attributes().get(EXTERNAL_ID_ATTRIBUTE) {
+            Some(AttributeValue::String(external_id)) => Some(external_id.clone()),
+            _ => None,
+        };
+
+        Ok(ScimUser {
+            schemas: vec![USER_SCHEMA.to_string()],
+            external_id,
+            user_name: subject.get_name(),
+            active: subject.is_active(),
+            groups,
+            meta: ScimMeta {
+                resource_type: "User".to_string(),
+                created: subject.get_created_at(),
+                last_modified: subject.get_updated_at(),
+                version: user_etag(subject),
+                location: self.location("Users", &id),
+            },
+            id,
+        })
+    }
+
+    async fn to_group(&self, group: &Group) -> Result<ScimGroup, ScimError> {
+        let id = String::from(group.get_id());
+        let mut members = Vec::new();
+        for subject_id in group.get_subjects() {
+            if let Some(subject) = self.subject_repository.get_by_id(subject_id.clone()).await? {
+                let subject_id = String::from(subject_id.clone());
+                members.push(ScimReference {
+                    reference: self.location("Users", &subject_id),
+                    value: subject_id,
+                    display: subject.get_name(),
+                    kind: "User".to_string(),
+                });
+            }
+        }
+        members.sort_by(|left, right| left.value.cmp(&right.value));
+
+        Ok(ScimGroup {
+            schemas: vec![GROUP_SCHEMA.to_string()],
+            display_name: group.get_name(),
+            members,
+            meta: ScimMeta {
+                resource_type: "Group".to_string(),
+                created: group.get_created_at(),
+                last_modified: group.get_updated_at(),
+                version: group_etag(group),
+                location: self.location("Groups", &id),
+            },
+            id,
+        })
+    }
+
+    // user names are matched without regard to case, and service accounts hold on to theirs too
+    async fn ensure_user_name_is_free(&self, user_name: &str, except: Option<&SubjectId>) -> Result<(), ScimError> {
+        let taken = self.subject_repository.find_all()
+            .await?
+            .into_iter()
+            .any(|subject| subject.get_deleted_at().is_none()
+                && Some(&subject.get_id()) != except
+                && subject.get_name().eq_ignore_ascii_case(user_name));
+        if taken {
+            return Err(ScimError::uniqueness(format!("userName {} is taken", user_name)));
+        }
+        Ok(())
+    }
+
+    async fn ensure_display_name_is_free(&self, display_name: &str, except: Option<&GroupId>) -> Result<(), ScimError> {
+        let taken = self.group_repository.find_all()
+            .await?
+            .into_iter()
+            .any(|group| Some(&group.get_id()) != except && group.get_name().eq_ignore_ascii_case(display_name));
+        if taken {
+            return Err(ScimError::uniqueness(format!("displayName {} is taken", display_name)));
+        }
+        Ok(())
+    }
+
+    // a save that failed because the subject moved past the version it was read at lost a race with
+    // another change: the precondition no longer holds
+    async fn save_failed(&self, error: Error, subject_id: SubjectId, read_version: i64) -> ScimError {
+        match self.subject_repository.get_by_id(subject_id).await {
+            Ok(Some(stored)) if stored.get_version() != read_version => {
+                ScimError::precondition_failed(format!("the resource changed, it is at {}", user_etag(&stored)))
+            },
+            _ => ScimError::from(error),
+        }
+    }
+
+    async fn update_user(&self, mut subject: Subject, resource: &Value) -> Result<ScimUser, ScimError> {
+        let input = UserInput::parse(resource)?;
+        self.ensure_user_name_is_free(&input.user_name, Some(&subject.get_id())).await?;
+        let read_version = subject.get_version();
+        input.apply(&mut subject);
+        if !subject.get_pending_events().is_empty() {
+            if let Err(error) = self.subject_repository.save(subject.clone()).await {
+                return Err(self.save_failed(error, subject.get_id(), read_version).await);
+            }
+        }
+        self.to_user(&subject).await
+    }
+
+    // members must be users. a group that is not stored yet is stored whatever changes
+    async fn update_group(&self, mut group: Group, resource: &Value, created: bool) -> Result<ScimGroup, ScimError> {
+        let input = GroupInput::parse(resource)?;
+        self.ensure_display_name_is_free(&input.display_name, Some(&group.get_id())).await?;
+        for subject_id in &input.members {
+            let subject = self.subject_repository.get_by_id(subject_id.clone()).await?;
+            if !subject.is_some_and(|subject| self.is_user(&subject)) {
+                return Err(ScimError::invalid_value(format!("member {} is not a user", String::from(subject_id.clone()))));
+            }
+        }
+
+        let mut changed = created;
+        if group.get_name() != input.display_name {
+            group.rename(&input.display_name);
+            changed = true;
+        }
+        let removed: Vec<SubjectId> = group.get_subjects().difference(&input.members).cloned().collect();
+        for subject_id in &removed {
+            group.remove_subject(subject_id);
+        }
+        let added: Vec<SubjectId> = input.members.difference(group.get_subjects()).cloned().collect();
+        if !added.is_empty() {
+            let duties = self.duties_loader.load().await?;
+            for subject_id in &added {
+                group.add_subject(subject_id.clone(), &duties).map_err(ScimError::refused)?;
+            }
+        }
+
+        if changed || !removed.is_empty() || !added.is_empty() {
+            self.group_repository.save(group.clone()).await?;
+        }
+        self.to_group(&group).await
+    }
+}
+
+#[async_trait]
+impl ScimService for ScimServiceImpl {
+    // filters are evaluated over the users as SCIM represents them
+    async fn list_users(&self, req: ScimListRequest) -> Result<ScimListResponse<ScimUser>, ScimError> {
+        let filter = parse_filter(&req.filter)?;
+        let mut users = Vec::new();
+        for subject in self.subject_repository.find_all().await?.iter().filter(|subject| self.is_user(subject)) {
+            let user = self.to_user(subject).await?;
+            if filter.as_ref().is_none_or(|filter| filter.matches(&to_json(&user))) {
+                users.push(user);
+            }
+        }
+        Ok(page(users, req.start_index, req.count))
+    }
+
+    async fn get_user(&self, req: ScimGetRequest) -> Result<ScimUser, ScimError> {
+        let subject = self.find_user(&req.id).await?;
+        self.to_user(&subject).await
+    }
+
+    async fn create_user(&self, req: ScimCreateRequest) -> Result<ScimUser, ScimError> {
+        let input = UserInput::parse(&req.resource)?;
+        self.ensure_user_name_is_free(&input.user_name, None).await?;
+        let mut subject = Subject::new(self.tenant_id.clone(), &input.user_name);
+        input.apply(&mut subject);
+        self.subject_repository.save(subject.clone()).await?;
+        self.to_user(&subject).await
+    }
+
+    async fn replace_user(&self, req: ScimReplaceRequest) -> Result<ScimUser, ScimError> {
+        let subject = self.find_user(&req.id).await?;
+        ensure_matches(&req.if_match, &user_etag(&subject))?;
+        self.update_user(subject, &req.resource).await
+    }
+
+    async fn patch_user(&self, req: ScimPatchRequest) -> Result<ScimUser, ScimError> {
+        let subject = self.find_user(&req.id).await?;
+        ensure_matches(&req.if_match, &user_etag(&subject))?;
+        let user = to_json(&self.to_user(&subject).await?);
+        let patched = apply_patch(&user, &req.patch)?;
+        ensure_unchanged(&user, &patched, &["id", "groups", "meta"])?;
+        self.update_user(subject, &patched).await
+    }
+
+    // the user leaves its groups as it is deleted
+    async fn delete_user(&self, req: ScimDeleteRequest) -> Result<ScimDeleteResponse, ScimError> {
+        let mut subject = self.find_user(&req.id).await?;
+        ensure_matches(&req.if_match, &user_etag(&subject))?;
+        let read_version = subject.get_version();
+        subject.delete();
+
+        let mut changes = Vec::new();
+        for mut group in self.group_repository.find_by_subject(subject.get_id()).await? {
+            group.remove_subject(&subject.get_id());
+            changes.push(EntityChange::SaveGroup(group));
+        }
+        changes.push(EntityChange::SaveSubject(subject.clone()));
+        if let Err(error) = self.unit_of_work.commit(changes).await {
+            return Err(self.save_failed(error, subject.get_id(), read_version).await);
+        }
+        Ok(ScimDeleteResponse {})
+    }
+
+    async fn list_groups(&self, req: ScimListRequest) -> Result<ScimListResponse<ScimGroup>, ScimError> {
+        let filter = parse_filter(&req.filter)?;
+        let mut groups = Vec::new();
+        for group in self.group_repository.find_all().await? {
+            let group = self.to_group(&group).await?;
+            if filter.as_ref().is_none_or(|filter| filter.matches(&to_json(&group))) {
+                groups.push(group);
+            }
+        }
+        Ok(page(groups, req.start_index, req.count))
+    }
+
+    async fn get_group(&self, req: ScimGetRequest) -> Result<ScimGroup, ScimError> {
+        let group = self.find_group(&req.id).await?;
+        self.to_group(&group).await
+    }
+
+    async fn create_group(&self, req: ScimCreateRequest) -> Result<ScimGroup, ScimError> {
+        let input = GroupInput::parse(&req.resource)?;
+        let group = Group::new(self.tenant_id.clone(), &input.display_name);
+        self.update_group(group, &req.resource, true).await
+    }
+
+    // groups are not versioned in the store, so the ETag is only compared as the group is read: a
+    // change committed between that read and this write is overwritten all the same
+    async fn replace_group(&self, req: ScimReplaceRequest) -> Result<ScimGroup, ScimError> {
+        let group = self.find_group(&req.id).await?;
+        ensure_matches(&req.if_match, &group_etag(&group))?;
+        self.update_group(group, &req.resource, false).await
+    }
+
+    async fn patch_group(&self, req: ScimPatchRequest) -> Result<ScimGroup, ScimError> {
+        let group = self.find_group(&req.id).await?;
+        ensure_matches(&req.if_match, &group_etag(&group))?;
+        let scim_group = to_json(&self.to_group(&group).await?);
+        let patched = apply_patch(&scim_group, &req.patch)?;
+        ensure_unchanged(&scim_group, &patched, &["id", "meta"])?;
+        self.update_group(group, &patched, false).await
+    }
+
+    // the groups that contain the deleted group lose it as a member in the same commit
+    async fn delete_group(&self, req: ScimDeleteRequest) -> Result<ScimDeleteResponse, ScimError> {
+        let group = self.find_group(&req.id).await?;
+        ensure_matches(&req.if_match, &group_etag(&group))?;
+        self.unit_of_work.commit(vec![EntityChange::DeleteGroup(group.get_id())]).await?;
+        Ok(ScimDeleteResponse {})
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use crate::domain::conditions::Attributes;
+use crate::domain::groups::{Group, GroupId};
+use crate::domain::operations::Operation;
+use crate::domain::permissions::{Permission, PermissionId};
+use crate::domain::repositories::{
+    EntityChange, Error, GroupRepository, PermissionRepository, ResourceRepository, RoleRepository, SodConstraintRepository,
+    SubjectRepository, UnitOfWork,
+};
+use crate::domain::resources::{Resource, ResourceId};
+use crate::domain::roles::{Role, RoleId};
+use crate::domain::seeds::{Seed, SeedFormat};
+use crate::domain::separation_of_duties::SeparationOfDuties;
+use crate::domain::subjects::{Subject, SubjectId};
+use crate::domain::tenants::TenantId;
+
+pub async fn read_seed(path: impl AsRef<Path>) -> Result<Seed, Error> {
+    let path = path.as_ref();
+    let format = SeedFormat::for_path(path)?;
+    let source = async_std::fs::read_to_string(path)
+        .await
+        .map_err(|error| Error::Simple(format!("unable to read seed {}: {}", path.display(), error)))?;
+    Seed::parse(&source, format)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    Create,
+    Update,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedChange {
+    pub kind: ChangeKind,
+    pub entity: String,
+    pub name: String,
+    // one line per field that changes, e.g. `permissions: +list users, -update user`
+    pub details: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    changes: Vec<PlannedChange>,
+    writes: Vec<EntityChange>,
+}
+
+impl Plan {
+    pub fn get_changes(&self) -> Vec<PlannedChange> {
+        self.changes.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // what applying the plan would commit
+    pub fn get_writes(&self) -> Vec<EntityChange> {
+        self.writes.clone()
+    }
+
+    fn record(&mut self, entity: &str, name: &str, exists: bool, details: Vec<String>, write: EntityChange) {
+        if exists && details.is_empty() {
+            return;
+        }
+        let kind = if exists { ChangeKind::Update } else { ChangeKind::Create };
+        self.changes.push(PlannedChange {
+            kind,
+            entity: entity.to_string(),
+            name: name.to_string(),
+            details,
+        });
+        self.writes.push(write);
+    }
+}
+
+// reads like a terraform plan:
+//
+//   + role auditor
+//       permissions: +list users
+//   ~ subject alec leamas
+//       roles: +auditor
+//   1 to create, 1 to update
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        for change in &self.changes {
+            let marker = match change.kind {
+                ChangeKind::Create => '+',
+                ChangeKind::Update => '~',
+            };
+            writeln!(f, "{} {} {}", marker, change.entity, change.name)?;
+            for detail in &change.details {
+                writeln!(f, "    {}", detail)?;
+            }
+        }
+        let creates = self.changes.iter().filter(|change| change.kind == ChangeKind::Create).count();
+        writeln!(f, "{} to create, {} to update", creates, self.changes.len() - creates)
+    }
+}
+
+// the seed names entities, the store knows them by id. only entities the seed mentions are touched;
+// when stored names repeat, the oldest entity with the name is the one the seed manages
+pub struct SeedPlanner {
+    tenant_id: TenantId,
+    resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+    permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+    role_repository: Box<dyn RoleRepository + Send + Sync>,
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+    sod_constraint_repository: Box<dyn SodConstraintRepository + Send + Sync>,
+    unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+}
+
+impl SeedPlanner {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        tenant_id: TenantId,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+        permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+        role_repository: Box<dyn RoleRepository + Send + Sync>,
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+        sod_constraint_repository: Box<dyn SodConstraintRepository + Send + Sync>,
+        unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+    ) -> SeedPlanner {
+        SeedPlanner {
+            tenant_id,
+            resource_repository,
+            permission_repository,
+            role_repository,
+            subject_repository,
+            group_repository,
+            sod_constraint_repository,
+            unit_of_work,
+        }
+    }
+
+    // plans again against the current store rather than trusting an earlier plan, and writes
+    // every change in a single transaction
+    pub async fn apply(&self, seed: &Seed) -> Result<Plan, Error> {
+        let plan = self.plan(seed).await?;
+        if !plan.is_empty() {
+            self.unit_of_work.commit(plan.writes.clone()).await?;
+        }
+        Ok(plan)
+    }
+
+    pub async fn plan(&self, seed: &Seed) -> Result<Plan, Error> {
+        let resources = by_name(self.resource_repository.find_all().await?, Resource::get_name);
+        let permissions = by_name(self.permission_repository.find_all().await?, Permission::get_name);
+        let stored_roles = self.role_repository.find_all().await?;
+        let stored_subjects = self.subject_repository.find_all().await?;
+        let stored_groups = self.group_repository.find_all().await?;
+        let roles = by_name(stored_roles.clone(), Role::get_name);
+        let subjects = by_name(
+            stored_subjects.iter().filter(|subject| subject.get_deleted_at().is_none()).cloned().collect(),
+            Subject::get_name,
+        );
+        let groups = by_name(stored_groups.clone(), Group::get_name);
+
+        // ids of stored entities plus the ones the seed is about to create, by name
+        let mut resource_ids: HashMap<String, ResourceId> = resources.iter().map(|(name, resource)| (name.clone(), resource.get_id())).collect();
+        let mut permission_ids: HashMap<String, PermissionId> = permissions.iter().map(|(name, permission)| (name.clone(), permission.get_id())).collect();
+        let mut role_ids: HashMap<String, RoleId> = roles.iter().map(|(name, role)| (name.clone(), role.get_id())).collect();
+        let mut subject_ids: HashMap<String, SubjectId> = subjects.iter().map(|(name, subject)| (name.clone(), subject.get_id())).collect();
+        let mut group_ids: HashMap<String, GroupId> = groups.iter().map(|(name, group)| (name.clone(), group.get_id())).collect();
+        for entry in &seed.resources {
+            resource_ids.entry(entry.name.clone()).or_insert_with(|| ResourceId::for_name(&self.tenant_id, &entry.name));
+        }
+        for entry in &seed.permissions {
+            permission_ids.entry(entry.name.clone()).or_default();
+        }
+        for entry in &seed.roles {
+            role_ids.entry(entry.name.clone()).or_default();
+        }
+        for entry in &seed.subjects {
+            subject_ids.entry(entry.name.clone()).or_default();
+        }
+        for entry in &seed.groups {
+            group_ids.entry(entry.name.clone()).or_default();
+        }
+
+        // names by id, to describe changes to references
+        let mut names: HashMap<String, String> = HashMap::new();
+        names.extend(resource_ids.iter().map(|(name, id)| (id.clone().into(), name.clone())));
+        names.extend(permission_ids.iter().map(|(name, id)| (id.clone().into(), name.clone())));
+        names.extend(role_ids.iter().map(|(name, id)| (id.clone().into(), name.clone())));
+        names.extend(subject_ids.iter().map(|(name, id)| (id.clone().into(), name.clone())));
+        names.extend(group_ids.iter().map(|(name, id)| (id.clone().into(), name.clone())));
+        let name_of = |id: Option<String>| id.map(|id| names.get(&id).cloned().unwrap_or(id));
+
+        let now = Utc::now();
+        let mut plan = Plan::default();
+        // entities are shaped one change at a time, which may pass through states separation of duties
+        // forbids; the planned state is checked as a whole once every entity is shaped
+        let unchecked = SeparationOfDuties::default();
+
+        let mut parents: HashMap<ResourceId, Option<ResourceId>> = resources.values()
+            .map(|resource| (resource.get_id(), resource.get_parent_id()))
+            .collect();
+        let mut desired_resources: HashMap<String, Resource> = HashMap::new();
+        for entry in &seed.resources {
+            let owner = format!("resource {}", entry.name);
+            let current = resources.get(&entry.name);
+            let parent_id = entry.parent.as_ref()
+                .map(|parent| resolve(&resource_ids, &owner, "resource", parent))
+                .transpose()?;
+            let owner_id = entry.owner.as_ref()
+                .map(|subject| resolve(&subject_ids, &owner, "subject", subject))
+                .transpose()?;
+            let attributes = entry.attributes.clone()
+                .unwrap_or_else(|| current.map(Resource::get_attributes).unwrap_or_default());
+
+            let desired = Resource::builder()
+                .id(resource_ids[&entry.name].clone())
+                .tenant_id(self.tenant_id.clone())
+                .name(entry.name.clone())
+                .parent_id(parent_id.clone())
+                .owner_id(owner_id)
+                .attributes(attributes)
+                .build();
+            parents.insert(desired.get_id(), parent_id);
+
+            let mut details = Vec::new();
+            details.extend(describe_value(
+                "parent",
+                name_of(current.and_then(Resource::get_parent_id).map(String::from)),
+                name_of(desired.get_parent_id().map(String::from)),
+            ));
+            details.extend(describe_value(
+                "owner",
+                name_of(current.and_then(Resource::get_owner_id).map(String::from)),
+                name_of(desired.get_owner_id().map(String::from)),
+            ));
+            details.extend(describe_attributes(&current.map(Resource::get_attributes).unwrap_or_default(), &desired.get_attributes()));
+
+            plan.record("resource", &entry.name, current.is_some(), details, EntityChange::SaveResource(desired.clone()));
+            desired_resources.insert(entry.name.clone(), desired);
+        }
+        for entry in &seed.resources {
+            ensure_acyclic(&parents, &resource_ids[&entry.name], &entry.name)?;
+        }
+
+        for entry in &seed.permissions {
+            let owner = format!("permission {}", entry.name);
+            let current = permissions.get(&entry.name);
+            let resource = desired_resources.get(&entry.resource)
+                .or_else(|| resources.get(&entry.resource))
+                .ok_or_else(|| Error::Simple(format!("{} refers to unknown resource {}", owner, entry.resource)))?;
+
+            let desired = Permission::builder()
+                .id(permission_ids[&entry.name].clone())
+                .tenant_id(self.tenant_id.clone())
+                .name(entry.name.clone())
+                .operation(Operation::Invoke(resource.clone()))
+                .effect(entry.get_effect()?)
+                .grantee(entry.get_grantee()?)
+                .condition(entry.get_condition()?)
+                .created_at(current.map_or(now, Permission::get_created_at))
+                .updated_at(now)
+                .build();
+
+            let resource_of = |permission: &Permission| match permission.get_operation() {
+                Operation::Invoke(resource) => resource.get_name(),
+            };
+            let mut details = Vec::new();
+            details.extend(describe_value("resource", current.map(resource_of), Some(resource_of(&desired))));
+            details.extend(describe_value("effect", current.map(|permission| permission.get_effect().to_string()), Some(desired.get_effect().to_string())));
+            details.extend(describe_value("grantee", current.map(|permission| permission.get_grantee().to_string()), Some(desired.get_grantee().to_string())));
+            details.extend(describe_value(
+                "condition",
+                current.and_then(Permission::get_condition).map(|condition| condition.to_string()),
+                desired.get_condition().map(|condition| condition.to_string()),
+            ));
+
+            plan.record("permission", &entry.name, current.is_some(), details, EntityChange::SavePermission(desired));
+        }
+
+        for entry in &seed.roles {
+            let owner = format!("role {}", entry.name);
+            let current = roles.get(&entry.name);
+            let mut desired = current.cloned().unwrap_or_else(|| Role::builder()
+                .id(role_ids[&entry.name].clone())
+                .tenant_id(self.tenant_id.clone())
+                .name(entry.name.clone())
+                .permissions(HashSet::new())
+                .approvers(HashSet::new())
+                .created_at(now)
+                .updated_at(now)
+                .build());
+
+            if let Some(names) = &entry.permissions {
+                let wanted = resolve_all(&permission_ids, &owner, "permission", names)?;
+                for permission_id in desired.get_permissions().difference(&wanted) {
+                    desired.remove_permission(permission_id);
+                }
+                for permission_id in wanted.difference(&desired.get_permissions()) {
+                    desired.add_permission(permission_id.clone());
+                }
+            }
+            if let Some(names) = &entry.approvers {
+                let wanted = resolve_all(&subject_ids, &owner, "subject", names)?;
+                for subject_id in desired.get_approvers().difference(&wanted) {
+                    desired.remove_approver(subject_id);
+                }
+                for subject_id in wanted.difference(&desired.get_approvers()) {
+                    desired.add_approver(subject_id.clone());
+                }
+            }
+
+            let mut details = Vec::new();
+            details.extend(describe_set(
+                "permissions",
+                current.map(Role::get_permissions).unwrap_or_default().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+                desired.get_permissions().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+            ));
+            details.extend(describe_set(
+                "approvers",
+                current.map(Role::get_approvers).unwrap_or_default().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+                desired.get_approvers().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+            ));
+
+            plan.record("role", &entry.name, current.is_some(), details, EntityChange::SaveRole(desired));
+        }
+
+        for entry in &seed.subjects {
+            let owner = format!("subject {}", entry.name);
+            let current = subjects.get(&entry.name);
+            let mut desired = current.cloned()
+                .unwrap_or_else(|| Subject::with_id(subject_ids[&entry.name].clone(), self.tenant_id.clone(), &entry.name));
+
+            // assignments the seed keeps are left as they are, including their validity window
+            if let Some(names) = &entry.roles {
+                let wanted = resolve_all(&role_ids, &owner, "role", names)?;
+                for role_id in desired.get_roles().difference(&wanted) {
+                    desired.remove_role(role_id);
+                }
+                for role_id in wanted.difference(&desired.get_roles()) {
+                    desired.add_role(role_id.clone(), &unchecked)?;
+                }
+            }
+            if let Some(attributes) = &entry.attributes {
+                for name in desired.get_attributes().keys().filter(|name| !attributes.contains_key(*name)) {
+                    desired.remove_attribute(name);
+                }
+                for (name, value) in attributes {
+                    if desired.get_attributes().get(name) != Some(value) {
+                        desired.set_attribute(name, value.clone());
+                    }
+                }
+            }
+
+            let mut details = Vec::new();
+            details.extend(describe_set(
+                "roles",
+                current.map(Subject::get_roles).unwrap_or_default().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+                desired.get_roles().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+            ));
+            details.extend(describe_attributes(&current.map(Subject::get_attributes).unwrap_or_default(), &desired.get_attributes()));
+
+            plan.record("subject", &entry.name, current.is_some(), details, EntityChange::SaveSubject(desired));
+        }
+
+        let mut member_groups: HashMap<GroupId, HashSet<GroupId>> = stored_groups.iter()
+            .map(|group| (group.get_id(), group.get_groups().clone()))
+            .collect();
+        for entry in &seed.groups {
+            let owner = format!("group {}", entry.name);
+            let current = groups.get(&entry.name);
+            let mut desired = current.cloned()
+                .unwrap_or_else(|| Group::with_id(group_ids[&entry.name].clone(), self.tenant_id.clone(), &entry.name));
+
+            if let Some(names) = &entry.subjects {
+                let wanted = resolve_all(&subject_ids, &owner, "subject", names)?;
+                for subject_id in desired.get_subjects().clone().difference(&wanted) {
+                    desired.remove_subject(subject_id);
+                }
+                for subject_id in wanted.difference(&desired.get_subjects().clone()) {
+                    desired.add_subject(subject_id.clone(), &unchecked)?;
+                }
+            }
+            // cycles are looked for once every group is shaped
+            if let Some(names) = &entry.groups {
+                let wanted = resolve_all(&group_ids, &owner, "group", names)?;
+                for group_id in desired.get_groups().clone().difference(&wanted) {
+                    desired.remove_group(group_id);
+                }
+                for group_id in wanted.difference(&desired.get_groups().clone()) {
+                    desired.add_group(group_id.clone(), &[], &unchecked)?;
+                }
+            }
+            if let Some(names) = &entry.roles {
+                let wanted = resolve_all(&role_ids, &owner, "role", names)?;
+                for role_id in desired.get_roles().difference(&wanted) {
+                    desired.remove_role(role_id);
+                }
+                for role_id in wanted.difference(&desired.get_roles()) {
+                    desired.add_role(role_id.clone(), &unchecked)?;
+                }
+            }
+            member_groups.insert(desired.get_id(), desired.get_groups().clone());
+
+            let mut details = Vec::new();
+            details.extend(describe_set(
+                "subjects",
+                current.map(|group| group.get_subjects().clone()).unwrap_or_default().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+                desired.get_subjects().clone().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+            ));
+            details.extend(describe_set(
+                "groups",
+                current.map(|group| group.get_groups().clone()).unwrap_or_default().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+                desired.get_groups().clone().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+            ));
+            details.extend(describe_set(
+                "roles",
+                current.map(Group::get_roles).unwrap_or_default().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+                desired.get_roles().into_iter().map(|id| name_of(Some(id.into())).unwrap()),
+            ));
+
+            plan.record("group", &entry.name, current.is_some(), details, EntityChange::SaveGroup(desired));
+        }
+        for entry in &seed.groups {
+            ensure_no_cycle(&member_groups, &group_ids[&entry.name], &entry.name)?;
+        }
+
+        self.ensure_separation_of_duties(&plan, stored_roles, stored_subjects, stored_groups).await?;
+        Ok(plan)
+    }
+
+    // the store as it would be once the plan is applied
+    async fn ensure_separation_of_duties(
+        &self,
+        plan: &Plan,
+        roles: Vec<Role>,
+        subjects: Vec<Subject>,
+        groups: Vec<Group>,
+    ) -> Result<(), Error> {
+        let mut roles: HashMap<RoleId, Role> = roles.into_iter().map(|role| (role.get_id(), role)).collect();
+        let mut subjects: HashMap<SubjectId, Subject> = subjects.into_iter().map(|subject| (subject.get_id(), subject)).collect();
+        let mut groups: HashMap<GroupId, Group> = groups.into_iter().map(|group| (group.get_id(), group)).collect();
+        for write in &plan.writes {
+            match write {
+                EntityChange::SaveRole(role) => { roles.insert(role.get_id(), role.clone()); },
+                EntityChange::SaveSubject(subject) => { subjects.insert(subject.get_id(), subject.clone()); },
+                EntityChange::SaveGroup(group) => { groups.insert(group.get_id(), group.clone()); },
+                _ => {},
+            }
+        }
+
+        SeparationOfDuties::new(
+            self.sod_constraint_repository.find_all().await?,
+            &roles.into_values().collect::<Vec<_>>(),
+            &subjects.into_values().collect::<Vec<_>>(),
+            &groups.into_values().collect::<Vec<_>>(),
+        ).ensure_no_violations()
+    }
+}
+
+// expects entities oldest first, so that the oldest one keeps a repeated name
+fn by_name<T>(entities: Vec<T>, name: impl Fn(&T) -> String) -> HashMap<String, T> {
+    let mut by_name = HashMap::new();
+    for entity in entities {
+        by_name.entry(name(&entity)).or_insert(entity);
+    }
+    by_name
+}
+
+fn resolve<Id: Clone>(ids: &HashMap<String, Id>, owner: &str, kind: &str, name: &str) -> Result<Id, Error> {
+    ids.get(name)
+        .cloned()
+        .ok_or_else(|| Error::Simple(format!("{} refers to unknown {} {}", owner, kind, name)))
+}
+
+fn resolve_all<Id: Clone + Eq + std::hash::Hash>(ids: &HashMap<String, Id>, owner: &str, kind: &str, names: &[String]) -> Result<HashSet<Id>, Error> {
+    names.iter().map(|name| resolve(ids, owner, kind, name)).collect()
+}
+
+fn ensure_acyclic(parents: &HashMap<ResourceId, Option<ResourceId>>, resource_id: &ResourceId, name: &str) -> Result<(), Error> {
+    let mut seen = HashSet::new();
+    let mut current = Some(resource_id.clone());
+    while let Some(id) = current {
+        if !seen.insert(id.clone()) {
+            return Err(Error::Simple(format!("resource {} would become its own ancestor", name)));
+        }
+        current = parents.get(&id).cloned().flatten();
+    }
+    Ok(())
+}
+
+// whether the group can reach itself through its member groups
+fn ensure_no_cycle(member_groups: &HashMap<GroupId, HashSet<GroupId>>, group_id: &GroupId, name: &str) -> Result<(), Error> {
+    let mut seen = HashSet::new();
+    let mut pending: Vec<GroupId> = member_groups.get(group_id).into_iter().flatten().cloned().collect();
+    while let Some(id) = pending.pop() {
+        if &id == group_id {
+            return Err(Error::Simple(format!("group {} would become a member of itself", name)));
+        }
+        if seen.insert(id.clone()) {
+            pending.extend(member_groups.get(&id).into_iter().flatten().cloned());
+        }
+    }
+    Ok(())
+}
+
+fn describe_value(field: &str, current: Option<String>, desired: Option<String>) -> Option<String> {
+    if current == desired {
+        return None;
+    }
+    let none = || "none".to_string();
+    Some(format!("{}: {} -> {}", field, current.unwrap_or_else(none), desired.unwrap_or_else(none)))
+}
+
+fn describe_set(field: &str, current: impl IntoIterator<Item = String>, desired: impl IntoIterator<Item = String>) -> Option<String> {
+    let current: BTreeSet<String> = current.into_iter().collect();
+    let desired: BTreeSet<String> = desired.into_iter().collect();
+    let changes: Vec<String> = desired.difference(&current).map(|name| format!("+{}", name))
+        .chain(current.difference(&desired).map(|name| format!("-{}", name)))
+        .collect();
+    if changes.is_empty() {
+        return None;
+    }
+    Some(format!("{}: {}", field, changes.join(", ")))
+}
+
+fn describe_attributes(current: &Attributes, desired: &Attributes) -> Vec<String> {
+    let names: BTreeSet<&String> = current.keys().chain(desired.keys()).collect();
+    names.into_iter()
+        .filter_map(|name| describe_value(
+            &format!("attributes.{}", name),
+            current.get(name).map(|value| value.to_string()),
+            desired.get(name).map(|value| value.to_string()),
+        ))
+        .collect()
+}
@@ -0,0 +1,91 @@
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::seeds::{ChangeKind, SeedPlanner};
+use crate::domain::repositories::{ChangeFeed, SubjectRepository};
+use crate::domain::seeds::{Seed, SeedFormat};
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::outbox::SqliteChangeFeed;
+use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use crate::infrastructure::sqlite::resource::SqliteResourceRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+use crate::test_support::{empty_database, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: reports
+      - name: ledgers
+    permissions:
+      - name: read reports
+        resource: reports
+      - name: read ledgers
+        resource: ledgers
+    roles:
+      - name: analyst
+        permissions: [read reports]
+    subjects:
+      - name: alec leamas
+        roles: [analyst]
+    groups:
+      - name: the circus
+        subjects: [alec leamas]
+";
+
+fn seed_planner(connection_pool: &Pool<Sqlite>) -> SeedPlanner {
+    SeedPlanner::new(
+        tenant_id(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+fn seed(yaml: &str) -> Seed {
+    Seed::parse(yaml, SeedFormat::Yaml).unwrap()
+}
+
+#[async_std::test]
+async fn test_planning_writes_nothing_and_applying_twice_changes_nothing_the_second_time() {
+    let connection_pool = empty_database().await;
+    let planner = seed_planner(&connection_pool);
+
+    let plan = planner.plan(&seed(SEED)).await.unwrap();
+    assert_eq!(plan.get_changes().len(), 7);
+    assert!(plan.get_changes().iter().all(|change| change.kind == ChangeKind::Create));
+    assert!(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).find_all().await.unwrap().is_empty());
+
+    assert_eq!(planner.apply(&seed(SEED)).await.unwrap().get_changes().len(), 7);
+    let change_feed = SqliteChangeFeed::new(connection_pool.clone(), tenant_id());
+    let head = change_feed.get_head_position().await.unwrap();
+
+    let replanned = planner.plan(&seed(SEED)).await.unwrap();
+    assert!(replanned.is_empty());
+    assert_eq!(replanned.to_string(), "no changes\n");
+    assert!(planner.apply(&seed(SEED)).await.unwrap().is_empty());
+    assert_eq!(change_feed.get_head_position().await.unwrap(), head);
+}
+
+#[async_std::test]
+async fn test_plans_against_a_seeded_store_list_only_what_differs() {
+    let connection_pool = empty_database().await;
+    let planner = seed_planner(&connection_pool);
+    planner.apply(&seed(SEED)).await.unwrap();
+
+    let changed = SEED.replace("permissions: [read reports]", "permissions: [read reports, read ledgers]");
+    let plan = planner.apply(&seed(&changed)).await.unwrap();
+    let changes = plan.get_changes();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].kind, ChangeKind::Update);
+    assert_eq!((changes[0].entity.as_str(), changes[0].name.as_str()), ("role", "analyst"));
+    assert_eq!(changes[0].details, vec!["permissions: +read ledgers".to_string()]);
+    assert_eq!(plan.to_string(), "~ role analyst\n    permissions: +read ledgers\n0 to create, 1 to update\n");
+
+    assert!(planner.plan(&seed(&changed)).await.unwrap().is_empty());
+}
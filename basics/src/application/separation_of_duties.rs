@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+
+use crate::domain::repositories::{Error, GroupRepository, RoleRepository, SodConstraintRepository, SubjectRepository};
+use crate::domain::roles::RoleId;
+use crate::domain::separation_of_duties::{SeparationOfDuties, SodConstraint, SodConstraintId, SodKind};
+use crate::domain::tenants::TenantId;
+
+// reads what `SeparationOfDuties` needs from the store. services load it right before changing
+// subjects or groups, so that the check sees the latest assignments and can tell which change breaks
+// which constraint. the store judges the assignments once more as it writes them, which also catches
+// changes that only break a constraint together with one committed in the meantime
+pub struct DutiesLoader {
+    sod_constraint_repository: Box<dyn SodConstraintRepository + Send + Sync>,
+    role_repository: Box<dyn RoleRepository + Send + Sync>,
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+}
+
+impl DutiesLoader {
+    pub fn new(
+        sod_constraint_repository: Box<dyn SodConstraintRepository + Send + Sync>,
+        role_repository: Box<dyn RoleRepository + Send + Sync>,
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+    ) -> DutiesLoader {
+        DutiesLoader {
+            sod_constraint_repository,
+            role_repository,
+            subject_repository,
+            group_repository,
+        }
+    }
+
+    pub async fn load(&self) -> Result<SeparationOfDuties, Error> {
+        let constraints = self.sod_constraint_repository.find_all().await?;
+        self.load_for(constraints).await
+    }
+
+    // the current assignments judged by the given constraints instead of the stored ones
+    pub async fn load_for(&self, constraints: Vec<SodConstraint>) -> Result<SeparationOfDuties, Error> {
+        Ok(SeparationOfDuties::new(
+            constraints,
+            &self.role_repository.find_all().await?,
+            &self.subject_repository.find_all().await?,
+            &self.group_repository.find_all().await?,
+        ))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateSodConstraintRequest {
+    pub name: String,
+    pub kind: SodKind,
+    pub role_ids: HashSet<RoleId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateSodConstraintResponse {
+    pub sod_constraint_id: SodConstraintId,
+}
+
+#[async_trait]
+pub trait SodConstraintService {
+    async fn create_constraint(&self, req: CreateSodConstraintRequest) -> Result<CreateSodConstraintResponse, Error>;
+}
+
+pub struct SodConstraintServiceImpl {
+    tenant_id: TenantId,
+    sod_constraint_repository: Box<dyn SodConstraintRepository + Send + Sync>,
+    duties_loader: DutiesLoader,
+}
+
+impl SodConstraintServiceImpl {
+    pub fn new(
+        tenant_id: TenantId,
+        sod_constraint_repository: Box<dyn SodConstraintRepository + Send + Sync>,
+        duties_loader: DutiesLoader,
+    ) -> Self {
+        SodConstraintServiceImpl {
+            tenant_id,
+            sod_constraint_repository,
+            duties_loader,
+        }
+    }
+}
+
+#[async_trait]
+impl SodConstraintService for SodConstraintServiceImpl {
+    // constraint names are unique. a static constraint that subjects already break is refused, naming the first subject that breaks it;
+    // dynamic constraints only ever apply to sessions, which are checked when they are used
+    async fn create_constraint(&self, req: CreateSodConstraintRequest) -> Result<CreateSodConstraintResponse, Error> {
+        if self.sod_constraint_repository.get_by_name(&req.name).await?.is_some() {
+            return Err(Error::Simple(format!("separation of duties constraint {} already exists", req.name)));
+        }
+        let constraint = SodConstraint::new(self.tenant_id.clone(), &req.name, req.kind, req.role_ids)?;
+
+        if constraint.get_kind() == SodKind::Static {
+            let mut constraints = self.sod_constraint_repository.find_all().await?;
+            constraints.push(constraint.clone());
+            self.duties_loader.load_for(constraints).await?.ensure_no_violations()?;
+        }
+        self.sod_constraint_repository.save(constraint.clone()).await?;
+
+        Ok(CreateSodConstraintResponse {
+            sod_constraint_id: constraint.get_id(),
+        })
+    }
+}
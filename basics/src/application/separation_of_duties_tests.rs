@@ -0,0 +1,203 @@
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::groups::{AddGroupRoleRequest, AddGroupSubjectRequest, CreateGroupRequest, GroupMemberGroupRequest, GroupService, GroupServiceImpl};
+use crate::application::separation_of_duties::{CreateSodConstraintRequest, DutiesLoader, SodConstraintService, SodConstraintServiceImpl};
+use crate::application::sessions::{SessionRoleRequest, SessionService, SessionServiceImpl, StartSessionRequest};
+use crate::domain::groups::{Group, GroupId};
+use crate::domain::repositories::{EntityChange, GroupRepository, Repository, RoleRepository, SubjectRepository, UnitOfWork};
+use crate::domain::roles::RoleId;
+use crate::domain::separation_of_duties::{SeparationOfDuties, SodKind};
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::session::SqliteSessionRepository;
+use crate::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+use crate::test_support::{seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    roles:
+      - name: initiate payments
+      - name: approve payments
+      - name: draft cheques
+      - name: sign cheques
+    subjects:
+      - name: alec leamas
+        roles: [initiate payments]
+      - name: george smiley
+      - name: karla
+        roles: [draft cheques, sign cheques]
+    groups:
+      - name: approvers
+        subjects: [george smiley]
+        roles: [approve payments]
+";
+
+fn duties_loader(connection_pool: &Pool<Sqlite>) -> DutiesLoader {
+    DutiesLoader::new(
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+async fn role_id(connection_pool: &Pool<Sqlite>, name: &str) -> RoleId {
+    SqliteRoleRepository::new(connection_pool.clone(), tenant_id()).get_by_name(name).await.unwrap().unwrap().get_id()
+}
+
+fn group_service(connection_pool: &Pool<Sqlite>) -> GroupServiceImpl {
+    GroupServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        duties_loader(connection_pool),
+    )
+}
+
+async fn group_id(connection_pool: &Pool<Sqlite>, name: &str) -> GroupId {
+    SqliteGroupRepository::new(connection_pool.clone(), tenant_id())
+        .find_all()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|group| group.get_name() == name)
+        .unwrap()
+        .get_id()
+}
+
+// payments may not be initiated and approved by the same subject, and cheques may be drafted and signed
+// by the same subject but not in the same session
+async fn constrained_database() -> Pool<Sqlite> {
+    let connection_pool = seeded_database(SEED).await;
+    let constraint_service = SodConstraintServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+        duties_loader(&connection_pool),
+    );
+    constraint_service.create_constraint(CreateSodConstraintRequest {
+        name: "four eyes".to_string(),
+        kind: SodKind::Static,
+        role_ids: [role_id(&connection_pool, "initiate payments").await, role_id(&connection_pool, "approve payments").await].into(),
+    }).await.unwrap();
+    constraint_service.create_constraint(CreateSodConstraintRequest {
+        name: "one pen".to_string(),
+        kind: SodKind::Dynamic,
+        role_ids: [role_id(&connection_pool, "draft cheques").await, role_id(&connection_pool, "sign cheques").await].into(),
+    }).await.unwrap();
+    connection_pool
+}
+
+#[async_std::test]
+async fn test_groups_cannot_grant_what_a_constraint_keeps_apart() {
+    let connection_pool = constrained_database().await;
+    let group_service = group_service(&connection_pool);
+    let alec_leamas = subject_id(&connection_pool, "alec leamas").await;
+    let approvers = group_id(&connection_pool, "approvers").await;
+
+    let error = group_service.add_subject(AddGroupSubjectRequest {
+        group_id: approvers.clone(),
+        subject_id: alec_leamas.clone(),
+    }).await.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "subject alec leamas cannot hold approve payments (through group approvers) and initiate payments (directly): \
+         static separation of duties constraint four eyes allows at most one of approve payments, initiate payments",
+    );
+
+    // alec may join a group without roles, but that group may then not join the approvers
+    let trainees = group_service.create_group(CreateGroupRequest { name: "trainees".to_string() }).await.unwrap().group_id;
+    group_service.add_subject(AddGroupSubjectRequest {
+        group_id: trainees.clone(),
+        subject_id: alec_leamas,
+    }).await.unwrap();
+    let error = group_service.add_group(GroupMemberGroupRequest {
+        group_id: approvers.clone(),
+        member_group_id: trainees.clone(),
+    }).await.unwrap_err();
+    assert!(error.to_string().contains("(through group approvers)"), "{}", error);
+
+    // nor may the approvers join a group that initiates payments, or be given that role themselves
+    let payments = group_service.create_group(CreateGroupRequest { name: "payments".to_string() }).await.unwrap().group_id;
+    group_service.add_role(AddGroupRoleRequest {
+        group_id: payments.clone(),
+        role_id: role_id(&connection_pool, "initiate payments").await,
+    }).await.unwrap();
+    let error = group_service.add_group(GroupMemberGroupRequest {
+        group_id: payments,
+        member_group_id: approvers.clone(),
+    }).await.unwrap_err();
+    assert!(error.to_string().starts_with("subject george smiley cannot hold"), "{}", error);
+    let error = group_service.add_role(AddGroupRoleRequest {
+        group_id: approvers,
+        role_id: role_id(&connection_pool, "initiate payments").await,
+    }).await.unwrap_err();
+    assert!(error.to_string().contains("four eyes"), "{}", error);
+}
+
+#[async_std::test]
+async fn test_a_session_uses_at_most_one_of_the_roles_a_dynamic_constraint_keeps_apart() {
+    let connection_pool = constrained_database().await;
+    let session_service = SessionServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteSessionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        duties_loader(&connection_pool),
+    );
+    let draft_cheques = role_id(&connection_pool, "draft cheques").await;
+    let sign_cheques = role_id(&connection_pool, "sign cheques").await;
+    let session_id = session_service.start_session(StartSessionRequest {
+        subject_id: subject_id(&connection_pool, "karla").await,
+    }).await.unwrap().session_id;
+    let request = |role_id: &RoleId| SessionRoleRequest {
+        session_id: session_id.clone(),
+        role_id: role_id.clone(),
+    };
+
+    let response = session_service.activate_role(request(&draft_cheques)).await.unwrap();
+    assert_eq!(response.active_roles, vec![draft_cheques.clone()]);
+    let error = session_service.activate_role(request(&sign_cheques)).await.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "subject karla cannot use draft cheques and sign cheques in the same session: \
+         dynamic separation of duties constraint one pen allows at most one of draft cheques, sign cheques",
+    );
+
+    session_service.deactivate_role(request(&draft_cheques)).await.unwrap();
+    let response = session_service.activate_role(request(&sign_cheques)).await.unwrap();
+    assert_eq!(response.active_roles, vec![sign_cheques]);
+
+    // another session of the same subject is judged on its own
+    let other_session_id = session_service.start_session(StartSessionRequest {
+        subject_id: subject_id(&connection_pool, "karla").await,
+    }).await.unwrap().session_id;
+    session_service.activate_role(SessionRoleRequest {
+        session_id: other_session_id,
+        role_id: draft_cheques,
+    }).await.unwrap();
+}
+
+#[async_std::test]
+async fn test_the_store_refuses_grants_that_break_a_constraint_together() {
+    let connection_pool = constrained_database().await;
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id());
+    let group_repository = SqliteGroupRepository::new(connection_pool.clone(), tenant_id());
+    let alec_leamas = subject_repository.get_by_name("alec leamas").await.unwrap().unwrap();
+
+    // stands in for a service that checked the duties before alec's grant was committed
+    let mut approvers: Group = group_repository.find_all().await.unwrap().remove(0);
+    approvers.add_subject(alec_leamas.get_id(), &SeparationOfDuties::default()).unwrap();
+    let error = group_repository.save(approvers.clone()).await.unwrap_err();
+    assert!(error.to_string().contains("four eyes"), "{}", error);
+
+    let error = SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())
+        .commit(vec![EntityChange::SaveGroup(approvers)])
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("four eyes"), "{}", error);
+    assert!(!group_repository.find_all().await.unwrap()[0].get_subjects().contains(&alec_leamas.get_id()));
+
+    let mut george_smiley = subject_repository.get_by_name("george smiley").await.unwrap().unwrap();
+    george_smiley.add_role(role_id(&connection_pool, "initiate payments").await, &SeparationOfDuties::default()).unwrap();
+    assert!(subject_repository.save(george_smiley).await.is_err());
+}
@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+
+use crate::application::separation_of_duties::DutiesLoader;
+use crate::domain::repositories::{Error, Repository};
+use crate::domain::roles::RoleId;
+use crate::domain::sessions::{Session, SessionId};
+use crate::domain::subjects::{Subject, SubjectId};
+use crate::domain::tenants::TenantId;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartSessionRequest {
+    pub subject_id: SubjectId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartSessionResponse {
+    pub session_id: SessionId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionRoleRequest {
+    pub session_id: SessionId,
+    pub role_id: RoleId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionRoleResponse {
+    pub active_roles: Vec<RoleId>,
+}
+
+#[async_trait]
+pub trait SessionService {
+    async fn start_session(&self, req: StartSessionRequest) -> Result<StartSessionResponse, Error>;
+    async fn activate_role(&self, req: SessionRoleRequest) -> Result<SessionRoleResponse, Error>;
+    async fn deactivate_role(&self, req: SessionRoleRequest) -> Result<SessionRoleResponse, Error>;
+}
+
+pub struct SessionServiceImpl {
+    tenant_id: TenantId,
+    session_repository: Box<dyn Repository<SessionId, Session> + Send + Sync>,
+    subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+    duties_loader: DutiesLoader,
+}
+
+impl SessionServiceImpl {
+    pub fn new(
+        tenant_id: TenantId,
+        session_repository: Box<dyn Repository<SessionId, Session> + Send + Sync>,
+        subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+        duties_loader: DutiesLoader,
+    ) -> Self {
+        SessionServiceImpl {
+            tenant_id,
+            session_repository,
+            subject_repository,
+            duties_loader,
+        }
+    }
+
+    async fn get_session(&self, session_id: SessionId) -> Result<Session, Error> {
+        self.session_repository.get_by_id(session_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("session {} not found", String::from(session_id))))
+    }
+
+    async fn get_subject(&self, subject_id: SubjectId) -> Result<Subject, Error> {
+        let subject = self.subject_repository.get_by_id(subject_id.clone())
+            .await?
+            .ok_or_else(|| Error::Simple(format!("subject {} not found", String::from(subject_id.clone()))))?;
+
+        if subject.get_deleted_at().is_some() {
+            return Err(Error::Simple(format!("subject {} is deleted", String::from(subject_id))));
+        }
+        Ok(subject)
+    }
+}
+
+#[async_trait]
+impl SessionService for SessionServiceImpl {
+    async fn start_session(&self, req: StartSessionRequest) -> Result<StartSessionResponse, Error> {
+        let subject = self.get_subject(req.subject_id).await?;
+        let session = Session::new(self.tenant_id.clone(), subject.get_id());
+        self.session_repository.save(session.clone()).await?;
+
+        Ok(StartSessionResponse {
+            session_id: session.get_id(),
+        })
+    }
+
+    async fn activate_role(&self, req: SessionRoleRequest) -> Result<SessionRoleResponse, Error> {
+        let mut session = self.get_session(req.session_id).await?;
+        let subject = self.get_subject(session.get_subject_id()).await?;
+        let duties = self.duties_loader.load().await?;
+        session.activate_role(&subject, req.role_id, &duties)?;
+        self.session_repository.save(session.clone()).await?;
+
+        Ok(SessionRoleResponse {
+            active_roles: session.get_active_roles().into_iter().collect(),
+        })
+    }
+
+    async fn deactivate_role(&self, req: SessionRoleRequest) -> Result<SessionRoleResponse, Error> {
+        let mut session = self.get_session(req.session_id).await?;
+        session.deactivate_role(&req.role_id);
+        self.session_repository.save(session.clone()).await?;
+
+        Ok(SessionRoleResponse {
+            active_roles: session.get_active_roles().into_iter().collect(),
+        })
+    }
+}
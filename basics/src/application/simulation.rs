@@ -0,0 +1,430 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use crate::application::access_checker::AccessChecker;
+use crate::application::overlay::{
+    Overlay, OverlayDelegationRepository, OverlayGroupRepository, OverlayPermissionRepository,
+    OverlayResourceRepository, OverlayRoleRepository, OverlaySodConstraintRepository, OverlaySubjectRepository,
+};
+use crate::application::resources::resolve_ancestry;
+use crate::domain::conditions::RequestContext;
+use crate::domain::repositories::{
+    DelegationRepository, EntityChange, Error, GroupRepository, PermissionRepository, Repository, ResourceRepository,
+    RoleRepository, SodConstraintRepository, SubjectRepository,
+};
+use crate::domain::operations::Operation::Invoke;
+use crate::domain::permissions::{Grantee, Permission, PermissionId};
+use crate::domain::resources::{Resource, ResourceId};
+use crate::domain::subjects::{Subject, SubjectId};
+use crate::domain::tenants::TenantId;
+
+#[derive(Debug)]
+pub struct SimulateRequest {
+    // applied in order, as a unit of work would commit them
+    pub changes: Vec<EntityChange>,
+    pub context: RequestContext,
+    // the subjects and the resources to check, each defaulting to those the changes could reach
+    pub subject_ids: Option<Vec<SubjectId>>,
+    pub resource_ids: Option<Vec<ResourceId>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionFlip {
+    pub subject_id: SubjectId,
+    pub subject_name: String,
+    pub resource_id: ResourceId,
+    pub resource_name: String,
+    pub operation: String,
+    pub before: bool,
+    pub after: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SimulateResponse {
+    // how many subject and resource pairs were checked before and after the changes
+    pub checked: usize,
+    // sorted by subject name, then resource name
+    pub flips: Vec<DecisionFlip>,
+}
+
+#[async_trait]
+pub trait SimulationService {
+    // checks subjects against resources with and without the changes, and tells which decisions the
+    // changes would flip. nothing is written: the changes only ever live in an overlay. unless the request
+    // names them, the subjects checked are those the changes touch, the members of groups they touch,
+    // the holders of roles and permissions they touch and the delegates of all of these, and the
+    // resources checked are those such subjects reach through their roles or own, those of permissions
+    // the changes touch and those the changes save or delete, along with everything beneath them.
+    // a change to a resource is checked against every subject
+    async fn simulate(&self, req: SimulateRequest) -> Result<SimulateResponse, Error>;
+}
+
+pub struct SimulationServiceImpl {
+    tenant_id: TenantId,
+    subject_repository: Arc<dyn SubjectRepository + Send + Sync>,
+    group_repository: Arc<dyn GroupRepository + Send + Sync>,
+    role_repository: Arc<dyn RoleRepository + Send + Sync>,
+    permission_repository: Arc<dyn PermissionRepository + Send + Sync>,
+    resource_repository: Arc<dyn ResourceRepository + Send + Sync>,
+    sod_constraint_repository: Arc<dyn SodConstraintRepository + Send + Sync>,
+    delegation_repository: Arc<dyn DelegationRepository + Send + Sync>,
+}
+
+// the subjects and resources whose decisions the changes could flip
+#[derive(Default)]
+struct Scope {
+    subject_ids: HashSet<SubjectId>,
+    resource_ids: HashSet<ResourceId>,
+    every_subject: bool,
+}
+
+// what the repositories of one state hold, and a checker that reads them
+struct State {
+    access_checker: AccessChecker,
+    subject_repository: OverlaySubjectRepository,
+    role_repository: OverlayRoleRepository,
+    permission_repository: OverlayPermissionRepository,
+    resource_repository: OverlayResourceRepository,
+    group_repository: OverlayGroupRepository,
+    delegation_repository: OverlayDelegationRepository,
+}
+
+impl SimulationServiceImpl {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        tenant_id: TenantId,
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+        role_repository: Box<dyn RoleRepository + Send + Sync>,
+        permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+        sod_constraint_repository: Box<dyn SodConstraintRepository + Send + Sync>,
+        delegation_repository: Box<dyn DelegationRepository + Send + Sync>,
+    ) -> Self {
+        SimulationServiceImpl {
+            tenant_id,
+            subject_repository: Arc::from(subject_repository),
+            group_repository: Arc::from(group_repository),
+            role_repository: Arc::from(role_repository),
+            permission_repository: Arc::from(permission_repository),
+            resource_repository: Arc::from(resource_repository),
+            sod_constraint_repository: Arc::from(sod_constraint_repository),
+            delegation_repository: Arc::from(delegation_repository),
+        }
+    }
+
+    fn state(&self, changes: Vec<EntityChange>) -> State {
+        let overlay = Arc::new(RwLock::new(Overlay::new(changes)));
+        let subjects = || OverlaySubjectRepository::new(self.subject_repository.clone(), overlay.clone());
+        let roles = || OverlayRoleRepository::new(self.role_repository.clone(), overlay.clone());
+        let permissions = || OverlayPermissionRepository::new(self.permission_repository.clone(), overlay.clone());
+        let resources = || OverlayResourceRepository::new(self.resource_repository.clone(), overlay.clone());
+        let groups = || OverlayGroupRepository::new(self.group_repository.clone(), overlay.clone());
+
+        State {
+            access_checker: AccessChecker::new(
+                self.tenant_id.clone(),
+                Box::new(subjects()),
+                Box::new(groups()),
+                Box::new(roles()),
+                Box::new(permissions()),
+                Box::new(resources()),
+                Box::new(OverlaySodConstraintRepository::new(self.sod_constraint_repository.clone())),
+                Box::new(OverlayDelegationRepository::new(self.delegation_repository.clone())),
+            ),
+            subject_repository: subjects(),
+            role_repository: roles(),
+            permission_repository: permissions(),
+            resource_repository: resources(),
+            group_repository: groups(),
+            delegation_repository: OverlayDelegationRepository::new(self.delegation_repository.clone()),
+        }
+    }
+
+    // the changes must belong to the tenant
+    fn ensure_tenant(&self, changes: &[EntityChange]) -> Result<(), Error> {
+        for change in changes {
+            let (kind, name, tenant_id) = match change {
+                EntityChange::SaveResource(resource) => ("resource", resource.get_name(), resource.get_tenant_id()),
+                EntityChange::SavePermission(permission) => ("permission", permission.get_name(), permission.get_tenant_id()),
+                EntityChange::SaveRole(role) => ("role", role.get_name(), role.get_tenant_id()),
+                EntityChange::SaveSubject(subject) | EntityChange::RestoreSubject(subject) => ("subject", subject.get_name(), subject.get_tenant_id()),
+                EntityChange::SaveGroup(group) => ("group", group.get_name(), group.get_tenant_id()),
+                _ => continue,
+            };
+            if tenant_id != self.tenant_id {
+                return Err(Error::Simple(format!(
+                    "{} {} belongs to another tenant than {}",
+                    kind, name, String::from(self.tenant_id.clone()),
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    // the live subjects and every resource, by id
+    async fn load(&self) -> Result<(HashMap<SubjectId, Subject>, HashMap<ResourceId, Resource>), Error> {
+        let subjects = self.subject_repository.find_all().await?
+            .into_iter()
+            .filter(|subject| subject.get_deleted_at().is_none())
+            .map(|subject| (subject.get_id(), subject))
+            .collect();
+        let resources = self.resource_repository.find_all().await?
+            .into_iter()
+            .map(|resource| (resource.get_id(), resource))
+            .collect();
+        Ok((subjects, resources))
+    }
+
+    // the checker takes the references it follows for granted, as committed changes would have had
+    // them checked; changes that were never committed are checked here instead
+    async fn ensure_references(&self, subjects: &HashMap<SubjectId, Subject>, resources: &HashMap<ResourceId, Resource>) -> Result<(), Error> {
+        for subject in subjects.values() {
+            for role_id in subject.get_roles() {
+                if self.role_repository.get_by_id(role_id.clone()).await?.is_none() {
+                    return Err(Error::Simple(format!("subject {} refers to role {} which does not exist", subject.get_name(), String::from(role_id))));
+                }
+            }
+        }
+        for group in self.group_repository.find_all().await? {
+            for role_id in group.get_roles() {
+                if self.role_repository.get_by_id(role_id.clone()).await?.is_none() {
+                    return Err(Error::Simple(format!("group {} refers to role {} which does not exist", group.get_name(), String::from(role_id))));
+                }
+            }
+        }
+        for role in self.role_repository.find_all().await? {
+            for permission_id in role.get_permissions() {
+                if self.permission_repository.get_by_id(permission_id.clone()).await?.is_none() {
+                    return Err(Error::Simple(format!("role {} refers to permission {} which does not exist", role.get_name(), String::from(permission_id))));
+                }
+            }
+        }
+        for resource_id in resources.keys() {
+            resolve_ancestry(&self.resource_repository, resource_id.clone()).await?;
+        }
+        Ok(())
+    }
+
+    // adds what the changes could reach in this state to the scope. roles, permissions and groups are
+    // looked at as they are in this state, so that running it on the states before and after the changes
+    // covers what the changes take away as well as what they grant
+    async fn reach(
+        &self,
+        changes: &[EntityChange],
+        subjects: &HashMap<SubjectId, Subject>,
+        resources: &HashMap<ResourceId, Resource>,
+        scope: &mut Scope,
+    ) -> Result<(), Error> {
+        let roles = self.role_repository.find_all().await?;
+        let permissions: HashMap<PermissionId, Permission> = self.permission_repository.find_all().await?
+            .into_iter()
+            .map(|permission| (permission.get_id(), permission))
+            .collect();
+        let groups = self.group_repository.find_all().await?;
+        let granted_on = |permission_id: &PermissionId| permissions.get(permission_id).map(|permission| match permission.get_operation() {
+            Invoke(resource) => resource.get_id(),
+        });
+
+        let mut touched_subjects = HashSet::new();
+        let mut touched_roles = HashSet::new();
+        let mut touched_permissions = HashSet::new();
+        for change in changes {
+            match change {
+                EntityChange::SaveSubject(subject) | EntityChange::RestoreSubject(subject) => {
+                    touched_subjects.insert(subject.get_id());
+                },
+                EntityChange::DeleteSubject(id) => {
+                    touched_subjects.insert(id.clone());
+                },
+                EntityChange::SaveGroup(group) => {
+                    touched_subjects.extend(self.group_repository.find_members(group.get_id()).await?);
+                },
+                EntityChange::DeleteGroup(id) => {
+                    touched_subjects.extend(self.group_repository.find_members(id.clone()).await?);
+                },
+                EntityChange::SaveRole(role) => {
+                    touched_roles.insert(role.get_id());
+                },
+                EntityChange::DeleteRole(id) => {
+                    touched_roles.insert(id.clone());
+                },
+                EntityChange::SavePermission(permission) => {
+                    touched_permissions.insert(permission.get_id());
+                },
+                EntityChange::DeletePermission(id) => {
+                    touched_permissions.insert(id.clone());
+                },
+                EntityChange::SaveResource(resource) => {
+                    scope.resource_ids.insert(resource.get_id());
+                    scope.every_subject = true;
+                },
+                EntityChange::DeleteResource(id) => {
+                    scope.resource_ids.insert(id.clone());
+                    scope.every_subject = true;
+                },
+                EntityChange::SaveAccessRequest(_) | EntityChange::AppendAuditRecord(_) => {},
+            }
+        }
+
+        // a touched subject may see any decision it reaches through its roles or ownership flip
+        for subject_id in touched_subjects {
+            if let Some(subject) = subjects.get(&subject_id) {
+                let mut held = subject.get_roles();
+                for group in self.group_repository.find_by_subject_transitively(subject_id.clone()).await? {
+                    held.extend(group.get_roles());
+                }
+                for role in roles.iter().filter(|role| held.contains(&role.get_id())) {
+                    scope.resource_ids.extend(role.get_permissions().iter().filter_map(granted_on));
+                }
+                scope.resource_ids.extend(resources.values()
+                    .filter(|resource| resource.is_owned_by(&subject_id))
+                    .map(|resource| resource.get_id()));
+            }
+            scope.subject_ids.insert(subject_id);
+        }
+
+        // roles that include a touched permission are touched along with it
+        for permission_id in &touched_permissions {
+            scope.resource_ids.extend(granted_on(permission_id));
+            if permissions.get(permission_id).is_some_and(|permission| permission.get_grantee() == Grantee::Owner) {
+                scope.subject_ids.extend(resources.values().filter_map(|resource| resource.get_owner_id()));
+            }
+        }
+        for role in &roles {
+            if touched_roles.contains(&role.get_id()) {
+                scope.resource_ids.extend(role.get_permissions().iter().filter_map(granted_on));
+            } else if role.get_permissions().iter().any(|permission_id| touched_permissions.contains(permission_id)) {
+                touched_roles.insert(role.get_id());
+            }
+        }
+        for subject in subjects.values() {
+            if subject.get_roles().iter().any(|role_id| touched_roles.contains(role_id)) {
+                scope.subject_ids.insert(subject.get_id());
+            }
+        }
+        for group in groups.iter().filter(|group| group.get_roles().iter().any(|role_id| touched_roles.contains(role_id))) {
+            scope.subject_ids.extend(self.group_repository.find_members(group.get_id()).await?);
+        }
+
+        // delegates are only let in where their delegators are
+        let now = Utc::now();
+        for subject_id in subjects.keys() {
+            for delegation in self.delegation_repository.find_active_for_delegate(subject_id.clone(), now).await? {
+                if scope.subject_ids.contains(&delegation.get_delegator_id()) {
+                    scope.subject_ids.insert(subject_id.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // subjects that do not exist in the state are not let in anywhere
+    async fn decide(&self, subject: Option<&Subject>, resource_id: &ResourceId, context: &RequestContext) -> Result<bool, Error> {
+        match subject {
+            Some(subject) => Ok(self.access_checker.evaluate_with_delegations(subject, resource_id.clone(), context).await?.allowed),
+            None => Ok(false),
+        }
+    }
+}
+
+#[async_trait]
+impl SimulationService for SimulationServiceImpl {
+    async fn simulate(&self, req: SimulateRequest) -> Result<SimulateResponse, Error> {
+        self.ensure_tenant(&req.changes)?;
+
+        let before = self.state(Vec::new());
+        let after = self.state(req.changes.clone());
+        let (subjects_before, resources_before) = before.load().await?;
+        let (subjects_after, resources_after) = after.load().await?;
+        after.ensure_references(&subjects_after, &resources_after).await?;
+
+        let mut scope = Scope::default();
+        before.reach(&req.changes, &subjects_before, &resources_before, &mut scope).await?;
+        after.reach(&req.changes, &subjects_after, &resources_after, &mut scope).await?;
+        with_descendants(&mut scope.resource_ids, &resources_before);
+        with_descendants(&mut scope.resource_ids, &resources_after);
+
+        let known_subject = |subject_id: &SubjectId| subjects_before.contains_key(subject_id) || subjects_after.contains_key(subject_id);
+        let subject_ids = match req.subject_ids {
+            Some(subject_ids) => match subject_ids.iter().find(|subject_id| !known_subject(subject_id)) {
+                Some(unknown) => return Err(Error::Simple(format!("subject {} not found", String::from(unknown.clone())))),
+                None => subject_ids,
+            },
+            None if scope.every_subject => subjects_before.keys().chain(subjects_after.keys()).cloned().collect(),
+            None => scope.subject_ids.into_iter().filter(known_subject).collect(),
+        };
+        let known_resource = |resource_id: &ResourceId| resources_before.contains_key(resource_id) || resources_after.contains_key(resource_id);
+        let resource_ids = match req.resource_ids {
+            Some(resource_ids) => match resource_ids.iter().find(|resource_id| !known_resource(resource_id)) {
+                Some(unknown) => return Err(Error::Simple(format!("resource {} not found", String::from(unknown.clone())))),
+                None => resource_ids,
+            },
+            None => scope.resource_ids.into_iter().filter(known_resource).collect(),
+        };
+        let subject_ids = sorted(subject_ids);
+        let resource_ids = sorted(resource_ids);
+
+        let mut checked = 0;
+        let mut flips = Vec::new();
+        for subject_id in &subject_ids {
+            let subject_before = subjects_before.get(subject_id);
+            let subject_after = subjects_after.get(subject_id);
+            for resource_id in &resource_ids {
+                let allowed_before = before.decide(subject_before, resource_id, &req.context).await?;
+                let allowed_after = after.decide(subject_after, resource_id, &req.context).await?;
+                checked += 1;
+                if allowed_before == allowed_after {
+                    continue;
+                }
+
+                let subject = subject_after.or(subject_before).unwrap();
+                let resource = resources_after.get(resource_id).or_else(|| resources_before.get(resource_id)).unwrap();
+                flips.push(DecisionFlip {
+                    subject_id: subject.get_id(),
+                    subject_name: subject.get_name(),
+                    resource_id: resource.get_id(),
+                    resource_name: resource.get_name(),
+                    operation: "invoke".to_string(),
+                    before: allowed_before,
+                    after: allowed_after,
+                });
+            }
+        }
+        flips.sort_by(|a, b| (&a.subject_name, &a.resource_name).cmp(&(&b.subject_name, &b.resource_name)));
+
+        Ok(SimulateResponse {
+            checked,
+            flips,
+        })
+    }
+}
+
+// ids in the order the checks run in, each once
+fn sorted<T: Clone + Into<String>>(ids: impl IntoIterator<Item = T>) -> Vec<T> {
+    let mut ids: Vec<T> = ids.into_iter().collect();
+    ids.sort_by_key(|id| id.clone().into());
+    ids.dedup_by_key(|id| id.clone().into());
+    ids
+}
+
+// adds the resources beneath those in the set, as they inherit what is granted on them
+fn with_descendants(resource_ids: &mut HashSet<ResourceId>, resources: &HashMap<ResourceId, Resource>) {
+    for resource in resources.values() {
+        let mut seen = HashSet::new();
+        let mut parent_id = resource.get_parent_id();
+        while let Some(id) = parent_id.filter(|id| seen.insert(id.clone())) {
+            if resource_ids.contains(&id) {
+                resource_ids.insert(resource.get_id());
+                break;
+            }
+            parent_id = resources.get(&id).and_then(|parent| parent.get_parent_id());
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::simulation::{DecisionFlip, SimulateRequest, SimulationService, SimulationServiceImpl};
+use crate::domain::conditions::RequestContext;
+use crate::domain::repositories::{EntityChange, PermissionRepository, Repository, RoleRepository, SubjectRepository};
+use crate::domain::separation_of_duties::SeparationOfDuties;
+use crate::domain::subjects::SubjectId;
+use crate::infrastructure::sqlite::delegation::SqliteDelegationRepository;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use crate::infrastructure::sqlite::resource::SqliteResourceRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::test_support::{access_checker, resource_id, seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: reports
+      - name: reports/karla
+        parent: reports
+      - name: payments
+    permissions:
+      - name: read reports
+        resource: reports
+      - name: make payments
+        resource: payments
+    roles:
+      - name: analyst
+        permissions: [read reports]
+      - name: treasurer
+        permissions: [make payments]
+    subjects:
+      - name: alec leamas
+        roles: [analyst]
+      - name: george smiley
+        roles: [treasurer]
+      - name: peter guillam
+";
+
+fn simulation_service(connection_pool: &Pool<Sqlite>) -> SimulationServiceImpl {
+    SimulationServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteDelegationRepository::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+fn request(changes: Vec<EntityChange>) -> SimulateRequest {
+    SimulateRequest {
+        changes,
+        context: RequestContext::default(),
+        subject_ids: None,
+        resource_ids: None,
+    }
+}
+
+// what the flips say, without the ids
+fn described(flips: &[DecisionFlip]) -> Vec<(&str, &str, bool)> {
+    flips.iter()
+        .map(|flip| (flip.subject_name.as_str(), flip.resource_name.as_str(), flip.after))
+        .collect()
+}
+
+// peter guillam made an analyst
+async fn peter_guillam_promoted(connection_pool: &Pool<Sqlite>) -> EntityChange {
+    let analyst = SqliteRoleRepository::new(connection_pool.clone(), tenant_id()).get_by_name("analyst").await.unwrap().unwrap();
+    let mut peter_guillam = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_name("peter guillam")
+        .await
+        .unwrap()
+        .unwrap();
+    peter_guillam.add_role(analyst.get_id(), &SeparationOfDuties::default()).unwrap();
+    EntityChange::SaveSubject(peter_guillam)
+}
+
+#[async_std::test]
+async fn test_simulation_tells_the_flips_and_saves_nothing() {
+    let connection_pool = seeded_database(SEED).await;
+    let change = peter_guillam_promoted(&connection_pool).await;
+
+    let simulation = simulation_service(&connection_pool).simulate(request(vec![change])).await.unwrap();
+    // he alone is checked, on what the role reaches and what is beneath it
+    assert_eq!(simulation.checked, 2);
+    assert_eq!(described(&simulation.flips), vec![("peter guillam", "reports", true), ("peter guillam", "reports/karla", true)]);
+    assert!(simulation.flips.iter().all(|flip| !flip.before && flip.operation == "invoke"));
+
+    let peter_guillam_id = subject_id(&connection_pool, "peter guillam").await;
+    let stored = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_id(peter_guillam_id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(stored.get_roles().is_empty());
+    assert_eq!(stored.get_version(), 0);
+    let reports_id = resource_id(&connection_pool, "reports").await;
+    assert!(!access_checker(&connection_pool).can_invoke(peter_guillam_id, reports_id).await.unwrap());
+}
+
+#[async_std::test]
+async fn test_simulation_checks_the_holders_of_changed_roles() {
+    let connection_pool = seeded_database(SEED).await;
+    let read_reports = SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_name("read reports")
+        .await
+        .unwrap()
+        .unwrap();
+    let mut analyst = SqliteRoleRepository::new(connection_pool.clone(), tenant_id()).get_by_name("analyst").await.unwrap().unwrap();
+    analyst.remove_permission(&read_reports.get_id());
+
+    let simulation = simulation_service(&connection_pool)
+        .simulate(request(vec![EntityChange::SaveRole(analyst)]))
+        .await
+        .unwrap();
+    assert_eq!(simulation.checked, 2);
+    assert_eq!(described(&simulation.flips), vec![("alec leamas", "reports", false), ("alec leamas", "reports/karla", false)]);
+}
+
+#[async_std::test]
+async fn test_simulation_checks_what_the_request_names() {
+    let connection_pool = seeded_database(SEED).await;
+    let change = peter_guillam_promoted(&connection_pool).await;
+    let subject_ids = vec![
+        subject_id(&connection_pool, "george smiley").await,
+        subject_id(&connection_pool, "peter guillam").await,
+    ];
+
+    let simulation = simulation_service(&connection_pool)
+        .simulate(SimulateRequest {
+            changes: vec![change.clone()],
+            context: RequestContext::default(),
+            subject_ids: Some(subject_ids.clone()),
+            resource_ids: Some(vec![resource_id(&connection_pool, "payments").await]),
+        })
+        .await
+        .unwrap();
+    assert_eq!(simulation.checked, 2);
+    assert!(simulation.flips.is_empty());
+
+    // naming only the subjects still checks them on what the changes reach
+    let simulation = simulation_service(&connection_pool)
+        .simulate(SimulateRequest {
+            changes: vec![change.clone()],
+            context: RequestContext::default(),
+            subject_ids: Some(subject_ids),
+            resource_ids: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(simulation.checked, 4);
+    assert_eq!(simulation.flips.len(), 2);
+
+    let error = simulation_service(&connection_pool)
+        .simulate(SimulateRequest {
+            changes: vec![change],
+            context: RequestContext::default(),
+            subject_ids: Some(vec![SubjectId::from("karla".to_string())]),
+            resource_ids: None,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.to_string(), "subject karla not found");
+}
@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Serialize, Deserialize};
+
+use crate::domain::repositories::{
+    EntityChange, Error, GroupRepository, PermissionRepository, ResourceRepository, RoleRepository, SubjectRepository, UnitOfWork,
+};
+use crate::domain::resources::ResourceId;
+use crate::domain::snapshots::Snapshot;
+use crate::domain::tenants::TenantId;
+
+pub struct SnapshotExporter {
+    tenant_id: TenantId,
+    resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+    permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+    role_repository: Box<dyn RoleRepository + Send + Sync>,
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+}
+
+impl SnapshotExporter {
+    pub fn new(
+        tenant_id: TenantId,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+        permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+        role_repository: Box<dyn RoleRepository + Send + Sync>,
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+    ) -> SnapshotExporter {
+        SnapshotExporter {
+            tenant_id,
+            resource_repository,
+            permission_repository,
+            role_repository,
+            subject_repository,
+            group_repository,
+        }
+    }
+
+    // deleted subjects are exported too, along with their version
+    pub async fn export(&self) -> Result<Snapshot, Error> {
+        Ok(Snapshot::new(
+            self.tenant_id.clone(),
+            self.resource_repository.find_all().await?,
+            self.permission_repository.find_all().await?,
+            self.role_repository.find_all().await?,
+            self.subject_repository.find_all().await?,
+            self.group_repository.find_all().await?,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImportMode {
+    // snapshot entities overwrite stored ones with the same id, anything else in the store is kept
+    Merge,
+    // the tenant ends up holding exactly what the snapshot holds. constraints, delegations and relation
+    // tuples are not part of snapshots: an import that would delete what they refer to is refused
+    Replace,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ImportSnapshotResponse {
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+// entities keep the ids they were exported with and are imported into this importer's tenant,
+// whichever tenant the snapshot was taken from. the whole import is a single unit of work
+pub struct SnapshotImporter {
+    tenant_id: TenantId,
+    resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+    permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+    role_repository: Box<dyn RoleRepository + Send + Sync>,
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+    unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+}
+
+impl SnapshotImporter {
+    pub fn new(
+        tenant_id: TenantId,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+        permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+        role_repository: Box<dyn RoleRepository + Send + Sync>,
+        subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+        unit_of_work: Box<dyn UnitOfWork + Send + Sync>,
+    ) -> SnapshotImporter {
+        SnapshotImporter {
+            tenant_id,
+            resource_repository,
+            permission_repository,
+            role_repository,
+            subject_repository,
+            group_repository,
+            unit_of_work,
+        }
+    }
+
+    pub async fn import(&self, snapshot: &Snapshot, mode: ImportMode) -> Result<ImportSnapshotResponse, Error> {
+        let stored_resources = self.resource_repository.find_all().await?;
+        let stored_permissions = self.permission_repository.find_all().await?;
+        let stored_roles = self.role_repository.find_all().await?;
+        let stored_subjects = self.subject_repository.find_all().await?;
+        let stored_groups = self.group_repository.find_all().await?;
+
+        let mut response = ImportSnapshotResponse::default();
+        let mut changes = Vec::new();
+
+        if mode == ImportMode::Replace {
+            let keep: HashSet<String> = snapshot.resources.iter().map(|record| record.id.clone().into())
+                .chain(snapshot.permissions.iter().map(|record| record.id.clone().into()))
+                .chain(snapshot.roles.iter().map(|record| record.id.clone().into()))
+                .chain(snapshot.subjects.iter().map(|record| record.id.clone().into()))
+                .chain(snapshot.groups.iter().map(|record| record.id.clone().into()))
+                .collect();
+            let deletes = stored_resources.iter().map(|resource| (resource.get_id().into(), EntityChange::DeleteResource(resource.get_id())))
+                .chain(stored_permissions.iter().map(|permission| (permission.get_id().into(), EntityChange::DeletePermission(permission.get_id()))))
+                .chain(stored_roles.iter().map(|role| (role.get_id().into(), EntityChange::DeleteRole(role.get_id()))))
+                .chain(stored_subjects.iter().map(|subject| (subject.get_id().into(), EntityChange::DeleteSubject(subject.get_id()))))
+                .chain(stored_groups.iter().map(|group| (group.get_id().into(), EntityChange::DeleteGroup(group.get_id()))))
+                .filter(|(id, _): &(String, EntityChange)| !keep.contains(id));
+            for (_, change) in deletes {
+                response.deleted += 1;
+                changes.push(change);
+            }
+        }
+
+        let stored: HashSet<String> = stored_resources.iter().map(|resource| resource.get_id().into())
+            .chain(stored_permissions.iter().map(|permission| permission.get_id().into()))
+            .chain(stored_roles.iter().map(|role| role.get_id().into()))
+            .chain(stored_subjects.iter().map(|subject| subject.get_id().into()))
+            .chain(stored_groups.iter().map(|group| group.get_id().into()))
+            .collect();
+        let mut count = |id: String| {
+            if stored.contains(&id) {
+                response.updated += 1;
+            } else {
+                response.created += 1;
+            }
+        };
+
+        // permissions embed their resource, which comes from the snapshot or, when merging, the store
+        let mut resources: HashMap<ResourceId, _> = HashMap::new();
+        if mode == ImportMode::Merge {
+            resources.extend(stored_resources.into_iter().map(|resource| (resource.get_id(), resource)));
+        }
+        for record in &snapshot.resources {
+            let resource = record.to_entity(&self.tenant_id);
+            resources.insert(resource.get_id(), resource.clone());
+            count(record.id.clone().into());
+            changes.push(EntityChange::SaveResource(resource));
+        }
+        for record in &snapshot.permissions {
+            let resource = resources.get(&record.resource_id).cloned().ok_or_else(|| Error::Simple(format!(
+                "permission {} refers to resource {} which is neither in the snapshot nor stored",
+                String::from(record.id.clone()),
+                String::from(record.resource_id.clone()),
+            )))?;
+            count(record.id.clone().into());
+            changes.push(EntityChange::SavePermission(record.to_entity(&self.tenant_id, resource)?));
+        }
+        for record in &snapshot.roles {
+            count(record.id.clone().into());
+            changes.push(EntityChange::SaveRole(record.to_entity(&self.tenant_id)));
+        }
+        for record in &snapshot.subjects {
+            count(record.id.clone().into());
+            changes.push(EntityChange::RestoreSubject(record.to_entity(&self.tenant_id)));
+        }
+        for record in &snapshot.groups {
+            count(record.id.clone().into());
+            changes.push(EntityChange::SaveGroup(record.to_entity(&self.tenant_id)));
+        }
+
+        self.unit_of_work.commit(changes).await?;
+        Ok(response)
+    }
+}
@@ -0,0 +1,316 @@
+use chrono::{Duration, Utc};
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::snapshots::{ImportMode, SnapshotExporter, SnapshotImporter};
+use crate::domain::access_requests::AccessRequest;
+use crate::domain::api_keys::ApiKey;
+use crate::domain::credentials::PasswordCredential;
+use crate::domain::repositories::{CredentialRepository, EntityChange, GroupRepository, Repository, RoleRepository, SubjectRepository, UnitOfWork};
+use crate::domain::roles::Role;
+use crate::domain::separation_of_duties::{SeparationOfDuties, SodConstraint, SodKind};
+use crate::domain::sessions::Session;
+use crate::domain::snapshots::Snapshot;
+use crate::domain::subjects::{RoleAssignment, Subject};
+use crate::infrastructure::sqlite::access_request::SqliteAccessRequestRepository;
+use crate::infrastructure::sqlite::api_key::SqliteApiKeyRepository;
+use crate::infrastructure::sqlite::credential::SqliteCredentialRepository;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use crate::infrastructure::sqlite::resource::SqliteResourceRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::session::SqliteSessionRepository;
+use crate::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+use crate::test_support::{empty_database, seeded_database, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: reports
+        attributes: {classification: secret}
+      - name: reports/karla
+        parent: reports
+        owner: alec leamas
+    permissions:
+      - name: read reports
+        resource: reports
+        condition: subject.clearance >= 3 && context.mfa
+      - name: shred reports
+        resource: reports/karla
+        effect: deny
+      - name: edit own reports
+        resource: reports
+        grantee: owner
+    roles:
+      - name: analyst
+        permissions: [read reports, shred reports]
+        approvers: [george smiley]
+    subjects:
+      - name: alec leamas
+        roles: [analyst]
+        attributes: {clearance: 3, stations: [berlin, london]}
+      - name: george smiley
+    groups:
+      - name: circus
+        subjects: [alec leamas, george smiley]
+        roles: [analyst]
+";
+
+async fn populated_database() -> Pool<Sqlite> {
+    let connection_pool = seeded_database(SEED).await;
+
+    // a deleted subject whose role assignment ran out, to carry version, deleted_at and validity windows across
+    let analyst = SqliteRoleRepository::new(connection_pool.clone(), tenant_id()).get_by_name("analyst").await.unwrap().unwrap();
+    let mut bill_haydon = Subject::new(tenant_id(), "bill haydon");
+    bill_haydon.add_role_assignment(RoleAssignment::bounded(
+        analyst.get_id(),
+        Some(Utc::now() - Duration::days(30)),
+        Some(Utc::now() - Duration::days(1)),
+    ), &SeparationOfDuties::default()).unwrap();
+    bill_haydon.delete();
+    SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).save(bill_haydon).await.unwrap();
+
+    connection_pool
+}
+
+fn exporter(connection_pool: &Pool<Sqlite>) -> SnapshotExporter {
+    SnapshotExporter::new(
+        tenant_id(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+fn importer(connection_pool: &Pool<Sqlite>) -> SnapshotImporter {
+    SnapshotImporter::new(
+        tenant_id(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+fn without_timestamp(mut snapshot: Snapshot) -> Snapshot {
+    snapshot.exported_at = Default::default();
+    snapshot
+}
+
+#[async_std::test]
+async fn test_round_trip_between_databases() {
+    let source = populated_database().await;
+    let target = empty_database().await;
+
+    let exported = exporter(&source).export().await.unwrap();
+    assert_eq!(exported.resources.len(), 2);
+    assert_eq!(exported.permissions.len(), 3);
+    assert_eq!(exported.roles.len(), 1);
+    assert_eq!(exported.subjects.len(), 3);
+    assert_eq!(exported.groups.len(), 1);
+
+    let json = exported.to_json().unwrap();
+    let imported = importer(&target).import(&Snapshot::from_json(&json).unwrap(), ImportMode::Replace).await.unwrap();
+    assert_eq!(imported.created, 10);
+    assert_eq!(imported.updated, 0);
+    assert_eq!(imported.deleted, 0);
+
+    let reexported = exporter(&target).export().await.unwrap();
+    assert_eq!(without_timestamp(reexported), without_timestamp(exported.clone()));
+
+    let bill_haydon = exported.subjects.iter().find(|subject| subject.name == "bill haydon").unwrap();
+    let restored = SqliteSubjectRepository::new(target.clone(), tenant_id()).get_by_id(bill_haydon.id.clone()).await.unwrap().unwrap();
+    assert_eq!(restored.get_version(), bill_haydon.version);
+    assert!(restored.get_deleted_at().is_some());
+    assert_eq!(restored.get_role_assignments(), bill_haydon.roles);
+}
+
+#[async_std::test]
+async fn test_import_is_idempotent() {
+    let source = populated_database().await;
+    let snapshot = exporter(&source).export().await.unwrap();
+
+    let imported = importer(&source).import(&snapshot, ImportMode::Merge).await.unwrap();
+    assert_eq!(imported.created, 0);
+    assert_eq!(imported.updated, 10);
+    assert_eq!(without_timestamp(exporter(&source).export().await.unwrap()), without_timestamp(snapshot));
+}
+
+#[async_std::test]
+async fn test_import_keeps_the_history_of_subjects() {
+    let source = populated_database().await;
+    let snapshot = exporter(&source).export().await.unwrap();
+    let subject_repository = SqliteSubjectRepository::new(source.clone(), tenant_id());
+    let mut alec_leamas = subject_repository.get_by_name("alec leamas").await.unwrap().unwrap();
+    let exported_version = alec_leamas.get_version();
+    alec_leamas.rename("alec");
+    subject_repository.save(alec_leamas.clone()).await.unwrap();
+
+    importer(&source).import(&snapshot, ImportMode::Merge).await.unwrap();
+
+    // the snapshot's alec comes back on top of his stream, with the rename still in his history
+    let restored = subject_repository.get_by_id(alec_leamas.get_id()).await.unwrap().unwrap();
+    assert_eq!(restored.get_name(), "alec leamas");
+    assert_eq!(restored.get_version(), exported_version + 2);
+    let history = subject_repository.get_history(alec_leamas.get_id()).await.unwrap();
+    assert_eq!(history.len() as i64, exported_version + 2);
+
+    // and later changes carry on from there
+    let mut restored = restored;
+    restored.rename("leamas");
+    subject_repository.save(restored.clone()).await.unwrap();
+    assert_eq!(subject_repository.get_by_id(restored.get_id()).await.unwrap().unwrap().get_name(), "leamas");
+}
+
+#[async_std::test]
+async fn test_saving_a_stale_copy_is_a_concurrent_change() {
+    let source = populated_database().await;
+    let subject_repository = SqliteSubjectRepository::new(source.clone(), tenant_id());
+    let stale = subject_repository.get_by_name("alec leamas").await.unwrap().unwrap();
+    let mut current = stale.clone();
+    current.rename("alec");
+    subject_repository.save(current.clone()).await.unwrap();
+
+    let error = subject_repository.save(stale).await.unwrap_err();
+    assert!(error.to_string().contains("was changed concurrently"), "{}", error);
+    assert_eq!(subject_repository.get_by_id(current.get_id()).await.unwrap().unwrap().get_name(), "alec");
+}
+
+#[async_std::test]
+async fn test_merge_keeps_and_replace_removes_unknown_entities() {
+    let source = populated_database().await;
+    let snapshot = exporter(&source).export().await.unwrap();
+
+    let target = empty_database().await;
+    let control = Subject::new(tenant_id(), "control");
+    let subject_repository = SqliteSubjectRepository::new(target.clone(), tenant_id());
+    subject_repository.save(control.clone()).await.unwrap();
+
+    let merged = importer(&target).import(&snapshot, ImportMode::Merge).await.unwrap();
+    assert_eq!(merged.deleted, 0);
+    assert!(subject_repository.get_by_id(control.get_id()).await.unwrap().is_some());
+
+    let replaced = importer(&target).import(&snapshot, ImportMode::Replace).await.unwrap();
+    assert_eq!(replaced.deleted, 1);
+    assert!(subject_repository.get_by_id(control.get_id()).await.unwrap().is_none());
+    assert_eq!(subject_repository.find_all().await.unwrap().len(), snapshot.subjects.len());
+}
+
+#[async_std::test]
+async fn test_replace_keeps_what_entities_outside_the_snapshot_refer_to() {
+    let source = populated_database().await;
+    let snapshot = exporter(&source).export().await.unwrap();
+
+    // constraints are not part of snapshots, so the roles this one refers to must stay
+    let target = empty_database().await;
+    let role_repository = SqliteRoleRepository::new(target.clone(), tenant_id());
+    let (burn, shred) = (Role::new(tenant_id(), "burn notices"), Role::new(tenant_id(), "shred files"));
+    role_repository.save(burn.clone()).await.unwrap();
+    role_repository.save(shred.clone()).await.unwrap();
+    let constraint = SodConstraint::new(tenant_id(), "cleanup", SodKind::Static, [burn.get_id(), shred.get_id()].into()).unwrap();
+    SqliteSodConstraintRepository::new(target.clone(), tenant_id()).save(constraint).await.unwrap();
+
+    let error = importer(&target).import(&snapshot, ImportMode::Replace).await.unwrap_err();
+    assert!(error.to_string().ends_with("separation of duties constraint cleanup still refers to it"), "{}", error);
+    assert_eq!(role_repository.find_all().await.unwrap().len(), 2);
+}
+
+#[async_std::test]
+async fn test_deletes_take_sessions_keys_credentials_and_requests_along() {
+    let connection_pool = populated_database().await;
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id());
+    let session_repository = SqliteSessionRepository::new(connection_pool.clone(), tenant_id());
+    let access_request_repository = SqliteAccessRequestRepository::new(connection_pool.clone(), tenant_id());
+    let unit_of_work = SqliteUnitOfWork::new(connection_pool.clone(), tenant_id());
+    let analyst = SqliteRoleRepository::new(connection_pool.clone(), tenant_id()).get_by_name("analyst").await.unwrap().unwrap();
+    let mut karla = Subject::new(tenant_id(), "karla");
+    karla.add_role(analyst.get_id(), &SeparationOfDuties::default()).unwrap();
+    subject_repository.save(karla.clone()).await.unwrap();
+    let mut karla = subject_repository.get_by_id(karla.get_id()).await.unwrap().unwrap();
+
+    let mut session = Session::new(tenant_id(), karla.get_id());
+    session.activate_role(&karla, analyst.get_id(), &SeparationOfDuties::default()).unwrap();
+    session_repository.save(session.clone()).await.unwrap();
+    let credential_repository = SqliteCredentialRepository::new(connection_pool.clone(), tenant_id());
+    credential_repository.save(PasswordCredential::new(&karla, "karla", "moscow centre").unwrap()).await.unwrap();
+    let access_request = AccessRequest::new(tenant_id(), karla.get_id(), analyst.get_id(), None, Utc::now() + Duration::days(1));
+    access_request_repository.save(access_request.clone()).await.unwrap();
+    let reporting_bot = Subject::service_account(tenant_id(), "reporting bot");
+    subject_repository.save(reporting_bot.clone()).await.unwrap();
+    let (api_key, _) = ApiKey::issue(&reporting_bot, "reports", None).unwrap();
+    let api_key_repository = SqliteApiKeyRepository::new(connection_pool.clone(), tenant_id());
+    api_key_repository.save(api_key.clone()).await.unwrap();
+
+    unit_of_work.commit(vec![EntityChange::DeleteSubject(reporting_bot.get_id())]).await.unwrap();
+    assert!(api_key_repository.get_by_id(api_key.get_id()).await.unwrap().is_none());
+
+    // once nothing holds or approves the role any more, it goes, and sessions stop using it
+    let mut circus = SqliteGroupRepository::new(connection_pool.clone(), tenant_id()).find_all().await.unwrap().remove(0);
+    circus.remove_role(&analyst.get_id());
+    let mut changes = vec![EntityChange::SaveGroup(circus)];
+    for mut subject in subject_repository.find_all().await.unwrap().into_iter().filter(|subject| subject.get_id() != karla.get_id()) {
+        subject.remove_role(&analyst.get_id());
+        changes.push(EntityChange::SaveSubject(subject));
+    }
+    karla.remove_role(&analyst.get_id());
+    changes.push(EntityChange::SaveSubject(karla.clone()));
+    changes.push(EntityChange::DeleteRole(analyst.get_id()));
+    unit_of_work.commit(changes).await.unwrap();
+    assert!(session_repository.get_by_id(session.get_id()).await.unwrap().unwrap().get_active_roles().is_empty());
+    assert!(access_request_repository.get_by_id(access_request.get_id()).await.unwrap().is_none());
+
+    unit_of_work.commit(vec![EntityChange::DeleteSubject(karla.get_id())]).await.unwrap();
+    assert!(session_repository.get_by_id(session.get_id()).await.unwrap().is_none());
+    assert!(credential_repository.get_by_id(karla.get_id()).await.unwrap().is_none());
+    assert!(credential_repository.get_by_login("karla").await.unwrap().is_none());
+}
+
+#[async_std::test]
+async fn test_import_refuses_state_that_breaks_a_constraint() {
+    let source = populated_database().await;
+    let mut snapshot = exporter(&source).export().await.unwrap();
+
+    // alec holds analyst, and now also a role the target's constraint keeps apart from it
+    let target = empty_database().await;
+    importer(&target).import(&snapshot, ImportMode::Replace).await.unwrap();
+    let role_repository = SqliteRoleRepository::new(target.clone(), tenant_id());
+    let burn = Role::new(tenant_id(), "burn notices");
+    role_repository.save(burn.clone()).await.unwrap();
+    let analyst_id = snapshot.roles[0].id.clone();
+    let constraint = SodConstraint::new(tenant_id(), "clean hands", SodKind::Static, [analyst_id, burn.get_id()].into()).unwrap();
+    SqliteSodConstraintRepository::new(target.clone(), tenant_id()).save(constraint).await.unwrap();
+    let alec_leamas = snapshot.subjects.iter_mut().find(|subject| subject.name == "alec leamas").unwrap();
+    alec_leamas.roles.push(RoleAssignment::new(burn.get_id()));
+
+    let error = importer(&target).import(&snapshot, ImportMode::Merge).await.unwrap_err();
+    assert!(error.to_string().contains("clean hands"), "{}", error);
+    let stored = SqliteSubjectRepository::new(target.clone(), tenant_id()).get_by_name("alec leamas").await.unwrap().unwrap();
+    assert!(!stored.get_roles().contains(&burn.get_id()));
+}
+
+#[async_std::test]
+async fn test_failed_import_leaves_the_store_untouched() {
+    let source = populated_database().await;
+    let mut snapshot = exporter(&source).export().await.unwrap();
+    // the role now refers to a subject that exists nowhere
+    snapshot.roles[0].approvers.push(Subject::new(tenant_id(), "karla").get_id());
+
+    let target = empty_database().await;
+    assert!(importer(&target).import(&snapshot, ImportMode::Replace).await.is_err());
+    assert!(SqliteSubjectRepository::new(target.clone(), tenant_id()).find_all().await.unwrap().is_empty());
+}
+
+#[async_std::test]
+async fn test_rejects_unknown_format_versions() {
+    let source = populated_database().await;
+    let json = exporter(&source).export().await.unwrap().to_json().unwrap()
+        .replacen("\"format_version\": 1", "\"format_version\": 2", 1);
+
+    let error = Snapshot::from_json(&json).unwrap_err();
+    assert_eq!(error.to_string(), "snapshot format version 2 is not supported, expected 1");
+}
@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 
-use crate::domain::{repositories::{Error, Repository}, subjects::{SubjectId, Subject}};
+use crate::domain::{repositories::{Error, Repository}, subjects::{SubjectId, Subject}, tenants::TenantId};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateSubjectRequest {
@@ -14,6 +14,11 @@ pub struct CreateSubjectResponse {
     pub subject_name: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateServiceAccountRequest {
+    pub name: String
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeleteSubjectRequest {
     pub subject_id: SubjectId
@@ -25,17 +30,20 @@ pub struct DeleteSubjectResponse {}
 #[async_trait]
 pub trait SubjectService {
     async fn create_subject(&self, req: CreateSubjectRequest) -> Result<CreateSubjectResponse, Error>;
+    async fn create_service_account(&self, req: CreateServiceAccountRequest) -> Result<CreateSubjectResponse, Error>;
     async fn delete_subject(&self, req: DeleteSubjectRequest) -> Result<DeleteSubjectResponse, Error>;
 }
 
 pub struct SubjectServiceImpl {
+    tenant_id: TenantId,
     subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>
 }
 
 impl SubjectServiceImpl {
-    pub fn new(subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>) -> Self {
+    pub fn new(tenant_id: TenantId, subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>) -> Self {
         SubjectServiceImpl {
-            subject_repository: subject_repository,
+            tenant_id,
+            subject_repository,
         }
     }
 }
@@ -43,7 +51,7 @@ impl SubjectServiceImpl {
 #[async_trait]
 impl SubjectService for SubjectServiceImpl {
     async fn create_subject(&self, req: CreateSubjectRequest) -> Result<CreateSubjectResponse, Error> {
-        let subject = Subject::new(&req.name);
+        let subject = Subject::new(self.tenant_id.clone(), &req.name);
         
         self.subject_repository.save(subject.clone())
             .await
@@ -54,6 +62,16 @@ impl SubjectService for SubjectServiceImpl {
             subject_name: subject.get_name(),
         })
     }
+
+    async fn create_service_account(&self, req: CreateServiceAccountRequest) -> Result<CreateSubjectResponse, Error> {
+        let subject = Subject::service_account(self.tenant_id.clone(), &req.name);
+        self.subject_repository.save(subject.clone()).await?;
+
+        Ok(CreateSubjectResponse {
+            subject_id: subject.get_id(),
+            subject_name: subject.get_name(),
+        })
+    }
     
     async fn delete_subject(&self, req: DeleteSubjectRequest) -> Result<DeleteSubjectResponse, Error> {
         let mut subject = self.subject_repository.get_by_id(req.subject_id)
@@ -0,0 +1,297 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::application::access_checker::{AccessChecker, AccessDecision};
+use crate::domain::conditions::{Attributes, RequestContext};
+use crate::domain::jwks::{Jwks, TokenAlgorithm};
+use crate::domain::operations::Operation::Invoke;
+use crate::domain::permissions::{Effect, Grantee, Permission};
+use crate::domain::repositories::{
+    Error, GroupRepository, KeySetRepository, PermissionRepository, Repository, ResourceRepository, RoleRepository,
+};
+use crate::domain::resources::{Resource, ResourceId};
+use crate::domain::roles::RoleId;
+use crate::domain::subjects::{Subject, SubjectId};
+use crate::domain::tenants::TenantId;
+use crate::domain::tokens::AccessTokenClaims;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssueTokenRequest {
+    pub subject_id: SubjectId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssueTokenResponse {
+    pub token: String,
+    pub token_id: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateKeysRequest {
+    pub algorithm: TokenAlgorithm,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateKeysResponse {
+    pub key_id: String,
+    // keys retired so long ago that every token they signed has expired
+    pub pruned_key_ids: Vec<String>,
+}
+
+#[async_trait]
+pub trait TokenService {
+    async fn issue_token(&self, req: IssueTokenRequest) -> Result<IssueTokenResponse, Error>;
+    // the new key signs from now on; the keys it replaces keep verifying for one token lifetime
+    async fn rotate_keys(&self, req: RotateKeysRequest) -> Result<RotateKeysResponse, Error>;
+    // the keys to hand to verifiers that do not share the HS256 secrets
+    async fn get_published_keys(&self) -> Result<Jwks, Error>;
+}
+
+pub struct TokenServiceImpl {
+    tenant_id: TenantId,
+    subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+    group_repository: Box<dyn GroupRepository + Send + Sync>,
+    key_set_repository: Box<dyn KeySetRepository + Send + Sync>,
+    issuer: String,
+    lifetime: Duration,
+}
+
+impl TokenServiceImpl {
+    pub fn new(
+        tenant_id: TenantId,
+        subject_repository: Box<dyn Repository<SubjectId, Subject> + Send + Sync>,
+        group_repository: Box<dyn GroupRepository + Send + Sync>,
+        key_set_repository: Box<dyn KeySetRepository + Send + Sync>,
+        issuer: &str,
+        lifetime: Duration,
+    ) -> Self {
+        TokenServiceImpl {
+            tenant_id,
+            subject_repository,
+            group_repository,
+            key_set_repository,
+            issuer: issuer.to_string(),
+            lifetime,
+        }
+    }
+}
+
+#[async_trait]
+impl TokenService for TokenServiceImpl {
+    async fn issue_token(&self, req: IssueTokenRequest) -> Result<IssueTokenResponse, Error> {
+        let subject = self.subject_repository.get_by_id(req.subject_id.clone())
+            .await?
+            .filter(|subject| subject.get_tenant_id() == self.tenant_id)
+            .ok_or_else(|| Error::Simple(format!("subject {} not found", String::from(req.subject_id))))?;
+        if !subject.is_active() {
+            return Err(Error::Simple(format!("subject {} is not active", subject.get_name())));
+        }
+
+        let key_set = self.key_set_repository.load().await?;
+        let key = key_set.signing_key()
+            .ok_or_else(|| Error::Simple("there is no signing key, rotate the keys first".to_string()))?;
+        let groups = self.group_repository.find_by_subject_transitively(subject.get_id()).await?;
+        let claims = AccessTokenClaims::new(&self.issuer, &subject, &groups, self.lifetime, Utc::now());
+
+        Ok(IssueTokenResponse {
+            token: claims.sign(key)?,
+            token_id: claims.get_token_id(),
+            expires_at: claims.get_expires_at(),
+        })
+    }
+
+    async fn rotate_keys(&self, req: RotateKeysRequest) -> Result<RotateKeysResponse, Error> {
+        let mut key_set = self.key_set_repository.load().await?;
+        let now = Utc::now();
+        let pruned_key_ids = key_set.prune(now - self.lifetime);
+        let key_id = key_set.rotate(req.algorithm, now);
+        self.key_set_repository.save(&key_set).await?;
+
+        Ok(RotateKeysResponse {
+            key_id,
+            pruned_key_ids,
+        })
+    }
+
+    async fn get_published_keys(&self) -> Result<Jwks, Error> {
+        Ok(self.key_set_repository.load().await?.public())
+    }
+}
+
+// what every role lets its holders do, with the resources those permissions are on, so that checks need
+// no store. it is a snapshot: load a fresh one when roles, permissions or resources change
+#[derive(Debug)]
+pub struct RolePermissionMap {
+    tenant_id: TenantId,
+    permissions: HashMap<RoleId, Vec<Permission>>,
+    resources: HashMap<ResourceId, Resource>,
+    resource_ids: HashMap<String, ResourceId>,
+    loaded_at: DateTime<Utc>,
+}
+
+impl RolePermissionMap {
+    pub fn get_loaded_at(&self) -> DateTime<Utc> {
+        self.loaded_at
+    }
+
+    pub fn get_resource_id(&self, resource_name: &str) -> Option<ResourceId> {
+        self.resource_ids.get(resource_name).cloned()
+    }
+
+    // the same rules as `AccessChecker`, limited to what a token tells: roles grant, the nearest resource
+    // with a grant decides and deny wins on it. the subject's attributes are not known, so conditions on
+    // them never let anyone in, and ownership and delegations never count
+    pub fn decide(&self, roles: &HashSet<RoleId>, resource_id: &ResourceId, context: &RequestContext) -> bool {
+        let mut lineage = Vec::new();
+        let mut next = Some(resource_id.clone());
+        while let Some(resource_id) = next.take() {
+            if lineage.contains(&resource_id) {
+                break;
+            }
+            next = self.resources.get(&resource_id).and_then(|resource| resource.get_parent_id());
+            lineage.push(resource_id);
+        }
+
+        let subject_attributes = Attributes::new();
+        let resource_attributes = self.resources.get(resource_id)
+            .map(|resource| resource.get_attributes())
+            .unwrap_or_default();
+
+        let mut grants: HashMap<ResourceId, HashSet<Effect>> = HashMap::new();
+        let permissions = roles.iter().flat_map(|role_id| self.permissions.get(role_id).into_iter().flatten());
+        for permission in permissions {
+            if permission.get_grantee() != Grantee::RoleHolders
+                || !AccessChecker::applies(permission, &subject_attributes, &resource_attributes, context)
+            {
+                continue;
+            }
+            match permission.get_operation() {
+                Invoke(resource) => {
+                    if resource.get_tenant_id() == self.tenant_id {
+                        grants.entry(resource.get_id()).or_default().insert(permission.get_effect());
+                    }
+                },
+            }
+        }
+
+        for resource_id in lineage {
+            if let Some(effects) = grants.get(&resource_id) {
+                return !effects.contains(&Effect::Deny);
+            }
+        }
+        false
+    }
+}
+
+pub struct RolePermissionLoader {
+    tenant_id: TenantId,
+    role_repository: Box<dyn RoleRepository + Send + Sync>,
+    permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+    resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+}
+
+impl RolePermissionLoader {
+    pub fn new(
+        tenant_id: TenantId,
+        role_repository: Box<dyn RoleRepository + Send + Sync>,
+        permission_repository: Box<dyn PermissionRepository + Send + Sync>,
+        resource_repository: Box<dyn ResourceRepository + Send + Sync>,
+    ) -> RolePermissionLoader {
+        RolePermissionLoader {
+            tenant_id,
+            role_repository,
+            permission_repository,
+            resource_repository,
+        }
+    }
+
+    pub async fn load(&self) -> Result<RolePermissionMap, Error> {
+        let permissions: HashMap<_, _> = self.permission_repository.find_all()
+            .await?
+            .into_iter()
+            .map(|permission| (permission.get_id(), permission))
+            .collect();
+        let resources: HashMap<_, _> = self.resource_repository.find_all()
+            .await?
+            .into_iter()
+            .map(|resource| (resource.get_id(), resource))
+            .collect();
+
+        Ok(RolePermissionMap {
+            tenant_id: self.tenant_id.clone(),
+            permissions: self.role_repository.find_all()
+                .await?
+                .into_iter()
+                .map(|role| {
+                    let granted = role.get_permissions()
+                        .iter()
+                        .filter_map(|permission_id| permissions.get(permission_id).cloned())
+                        .collect();
+                    (role.get_id(), granted)
+                })
+                .collect(),
+            resource_ids: resources.values().map(|resource| (resource.get_name(), resource.get_id())).collect(),
+            resources,
+            loaded_at: Utc::now(),
+        })
+    }
+}
+
+// checks access tokens without a store: the signature against a key set, the access against a role
+// permission map. both can be swapped while the verifier is in use, e.g. after the keys rotated
+pub struct TokenVerifier {
+    tenant_id: TenantId,
+    issuer: String,
+    key_set: RwLock<Arc<Jwks>>,
+    permission_map: RwLock<Arc<RolePermissionMap>>,
+}
+
+impl TokenVerifier {
+    pub fn new(tenant_id: TenantId, issuer: &str, key_set: Jwks, permission_map: RolePermissionMap) -> TokenVerifier {
+        TokenVerifier {
+            tenant_id,
+            issuer: issuer.to_string(),
+            key_set: RwLock::new(Arc::new(key_set)),
+            permission_map: RwLock::new(Arc::new(permission_map)),
+        }
+    }
+
+    pub fn replace_key_set(&self, key_set: Jwks) {
+        *self.key_set.write().unwrap() = Arc::new(key_set);
+    }
+
+    pub fn replace_permission_map(&self, permission_map: RolePermissionMap) {
+        *self.permission_map.write().unwrap() = Arc::new(permission_map);
+    }
+
+    // tokens of other tenants are refused like forged ones
+    pub fn verify(&self, token: &str) -> Result<AccessTokenClaims, Error> {
+        let key_set = self.key_set.read().unwrap().clone();
+        let claims = AccessTokenClaims::verify(token, &key_set, &self.issuer, Utc::now())?;
+        if claims.get_tenant_id() != self.tenant_id {
+            return Err(Error::Simple("invalid access token: issued for another tenant".to_string()));
+        }
+        Ok(claims)
+    }
+
+    // an error means the token itself was refused
+    pub fn check(&self, token: &str, resource_id: &ResourceId, context: &RequestContext) -> Result<AccessDecision, Error> {
+        let claims = self.verify(token)?;
+        let permission_map = self.permission_map.read().unwrap().clone();
+        Ok(AccessDecision::of(permission_map.decide(&claims.get_roles(), resource_id, context)))
+    }
+
+    // resources that were never registered cannot be invoked by anyone
+    pub fn check_by_name(&self, token: &str, resource_name: &str, context: &RequestContext) -> Result<AccessDecision, Error> {
+        let resource_id = self.permission_map.read().unwrap().get_resource_id(resource_name);
+        match resource_id {
+            Some(resource_id) => self.check(token, &resource_id, context),
+            None => self.verify(token).map(|_| AccessDecision::of(false)),
+        }
+    }
+}
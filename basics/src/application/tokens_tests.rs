@@ -0,0 +1,157 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+
+use crate::application::tokens::{IssueTokenRequest, RotateKeysRequest, TokenService, TokenServiceImpl};
+use crate::domain::jwks::{Jwks, TokenAlgorithm};
+use crate::domain::repositories::{Error, KeySetRepository};
+use crate::domain::tokens::AccessTokenClaims;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::test_support::{seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    roles:
+      - name: analyst
+    subjects:
+      - name: alec leamas
+        roles: [analyst]
+";
+
+const ISSUER: &str = "https://circus.example";
+
+#[derive(Default)]
+struct MemoryKeySetRepository {
+    key_set: Mutex<Jwks>,
+}
+
+#[async_trait]
+impl KeySetRepository for MemoryKeySetRepository {
+    async fn load(&self) -> Result<Jwks, Error> {
+        Ok(self.key_set.lock().unwrap().clone())
+    }
+
+    async fn save(&self, key_set: &Jwks) -> Result<(), Error> {
+        *self.key_set.lock().unwrap() = key_set.clone();
+        Ok(())
+    }
+}
+
+fn token_service(connection_pool: &Pool<Sqlite>) -> TokenServiceImpl {
+    TokenServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(MemoryKeySetRepository::default()),
+        ISSUER,
+        Duration::minutes(5),
+    )
+}
+
+// a token for alec leamas, signed with a fresh key of the given algorithm
+async fn signed_token(connection_pool: &Pool<Sqlite>, token_service: &TokenServiceImpl, algorithm: TokenAlgorithm) -> String {
+    token_service.rotate_keys(RotateKeysRequest { algorithm }).await.unwrap();
+    token_service.issue_token(IssueTokenRequest { subject_id: subject_id(connection_pool, "alec leamas").await })
+        .await
+        .unwrap()
+        .token
+}
+
+fn refusal(token: &str, key_set: &Jwks) -> String {
+    AccessTokenClaims::verify(token, key_set, ISSUER, Utc::now()).unwrap_err().to_string()
+}
+
+#[async_std::test]
+async fn test_tokens_are_refused_once_expired() {
+    let connection_pool = seeded_database(SEED).await;
+    let token_service = token_service(&connection_pool);
+    let token = signed_token(&connection_pool, &token_service, TokenAlgorithm::EdDsa).await;
+    let key_set = token_service.get_published_keys().await.unwrap();
+
+    let claims = AccessTokenClaims::verify(&token, &key_set, ISSUER, Utc::now()).unwrap();
+    assert_eq!(claims.get_subject_id(), subject_id(&connection_pool, "alec leamas").await);
+    assert_eq!(claims.get_roles().len(), 1);
+
+    let error = AccessTokenClaims::verify(&token, &key_set, ISSUER, claims.get_expires_at()).unwrap_err();
+    assert_eq!(error.to_string(), "invalid access token: expired");
+    let error = AccessTokenClaims::verify(&token, &key_set, "https://moscow-centre.example", Utc::now()).unwrap_err();
+    assert_eq!(error.to_string(), "invalid access token: unexpected issuer");
+}
+
+#[async_std::test]
+async fn test_tampered_tokens_are_refused() {
+    let connection_pool = seeded_database(SEED).await;
+    let token_service = token_service(&connection_pool);
+    let token = signed_token(&connection_pool, &token_service, TokenAlgorithm::EdDsa).await;
+    let key_set = token_service.get_published_keys().await.unwrap();
+
+    // the claims with a later expiry, under the signature of the original ones
+    let parts: Vec<&str> = token.split('.').collect();
+    let mut claims: serde_json::Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+    claims["exp"] = serde_json::json!(claims["exp"].as_i64().unwrap() + 3600);
+    let tampered = format!("{}.{}.{}", parts[0], URL_SAFE_NO_PAD.encode(claims.to_string()), parts[2]);
+    assert_eq!(refusal(&tampered, &key_set), "invalid access token: bad signature");
+
+    // the original claims under someone else's signature
+    let signature = URL_SAFE_NO_PAD.encode([7u8; 64]);
+    assert_eq!(refusal(&format!("{}.{}.{}", parts[0], parts[1], signature), &key_set), "invalid access token: bad signature");
+    // a token without its signature
+    assert_eq!(refusal(&format!("{}.{}", parts[0], parts[1]), &key_set), "invalid access token: malformed");
+}
+
+#[async_std::test]
+async fn test_the_published_key_cannot_be_used_as_an_hs256_secret() {
+    let connection_pool = seeded_database(SEED).await;
+    let token_service = token_service(&connection_pool);
+    let token = signed_token(&connection_pool, &token_service, TokenAlgorithm::EdDsa).await;
+    let key_set = token_service.get_published_keys().await.unwrap();
+    let public_key = serde_json::to_value(&key_set.get_keys()[0]).unwrap();
+    assert!(public_key.get("d").is_none());
+
+    // the same claims, MACed with the public key a verifier would be handed
+    let parts: Vec<&str> = token.split('.').collect();
+    let header = serde_json::json!({"alg": "HS256", "typ": "JWT", "kid": public_key["kid"]});
+    let signing_input = format!("{}.{}", URL_SAFE_NO_PAD.encode(header.to_string()), parts[1]);
+    let mut mac = Hmac::<Sha256>::new_from_slice(public_key["x"].as_str().unwrap().as_bytes()).unwrap();
+    mac.update(signing_input.as_bytes());
+    let forged = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()));
+    assert_eq!(refusal(&forged, &key_set), "invalid access token: algorithm does not match the key");
+}
+
+#[async_std::test]
+async fn test_hs256_keys_are_never_published() {
+    let connection_pool = seeded_database(SEED).await;
+    let token_service = token_service(&connection_pool);
+    let token = signed_token(&connection_pool, &token_service, TokenAlgorithm::Hs256).await;
+
+    let published = token_service.get_published_keys().await.unwrap();
+    assert!(published.get_keys().is_empty());
+    assert_eq!(refusal(&token, &published), "invalid access token: unknown key");
+}
+
+#[async_std::test]
+async fn test_tokens_outlive_a_rotation_until_their_key_is_pruned() {
+    let connection_pool = seeded_database(SEED).await;
+    let token_service = token_service(&connection_pool);
+    let old_token = signed_token(&connection_pool, &token_service, TokenAlgorithm::EdDsa).await;
+    let new_token = signed_token(&connection_pool, &token_service, TokenAlgorithm::EdDsa).await;
+
+    let mut key_set = token_service.get_published_keys().await.unwrap();
+    assert_eq!(key_set.get_keys().len(), 2);
+    assert!(key_set.get_keys()[1].get_retired_at().is_some());
+    AccessTokenClaims::verify(&old_token, &key_set, ISSUER, Utc::now()).unwrap();
+    AccessTokenClaims::verify(&new_token, &key_set, ISSUER, Utc::now()).unwrap();
+
+    // once every token the retired key signed has expired it can go
+    let pruned = key_set.prune(Utc::now() + Duration::seconds(1));
+    assert_eq!(pruned.len(), 1);
+    assert_eq!(refusal(&old_token, &key_set), "invalid access token: unknown key");
+    AccessTokenClaims::verify(&new_token, &key_set, ISSUER, Utc::now()).unwrap();
+}
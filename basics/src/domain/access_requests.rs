@@ -0,0 +1,291 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use super::repositories::Error;
+use super::roles::RoleId;
+use super::subjects::{RoleAssignment, SubjectId};
+use super::tenants::TenantId;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AccessRequestId(String);
+
+impl Default for AccessRequestId {
+    fn default() -> Self {
+        AccessRequestId(Uuid::new_v4().to_string())
+    }
+}
+
+impl From<String> for AccessRequestId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<AccessRequestId> for String {
+    fn from(value: AccessRequestId) -> Self {
+        value.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AccessRequestState {
+    Pending,
+    Approved,
+    Rejected,
+    Expired,
+}
+
+impl fmt::Display for AccessRequestState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            AccessRequestState::Pending => "pending",
+            AccessRequestState::Approved => "approved",
+            AccessRequestState::Rejected => "rejected",
+            AccessRequestState::Expired => "expired",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+impl TryFrom<String> for AccessRequestState {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "pending" => Ok(AccessRequestState::Pending),
+            "approved" => Ok(AccessRequestState::Approved),
+            "rejected" => Ok(AccessRequestState::Rejected),
+            "expired" => Ok(AccessRequestState::Expired),
+            _ => Err(Error::Simple(format!("unknown access request state: {}", value))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessRequest {
+    id: AccessRequestId,
+    tenant_id: TenantId,
+    subject_id: SubjectId,
+    role_id: RoleId,
+    state: AccessRequestState,
+    valid_until: Option<DateTime<Utc>>,
+    decided_by: Option<SubjectId>,
+    decided_at: Option<DateTime<Utc>>,
+    expires_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl AccessRequest {
+    // `valid_until` bounds the grant once approved, `expires_at` bounds how long the request waits for a decision
+    pub fn new(
+        tenant_id: TenantId,
+        subject_id: SubjectId,
+        role_id: RoleId,
+        valid_until: Option<DateTime<Utc>>,
+        expires_at: DateTime<Utc>,
+    ) -> AccessRequest {
+        AccessRequest {
+            id: AccessRequestId::default(),
+            tenant_id,
+            subject_id,
+            role_id,
+            state: AccessRequestState::Pending,
+            valid_until,
+            decided_by: None,
+            decided_at: None,
+            expires_at,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn builder() -> AccessRequestBuilder {
+        AccessRequestBuilder::new()
+    }
+
+    pub fn get_id(&self) -> AccessRequestId {
+        self.id.clone()
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_subject_id(&self) -> SubjectId {
+        self.subject_id.clone()
+    }
+
+    pub fn get_role_id(&self) -> RoleId {
+        self.role_id.clone()
+    }
+
+    pub fn get_state(&self) -> AccessRequestState {
+        self.state
+    }
+
+    pub fn get_valid_until(&self) -> Option<DateTime<Utc>> {
+        self.valid_until
+    }
+
+    pub fn get_decided_by(&self) -> Option<SubjectId> {
+        self.decided_by.clone()
+    }
+
+    pub fn get_decided_at(&self) -> Option<DateTime<Utc>> {
+        self.decided_at
+    }
+
+    pub fn get_expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn get_updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    // a grant that would have ended already is refused rather than handed out with its end before its start
+    pub fn approve(&mut self, approver: SubjectId, at: DateTime<Utc>) -> Result<RoleAssignment, Error> {
+        self.ensure_pending()?;
+        if let Some(valid_until) = self.valid_until.filter(|valid_until| *valid_until <= at) {
+            return Err(Error::Simple(format!("access request {} grants the role only until {}, which has passed", self.id.0, valid_until)));
+        }
+        self.decide(approver, at, AccessRequestState::Approved)?;
+        Ok(RoleAssignment::bounded(self.role_id.clone(), Some(at), self.valid_until))
+    }
+
+    pub fn reject(&mut self, approver: SubjectId, at: DateTime<Utc>) -> Result<(), Error> {
+        self.decide(approver, at, AccessRequestState::Rejected)
+    }
+
+    pub fn expire(&mut self, at: DateTime<Utc>) -> Result<(), Error> {
+        self.ensure_pending()?;
+        if at < self.expires_at {
+            return Err(Error::Simple(format!("access request {} does not expire until {}", self.id.0, self.expires_at)));
+        }
+        self.state = AccessRequestState::Expired;
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    fn decide(&mut self, approver: SubjectId, at: DateTime<Utc>, state: AccessRequestState) -> Result<(), Error> {
+        self.ensure_pending()?;
+        if at >= self.expires_at {
+            return Err(Error::Simple(format!("access request {} expired at {}", self.id.0, self.expires_at)));
+        }
+        if approver == self.subject_id {
+            return Err(Error::Simple(format!("access request {} cannot be decided by its requester", self.id.0)));
+        }
+        self.state = state;
+        self.decided_by = Some(approver);
+        self.decided_at = Some(at);
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    fn ensure_pending(&self) -> Result<(), Error> {
+        match self.state {
+            AccessRequestState::Pending => Ok(()),
+            state => Err(Error::Simple(format!("access request {} is already {}", self.id.0, state))),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AccessRequestBuilder {
+    id: Option<AccessRequestId>,
+    tenant_id: Option<TenantId>,
+    subject_id: Option<SubjectId>,
+    role_id: Option<RoleId>,
+    state: Option<AccessRequestState>,
+    valid_until: Option<DateTime<Utc>>,
+    decided_by: Option<SubjectId>,
+    decided_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl AccessRequestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn id(mut self, id: AccessRequestId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn subject_id(mut self, subject_id: SubjectId) -> Self {
+        self.subject_id = Some(subject_id);
+        self
+    }
+
+    pub fn role_id(mut self, role_id: RoleId) -> Self {
+        self.role_id = Some(role_id);
+        self
+    }
+
+    pub fn state(mut self, state: AccessRequestState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    pub fn valid_until(mut self, valid_until: Option<DateTime<Utc>>) -> Self {
+        self.valid_until = valid_until;
+        self
+    }
+
+    pub fn decided_by(mut self, decided_by: Option<SubjectId>) -> Self {
+        self.decided_by = decided_by;
+        self
+    }
+
+    pub fn decided_at(mut self, decided_at: Option<DateTime<Utc>>) -> Self {
+        self.decided_at = decided_at;
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn updated_at(mut self, updated_at: DateTime<Utc>) -> Self {
+        self.updated_at = Some(updated_at);
+        self
+    }
+
+    pub fn build(self) -> AccessRequest {
+        AccessRequest {
+            id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
+            subject_id: self.subject_id.unwrap(),
+            role_id: self.role_id.unwrap(),
+            state: self.state.unwrap(),
+            valid_until: self.valid_until,
+            decided_by: self.decided_by,
+            decided_at: self.decided_at,
+            expires_at: self.expires_at.unwrap(),
+            created_at: self.created_at.unwrap(),
+            updated_at: self.updated_at.unwrap(),
+        }
+    }
+}
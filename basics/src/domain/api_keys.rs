@@ -0,0 +1,266 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use super::repositories::Error;
+use super::secrets::{format_token, hash_secret, parse_token, random_hex, verify_secret};
+use super::subjects::{Subject, SubjectId, SubjectKind};
+use super::tenants::TenantId;
+
+// every key reads `bsk_<prefix>_<secret>`
+const KEY_SCHEME: &str = "bsk";
+const PREFIX_BYTES: usize = 6;
+const SECRET_BYTES: usize = 32;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ApiKeyId(String);
+
+impl Default for ApiKeyId {
+    fn default() -> Self {
+        ApiKeyId(Uuid::new_v4().to_string())
+    }
+}
+
+impl From<String> for ApiKeyId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<ApiKeyId> for String {
+    fn from(value: ApiKeyId) -> Self {
+        value.0
+    }
+}
+
+// splits a key into its lookup prefix and its secret
+pub fn parse_api_key(api_key: &str) -> Option<(String, String)> {
+    parse_token(KEY_SCHEME, api_key)
+}
+
+// a key a service account authenticates with. only the prefix and a hash of the secret are kept: the
+// key itself is handed out once, when it is issued
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    id: ApiKeyId,
+    tenant_id: TenantId,
+    subject_id: SubjectId,
+    name: String,
+    prefix: String,
+    hash: String,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl ApiKey {
+    // the key together with its plaintext
+    pub fn issue(subject: &Subject, name: &str, expires_at: Option<DateTime<Utc>>) -> Result<(ApiKey, String), Error> {
+        if subject.get_kind() != SubjectKind::ServiceAccount {
+            return Err(Error::Simple(format!("subject {} is not a service account", subject.get_name())));
+        }
+        if subject.get_deleted_at().is_some() {
+            return Err(Error::Simple(format!("subject {} is deleted", subject.get_name())));
+        }
+        let now = Utc::now();
+        if expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(Error::Simple(format!("an api key cannot expire in the past ({})", expires_at.unwrap())));
+        }
+
+        let prefix = random_hex(PREFIX_BYTES);
+        let secret = random_hex(SECRET_BYTES);
+        let api_key = ApiKey {
+            id: ApiKeyId::default(),
+            tenant_id: subject.get_tenant_id(),
+            subject_id: subject.get_id(),
+            name: name.to_string(),
+            prefix: prefix.clone(),
+            hash: hash_secret(&secret),
+            expires_at,
+            last_used_at: None,
+            revoked_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        Ok((api_key, format_token(KEY_SCHEME, &prefix, &secret)))
+    }
+
+    pub fn builder() -> ApiKeyBuilder {
+        ApiKeyBuilder::new()
+    }
+
+    pub fn get_id(&self) -> ApiKeyId {
+        self.id.clone()
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_subject_id(&self) -> SubjectId {
+        self.subject_id.clone()
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn get_prefix(&self) -> String {
+        self.prefix.clone()
+    }
+
+    pub fn get_hash(&self) -> String {
+        self.hash.clone()
+    }
+
+    pub fn get_expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    pub fn get_last_used_at(&self) -> Option<DateTime<Utc>> {
+        self.last_used_at
+    }
+
+    pub fn get_revoked_at(&self) -> Option<DateTime<Utc>> {
+        self.revoked_at
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn get_updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    pub fn is_usable_at(&self, at: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| at < expires_at)
+    }
+
+    pub fn verify(&self, secret: &str) -> bool {
+        verify_secret(secret, &self.hash)
+    }
+
+    // only ever brings the expiry closer
+    pub fn expire(&mut self, at: DateTime<Utc>) {
+        if self.expires_at.is_none_or(|expires_at| at < expires_at) {
+            self.expires_at = Some(at);
+            self.updated_at = Utc::now();
+        }
+    }
+
+    pub fn revoke(&mut self, at: DateTime<Utc>) -> Result<(), Error> {
+        if self.revoked_at.is_some() {
+            return Err(Error::Simple(format!("api key {} is already revoked", self.name)));
+        }
+        self.revoked_at = Some(at);
+        self.updated_at = at;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct ApiKeyBuilder {
+    id: Option<ApiKeyId>,
+    tenant_id: Option<TenantId>,
+    subject_id: Option<SubjectId>,
+    name: Option<String>,
+    prefix: Option<String>,
+    hash: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKeyBuilder {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            tenant_id: None,
+            subject_id: None,
+            name: None,
+            prefix: None,
+            hash: None,
+            expires_at: None,
+            last_used_at: None,
+            revoked_at: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    pub fn id(mut self, id: ApiKeyId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn subject_id(mut self, subject_id: SubjectId) -> Self {
+        self.subject_id = Some(subject_id);
+        self
+    }
+
+    pub fn name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn prefix(mut self, prefix: String) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    pub fn hash(mut self, hash: String) -> Self {
+        self.hash = Some(hash);
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: Option<DateTime<Utc>>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    pub fn last_used_at(mut self, last_used_at: Option<DateTime<Utc>>) -> Self {
+        self.last_used_at = last_used_at;
+        self
+    }
+
+    pub fn revoked_at(mut self, revoked_at: Option<DateTime<Utc>>) -> Self {
+        self.revoked_at = revoked_at;
+        self
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn updated_at(mut self, updated_at: DateTime<Utc>) -> Self {
+        self.updated_at = Some(updated_at);
+        self
+    }
+
+    pub fn build(self) -> ApiKey {
+        ApiKey {
+            id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
+            subject_id: self.subject_id.unwrap(),
+            name: self.name.unwrap(),
+            prefix: self.prefix.unwrap(),
+            hash: self.hash.unwrap(),
+            expires_at: self.expires_at,
+            last_used_at: self.last_used_at,
+            revoked_at: self.revoked_at,
+            created_at: self.created_at.unwrap(),
+            updated_at: self.updated_at.unwrap(),
+        }
+    }
+}
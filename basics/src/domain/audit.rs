@@ -0,0 +1,133 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use super::resources::ResourceId;
+use super::roles::RoleId;
+use super::subjects::SubjectId;
+use super::tenants::TenantId;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AuditRecordId(String);
+
+impl Default for AuditRecordId {
+    fn default() -> Self {
+        AuditRecordId(Uuid::new_v4().to_string())
+    }
+}
+
+impl From<String> for AuditRecordId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<AuditRecordId> for String {
+    fn from(value: AuditRecordId) -> Self {
+        value.0
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum AuditEvent {
+    RoleAssignmentExpired {
+        subject_id: SubjectId,
+        role_id: RoleId,
+        #[serde(with = "chrono::serde::ts_milliseconds_option")]
+        valid_until: Option<DateTime<Utc>>,
+    },
+    ResourceOwnershipTransferred {
+        resource_id: ResourceId,
+        previous_owner_id: Option<SubjectId>,
+        owner_id: SubjectId,
+        transferred_by: SubjectId,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    id: AuditRecordId,
+    tenant_id: TenantId,
+    event: AuditEvent,
+    created_at: DateTime<Utc>,
+}
+
+impl AuditRecord {
+    pub fn new(tenant_id: TenantId, event: AuditEvent) -> AuditRecord {
+        AuditRecord {
+            id: AuditRecordId::default(),
+            tenant_id,
+            event,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn builder() -> AuditRecordBuilder {
+        AuditRecordBuilder::new()
+    }
+
+    pub fn get_id(&self) -> AuditRecordId {
+        self.id.clone()
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_event(&self) -> AuditEvent {
+        self.event.clone()
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+#[derive(Default)]
+pub struct AuditRecordBuilder {
+    id: Option<AuditRecordId>,
+    tenant_id: Option<TenantId>,
+    event: Option<AuditEvent>,
+    created_at: Option<DateTime<Utc>>,
+}
+
+impl AuditRecordBuilder {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            tenant_id: None,
+            event: None,
+            created_at: None,
+        }
+    }
+
+    pub fn id(mut self, id: AuditRecordId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn event(mut self, event: AuditEvent) -> Self {
+        self.event = Some(event);
+        self
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn build(self) -> AuditRecord {
+        AuditRecord {
+            id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
+            event: self.event.unwrap(),
+            created_at: self.created_at.unwrap(),
+        }
+    }
+}
@@ -0,0 +1,758 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use super::repositories::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    List(Vec<AttributeValue>),
+}
+
+impl AttributeValue {
+    pub fn get_type(&self) -> Type {
+        match self {
+            AttributeValue::Bool(_) => Type::Bool,
+            AttributeValue::Int(_) => Type::Int,
+            AttributeValue::String(_) => Type::String,
+            AttributeValue::List(values) => Type::List(Box::new(
+                values.first().map_or(Type::Dynamic, AttributeValue::get_type),
+            )),
+        }
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        AttributeValue::Bool(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Int(value)
+    }
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::String(value.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+pub type Attributes = HashMap<String, AttributeValue>;
+
+// attributes describing the request being checked, e.g. the hour it was made or the network it came from
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    attributes: Attributes,
+}
+
+impl RequestContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &str, value: impl Into<AttributeValue>) -> Self {
+        self.attributes.insert(name.to_string(), value.into());
+        self
+    }
+
+    pub fn get_attributes(&self) -> Attributes {
+        self.attributes.clone()
+    }
+}
+
+// attributes are only known at check time, so they type as `any` and are checked once their values are in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Bool,
+    Int,
+    String,
+    List(Box<Type>),
+    Dynamic,
+}
+
+impl Type {
+    fn is_compatible_with(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Dynamic, _) | (_, Type::Dynamic) => true,
+            (Type::List(left), Type::List(right)) => left.is_compatible_with(right),
+            (left, right) => left == right,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Bool => write!(f, "bool"),
+            Type::Int => write!(f, "int"),
+            Type::String => write!(f, "string"),
+            Type::List(element) => write!(f, "list<{}>", element),
+            Type::Dynamic => write!(f, "any"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Subject,
+    Resource,
+    Context,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scope = match self {
+            Scope::Subject => "subject",
+            Scope::Resource => "resource",
+            Scope::Context => "context",
+        };
+        write!(f, "{}", scope)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOperator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    In,
+    And,
+    Or,
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessOrEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterOrEqual => ">=",
+            BinaryOperator::In => "in",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ExpressionKind {
+    Literal(AttributeValue),
+    List(Vec<Expression>),
+    Attribute(Scope, String),
+    Not(Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Expression {
+    kind: ExpressionKind,
+    column: usize,
+}
+
+// a boolean expression over `subject.*`, `resource.*` and `context.*` attributes, e.g.
+//
+//   subject.department == resource.department && context.hour >= 9 && context.hour < 17
+//
+// supports bool, int, string and list literals, `==`, `!=`, `<`, `<=`, `>`, `>=`, `in`, `!`, `&&`, `||`
+// and parentheses
+#[derive(Debug, Clone)]
+pub struct Condition {
+    source: String,
+    expression: Expression,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, Error> {
+        Self::parse_spanned(source).map_err(|error| condition_error(source, error.column, &error.message))
+    }
+
+    // like `parse`, but leaves it to the caller to place the error within a larger document
+    pub(crate) fn parse_spanned(source: &str) -> Result<Condition, SyntaxError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { source, tokens, position: 0 };
+
+        let expression = parser.parse_or()?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(syntax_error(token.column, &format!("unexpected {}", token.kind)));
+        }
+
+        let found = type_of(&expression)?;
+        if !found.is_compatible_with(&Type::Bool) {
+            return Err(syntax_error(1, &format!("condition must be a bool, found {}", found)));
+        }
+
+        Ok(Condition {
+            source: source.to_string(),
+            expression,
+        })
+    }
+
+    pub fn get_source(&self) -> String {
+        self.source.clone()
+    }
+
+    pub fn evaluate(&self, subject: &Attributes, resource: &Attributes, context: &RequestContext) -> Result<bool, Error> {
+        let environment = Environment { subject, resource, context: &context.attributes };
+        match evaluate(&self.source, &self.expression, &environment)? {
+            AttributeValue::Bool(holds) => Ok(holds),
+            value => Err(condition_error(&self.source, 1, &format!("condition must be a bool, found {}", value.get_type()))),
+        }
+    }
+}
+
+// conditions are equal when they read the same once formatted, whatever spacing their sources used
+impl PartialEq for Condition {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+// the canonical form: single spaces around operators and only the parentheses precedence requires
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl Expression {
+    fn precedence(&self) -> u8 {
+        match &self.kind {
+            ExpressionKind::Binary(BinaryOperator::Or, ..) => 1,
+            ExpressionKind::Binary(BinaryOperator::And, ..) => 2,
+            ExpressionKind::Not(_) => 3,
+            ExpressionKind::Binary(..) => 4,
+            _ => 5,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, parenthesize: bool) -> fmt::Result {
+        if parenthesize {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExpressionKind::Literal(value) => write!(f, "{}", value),
+            ExpressionKind::List(elements) => {
+                write!(f, "[")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            },
+            ExpressionKind::Attribute(scope, name) => write!(f, "{}.{}", scope, name),
+            ExpressionKind::Not(operand) => {
+                // `!a == b` would parse the same, but reads as if `!` applied to `a` alone
+                write!(f, "!")?;
+                operand.fmt_operand(f, matches!(operand.kind, ExpressionKind::Binary(..)))
+            },
+            ExpressionKind::Binary(operator, left, right) => {
+                let precedence = self.precedence();
+                // `&&` and `||` associate to the left; comparisons do not chain at all
+                let (left_parens, right_parens) = match operator {
+                    BinaryOperator::And | BinaryOperator::Or => (left.precedence() < precedence, right.precedence() <= precedence),
+                    _ => (left.precedence() <= precedence, right.precedence() <= precedence),
+                };
+                left.fmt_operand(f, left_parens)?;
+                write!(f, " {} ", operator)?;
+                right.fmt_operand(f, right_parens)
+            },
+        }
+    }
+}
+
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeValue::Bool(value) => write!(f, "{}", value),
+            AttributeValue::Int(value) => write!(f, "{}", value),
+            AttributeValue::String(value) => write!(f, "\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+            AttributeValue::List(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+        }
+    }
+}
+
+fn condition_error(source: &str, column: usize, message: &str) -> Error {
+    Error::Simple(format!("condition {:?}, column {}: {}", source, column, message))
+}
+
+#[derive(Debug)]
+pub(crate) struct SyntaxError {
+    pub(crate) column: usize,
+    pub(crate) message: String,
+}
+
+fn syntax_error(column: usize, message: &str) -> SyntaxError {
+    SyntaxError {
+        column,
+        message: message.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Int(i64),
+    String(String),
+    Identifier(String),
+    Dot,
+    Comma,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    Operator(BinaryOperator),
+    Bang,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Int(value) => write!(f, "{}", value),
+            TokenKind::String(value) => write!(f, "{:?}", value),
+            TokenKind::Identifier(name) => write!(f, "`{}`", name),
+            TokenKind::Dot => write!(f, "`.`"),
+            TokenKind::Comma => write!(f, "`,`"),
+            TokenKind::OpenParen => write!(f, "`(`"),
+            TokenKind::CloseParen => write!(f, "`)`"),
+            TokenKind::OpenBracket => write!(f, "`[`"),
+            TokenKind::CloseBracket => write!(f, "`]`"),
+            TokenKind::Operator(operator) => write!(f, "`{}`", operator),
+            TokenKind::Bang => write!(f, "`!`"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, SyntaxError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < chars.len() {
+        let column = position + 1;
+        let c = chars[position];
+        let next = chars.get(position + 1).copied();
+
+        let kind = match (c, next) {
+            _ if c.is_whitespace() => {
+                position += 1;
+                continue;
+            },
+            ('=', Some('=')) => TokenKind::Operator(BinaryOperator::Equal),
+            ('!', Some('=')) => TokenKind::Operator(BinaryOperator::NotEqual),
+            ('<', Some('=')) => TokenKind::Operator(BinaryOperator::LessOrEqual),
+            ('>', Some('=')) => TokenKind::Operator(BinaryOperator::GreaterOrEqual),
+            ('&', Some('&')) => TokenKind::Operator(BinaryOperator::And),
+            ('|', Some('|')) => TokenKind::Operator(BinaryOperator::Or),
+            ('<', _) => TokenKind::Operator(BinaryOperator::Less),
+            ('>', _) => TokenKind::Operator(BinaryOperator::Greater),
+            ('!', _) => TokenKind::Bang,
+            ('.', _) => TokenKind::Dot,
+            (',', _) => TokenKind::Comma,
+            ('(', _) => TokenKind::OpenParen,
+            (')', _) => TokenKind::CloseParen,
+            ('[', _) => TokenKind::OpenBracket,
+            (']', _) => TokenKind::CloseBracket,
+            ('"', _) => {
+                let mut value = String::new();
+                position += 1;
+                loop {
+                    match chars.get(position) {
+                        None => return Err(syntax_error(column, "unterminated string")),
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.get(position + 1) {
+                                Some(escaped @ ('"' | '\\')) => value.push(*escaped),
+                                _ => return Err(syntax_error(position + 1, "unsupported escape sequence")),
+                            }
+                            position += 2;
+                        },
+                        Some(c) => {
+                            value.push(*c);
+                            position += 1;
+                        },
+                    }
+                }
+                TokenKind::String(value)
+            },
+            _ if c.is_ascii_digit() || (c == '-' && next.is_some_and(|next| next.is_ascii_digit())) => {
+                let start = position;
+                position += 1;
+                while chars.get(position).is_some_and(|c| c.is_ascii_digit()) {
+                    position += 1;
+                }
+                let literal: String = chars[start..position].iter().collect();
+                let value = literal.parse()
+                    .map_err(|_| syntax_error(column, &format!("integer {} is out of range", literal)))?;
+                tokens.push(Token { kind: TokenKind::Int(value), column });
+                continue;
+            },
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let start = position;
+                while chars.get(position).is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    position += 1;
+                }
+                let name: String = chars[start..position].iter().collect();
+                let kind = match name.as_str() {
+                    "in" => TokenKind::Operator(BinaryOperator::In),
+                    _ => TokenKind::Identifier(name),
+                };
+                tokens.push(Token { kind, column });
+                continue;
+            },
+            _ => return Err(syntax_error(column, &format!("unexpected character {:?}", c))),
+        };
+
+        position += match kind {
+            TokenKind::Operator(BinaryOperator::Equal)
+            | TokenKind::Operator(BinaryOperator::NotEqual)
+            | TokenKind::Operator(BinaryOperator::LessOrEqual)
+            | TokenKind::Operator(BinaryOperator::GreaterOrEqual)
+            | TokenKind::Operator(BinaryOperator::And)
+            | TokenKind::Operator(BinaryOperator::Or) => 2,
+            _ => 1,
+        };
+        tokens.push(Token { kind, column });
+    }
+
+    Ok(tokens)
+}
+
+fn binary(operator: BinaryOperator, left: Expression, right: Expression) -> Expression {
+    let column = left.column;
+    Expression {
+        kind: ExpressionKind::Binary(operator, Box::new(left), Box::new(right)),
+        column,
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn column(&self) -> usize {
+        match self.tokens.get(self.position) {
+            Some(token) => token.column,
+            None => self.source.chars().count() + 1,
+        }
+    }
+
+    fn error(&self, expected: &str) -> SyntaxError {
+        let found = match self.peek() {
+            Some(kind) => kind.to_string(),
+            None => "end of condition".to_string(),
+        };
+        syntax_error(self.column(), &format!("expected {}, found {}", expected, found))
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &str) -> Result<(), SyntaxError> {
+        if self.peek() == Some(&kind) {
+            self.position += 1;
+            return Ok(());
+        }
+        Err(self.error(expected))
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, SyntaxError> {
+        let mut expression = self.parse_and()?;
+        while self.peek() == Some(&TokenKind::Operator(BinaryOperator::Or)) {
+            self.position += 1;
+            let right = self.parse_and()?;
+            expression = binary(BinaryOperator::Or, expression, right);
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, SyntaxError> {
+        let mut expression = self.parse_not()?;
+        while self.peek() == Some(&TokenKind::Operator(BinaryOperator::And)) {
+            self.position += 1;
+            let right = self.parse_not()?;
+            expression = binary(BinaryOperator::And, expression, right);
+        }
+        Ok(expression)
+    }
+
+    fn parse_not(&mut self) -> Result<Expression, SyntaxError> {
+        if self.peek() == Some(&TokenKind::Bang) {
+            let column = self.column();
+            self.position += 1;
+            let operand = self.parse_not()?;
+            return Ok(Expression { kind: ExpressionKind::Not(Box::new(operand)), column });
+        }
+        self.parse_comparison()
+    }
+
+    // comparisons do not chain: `a == b == c` is rejected
+    fn parse_comparison(&mut self) -> Result<Expression, SyntaxError> {
+        let left = self.parse_primary()?;
+        match self.peek() {
+            Some(TokenKind::Operator(operator)) if !matches!(operator, BinaryOperator::And | BinaryOperator::Or) => {
+                let operator = *operator;
+                self.position += 1;
+                let right = self.parse_primary()?;
+                Ok(binary(operator, left, right))
+            },
+            _ => Ok(left),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, SyntaxError> {
+        let column = self.column();
+        let kind = match self.peek().cloned() {
+            Some(TokenKind::Int(value)) => {
+                self.position += 1;
+                ExpressionKind::Literal(AttributeValue::Int(value))
+            },
+            Some(TokenKind::String(value)) => {
+                self.position += 1;
+                ExpressionKind::Literal(AttributeValue::String(value))
+            },
+            Some(TokenKind::OpenParen) => {
+                self.position += 1;
+                let expression = self.parse_or()?;
+                self.expect(TokenKind::CloseParen, "`)`")?;
+                return Ok(expression);
+            },
+            Some(TokenKind::OpenBracket) => {
+                self.position += 1;
+                let mut elements = Vec::new();
+                while self.peek() != Some(&TokenKind::CloseBracket) {
+                    elements.push(self.parse_primary()?);
+                    if self.peek() != Some(&TokenKind::CloseBracket) {
+                        self.expect(TokenKind::Comma, "`,` or `]`")?;
+                    }
+                }
+                self.position += 1;
+                ExpressionKind::List(elements)
+            },
+            Some(TokenKind::Identifier(name)) => {
+                self.position += 1;
+                let scope = match name.as_str() {
+                    "true" => return Ok(Expression { kind: ExpressionKind::Literal(AttributeValue::Bool(true)), column }),
+                    "false" => return Ok(Expression { kind: ExpressionKind::Literal(AttributeValue::Bool(false)), column }),
+                    "subject" => Scope::Subject,
+                    "resource" => Scope::Resource,
+                    "context" => Scope::Context,
+                    _ => {
+                        return Err(syntax_error(
+                            column,
+                            &format!("unknown name `{}`, attributes start with `subject.`, `resource.` or `context.`", name),
+                        ));
+                    },
+                };
+                self.expect(TokenKind::Dot, "`.`")?;
+                match self.peek().cloned() {
+                    Some(TokenKind::Identifier(attribute)) => {
+                        self.position += 1;
+                        ExpressionKind::Attribute(scope, attribute)
+                    },
+                    _ => return Err(self.error("attribute name")),
+                }
+            },
+            _ => return Err(self.error("a value, an attribute, `!`, `(` or `[`")),
+        };
+        Ok(Expression { kind, column })
+    }
+}
+
+fn type_of(expression: &Expression) -> Result<Type, SyntaxError> {
+    let expect = |found: Type, expected: Type, column: usize, what: &str| {
+        if found.is_compatible_with(&expected) {
+            Ok(())
+        } else {
+            Err(syntax_error(column, &format!("{} expects {}, found {}", what, expected, found)))
+        }
+    };
+
+    match &expression.kind {
+        ExpressionKind::Literal(value) => Ok(value.get_type()),
+        ExpressionKind::Attribute(..) => Ok(Type::Dynamic),
+        ExpressionKind::List(elements) => {
+            let mut element_type = Type::Dynamic;
+            for element in elements {
+                let found = type_of(element)?;
+                if !found.is_compatible_with(&element_type) {
+                    return Err(syntax_error(
+                        element.column,
+                        &format!("list elements must share a type, found {} after {}", found, element_type),
+                    ));
+                }
+                if element_type == Type::Dynamic {
+                    element_type = found;
+                }
+            }
+            Ok(Type::List(Box::new(element_type)))
+        },
+        ExpressionKind::Not(operand) => {
+            expect(type_of(operand)?, Type::Bool, operand.column, "`!`")?;
+            Ok(Type::Bool)
+        },
+        ExpressionKind::Binary(operator, left, right) => {
+            let left_type = type_of(left)?;
+            let right_type = type_of(right)?;
+            let what = format!("`{}`", operator);
+            match operator {
+                BinaryOperator::And | BinaryOperator::Or => {
+                    expect(left_type, Type::Bool, left.column, &what)?;
+                    expect(right_type, Type::Bool, right.column, &what)?;
+                },
+                BinaryOperator::Less | BinaryOperator::LessOrEqual | BinaryOperator::Greater | BinaryOperator::GreaterOrEqual => {
+                    expect(left_type, Type::Int, left.column, &what)?;
+                    expect(right_type, Type::Int, right.column, &what)?;
+                },
+                BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                    if !left_type.is_compatible_with(&right_type) {
+                        return Err(syntax_error(
+                            left.column,
+                            &format!("cannot compare {} with {}", left_type, right_type),
+                        ));
+                    }
+                },
+                BinaryOperator::In => match right_type {
+                    Type::List(element_type) => expect(left_type, *element_type, left.column, &what)?,
+                    Type::Dynamic => {},
+                    found => return Err(syntax_error(right.column, &format!("`in` expects a list, found {}", found))),
+                },
+            }
+            Ok(Type::Bool)
+        },
+    }
+}
+
+struct Environment<'a> {
+    subject: &'a Attributes,
+    resource: &'a Attributes,
+    context: &'a Attributes,
+}
+
+fn evaluate(source: &str, expression: &Expression, environment: &Environment) -> Result<AttributeValue, Error> {
+    let as_bool = |value: AttributeValue, column: usize, what: &str| match value {
+        AttributeValue::Bool(value) => Ok(value),
+        value => Err(condition_error(source, column, &format!("{} expects bool, found {}", what, value.get_type()))),
+    };
+    let as_int = |value: AttributeValue, column: usize, what: &str| match value {
+        AttributeValue::Int(value) => Ok(value),
+        value => Err(condition_error(source, column, &format!("{} expects int, found {}", what, value.get_type()))),
+    };
+
+    match &expression.kind {
+        ExpressionKind::Literal(value) => Ok(value.clone()),
+        ExpressionKind::List(elements) => Ok(AttributeValue::List(
+            elements.iter()
+                .map(|element| evaluate(source, element, environment))
+                .collect::<Result<_, _>>()?,
+        )),
+        ExpressionKind::Attribute(scope, name) => {
+            let attributes = match scope {
+                Scope::Subject => environment.subject,
+                Scope::Resource => environment.resource,
+                Scope::Context => environment.context,
+            };
+            attributes.get(name)
+                .cloned()
+                .ok_or_else(|| condition_error(source, expression.column, &format!("attribute {}.{} is not set", scope, name)))
+        },
+        ExpressionKind::Not(operand) => {
+            let value = as_bool(evaluate(source, operand, environment)?, operand.column, "`!`")?;
+            Ok(AttributeValue::Bool(!value))
+        },
+        ExpressionKind::Binary(operator, left, right) => {
+            let what = format!("`{}`", operator);
+            let left_value = evaluate(source, left, environment)?;
+
+            // `&&` and `||` short-circuit, so the right side may reference attributes that are not always set
+            match operator {
+                BinaryOperator::And => {
+                    if !as_bool(left_value, left.column, &what)? {
+                        return Ok(AttributeValue::Bool(false));
+                    }
+                    let right_value = as_bool(evaluate(source, right, environment)?, right.column, &what)?;
+                    return Ok(AttributeValue::Bool(right_value));
+                },
+                BinaryOperator::Or => {
+                    if as_bool(left_value, left.column, &what)? {
+                        return Ok(AttributeValue::Bool(true));
+                    }
+                    let right_value = as_bool(evaluate(source, right, environment)?, right.column, &what)?;
+                    return Ok(AttributeValue::Bool(right_value));
+                },
+                _ => {},
+            }
+
+            let right_value = evaluate(source, right, environment)?;
+            let result = match operator {
+                BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                    let (left_type, right_type) = (left_value.get_type(), right_value.get_type());
+                    if !left_type.is_compatible_with(&right_type) {
+                        return Err(condition_error(
+                            source,
+                            left.column,
+                            &format!("cannot compare {} with {}", left_type, right_type),
+                        ));
+                    }
+                    (left_value == right_value) == (*operator == BinaryOperator::Equal)
+                },
+                BinaryOperator::Less => as_int(left_value, left.column, &what)? < as_int(right_value, right.column, &what)?,
+                BinaryOperator::LessOrEqual => as_int(left_value, left.column, &what)? <= as_int(right_value, right.column, &what)?,
+                BinaryOperator::Greater => as_int(left_value, left.column, &what)? > as_int(right_value, right.column, &what)?,
+                BinaryOperator::GreaterOrEqual => as_int(left_value, left.column, &what)? >= as_int(right_value, right.column, &what)?,
+                BinaryOperator::In => match right_value {
+                    AttributeValue::List(values) => values.contains(&left_value),
+                    value => return Err(condition_error(source, right.column, &format!("`in` expects a list, found {}", value.get_type()))),
+                },
+                BinaryOperator::And | BinaryOperator::Or => unreachable!(),
+            };
+            Ok(AttributeValue::Bool(result))
+        },
+    }
+}
@@ -0,0 +1,60 @@
+use crate::domain::conditions::{AttributeValue, Attributes, Condition, RequestContext};
+
+fn parse_error(source: &str) -> String {
+    Condition::parse(source).unwrap_err().to_string()
+}
+
+fn clearance(value: impl Into<AttributeValue>) -> Attributes {
+    [("clearance".to_string(), value.into())].into_iter().collect()
+}
+
+#[test]
+fn test_syntax_errors_point_at_the_column() {
+    let cases = [
+        ("subject.name == \"karla", "column 17: unterminated string"),
+        ("subject.clearance + 1", "column 19: unexpected character '+'"),
+        ("(context.mfa", "column 13: expected `)`, found end of condition"),
+        ("subject. == 1", "column 10: expected attribute name, found `==`"),
+        ("context.mfa &&", "column 15: expected a value, an attribute, `!`, `(` or `[`, found end of condition"),
+        ("subject.clearance < 3 < 4", "column 23: unexpected `<`"),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(parse_error(source), format!("condition {:?}, {}", source, expected));
+    }
+}
+
+#[test]
+fn test_type_errors_are_found_before_evaluation() {
+    let cases = [
+        ("1 && true", "column 1: `&&` expects bool, found int"),
+        ("true || [1, 2]", "column 9: `||` expects bool, found list<int>"),
+        ("!3", "column 2: `!` expects bool, found int"),
+        ("1 == \"one\"", "column 1: cannot compare int with string"),
+        ("\"berlin\" in 3", "column 13: `in` expects a list, found int"),
+        ("[1, \"a\"] == [1]", "column 5: list elements must share a type, found string after int"),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(parse_error(source), format!("condition {:?}, {}", source, expected));
+    }
+}
+
+// attributes are only known at check time, so their types are checked then
+#[test]
+fn test_attributes_of_the_wrong_type_fail_the_evaluation() {
+    let no_attributes = Attributes::new();
+    let context = RequestContext::default();
+
+    let condition = Condition::parse("subject.clearance >= 3").unwrap();
+    assert!(condition.evaluate(&clearance(3), &no_attributes, &context).unwrap());
+    let error = condition.evaluate(&clearance("high"), &no_attributes, &context).unwrap_err();
+    assert_eq!(error.to_string(), "condition \"subject.clearance >= 3\", column 1: `>=` expects int, found string");
+
+    let error = Condition::parse("subject.clearance").unwrap().evaluate(&clearance("high"), &no_attributes, &context).unwrap_err();
+    assert_eq!(error.to_string(), "condition \"subject.clearance\", column 1: condition must be a bool, found string");
+
+    let error = Condition::parse("context.mfa && true").unwrap().evaluate(&no_attributes, &no_attributes, &context).unwrap_err();
+    assert_eq!(error.to_string(), "condition \"context.mfa && true\", column 1: attribute context.mfa is not set");
+    assert!(Condition::parse("context.mfa && true").unwrap()
+        .evaluate(&no_attributes, &no_attributes, &RequestContext::new().with("mfa", true))
+        .unwrap());
+}
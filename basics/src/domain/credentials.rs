@@ -0,0 +1,290 @@
+use std::sync::OnceLock;
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::{DateTime, Duration, Utc};
+
+use super::repositories::Error;
+use super::subjects::{Subject, SubjectId, SubjectKind};
+use super::tenants::TenantId;
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+fn hash_password(password: &str) -> Result<String, Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|error| Error::Simple(format!("unable to hash password: {}", error)))
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+// takes as long as checking a password does, for sign-ins of subjects without one: otherwise how long
+// a refusal takes would tell which names have a password
+pub fn verify_no_password(password: &str) {
+    static NO_PASSWORD: OnceLock<String> = OnceLock::new();
+    let hash = NO_PASSWORD.get_or_init(|| hash_password("no password").expect("hashing never fails with default parameters"));
+    verify_password(password, hash);
+}
+
+// how many wrong passwords in a row lock a subject out, and for how long. the count starts over once
+// the lockout is over
+#[derive(Debug, Clone, Copy)]
+pub struct LockoutPolicy {
+    max_failed_attempts: u32,
+    lockout: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        LockoutPolicy::new(5, Duration::minutes(15))
+    }
+}
+
+impl LockoutPolicy {
+    pub fn new(max_failed_attempts: u32, lockout: Duration) -> LockoutPolicy {
+        LockoutPolicy {
+            max_failed_attempts: max_failed_attempts.max(1),
+            lockout,
+        }
+    }
+
+    pub fn get_max_failed_attempts(&self) -> u32 {
+        self.max_failed_attempts
+    }
+
+    pub fn get_lockout(&self) -> Duration {
+        self.lockout
+    }
+}
+
+// the password a person signs in with, kept as an argon2 hash, and the login that goes with it, unique
+// within the tenant. service accounts have none: they use API keys. wrong passwords are counted by the
+// repository, see `CredentialRepository`
+#[derive(Debug, Clone)]
+pub struct PasswordCredential {
+    tenant_id: TenantId,
+    subject_id: SubjectId,
+    login: String,
+    hash: String,
+    failed_attempts: u32,
+    locked_until: Option<DateTime<Utc>>,
+    last_login_at: Option<DateTime<Utc>>,
+    changed_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl PasswordCredential {
+    pub fn new(subject: &Subject, login: &str, password: &str) -> Result<PasswordCredential, Error> {
+        ensure_may_have_password(subject)?;
+        let now = Utc::now();
+        Ok(PasswordCredential {
+            tenant_id: subject.get_tenant_id(),
+            subject_id: subject.get_id(),
+            login: ensure_valid_login(login)?.to_string(),
+            hash: hash_password(ensure_strong_enough(password)?)?,
+            failed_attempts: 0,
+            locked_until: None,
+            last_login_at: None,
+            changed_at: now,
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    pub fn builder() -> PasswordCredentialBuilder {
+        PasswordCredentialBuilder::new()
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_subject_id(&self) -> SubjectId {
+        self.subject_id.clone()
+    }
+
+    pub fn get_login(&self) -> String {
+        self.login.clone()
+    }
+
+    pub fn get_hash(&self) -> String {
+        self.hash.clone()
+    }
+
+    pub fn get_failed_attempts(&self) -> u32 {
+        self.failed_attempts
+    }
+
+    pub fn get_locked_until(&self) -> Option<DateTime<Utc>> {
+        self.locked_until
+    }
+
+    pub fn get_last_login_at(&self) -> Option<DateTime<Utc>> {
+        self.last_login_at
+    }
+
+    pub fn get_changed_at(&self) -> DateTime<Utc> {
+        self.changed_at
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn get_updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    pub fn is_locked_at(&self, at: DateTime<Utc>) -> bool {
+        self.locked_until.is_some_and(|locked_until| at < locked_until)
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        verify_password(password, &self.hash)
+    }
+
+    // a new password also lifts a lockout
+    pub fn change_password(&mut self, subject: &Subject, password: &str) -> Result<(), Error> {
+        ensure_may_have_password(subject)?;
+        self.hash = hash_password(ensure_strong_enough(password)?)?;
+        self.failed_attempts = 0;
+        self.locked_until = None;
+        self.changed_at = Utc::now();
+        self.updated_at = self.changed_at;
+        Ok(())
+    }
+
+    pub fn change_login(&mut self, login: &str) -> Result<(), Error> {
+        self.login = ensure_valid_login(login)?.to_string();
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+}
+
+// logins are taken as typed, so they may not start or end with blanks
+fn ensure_valid_login(login: &str) -> Result<&str, Error> {
+    if login.is_empty() || login.trim() != login {
+        return Err(Error::Simple(format!("login {:?} is not valid", login)));
+    }
+    Ok(login)
+}
+
+fn ensure_may_have_password(subject: &Subject) -> Result<(), Error> {
+    if subject.get_kind() != SubjectKind::Person {
+        return Err(Error::Simple(format!("subject {} is not a person", subject.get_name())));
+    }
+    if subject.get_deleted_at().is_some() {
+        return Err(Error::Simple(format!("subject {} is deleted", subject.get_name())));
+    }
+    Ok(())
+}
+
+fn ensure_strong_enough(password: &str) -> Result<&str, Error> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(Error::Simple(format!("a password needs at least {} characters", MIN_PASSWORD_LENGTH)));
+    }
+    Ok(password)
+}
+
+#[derive(Default)]
+pub struct PasswordCredentialBuilder {
+    tenant_id: Option<TenantId>,
+    subject_id: Option<SubjectId>,
+    login: Option<String>,
+    hash: Option<String>,
+    failed_attempts: Option<u32>,
+    locked_until: Option<DateTime<Utc>>,
+    last_login_at: Option<DateTime<Utc>>,
+    changed_at: Option<DateTime<Utc>>,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl PasswordCredentialBuilder {
+    pub fn new() -> Self {
+        Self {
+            tenant_id: None,
+            subject_id: None,
+            login: None,
+            hash: None,
+            failed_attempts: None,
+            locked_until: None,
+            last_login_at: None,
+            changed_at: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn subject_id(mut self, subject_id: SubjectId) -> Self {
+        self.subject_id = Some(subject_id);
+        self
+    }
+
+    pub fn login(mut self, login: String) -> Self {
+        self.login = Some(login);
+        self
+    }
+
+    pub fn hash(mut self, hash: String) -> Self {
+        self.hash = Some(hash);
+        self
+    }
+
+    pub fn failed_attempts(mut self, failed_attempts: u32) -> Self {
+        self.failed_attempts = Some(failed_attempts);
+        self
+    }
+
+    pub fn locked_until(mut self, locked_until: Option<DateTime<Utc>>) -> Self {
+        self.locked_until = locked_until;
+        self
+    }
+
+    pub fn last_login_at(mut self, last_login_at: Option<DateTime<Utc>>) -> Self {
+        self.last_login_at = last_login_at;
+        self
+    }
+
+    pub fn changed_at(mut self, changed_at: DateTime<Utc>) -> Self {
+        self.changed_at = Some(changed_at);
+        self
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn updated_at(mut self, updated_at: DateTime<Utc>) -> Self {
+        self.updated_at = Some(updated_at);
+        self
+    }
+
+    pub fn build(self) -> PasswordCredential {
+        PasswordCredential {
+            tenant_id: self.tenant_id.unwrap(),
+            subject_id: self.subject_id.unwrap(),
+            login: self.login.unwrap(),
+            hash: self.hash.unwrap(),
+            failed_attempts: self.failed_attempts.unwrap_or_default(),
+            locked_until: self.locked_until,
+            last_login_at: self.last_login_at,
+            changed_at: self.changed_at.unwrap(),
+            created_at: self.created_at.unwrap(),
+            updated_at: self.updated_at.unwrap(),
+        }
+    }
+}
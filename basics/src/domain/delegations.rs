@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use super::permissions::PermissionId;
+use super::repositories::Error;
+use super::subjects::SubjectId;
+use super::tenants::TenantId;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct DelegationId(String);
+
+impl Default for DelegationId {
+    fn default() -> Self {
+        DelegationId(Uuid::new_v4().to_string())
+    }
+}
+
+impl From<String> for DelegationId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<DelegationId> for String {
+    fn from(value: DelegationId) -> Self {
+        value.0
+    }
+}
+
+// a delegator lends some of its own permissions to a delegate until `valid_until`. the delegation
+// only ever carries permission ids: whether the delegator still holds them is decided when they are used
+#[derive(Debug, Clone)]
+pub struct Delegation {
+    id: DelegationId,
+    tenant_id: TenantId,
+    delegator_id: SubjectId,
+    delegate_id: SubjectId,
+    permissions: HashSet<PermissionId>,
+    valid_until: DateTime<Utc>,
+    revoked_by: Option<SubjectId>,
+    revoked_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl Delegation {
+    pub fn new(
+        tenant_id: TenantId,
+        delegator_id: SubjectId,
+        delegate_id: SubjectId,
+        permissions: HashSet<PermissionId>,
+        valid_until: DateTime<Utc>,
+    ) -> Result<Delegation, Error> {
+        if delegator_id == delegate_id {
+            return Err(Error::Simple("a subject cannot delegate to itself".to_string()));
+        }
+        if permissions.is_empty() {
+            return Err(Error::Simple("a delegation needs at least one permission".to_string()));
+        }
+        let now = Utc::now();
+        if valid_until <= now {
+            return Err(Error::Simple(format!("a delegation cannot end in the past ({})", valid_until)));
+        }
+        Ok(Delegation {
+            id: DelegationId::default(),
+            tenant_id,
+            delegator_id,
+            delegate_id,
+            permissions,
+            valid_until,
+            revoked_by: None,
+            revoked_at: None,
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    pub fn builder() -> DelegationBuilder {
+        DelegationBuilder::new()
+    }
+
+    pub fn get_id(&self) -> DelegationId {
+        self.id.clone()
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_delegator_id(&self) -> SubjectId {
+        self.delegator_id.clone()
+    }
+
+    pub fn get_delegate_id(&self) -> SubjectId {
+        self.delegate_id.clone()
+    }
+
+    pub fn get_permissions(&self) -> HashSet<PermissionId> {
+        self.permissions.clone()
+    }
+
+    pub fn get_valid_until(&self) -> DateTime<Utc> {
+        self.valid_until
+    }
+
+    pub fn get_revoked_by(&self) -> Option<SubjectId> {
+        self.revoked_by.clone()
+    }
+
+    pub fn get_revoked_at(&self) -> Option<DateTime<Utc>> {
+        self.revoked_at
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn get_updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    // the window is half-open like role assignments: [created_at, valid_until)
+    pub fn is_active_at(&self, at: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.created_at <= at && at < self.valid_until
+    }
+
+    pub fn revoke(&mut self, revoked_by: SubjectId, at: DateTime<Utc>) -> Result<(), Error> {
+        if self.revoked_at.is_some() {
+            return Err(Error::Simple(format!("delegation {} is already revoked", String::from(self.id.clone()))));
+        }
+        self.revoked_by = Some(revoked_by);
+        self.revoked_at = Some(at);
+        self.updated_at = at;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct DelegationBuilder {
+    id: Option<DelegationId>,
+    tenant_id: Option<TenantId>,
+    delegator_id: Option<SubjectId>,
+    delegate_id: Option<SubjectId>,
+    permissions: Option<HashSet<PermissionId>>,
+    valid_until: Option<DateTime<Utc>>,
+    revoked_by: Option<SubjectId>,
+    revoked_at: Option<DateTime<Utc>>,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl DelegationBuilder {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            tenant_id: None,
+            delegator_id: None,
+            delegate_id: None,
+            permissions: None,
+            valid_until: None,
+            revoked_by: None,
+            revoked_at: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    pub fn id(mut self, id: DelegationId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn delegator_id(mut self, delegator_id: SubjectId) -> Self {
+        self.delegator_id = Some(delegator_id);
+        self
+    }
+
+    pub fn delegate_id(mut self, delegate_id: SubjectId) -> Self {
+        self.delegate_id = Some(delegate_id);
+        self
+    }
+
+    pub fn permissions(mut self, permissions: HashSet<PermissionId>) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    pub fn valid_until(mut self, valid_until: DateTime<Utc>) -> Self {
+        self.valid_until = Some(valid_until);
+        self
+    }
+
+    pub fn revoked_by(mut self, revoked_by: Option<SubjectId>) -> Self {
+        self.revoked_by = revoked_by;
+        self
+    }
+
+    pub fn revoked_at(mut self, revoked_at: Option<DateTime<Utc>>) -> Self {
+        self.revoked_at = revoked_at;
+        self
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn updated_at(mut self, updated_at: DateTime<Utc>) -> Self {
+        self.updated_at = Some(updated_at);
+        self
+    }
+
+    pub fn build(self) -> Delegation {
+        Delegation {
+            id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
+            delegator_id: self.delegator_id.unwrap(),
+            delegate_id: self.delegate_id.unwrap(),
+            permissions: self.permissions.unwrap(),
+            valid_until: self.valid_until.unwrap(),
+            revoked_by: self.revoked_by,
+            revoked_at: self.revoked_at,
+            created_at: self.created_at.unwrap(),
+            updated_at: self.updated_at.unwrap(),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use super::groups::GroupId;
+use super::resources::ResourceId;
+use super::roles::RoleId;
+use super::subjects::SubjectId;
+use super::tenants::TenantId;
+
+// the decisions a subject gets on every registered resource from its own roles, those it holds
+// directly and those of its groups, without a request context, delegations or a session. the roles
+// and groups they were derived from tell which subjects a change to a role or group concerns
+#[derive(Debug, Clone)]
+pub struct EffectivePermissions {
+    tenant_id: TenantId,
+    subject_id: SubjectId,
+    roles: HashSet<RoleId>,
+    groups: HashSet<GroupId>,
+    // whether the subject may invoke each resource
+    decisions: HashMap<ResourceId, bool>,
+    // false when a condition takes part in the subject's decisions, which then depend on the request
+    indexable: bool,
+    // the next time a role assignment of the subject starts or ends
+    valid_until: Option<DateTime<Utc>>,
+    computed_at: DateTime<Utc>,
+}
+
+impl EffectivePermissions {
+    pub fn builder() -> EffectivePermissionsBuilder {
+        EffectivePermissionsBuilder::new()
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_subject_id(&self) -> SubjectId {
+        self.subject_id.clone()
+    }
+
+    pub fn get_roles(&self) -> HashSet<RoleId> {
+        self.roles.clone()
+    }
+
+    pub fn get_groups(&self) -> HashSet<GroupId> {
+        self.groups.clone()
+    }
+
+    pub fn get_decisions(&self) -> HashMap<ResourceId, bool> {
+        self.decisions.clone()
+    }
+
+    pub fn is_indexable(&self) -> bool {
+        self.indexable
+    }
+
+    pub fn get_valid_until(&self) -> Option<DateTime<Utc>> {
+        self.valid_until
+    }
+
+    pub fn get_computed_at(&self) -> DateTime<Utc> {
+        self.computed_at
+    }
+
+    pub fn is_current_at(&self, at: DateTime<Utc>) -> bool {
+        self.indexable && self.valid_until.is_none_or(|valid_until| at < valid_until)
+    }
+}
+
+#[derive(Default)]
+pub struct EffectivePermissionsBuilder {
+    tenant_id: Option<TenantId>,
+    subject_id: Option<SubjectId>,
+    roles: Option<HashSet<RoleId>>,
+    groups: Option<HashSet<GroupId>>,
+    decisions: Option<HashMap<ResourceId, bool>>,
+    indexable: Option<bool>,
+    valid_until: Option<DateTime<Utc>>,
+    computed_at: Option<DateTime<Utc>>,
+}
+
+impl EffectivePermissionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            tenant_id: None,
+            subject_id: None,
+            roles: None,
+            groups: None,
+            decisions: None,
+            indexable: None,
+            valid_until: None,
+            computed_at: None,
+        }
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn subject_id(mut self, subject_id: SubjectId) -> Self {
+        self.subject_id = Some(subject_id);
+        self
+    }
+
+    pub fn roles(mut self, roles: HashSet<RoleId>) -> Self {
+        self.roles = Some(roles);
+        self
+    }
+
+    pub fn groups(mut self, groups: HashSet<GroupId>) -> Self {
+        self.groups = Some(groups);
+        self
+    }
+
+    pub fn decisions(mut self, decisions: HashMap<ResourceId, bool>) -> Self {
+        self.decisions = Some(decisions);
+        self
+    }
+
+    pub fn indexable(mut self, indexable: bool) -> Self {
+        self.indexable = Some(indexable);
+        self
+    }
+
+    pub fn valid_until(mut self, valid_until: Option<DateTime<Utc>>) -> Self {
+        self.valid_until = valid_until;
+        self
+    }
+
+    pub fn computed_at(mut self, computed_at: DateTime<Utc>) -> Self {
+        self.computed_at = Some(computed_at);
+        self
+    }
+
+    pub fn build(self) -> EffectivePermissions {
+        EffectivePermissions {
+            tenant_id: self.tenant_id.unwrap(),
+            subject_id: self.subject_id.unwrap(),
+            roles: self.roles.unwrap_or_default(),
+            groups: self.groups.unwrap_or_default(),
+            decisions: self.decisions.unwrap_or_default(),
+            indexable: self.indexable.unwrap(),
+            valid_until: self.valid_until,
+            computed_at: self.computed_at.unwrap(),
+        }
+    }
+}
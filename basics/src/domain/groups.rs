@@ -1,11 +1,15 @@
 use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use std::collections::HashSet;
 
+use super::repositories::Error;
 use super::subjects::SubjectId;
 use super::roles::RoleId;
+use super::separation_of_duties::SeparationOfDuties;
+use super::tenants::TenantId;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct GroupId(String);
 
 impl Default for GroupId {
@@ -20,28 +24,38 @@ impl From<String> for GroupId {
     }
 }
 
-impl Into<String> for GroupId {
-    fn into(self) -> String {
-        self.0
+impl From<GroupId> for String {
+    fn from(value: GroupId) -> Self {
+        value.0
     }
 }
 
-#[derive(Debug)]
+// groups may be members of other groups. the members of a member group are members of every group
+// that contains it, directly or further up, and hold the roles of all of them
+#[derive(Debug, Clone)]
 pub struct Group {
     id: GroupId,
+    tenant_id: TenantId,
     name: String,
     subjects: HashSet<SubjectId>,
+    groups: HashSet<GroupId>,
     roles: HashSet<RoleId>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
 
 impl Group {
-    pub fn new(name: &str) -> Group {
+    pub fn new(tenant_id: TenantId, name: &str) -> Group {
+        Group::with_id(GroupId::default(), tenant_id, name)
+    }
+
+    pub fn with_id(id: GroupId, tenant_id: TenantId, name: &str) -> Group {
         Group {
-            id: GroupId::default(),
+            id,
+            tenant_id,
             name: name.to_string(),
             subjects: HashSet::new(),
+            groups: HashSet::new(),
             roles: HashSet::new(),
             created_at: Utc::now(),
             updated_at: Utc::now(),
@@ -56,13 +70,26 @@ impl Group {
         self.id.clone()
     }
 
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
 
-    pub fn add_subject(&mut self, subject_id: SubjectId) {
+    pub fn rename(&mut self, name: &str) {
+        self.name = name.to_string();
+        self.updated_at = Utc::now();
+    }
+
+    // both additions are rejected when a member would end up holding roles that a static
+    // separation of duties constraint keeps apart
+    pub fn add_subject(&mut self, subject_id: SubjectId, duties: &SeparationOfDuties) -> Result<(), Error> {
+        duties.ensure_member_may_join(self, &subject_id)?;
         self.subjects.insert(subject_id);
         self.updated_at = Utc::now();
+        Ok(())
     }
 
     pub fn remove_subject(&mut self, subject_id: &SubjectId) {
@@ -74,9 +101,40 @@ impl Group {
         &self.subjects
     }
 
-    pub fn add_role(&mut self, role: RoleId) {
+    // `ancestors` are the groups that contain this one, directly or further up. a group cannot
+    // contain itself or any of them, so that membership never goes round in a circle
+    pub fn add_group(&mut self, group_id: GroupId, ancestors: &[Group], duties: &SeparationOfDuties) -> Result<(), Error> {
+        if group_id == self.id {
+            return Err(Error::Simple(format!("group {} cannot contain itself", self.name)));
+        }
+        if let Some(ancestor) = ancestors.iter().find(|ancestor| ancestor.get_id() == group_id) {
+            return Err(Error::Simple(format!(
+                "group {} cannot contain group {}: it is already a member of it",
+                self.name,
+                ancestor.get_name(),
+            )));
+        }
+        duties.ensure_group_may_join(self, &group_id)?;
+        self.groups.insert(group_id);
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    pub fn remove_group(&mut self, group_id: &GroupId) {
+        self.groups.remove(group_id);
+        self.updated_at = Utc::now();
+    }
+
+    // the groups that are direct members of this one
+    pub fn get_groups(&self) -> &HashSet<GroupId> {
+        &self.groups
+    }
+
+    pub fn add_role(&mut self, role: RoleId, duties: &SeparationOfDuties) -> Result<(), Error> {
+        duties.ensure_members_may_hold(self, &role)?;
         self.roles.insert(role);
         self.updated_at = Utc::now();
+        Ok(())
     }
 
     pub fn remove_role(&mut self, role_id: &RoleId) {
@@ -97,10 +155,44 @@ impl Group {
     }
 }
 
+// the subjects of `group` and of its member groups, however deeply nested, as far as `groups` knows them
+pub fn members_of(groups: &[Group], group: &Group) -> HashSet<SubjectId> {
+    let mut members = group.get_subjects().clone();
+    let mut reached: HashSet<GroupId> = [group.get_id()].into_iter().collect();
+    let mut pending: Vec<GroupId> = group.get_groups().iter().cloned().collect();
+    while let Some(group_id) = pending.pop() {
+        if !reached.insert(group_id.clone()) {
+            continue;
+        }
+        if let Some(member) = groups.iter().find(|candidate| candidate.get_id() == group_id) {
+            members.extend(member.get_subjects().iter().cloned());
+            pending.extend(member.get_groups().iter().cloned());
+        }
+    }
+    members
+}
+
+// the given groups together with every group in `groups` that contains one of them, however far up
+pub fn containing(groups: &[Group], group_ids: HashSet<GroupId>) -> HashSet<GroupId> {
+    let mut reached = group_ids;
+    let mut pending: Vec<GroupId> = reached.iter().cloned().collect();
+    while let Some(group_id) = pending.pop() {
+        for parent in groups.iter().filter(|parent| parent.get_groups().contains(&group_id)) {
+            if reached.insert(parent.get_id()) {
+                pending.push(parent.get_id());
+            }
+        }
+    }
+    reached
+}
+
+#[derive(Default)]
 pub struct GroupBuilder {
     id: Option<GroupId>,
+    tenant_id: Option<TenantId>,
     name: Option<String>,
     subjects: Option<HashSet<SubjectId>>,
+    groups: Option<HashSet<GroupId>>,
     roles: Option<HashSet<RoleId>>,
     created_at: Option<DateTime<Utc>>,
     updated_at: Option<DateTime<Utc>>,
@@ -110,8 +202,10 @@ impl GroupBuilder {
     pub fn new() -> Self {
         Self {
             id: None,
+            tenant_id: None,
             name: None,
             subjects: None,
+            groups: None,
             roles: None,
             created_at: None,
             updated_at: None,
@@ -123,6 +217,11 @@ impl GroupBuilder {
         self
     }
 
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
     pub fn name(mut self, name: String) -> Self {
         self.name = Some(name);
         self
@@ -133,6 +232,11 @@ impl GroupBuilder {
         self
     }
 
+    pub fn groups(mut self, groups: HashSet<GroupId>) -> Self {
+        self.groups = Some(groups);
+        self
+    }
+
     pub fn roles(mut self, roles: HashSet<RoleId>) -> Self {
         self.roles = Some(roles);
         self
@@ -151,8 +255,10 @@ impl GroupBuilder {
     pub fn build(self) -> Group {
         Group {
             id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
             name: self.name.unwrap(),
             subjects: self.subjects.unwrap(),
+            groups: self.groups.unwrap(),
             roles: self.roles.unwrap(),
             created_at: self.created_at.unwrap(),
             updated_at: self.updated_at.unwrap(),
@@ -0,0 +1,235 @@
+use std::fmt;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use super::repositories::Error;
+
+const HS256_KEY_BYTES: usize = 32;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum TokenAlgorithm {
+    // HMAC with SHA-256: whoever verifies a token could sign one too
+    Hs256,
+    // Ed25519 signatures, which anyone with the published key can verify
+    EdDsa,
+}
+
+impl fmt::Display for TokenAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let algorithm = match self {
+            TokenAlgorithm::Hs256 => "HS256",
+            TokenAlgorithm::EdDsa => "EdDSA",
+        };
+        write!(f, "{}", algorithm)
+    }
+}
+
+impl TryFrom<String> for TokenAlgorithm {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "HS256" => Ok(TokenAlgorithm::Hs256),
+            "EdDSA" => Ok(TokenAlgorithm::EdDsa),
+            _ => Err(Error::Simple(format!("unknown token algorithm: {}", value))),
+        }
+    }
+}
+
+fn encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn decode(value: &Option<String>, what: &str, kid: &str) -> Result<Vec<u8>, Error> {
+    value.as_ref()
+        .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+        .ok_or_else(|| Error::Simple(format!("key {} has no valid {}", kid, what)))
+}
+
+fn decode_32(value: &Option<String>, what: &str, kid: &str) -> Result<[u8; 32], Error> {
+    decode(value, what, kid)?
+        .try_into()
+        .map_err(|_| Error::Simple(format!("key {} has no valid {}", kid, what)))
+}
+
+// a single key as RFC 7517 lays it out: `oct` keys for HS256 and `OKP` keys on Ed25519 for EdDSA.
+// private material (`k`, `d`) only ever lives in the local key set; `retired_at` is our own addition and
+// tells when the key stopped signing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwk {
+    kty: String,
+    kid: String,
+    alg: String,
+    #[serde(rename = "use")]
+    usage: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crv: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    x: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    d: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    k: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "chrono::serde::ts_seconds_option")]
+    retired_at: Option<DateTime<Utc>>,
+}
+
+impl Jwk {
+    pub fn generate(algorithm: TokenAlgorithm) -> Jwk {
+        let kid = Uuid::new_v4().to_string();
+        match algorithm {
+            TokenAlgorithm::Hs256 => {
+                let mut secret = [0u8; HS256_KEY_BYTES];
+                rand::thread_rng().fill_bytes(&mut secret);
+                Jwk::new("oct", kid, algorithm, None, None, Some(encode(&secret)))
+            },
+            TokenAlgorithm::EdDsa => {
+                let mut seed = [0u8; 32];
+                rand::thread_rng().fill_bytes(&mut seed);
+                let signing_key = SigningKey::from_bytes(&seed);
+                let public_key = encode(signing_key.verifying_key().as_bytes());
+                Jwk::new("OKP", kid, algorithm, Some(public_key), Some(encode(&seed)), None)
+            },
+        }
+    }
+
+    fn new(kty: &str, kid: String, algorithm: TokenAlgorithm, x: Option<String>, d: Option<String>, k: Option<String>) -> Jwk {
+        Jwk {
+            kty: kty.to_string(),
+            kid,
+            alg: algorithm.to_string(),
+            usage: "sig".to_string(),
+            crv: (algorithm == TokenAlgorithm::EdDsa).then(|| "Ed25519".to_string()),
+            x,
+            d,
+            k,
+            retired_at: None,
+        }
+    }
+
+    pub fn get_kid(&self) -> String {
+        self.kid.clone()
+    }
+
+    pub fn get_algorithm(&self) -> Result<TokenAlgorithm, Error> {
+        TokenAlgorithm::try_from(self.alg.clone())
+    }
+
+    pub fn get_retired_at(&self) -> Option<DateTime<Utc>> {
+        self.retired_at
+    }
+
+    // the key as it may be handed out; HS256 keys are secrets through and through, so they have none
+    pub fn to_public(&self) -> Option<Jwk> {
+        match self.get_algorithm() {
+            Ok(TokenAlgorithm::EdDsa) => Some(Jwk {
+                d: None,
+                k: None,
+                ..self.clone()
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        match self.get_algorithm()? {
+            TokenAlgorithm::Hs256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(&decode(&self.k, "secret", &self.kid)?)
+                    .map_err(|error| Error::Simple(format!("key {} cannot sign: {}", self.kid, error)))?;
+                mac.update(message);
+                Ok(mac.finalize().into_bytes().to_vec())
+            },
+            TokenAlgorithm::EdDsa => {
+                let signing_key = SigningKey::from_bytes(&decode_32(&self.d, "private key", &self.kid)?);
+                Ok(signing_key.sign(message).to_bytes().to_vec())
+            },
+        }
+    }
+
+    // compares HMACs in constant time and refuses malleable Ed25519 signatures
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match self.get_algorithm() {
+            Ok(TokenAlgorithm::Hs256) => decode(&self.k, "secret", &self.kid)
+                .ok()
+                .and_then(|secret| Hmac::<Sha256>::new_from_slice(&secret).ok())
+                .is_some_and(|mut mac| {
+                    mac.update(message);
+                    mac.verify_slice(signature).is_ok()
+                }),
+            Ok(TokenAlgorithm::EdDsa) => decode_32(&self.x, "public key", &self.kid)
+                .ok()
+                .and_then(|public_key| VerifyingKey::from_bytes(&public_key).ok())
+                .zip(Signature::from_slice(signature).ok())
+                .is_some_and(|(verifying_key, signature)| verifying_key.verify_strict(message, &signature).is_ok()),
+            Err(_) => false,
+        }
+    }
+}
+
+// a JSON Web Key Set. the first key that is not retired signs; retired keys stay around to verify the
+// tokens they signed until those expired
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+impl Jwks {
+    pub fn new() -> Jwks {
+        Jwks::default()
+    }
+
+    pub fn parse(source: &str) -> Result<Jwks, Error> {
+        serde_json::from_str(source).map_err(|error| Error::Simple(format!("invalid key set: {}", error)))
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|error| Error::Simple(format!("unable to write key set: {}", error)))
+    }
+
+    pub fn get_keys(&self) -> &[Jwk] {
+        &self.keys
+    }
+
+    pub fn signing_key(&self) -> Option<&Jwk> {
+        self.keys.iter().find(|key| key.retired_at.is_none())
+    }
+
+    pub fn find(&self, kid: &str) -> Option<&Jwk> {
+        self.keys.iter().find(|key| key.kid == kid)
+    }
+
+    // retires the keys that were signing and puts a new one in front of them; returns the new key id
+    pub fn rotate(&mut self, algorithm: TokenAlgorithm, at: DateTime<Utc>) -> String {
+        for key in self.keys.iter_mut().filter(|key| key.retired_at.is_none()) {
+            key.retired_at = Some(at);
+        }
+        let key = Jwk::generate(algorithm);
+        let kid = key.get_kid();
+        self.keys.insert(0, key);
+        kid
+    }
+
+    // drops the keys retired before the given time and returns their ids
+    pub fn prune(&mut self, retired_before: DateTime<Utc>) -> Vec<String> {
+        let (pruned, kept): (Vec<Jwk>, Vec<Jwk>) = std::mem::take(&mut self.keys)
+            .into_iter()
+            .partition(|key| key.retired_at.is_some_and(|retired_at| retired_at < retired_before));
+        self.keys = kept;
+        pruned.into_iter().map(|key| key.kid).collect()
+    }
+
+    // the set to publish to verifiers that do not share secrets: Ed25519 public keys only
+    pub fn public(&self) -> Jwks {
+        Jwks {
+            keys: self.keys.iter().filter_map(Jwk::to_public).collect(),
+        }
+    }
+}
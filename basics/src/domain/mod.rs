@@ -1,8 +1,32 @@
+pub mod access_requests;
+pub mod api_keys;
+pub mod audit;
+pub mod conditions;
+#[cfg(test)]
+mod conditions_tests;
+pub mod credentials;
+pub mod delegations;
+pub mod effective_permissions;
 pub mod groups;
+pub mod jwks;
+pub mod namespaces;
 pub mod operations;
+pub mod outbox;
 pub mod permissions;
+pub mod policies;
+#[cfg(test)]
+mod policies_tests;
+pub mod relationships;
 pub mod resources;
 pub mod roles;
+pub mod scim_filters;
+pub mod secrets;
+pub mod seeds;
+pub mod separation_of_duties;
+pub mod sessions;
+pub mod snapshots;
 pub mod subjects;
+pub mod tenants;
+pub mod tokens;
 
-pub mod repositories;
\ No newline at end of file
+pub mod repositories;
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use super::repositories::Error;
+
+// namespace configuration, e.g.
+//
+//   namespace document {
+//       relation parent
+//       relation owner
+//       relation editor = this | owner | parent->owner
+//       relation banned
+//       relation viewer = (this | editor | parent->viewer) - banned
+//   }
+//
+// `this` stands for the tuples written directly under the relation, a bare name for another relation
+// of the same object and `tupleset->relation` for a relation on the objects the tupleset points to.
+// `&` binds tighter than `|`, which binds tighter than `-`; a relation without a rewrite is just `this`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rewrite {
+    This,
+    ComputedUserset(String),
+    TupleToUserset { tupleset: String, computed_userset: String },
+    Union(Box<Rewrite>, Box<Rewrite>),
+    Intersection(Box<Rewrite>, Box<Rewrite>),
+    Exclusion(Box<Rewrite>, Box<Rewrite>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Namespace {
+    name: String,
+    relations: HashMap<String, Rewrite>,
+}
+
+impl Namespace {
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn get_relation(&self, relation: &str) -> Option<&Rewrite> {
+        self.relations.get(relation)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NamespaceConfig {
+    namespaces: HashMap<String, Namespace>,
+}
+
+impl NamespaceConfig {
+    pub fn parse(source: &str) -> Result<NamespaceConfig, Error> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0 };
+
+        let mut config = NamespaceConfig::default();
+        while !parser.is_at_end() {
+            let (namespace, line, column) = parser.parse_namespace()?;
+            if config.namespaces.contains_key(&namespace.name) {
+                return Err(syntax_error(line, column, &format!("namespace {} is defined more than once", namespace.name)));
+            }
+            config.namespaces.insert(namespace.name.clone(), namespace);
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn get_namespace(&self, namespace: &str) -> Option<&Namespace> {
+        self.namespaces.get(namespace)
+    }
+
+    pub fn get_rewrite(&self, namespace: &str, relation: &str) -> Result<&Rewrite, Error> {
+        self.get_namespace(namespace)
+            .ok_or_else(|| Error::Simple(format!("unknown namespace {}", namespace)))?
+            .get_relation(relation)
+            .ok_or_else(|| Error::Simple(format!("unknown relation {}#{}", namespace, relation)))
+    }
+
+    // relations on the far side of a tupleset depend on the tuples, so only local references are checked here
+    fn validate(&self) -> Result<(), Error> {
+        for namespace in self.namespaces.values() {
+            for (relation, rewrite) in &namespace.relations {
+                for referenced in local_references(rewrite) {
+                    if !namespace.relations.contains_key(referenced) {
+                        return Err(Error::Simple(format!(
+                            "relation {}#{} refers to undefined relation {}",
+                            namespace.name,
+                            relation,
+                            referenced,
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn local_references(rewrite: &Rewrite) -> Vec<&str> {
+    match rewrite {
+        Rewrite::This => vec![],
+        Rewrite::ComputedUserset(relation) => vec![relation],
+        Rewrite::TupleToUserset { tupleset, .. } => vec![tupleset],
+        Rewrite::Union(left, right)
+        | Rewrite::Intersection(left, right)
+        | Rewrite::Exclusion(left, right) => {
+            let mut references = local_references(left);
+            references.extend(local_references(right));
+            references
+        },
+    }
+}
+
+fn syntax_error(line: usize, column: usize, message: &str) -> Error {
+    Error::Simple(format!("namespace config {}:{}: {}", line, column, message))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Identifier(String),
+    OpenBrace,
+    CloseBrace,
+    OpenParen,
+    CloseParen,
+    Equals,
+    Pipe,
+    Ampersand,
+    Minus,
+    Arrow,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let chars: Vec<char> = text.chars().collect();
+        let mut position = 0;
+
+        while position < chars.len() {
+            let column = position + 1;
+            let c = chars[position];
+
+            let kind = match c {
+                _ if c.is_whitespace() => {
+                    position += 1;
+                    continue;
+                },
+                '/' if chars.get(position + 1) == Some(&'/') => break,
+                '{' => TokenKind::OpenBrace,
+                '}' => TokenKind::CloseBrace,
+                '(' => TokenKind::OpenParen,
+                ')' => TokenKind::CloseParen,
+                '=' => TokenKind::Equals,
+                '|' => TokenKind::Pipe,
+                '&' => TokenKind::Ampersand,
+                '-' if chars.get(position + 1) == Some(&'>') => {
+                    position += 1;
+                    TokenKind::Arrow
+                },
+                '-' => TokenKind::Minus,
+                _ if c.is_ascii_alphabetic() || c == '_' => {
+                    let start = position;
+                    while position + 1 < chars.len() && (chars[position + 1].is_ascii_alphanumeric() || chars[position + 1] == '_') {
+                        position += 1;
+                    }
+                    TokenKind::Identifier(chars[start..=position].iter().collect())
+                },
+                _ => return Err(syntax_error(line, column, &format!("unexpected character {:?}", c))),
+            };
+
+            tokens.push(Token { kind, line, column });
+            position += 1;
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn is_at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    // where the next token starts, or just past the last one once the input is exhausted
+    fn location(&self) -> (usize, usize) {
+        match self.tokens.get(self.position) {
+            Some(token) => (token.line, token.column),
+            None => self.tokens.last().map_or((1, 1), |token| (token.line, token.column + 1)),
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        let (line, column) = self.location();
+        syntax_error(line, column, message)
+    }
+
+    fn expect(&mut self, kind: TokenKind, description: &str) -> Result<(), Error> {
+        if self.peek() == Some(&kind) {
+            self.position += 1;
+            return Ok(());
+        }
+        Err(self.error(&format!("expected {}", description)))
+    }
+
+    fn expect_identifier(&mut self, description: &str) -> Result<String, Error> {
+        if let Some(TokenKind::Identifier(name)) = self.peek() {
+            let name = name.clone();
+            self.position += 1;
+            return Ok(name);
+        }
+        Err(self.error(&format!("expected {}", description)))
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.peek() == Some(&TokenKind::Identifier(keyword.to_string())) {
+            self.position += 1;
+            return Ok(());
+        }
+        Err(self.error(&format!("expected `{}`", keyword)))
+    }
+
+    fn parse_namespace(&mut self) -> Result<(Namespace, usize, usize), Error> {
+        self.expect_keyword("namespace")?;
+        let (line, column) = self.location();
+        let name = self.expect_identifier("namespace name")?;
+        self.expect(TokenKind::OpenBrace, "`{`")?;
+
+        let mut relations = HashMap::new();
+        while self.peek() != Some(&TokenKind::CloseBrace) {
+            if self.is_at_end() {
+                return Err(self.error("expected `}`"));
+            }
+
+            self.expect_keyword("relation")?;
+            let (line, column) = self.location();
+            let relation = self.expect_identifier("relation name")?;
+
+            let rewrite = if self.peek() == Some(&TokenKind::Equals) {
+                self.position += 1;
+                self.parse_exclusion()?
+            } else {
+                Rewrite::This
+            };
+
+            if relations.insert(relation.clone(), rewrite).is_some() {
+                return Err(syntax_error(line, column, &format!("relation {}#{} is defined more than once", name, relation)));
+            }
+        }
+        self.position += 1;
+
+        Ok((Namespace { name, relations }, line, column))
+    }
+
+    fn parse_exclusion(&mut self) -> Result<Rewrite, Error> {
+        let mut rewrite = self.parse_union()?;
+        while self.peek() == Some(&TokenKind::Minus) {
+            self.position += 1;
+            rewrite = Rewrite::Exclusion(Box::new(rewrite), Box::new(self.parse_union()?));
+        }
+        Ok(rewrite)
+    }
+
+    fn parse_union(&mut self) -> Result<Rewrite, Error> {
+        let mut rewrite = self.parse_intersection()?;
+        while self.peek() == Some(&TokenKind::Pipe) {
+            self.position += 1;
+            rewrite = Rewrite::Union(Box::new(rewrite), Box::new(self.parse_intersection()?));
+        }
+        Ok(rewrite)
+    }
+
+    fn parse_intersection(&mut self) -> Result<Rewrite, Error> {
+        let mut rewrite = self.parse_primary()?;
+        while self.peek() == Some(&TokenKind::Ampersand) {
+            self.position += 1;
+            rewrite = Rewrite::Intersection(Box::new(rewrite), Box::new(self.parse_primary()?));
+        }
+        Ok(rewrite)
+    }
+
+    fn parse_primary(&mut self) -> Result<Rewrite, Error> {
+        if self.peek() == Some(&TokenKind::OpenParen) {
+            self.position += 1;
+            let rewrite = self.parse_exclusion()?;
+            self.expect(TokenKind::CloseParen, "`)`")?;
+            return Ok(rewrite);
+        }
+
+        let name = self.expect_identifier("`this`, a relation or `(`")?;
+        if name == "this" {
+            return Ok(Rewrite::This);
+        }
+
+        if self.peek() == Some(&TokenKind::Arrow) {
+            self.position += 1;
+            let computed_userset = self.expect_identifier("relation name after `->`")?;
+            return Ok(Rewrite::TupleToUserset { tupleset: name, computed_userset });
+        }
+
+        Ok(Rewrite::ComputedUserset(name))
+    }
+}
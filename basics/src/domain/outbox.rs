@@ -0,0 +1,105 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use super::groups::GroupId;
+use super::permissions::PermissionId;
+use super::resources::ResourceId;
+use super::roles::RoleId;
+use super::subjects::SubjectId;
+use super::tenants::TenantId;
+
+// what changed, not how: consumers read the entity back if they need its new state
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ChangeEvent {
+    SubjectSaved { subject_id: SubjectId },
+    SubjectDeleted { subject_id: SubjectId },
+    RoleSaved { role_id: RoleId },
+    RoleDeleted { role_id: RoleId },
+    GroupSaved { group_id: GroupId },
+    GroupDeleted { group_id: GroupId },
+    PermissionSaved { permission_id: PermissionId },
+    PermissionDeleted { permission_id: PermissionId },
+    ResourceSaved { resource_id: ResourceId },
+    ResourceDeleted { resource_id: ResourceId },
+}
+
+// an event as stored in the outbox. positions grow in commit order, so a consumer that remembers
+// the last position it handled can resume right after it
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    position: i64,
+    tenant_id: TenantId,
+    event: ChangeEvent,
+    created_at: DateTime<Utc>,
+}
+
+impl OutboxEntry {
+    pub fn builder() -> OutboxEntryBuilder {
+        OutboxEntryBuilder::new()
+    }
+
+    pub fn get_position(&self) -> i64 {
+        self.position
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_event(&self) -> ChangeEvent {
+        self.event.clone()
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+#[derive(Default)]
+pub struct OutboxEntryBuilder {
+    position: Option<i64>,
+    tenant_id: Option<TenantId>,
+    event: Option<ChangeEvent>,
+    created_at: Option<DateTime<Utc>>,
+}
+
+impl OutboxEntryBuilder {
+    pub fn new() -> Self {
+        Self {
+            position: None,
+            tenant_id: None,
+            event: None,
+            created_at: None,
+        }
+    }
+
+    pub fn position(mut self, position: i64) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn event(mut self, event: ChangeEvent) -> Self {
+        self.event = Some(event);
+        self
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn build(self) -> OutboxEntry {
+        OutboxEntry {
+            position: self.position.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
+            event: self.event.unwrap(),
+            created_at: self.created_at.unwrap(),
+        }
+    }
+}
@@ -1,8 +1,13 @@
+use std::fmt;
+
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
+use super::conditions::Condition;
 use super::operations::Operation;
+use super::repositories::Error;
+use super::tenants::TenantId;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct PermissionId(String);
@@ -13,38 +18,126 @@ impl Default for PermissionId {
     }
 }
 
+impl PermissionId {
+    // for permissions generated from a stable description, such as a policy statement
+    pub fn for_name(tenant_id: &TenantId, name: &str) -> Self {
+        let key = format!("urn:basics:{}:permissions:{}", tenant_id, name);
+        Self(Uuid::new_v5(&Uuid::NAMESPACE_URL, key.as_bytes()).to_string())
+    }
+}
+
 impl From<String> for PermissionId {
     fn from(value: String) -> Self {
         Self(value)
     }
 }
 
-impl Into<String> for PermissionId {
-    fn into(self) -> String {
-        self.0
+impl From<PermissionId> for String {
+    fn from(value: PermissionId) -> Self {
+        value.0
+    }
+}
+
+// on a given resource deny wins over allow, and a grant on a resource overrides whatever it inherits
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let effect = match self {
+            Effect::Allow => "allow",
+            Effect::Deny => "deny",
+        };
+        write!(f, "{}", effect)
+    }
+}
+
+impl TryFrom<String> for Effect {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "allow" => Ok(Effect::Allow),
+            "deny" => Ok(Effect::Deny),
+            _ => Err(Error::Simple(format!("unknown permission effect: {}", value))),
+        }
+    }
+}
+
+// role holders receive a permission through the roles that include it; owner permissions need no role
+// and apply to whoever owns the resource being invoked
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Grantee {
+    RoleHolders,
+    Owner,
+}
+
+impl fmt::Display for Grantee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let grantee = match self {
+            Grantee::RoleHolders => "role_holders",
+            Grantee::Owner => "owner",
+        };
+        write!(f, "{}", grantee)
+    }
+}
+
+impl TryFrom<String> for Grantee {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "role_holders" => Ok(Grantee::RoleHolders),
+            "owner" => Ok(Grantee::Owner),
+            _ => Err(Error::Simple(format!("unknown permission grantee: {}", value))),
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Permission {
     id: PermissionId,
+    tenant_id: TenantId,
     name: String,
     operation: Operation,
+    effect: Effect,
+    grantee: Grantee,
+    condition: Option<Condition>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
 
 impl Permission {
-    pub fn new(name: &str, operation: Operation) -> Permission {
+    pub fn new(tenant_id: TenantId, name: &str, operation: Operation) -> Permission {
         Permission {
             id: PermissionId::default(),
+            tenant_id,
             name: name.to_string(),
-            operation: operation,
+            operation,
+            effect: Effect::Allow,
+            grantee: Grantee::RoleHolders,
+            condition: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
     }
 
+    pub fn deny(tenant_id: TenantId, name: &str, operation: Operation) -> Permission {
+        let mut permission = Self::new(tenant_id, name, operation);
+        permission.effect = Effect::Deny;
+        permission
+    }
+
+    // grants the operation to whoever owns the invoked resource; on a parent it covers the owner of each resource below it
+    pub fn for_owner(tenant_id: TenantId, name: &str, operation: Operation) -> Permission {
+        let mut permission = Self::new(tenant_id, name, operation);
+        permission.grantee = Grantee::Owner;
+        permission
+    }
+
     pub fn builder() -> PermissionBuilder {
         PermissionBuilder::new()
     }
@@ -53,6 +146,10 @@ impl Permission {
         self.id.clone()
     }
 
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
@@ -61,6 +158,24 @@ impl Permission {
         self.operation.clone()
     }
 
+    pub fn get_effect(&self) -> Effect {
+        self.effect
+    }
+
+    pub fn get_grantee(&self) -> Grantee {
+        self.grantee
+    }
+
+    pub fn get_condition(&self) -> Option<Condition> {
+        self.condition.clone()
+    }
+
+    // a conditional permission only takes effect for checks whose attributes satisfy the condition
+    pub fn set_condition(&mut self, condition: Option<Condition>) {
+        self.condition = condition;
+        self.updated_at = Utc::now();
+    }
+
     pub fn get_created_at(&self) -> DateTime<Utc> {
         self.created_at
     }
@@ -70,11 +185,15 @@ impl Permission {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct PermissionBuilder {
     id: Option<PermissionId>,
+    tenant_id: Option<TenantId>,
     name: Option<String>,
     operation: Option<Operation>,
+    effect: Option<Effect>,
+    grantee: Option<Grantee>,
+    condition: Option<Condition>,
     created_at: Option<DateTime<Utc>>,
     updated_at: Option<DateTime<Utc>>,
 }
@@ -83,8 +202,12 @@ impl PermissionBuilder {
     pub fn new() -> Self {
         Self {
             id: None,
+            tenant_id: None,
             name: None,
             operation: None,
+            effect: None,
+            grantee: None,
+            condition: None,
             created_at: None,
             updated_at: None,
         }
@@ -95,6 +218,11 @@ impl PermissionBuilder {
         self
     }
 
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
     pub fn name(mut self, name: String) -> Self {
         self.name = Some(name);
         self
@@ -105,6 +233,21 @@ impl PermissionBuilder {
         self
     }
 
+    pub fn effect(mut self, effect: Effect) -> Self {
+        self.effect = Some(effect);
+        self
+    }
+
+    pub fn grantee(mut self, grantee: Grantee) -> Self {
+        self.grantee = Some(grantee);
+        self
+    }
+
+    pub fn condition(mut self, condition: Option<Condition>) -> Self {
+        self.condition = condition;
+        self
+    }
+
     pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
         self.created_at = Some(created_at);
         self
@@ -118,8 +261,12 @@ impl PermissionBuilder {
     pub fn build(self) -> Permission {
         Permission {
             id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
             name: self.name.unwrap(),
             operation: self.operation.unwrap(),
+            effect: self.effect.unwrap_or(Effect::Allow),
+            grantee: self.grantee.unwrap_or(Grantee::RoleHolders),
+            condition: self.condition,
             created_at: self.created_at.unwrap(),
             updated_at: self.updated_at.unwrap(),
         }
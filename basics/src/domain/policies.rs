@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::Utc;
+
+use super::conditions::Condition;
+use super::operations::Operation;
+use super::permissions::{Effect, Grantee, Permission, PermissionId};
+use super::repositories::Error;
+use super::resources::Resource;
+use super::tenants::TenantId;
+
+// a policy file holds one statement per line:
+//
+//   # engineers may manage users, but only with a second factor
+//   permit role:engineer to invoke users/* when context.mfa
+//   forbid role:contractor to invoke users/delete_user
+//   permit owner to invoke reports/* when context.hour >= 9 && context.hour < 17
+//
+// statement := ("permit" | "forbid") principal "to" action resource ("when" condition)?
+// principal := "role:" name | "owner"
+// action    := "invoke"
+// resource  := name | name "/*"
+//
+// `users/*` reads as "users and everything below it"; since grants are inherited down the resource
+// hierarchy it compiles to a grant on `users` itself. lines starting with `#` are comments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    Role(String),
+    Owner,
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Principal::Role(name) => write!(f, "role:{}", name),
+            Principal::Owner => write!(f, "owner"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Invoke,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Invoke => write!(f, "invoke"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourcePattern {
+    name: String,
+    descendants: bool,
+}
+
+impl ResourcePattern {
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn includes_descendants(&self) -> bool {
+        self.descendants
+    }
+}
+
+impl fmt::Display for ResourcePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.descendants {
+            write!(f, "{}/*", self.name)
+        } else {
+            write!(f, "{}", self.name)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyStatement {
+    effect: Effect,
+    principal: Principal,
+    action: Action,
+    resource: ResourcePattern,
+    condition: Option<Condition>,
+}
+
+impl PolicyStatement {
+    pub fn get_effect(&self) -> Effect {
+        self.effect
+    }
+
+    pub fn get_principal(&self) -> Principal {
+        self.principal.clone()
+    }
+
+    pub fn get_action(&self) -> Action {
+        self.action
+    }
+
+    pub fn get_resource(&self) -> ResourcePattern {
+        self.resource.clone()
+    }
+
+    pub fn get_condition(&self) -> Option<Condition> {
+        self.condition.clone()
+    }
+}
+
+impl fmt::Display for PolicyStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let effect = match self.effect {
+            Effect::Allow => "permit",
+            Effect::Deny => "forbid",
+        };
+        write!(f, "{} {} to {} {}", effect, self.principal, self.action, self.resource)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " when {}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyItem {
+    Statement(PolicyStatement),
+    Comment(String),
+    Blank,
+}
+
+// the permission a statement compiles to, along with who it is granted to
+#[derive(Debug, Clone)]
+pub struct PolicyGrant {
+    pub principal: Principal,
+    pub permission: Permission,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Policy {
+    items: Vec<PolicyItem>,
+}
+
+impl Policy {
+    pub fn parse(source: &str) -> Result<Policy, Error> {
+        let mut items = Vec::new();
+
+        for (index, text) in source.lines().enumerate() {
+            let trimmed = text.trim();
+            let item = if trimmed.is_empty() {
+                // runs of blank lines collapse into one
+                if items.last() == Some(&PolicyItem::Blank) {
+                    continue;
+                }
+                PolicyItem::Blank
+            } else if let Some(comment) = trimmed.strip_prefix('#') {
+                PolicyItem::Comment(comment.trim().to_string())
+            } else {
+                PolicyItem::Statement(parse_statement(index + 1, text)?)
+            };
+            items.push(item);
+        }
+
+        // blank lines only separate items, so none are kept at either end
+        if items.first() == Some(&PolicyItem::Blank) {
+            items.remove(0);
+        }
+        if items.last() == Some(&PolicyItem::Blank) {
+            items.pop();
+        }
+
+        Ok(Policy { items })
+    }
+
+    pub fn get_items(&self) -> Vec<PolicyItem> {
+        self.items.clone()
+    }
+
+    pub fn get_statements(&self) -> Vec<PolicyStatement> {
+        self.items.iter()
+            .filter_map(|item| match item {
+                PolicyItem::Statement(statement) => Some(statement.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // names of every resource the statements refer to, in order of first appearance
+    pub fn get_resource_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for statement in self.get_statements() {
+            if !names.contains(&statement.resource.name) {
+                names.push(statement.resource.name);
+            }
+        }
+        names
+    }
+
+    // `resources` maps each of `get_resource_names` to the registered resource.
+    // permission ids derive from the statement, so compiling the same statement twice yields the same permission
+    pub fn compile(&self, tenant_id: &TenantId, resources: &HashMap<String, Resource>) -> Result<Vec<PolicyGrant>, Error> {
+        self.get_statements()
+            .into_iter()
+            .map(|statement| {
+                let resource = resources.get(&statement.resource.name)
+                    .ok_or_else(|| Error::Simple(format!("resource {} is not registered", statement.resource.name)))?;
+                let operation = match statement.action {
+                    Action::Invoke => Operation::Invoke(resource.clone()),
+                };
+                let grantee = match statement.principal {
+                    Principal::Role(_) => Grantee::RoleHolders,
+                    Principal::Owner => Grantee::Owner,
+                };
+                let name = statement.to_string();
+
+                let permission = Permission::builder()
+                    .id(PermissionId::for_name(tenant_id, &name))
+                    .tenant_id(tenant_id.clone())
+                    .name(name)
+                    .operation(operation)
+                    .effect(statement.effect)
+                    .grantee(grantee)
+                    .condition(statement.condition)
+                    .created_at(Utc::now())
+                    .updated_at(Utc::now())
+                    .build();
+
+                Ok(PolicyGrant {
+                    principal: statement.principal,
+                    permission,
+                })
+            })
+            .collect()
+    }
+}
+
+// the canonical layout: one statement per line, single spaces and `# ` comments;
+// parsing the formatted text yields the same policy
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            match item {
+                PolicyItem::Statement(statement) => writeln!(f, "{}", statement)?,
+                PolicyItem::Comment(comment) if comment.is_empty() => writeln!(f, "#")?,
+                PolicyItem::Comment(comment) => writeln!(f, "# {}", comment)?,
+                PolicyItem::Blank => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// errors point at the offending columns of the line, e.g.
+//
+//   policy line 2, columns 21-26: unknown action `inovke`, expected `invoke`
+//     permit role:engineer to inovke users/*
+//                             ^^^^^^
+fn policy_error(line: usize, text: &str, start: usize, end: usize, message: &str) -> Error {
+    let columns = if end > start {
+        format!("columns {}-{}", start, end)
+    } else {
+        format!("column {}", start)
+    };
+    let marker = format!("{}{}", " ".repeat(start - 1), "^".repeat(end.max(start) - start + 1));
+    Error::Simple(format!("policy line {}, {}: {}\n  {}\n  {}", line, columns, message, text, marker))
+}
+
+struct Word<'a> {
+    text: &'a str,
+    // 1-based, in characters
+    start: usize,
+    end: usize,
+}
+
+fn split_words(text: &str) -> Vec<Word<'_>> {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+
+    for (column, (offset, c)) in text.char_indices().enumerate() {
+        match (c.is_whitespace(), current) {
+            (false, None) => current = Some((offset, column + 1)),
+            (true, Some((start_offset, start))) => {
+                words.push(Word { text: &text[start_offset..offset], start, end: column });
+                current = None;
+            },
+            _ => {},
+        }
+    }
+    if let Some((start_offset, start)) = current {
+        words.push(Word { text: &text[start_offset..], start, end: text.chars().count() });
+    }
+
+    words
+}
+
+fn is_name(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'))
+}
+
+fn parse_statement(line: usize, text: &str) -> Result<PolicyStatement, Error> {
+    let words = split_words(text);
+    let end_of_line = text.chars().count() + 1;
+    let word = |index: usize, expected: &str| {
+        words.get(index)
+            .ok_or_else(|| policy_error(line, text, end_of_line, end_of_line, &format!("expected {}, found end of line", expected)))
+    };
+
+    let effect_word = word(0, "`permit` or `forbid`")?;
+    let effect = match effect_word.text {
+        "permit" => Effect::Allow,
+        "forbid" => Effect::Deny,
+        other => {
+            return Err(policy_error(line, text, effect_word.start, effect_word.end, &format!(
+                "unknown effect `{}`, expected `permit` or `forbid`",
+                other,
+            )));
+        },
+    };
+
+    let principal_word = word(1, "a principal")?;
+    let principal = match principal_word.text.split_once(':') {
+        None if principal_word.text == "owner" => Principal::Owner,
+        Some(("role", name)) if is_name(name) && !name.contains('/') => Principal::Role(name.to_string()),
+        Some(("role", _)) => {
+            return Err(policy_error(line, text, principal_word.start + 5, principal_word.end.max(principal_word.start + 5),
+                "expected a role name made of letters, digits, `_`, `-` or `.`"));
+        },
+        _ => {
+            return Err(policy_error(line, text, principal_word.start, principal_word.end, &format!(
+                "unknown principal `{}`, expected `role:<name>` or `owner`",
+                principal_word.text,
+            )));
+        },
+    };
+
+    let to_word = word(2, "`to`")?;
+    if to_word.text != "to" {
+        return Err(policy_error(line, text, to_word.start, to_word.end, &format!("expected `to`, found `{}`", to_word.text)));
+    }
+
+    let action_word = word(3, "an action")?;
+    let action = match action_word.text {
+        "invoke" => Action::Invoke,
+        other => {
+            return Err(policy_error(line, text, action_word.start, action_word.end, &format!(
+                "unknown action `{}`, expected `invoke`",
+                other,
+            )));
+        },
+    };
+
+    let resource_word = word(4, "a resource")?;
+    let (name, descendants) = match resource_word.text.strip_suffix("/*") {
+        Some(name) => (name, true),
+        None => (resource_word.text, false),
+    };
+    if !is_name(name) {
+        let message = if name.contains('*') {
+            "wildcards are only supported as a trailing `/*`"
+        } else {
+            "expected a resource name made of letters, digits, `_`, `-`, `.` or `/`"
+        };
+        return Err(policy_error(line, text, resource_word.start, resource_word.end, message));
+    }
+    let resource = ResourcePattern { name: name.to_string(), descendants };
+
+    let condition = match words.get(5) {
+        None => None,
+        Some(when_word) if when_word.text == "when" => {
+            // the condition runs to the end of the line
+            let offset = text.char_indices().nth(when_word.end).map_or(text.len(), |(offset, _)| offset);
+            let source = &text[offset..];
+            let leading = source.chars().take_while(|c| c.is_whitespace()).count();
+            let source = source.trim();
+            if source.is_empty() {
+                return Err(policy_error(line, text, end_of_line, end_of_line, "expected a condition after `when`"));
+            }
+
+            let condition_start = when_word.end + 1 + leading;
+            let condition = Condition::parse_spanned(source).map_err(|error| {
+                let column = condition_start + error.column - 1;
+                policy_error(line, text, column, column, &error.message)
+            })?;
+            Some(condition)
+        },
+        Some(extra) => {
+            return Err(policy_error(line, text, extra.start, extra.end, &format!("expected `when` or end of line, found `{}`", extra.text)));
+        },
+    };
+
+    Ok(PolicyStatement {
+        effect,
+        principal,
+        action,
+        resource,
+        condition,
+    })
+}
@@ -0,0 +1,76 @@
+use crate::domain::policies::{Policy, PolicyItem, Principal};
+
+fn parse_error(source: &str) -> String {
+    Policy::parse(source).unwrap_err().to_string()
+}
+
+#[test]
+fn test_errors_underline_the_offending_columns() {
+    let error = parse_error("# engineers\npermit role:engineer to inovke users/*");
+    assert_eq!(error, [
+        "policy line 2, columns 25-30: unknown action `inovke`, expected `invoke`",
+        "  permit role:engineer to inovke users/*",
+        "                          ^^^^^^",
+    ].join("\n"));
+
+    let error = parse_error("permit role:engineer to invoke users/*/list");
+    assert_eq!(error, [
+        "policy line 1, columns 32-43: wildcards are only supported as a trailing `/*`",
+        "  permit role:engineer to invoke users/*/list",
+        "                                 ^^^^^^^^^^^^",
+    ].join("\n"));
+
+    let error = parse_error("permit owner to");
+    assert_eq!(error, [
+        "policy line 1, column 16: expected an action, found end of line",
+        "  permit owner to",
+        "                 ^",
+    ].join("\n"));
+}
+
+#[test]
+fn test_condition_errors_are_placed_within_the_line() {
+    let error = parse_error("permit owner to invoke reports/*   when  context.hour >= 9 &&");
+    assert_eq!(error, [
+        "policy line 1, column 62: expected a value, an attribute, `!`, `(` or `[`, found end of condition",
+        "  permit owner to invoke reports/*   when  context.hour >= 9 &&",
+        "                                                               ^",
+    ].join("\n"));
+
+    let error = parse_error("forbid role:contractor to invoke users when 1 && context.mfa");
+    assert!(error.starts_with("policy line 1, column 45: `&&` expects bool, found int\n"), "{}", error);
+}
+
+#[test]
+fn test_formatting_round_trips() {
+    let source = "
+
+#engineers may manage users
+permit   role:engineer to invoke users/*   when (context.mfa)&&(context.hour>=9 || context.on_call)
+
+
+forbid role:contractor to invoke users/delete_user
+#
+permit owner to invoke reports/* when !(context.hour < 9)
+";
+    let policy = Policy::parse(source).unwrap();
+    let formatted = policy.to_string();
+    assert_eq!(formatted, "\
+# engineers may manage users
+permit role:engineer to invoke users/* when context.mfa && (context.hour >= 9 || context.on_call)
+
+forbid role:contractor to invoke users/delete_user
+#
+permit owner to invoke reports/* when !(context.hour < 9)
+");
+    let reparsed = Policy::parse(&formatted).unwrap();
+    assert_eq!(reparsed, policy);
+    assert_eq!(reparsed.to_string(), formatted);
+
+    let items = reparsed.get_items();
+    assert_eq!(items.len(), 6);
+    assert_eq!(items[2], PolicyItem::Blank);
+    let principals: Vec<Principal> = reparsed.get_statements().iter().map(|statement| statement.get_principal()).collect();
+    assert_eq!(principals, vec![Principal::Role("engineer".to_string()), Principal::Role("contractor".to_string()), Principal::Owner]);
+    assert_eq!(reparsed.get_resource_names(), vec!["users", "users/delete_user", "reports"]);
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use super::repositories::Error;
+use super::subjects::SubjectId;
+use super::tenants::TenantId;
+
+// the relation used in `ns:id#...` subject sets that stand for the object itself
+pub const OBJECT_ITSELF: &str = "...";
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ObjectRef {
+    namespace: String,
+    object_id: String,
+}
+
+impl ObjectRef {
+    pub fn new(namespace: &str, object_id: &str) -> ObjectRef {
+        ObjectRef {
+            namespace: namespace.to_string(),
+            object_id: object_id.to_string(),
+        }
+    }
+
+    pub fn get_namespace(&self) -> String {
+        self.namespace.clone()
+    }
+
+    pub fn get_object_id(&self) -> String {
+        self.object_id.clone()
+    }
+}
+
+impl fmt::Display for ObjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.object_id)
+    }
+}
+
+impl TryFrom<&str> for ObjectRef {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.split_once(':') {
+            Some((namespace, object_id)) if !namespace.is_empty() && !object_id.is_empty() => {
+                Ok(ObjectRef::new(namespace, object_id))
+            },
+            _ => Err(Error::Simple(format!("invalid object {:?}, expected namespace:object_id", value))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum TupleSubject {
+    Subject(SubjectId),
+    SubjectSet { object: ObjectRef, relation: String },
+}
+
+impl fmt::Display for TupleSubject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TupleSubject::Subject(subject_id) => write!(f, "{}", String::from(subject_id.clone())),
+            TupleSubject::SubjectSet { object, relation } => write!(f, "{}#{}", object, relation),
+        }
+    }
+}
+
+impl TryFrom<&str> for TupleSubject {
+    type Error = Error;
+
+    // subject sets are written `namespace:object_id#relation`, anything else is a subject id
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Err(Error::Simple("empty tuple subject".to_string()));
+        }
+        if !value.contains(':') {
+            return Ok(TupleSubject::Subject(SubjectId::from(value.to_string())));
+        }
+
+        match value.split_once('#') {
+            Some((object, relation)) if !relation.is_empty() => Ok(TupleSubject::SubjectSet {
+                object: ObjectRef::try_from(object)?,
+                relation: relation.to_string(),
+            }),
+            _ => Err(Error::Simple(format!("invalid subject set {:?}, expected namespace:object_id#relation", value))),
+        }
+    }
+}
+
+// `object#relation@subject`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RelationTuple {
+    tenant_id: TenantId,
+    object: ObjectRef,
+    relation: String,
+    subject: TupleSubject,
+}
+
+impl RelationTuple {
+    pub fn new(tenant_id: TenantId, object: ObjectRef, relation: &str, subject: TupleSubject) -> RelationTuple {
+        RelationTuple {
+            tenant_id,
+            object,
+            relation: relation.to_string(),
+            subject,
+        }
+    }
+
+    pub fn parse(tenant_id: TenantId, value: &str) -> Result<RelationTuple, Error> {
+        let invalid = || Error::Simple(format!("invalid relation tuple {:?}, expected object#relation@subject", value));
+
+        let (object, rest) = value.split_once('#').ok_or_else(invalid)?;
+        let (relation, subject) = rest.split_once('@').ok_or_else(invalid)?;
+        if relation.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(RelationTuple::new(
+            tenant_id,
+            ObjectRef::try_from(object)?,
+            relation,
+            TupleSubject::try_from(subject)?,
+        ))
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_object(&self) -> ObjectRef {
+        self.object.clone()
+    }
+
+    pub fn get_relation(&self) -> String {
+        self.relation.clone()
+    }
+
+    pub fn get_subject(&self) -> TupleSubject {
+        self.subject.clone()
+    }
+}
+
+impl fmt::Display for RelationTuple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}@{}", self.object, self.relation, self.subject)
+    }
+}
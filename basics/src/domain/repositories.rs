@@ -1,12 +1,215 @@
+use std::fmt;
+
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::access_requests::{AccessRequest, AccessRequestId};
+use super::api_keys::{ApiKey, ApiKeyId};
+use super::audit::AuditRecord;
+use super::credentials::{LockoutPolicy, PasswordCredential};
+use super::delegations::{Delegation, DelegationId};
+use super::effective_permissions::EffectivePermissions;
+use super::groups::{Group, GroupId};
+use super::jwks::Jwks;
+use super::outbox::OutboxEntry;
+use super::permissions::{Permission, PermissionId};
+use super::relationships::{ObjectRef, RelationTuple};
+use super::resources::{Resource, ResourceId};
+use super::roles::{Role, RoleId};
+use super::separation_of_duties::{SodConstraint, SodConstraintId};
+use super::sessions::{Session, SessionId};
+use super::subjects::{Subject, SubjectEventEnvelope, SubjectId};
 
 #[derive(Debug)]
 pub enum Error {
     Simple(String)
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Simple(message) => write!(f, "{}", message),
+        }
+    }
+}
+
 #[async_trait]
 pub trait Repository<Id, Entity> {
     async fn get_by_id(&self, id: Id) -> Result<Option<Entity>, Error>;
     async fn save(&self, entity: Entity) -> Result<(), Error>;
-}
\ No newline at end of file
+}
+
+#[async_trait]
+pub trait SubjectRepository: Repository<SubjectId, Subject> {
+    // every stored event of the subject, oldest first. a stream that was restored, or that predates
+    // event sourcing, starts from a snapshot and has no events before it
+    async fn get_history(&self, id: SubjectId) -> Result<Vec<SubjectEventEnvelope>, Error>;
+    async fn find_with_expired_roles(&self, at: DateTime<Utc>) -> Result<Vec<Subject>, Error>;
+    // subject names are not unique either; the oldest subject with the name that is not deleted wins
+    async fn get_by_name(&self, name: &str) -> Result<Option<Subject>, Error>;
+    // deleted subjects included
+    async fn find_all(&self) -> Result<Vec<Subject>, Error>;
+}
+
+#[async_trait]
+pub trait AccessRequestRepository: Repository<AccessRequestId, AccessRequest> {
+    async fn find_expired_pending(&self, at: DateTime<Utc>) -> Result<Vec<AccessRequest>, Error>;
+}
+
+#[async_trait]
+pub trait PermissionRepository: Repository<PermissionId, Permission> {
+    async fn find_owner_permissions(&self) -> Result<Vec<Permission>, Error>;
+    // permission names are not unique; the oldest permission with the name wins
+    async fn get_by_name(&self, name: &str) -> Result<Option<Permission>, Error>;
+    async fn find_all(&self) -> Result<Vec<Permission>, Error>;
+}
+
+#[async_trait]
+pub trait RoleRepository: Repository<RoleId, Role> {
+    // role names are not unique; the oldest role with the name wins
+    async fn get_by_name(&self, name: &str) -> Result<Option<Role>, Error>;
+    async fn find_all(&self) -> Result<Vec<Role>, Error>;
+}
+
+#[async_trait]
+pub trait ResourceRepository: Repository<ResourceId, Resource> {
+    async fn get_by_name(&self, name: &str) -> Result<Option<Resource>, Error>;
+    // stores the resource unless its name is already registered, and returns whichever one is registered
+    async fn register(&self, resource: Resource) -> Result<Resource, Error>;
+    async fn find_all(&self) -> Result<Vec<Resource>, Error>;
+}
+
+#[async_trait]
+pub trait GroupRepository: Repository<GroupId, Group> {
+    async fn find_all(&self) -> Result<Vec<Group>, Error>;
+    // the groups the subject is a direct member of
+    async fn find_by_subject(&self, subject_id: SubjectId) -> Result<Vec<Group>, Error>;
+    // the groups the subject is a member of directly or through member groups, however deeply nested
+    async fn find_by_subject_transitively(&self, subject_id: SubjectId) -> Result<Vec<Group>, Error>;
+    // the groups that contain the group, directly or further up
+    async fn find_ancestors(&self, group_id: GroupId) -> Result<Vec<Group>, Error>;
+    // the subjects of the group and of its member groups, however deeply nested
+    async fn find_members(&self, group_id: GroupId) -> Result<Vec<SubjectId>, Error>;
+}
+
+#[async_trait]
+pub trait SodConstraintRepository: Repository<SodConstraintId, SodConstraint> {
+    async fn get_by_name(&self, name: &str) -> Result<Option<SodConstraint>, Error>;
+    async fn find_all(&self) -> Result<Vec<SodConstraint>, Error>;
+}
+
+#[async_trait]
+pub trait DelegationRepository: Repository<DelegationId, Delegation> {
+    // neither revoked nor past `valid_until`, oldest first
+    async fn find_active_for_delegate(&self, delegate_id: SubjectId, at: DateTime<Utc>) -> Result<Vec<Delegation>, Error>;
+}
+
+#[async_trait]
+pub trait ApiKeyRepository: Repository<ApiKeyId, ApiKey> {
+    async fn get_by_prefix(&self, prefix: &str) -> Result<Option<ApiKey>, Error>;
+    // revoked and expired keys included, oldest first
+    async fn find_by_subject(&self, subject_id: SubjectId) -> Result<Vec<ApiKey>, Error>;
+    // touches nothing but the last use, so that it never undoes a revocation saved in the meantime
+    async fn record_use(&self, id: ApiKeyId, at: DateTime<Utc>) -> Result<(), Error>;
+    // stores the issued key and retires the replaced one in a single transaction, reading the replaced
+    // key there so that a revocation made in the meantime is neither missed nor undone. the replaced key
+    // expires at `grace_until`, or is revoked at `at` without one
+    async fn rotate(&self, replaced_id: ApiKeyId, issued: ApiKey, grace_until: Option<DateTime<Utc>>, at: DateTime<Utc>) -> Result<(), Error>;
+}
+
+// credentials are keyed by the subject they belong to
+#[async_trait]
+pub trait CredentialRepository: Repository<SubjectId, PasswordCredential> {
+    async fn get_by_login(&self, login: &str) -> Result<Option<PasswordCredential>, Error>;
+    // counts a wrong password in a single statement, so that guesses made in parallel are all counted;
+    // reaching the limit locks the subject out and starts the count over. guesses made while the subject
+    // is locked out are not counted
+    async fn record_failed_login(&self, subject_id: SubjectId, policy: &LockoutPolicy, at: DateTime<Utc>) -> Result<(), Error>;
+    // false, recording nothing, when the subject is locked out at `at` as stored. the lockout a login read
+    // beforehand may be out of date, as guesses made in parallel may have locked the subject out since
+    async fn record_successful_login(&self, subject_id: SubjectId, at: DateTime<Utc>) -> Result<bool, Error>;
+}
+
+// the signing keys, kept together as one key set document
+#[async_trait]
+pub trait KeySetRepository {
+    // an empty set until one is saved
+    async fn load(&self) -> Result<Jwks, Error>;
+    async fn save(&self, key_set: &Jwks) -> Result<(), Error>;
+}
+
+// a revoked session stays revoked, whatever copy of it is saved afterwards
+#[async_trait]
+pub trait SessionRepository: Repository<SessionId, Session> {
+    // sessions that neither expired nor were revoked by then, oldest first
+    async fn find_active_by_subject(&self, subject_id: SubjectId, at: DateTime<Utc>) -> Result<Vec<Session>, Error>;
+    // how many sessions it revoked
+    async fn revoke_all_by_subject(&self, subject_id: SubjectId, at: DateTime<Utc>) -> Result<u64, Error>;
+}
+
+#[async_trait]
+pub trait RelationTupleRepository {
+    async fn write(&self, tuple: RelationTuple) -> Result<(), Error>;
+    async fn delete(&self, tuple: RelationTuple) -> Result<(), Error>;
+    async fn find(&self, object: ObjectRef, relation: &str) -> Result<Vec<RelationTuple>, Error>;
+}
+
+// a subject's entry replaces whatever the index held for it. the index remembers the change feed
+// position it reflects, so that it can tell when it lags behind
+#[async_trait]
+pub trait EffectivePermissionIndex {
+    async fn store(&self, entry: EffectivePermissions) -> Result<(), Error>;
+    async fn remove(&self, subject_id: SubjectId) -> Result<(), Error>;
+    async fn clear(&self) -> Result<(), Error>;
+    // subjects whose entries were derived from the role or the group
+    async fn find_subjects_with_role(&self, role_id: RoleId) -> Result<Vec<SubjectId>, Error>;
+    async fn find_subjects_in_group(&self, group_id: GroupId) -> Result<Vec<SubjectId>, Error>;
+    async fn find_all(&self) -> Result<Vec<EffectivePermissions>, Error>;
+    // never moves the position backwards
+    async fn advance_to(&self, position: i64) -> Result<(), Error>;
+    // `None` when the index cannot answer: the subject or the resource is not indexed, the subject's
+    // decisions depend on the request or went stale at `at`, or the index lags behind the change feed
+    async fn lookup(&self, subject_id: SubjectId, resource_id: ResourceId, at: DateTime<Utc>) -> Result<Option<bool>, Error>;
+}
+
+// reads the outbox that saves of subjects, roles, groups, permissions and resources write to. each consumer
+// keeps its own offset: the position of the last entry it handled, 0 before the first one
+#[async_trait]
+pub trait ChangeFeed {
+    async fn read_after(&self, position: i64, limit: u32) -> Result<Vec<OutboxEntry>, Error>;
+    // the position of the newest entry, 0 while the feed is empty
+    async fn get_head_position(&self) -> Result<i64, Error>;
+    async fn get_offset(&self, consumer: &str) -> Result<i64, Error>;
+    async fn commit_offset(&self, consumer: &str, position: i64) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone)]
+pub enum EntityChange {
+    SaveResource(Resource),
+    SavePermission(Permission),
+    SaveRole(Role),
+    SaveSubject(Subject),
+    SaveGroup(Group),
+    // a copy of a subject, e.g. from a snapshot, that takes the place of its current state without
+    // events of its own; the history before it is kept
+    RestoreSubject(Subject),
+    // deletes remove the row outright, unlike `Subject::delete` which only marks the subject deleted.
+    // a deleted group is also taken out of the groups that contained it
+    DeleteResource(ResourceId),
+    DeletePermission(PermissionId),
+    DeleteRole(RoleId),
+    DeleteSubject(SubjectId),
+    DeleteGroup(GroupId),
+    // records that must be written together with the changes they go along with
+    SaveAccessRequest(AccessRequest),
+    AppendAuditRecord(AuditRecord),
+}
+
+// writes a batch of changes atomically: either every change is stored or none is.
+// references between entities are checked once the whole batch is written, so a batch may
+// refer to entities it creates in any order, and may delete an entity only along with whatever
+// still refers to it. static separation of duties constraints are judged on the batch as a whole too
+#[async_trait]
+pub trait UnitOfWork {
+    async fn commit(&self, changes: Vec<EntityChange>) -> Result<(), Error>;
+}
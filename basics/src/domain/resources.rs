@@ -1,30 +1,169 @@
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+use super::conditions::{AttributeValue, Attributes};
+use super::subjects::SubjectId;
+use super::tenants::TenantId;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct ResourceId(String);
 
-impl Default for ResourceId {
-    fn default() -> Self {
-        Self(Uuid::new_v4().to_string())
+impl ResourceId {
+    // derived from the tenant and name so every process agrees on a resource's id without coordination
+    pub fn for_name(tenant_id: &TenantId, name: &str) -> Self {
+        let key = format!("urn:basics:{}:resources:{}", tenant_id, name);
+        Self(Uuid::new_v5(&Uuid::NAMESPACE_URL, key.as_bytes()).to_string())
+    }
+}
+
+impl From<String> for ResourceId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<ResourceId> for String {
+    fn from(value: ResourceId) -> Self {
+        value.0
     }
 }
 
+// copies of a resource embedded elsewhere (e.g. in operations) may carry a stale parent or owner;
+// both are always resolved through the resource repository
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Resource {
     id: ResourceId,
+    tenant_id: TenantId,
     name: String,
+    #[serde(default)]
+    parent_id: Option<ResourceId>,
+    #[serde(default)]
+    owner_id: Option<SubjectId>,
+    #[serde(default)]
+    attributes: Attributes,
 }
 
 impl Resource {
-    pub fn new(name: &str) -> Resource {
+    pub fn new(tenant_id: TenantId, name: &str) -> Resource {
         Self {
-            id: ResourceId::default(),
+            id: ResourceId::for_name(&tenant_id, name),
+            tenant_id,
             name: name.to_string(),
+            parent_id: None,
+            owner_id: None,
+            attributes: Attributes::new(),
         }
     }
 
+    pub fn with_parent(tenant_id: TenantId, name: &str, parent_id: ResourceId) -> Resource {
+        let mut resource = Self::new(tenant_id, name);
+        resource.parent_id = Some(parent_id);
+        resource
+    }
+
+    pub fn builder() -> ResourceBuilder {
+        ResourceBuilder::new()
+    }
+
     pub fn get_id(&self) -> ResourceId {
         self.id.clone()
     }
-}
\ No newline at end of file
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn get_parent_id(&self) -> Option<ResourceId> {
+        self.parent_id.clone()
+    }
+
+    pub fn get_owner_id(&self) -> Option<SubjectId> {
+        self.owner_id.clone()
+    }
+
+    pub fn is_owned_by(&self, subject_id: &SubjectId) -> bool {
+        self.owner_id.as_ref() == Some(subject_id)
+    }
+
+    pub fn get_attributes(&self) -> Attributes {
+        self.attributes.clone()
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: AttributeValue) {
+        self.attributes.insert(name.to_string(), value);
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) {
+        self.attributes.remove(name);
+    }
+
+    pub fn move_to(&mut self, parent_id: Option<ResourceId>) {
+        self.parent_id = parent_id;
+    }
+
+    // returns the previous owner
+    pub fn transfer_ownership(&mut self, owner_id: SubjectId) -> Option<SubjectId> {
+        self.owner_id.replace(owner_id)
+    }
+}
+
+#[derive(Default)]
+pub struct ResourceBuilder {
+    id: Option<ResourceId>,
+    tenant_id: Option<TenantId>,
+    name: Option<String>,
+    parent_id: Option<ResourceId>,
+    owner_id: Option<SubjectId>,
+    attributes: Option<Attributes>,
+}
+
+impl ResourceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn id(mut self, id: ResourceId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn parent_id(mut self, parent_id: Option<ResourceId>) -> Self {
+        self.parent_id = parent_id;
+        self
+    }
+
+    pub fn owner_id(mut self, owner_id: Option<SubjectId>) -> Self {
+        self.owner_id = owner_id;
+        self
+    }
+
+    pub fn attributes(mut self, attributes: Attributes) -> Self {
+        self.attributes = Some(attributes);
+        self
+    }
+
+    pub fn build(self) -> Resource {
+        Resource {
+            id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
+            name: self.name.unwrap(),
+            parent_id: self.parent_id,
+            owner_id: self.owner_id,
+            attributes: self.attributes.unwrap_or_default(),
+        }
+    }
+}
@@ -5,6 +5,8 @@ use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
 use super::permissions::PermissionId;
+use super::subjects::SubjectId;
+use super::tenants::TenantId;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct RoleId(String);
@@ -21,27 +23,31 @@ impl From<String> for RoleId {
     }
 }
 
-impl Into<String> for RoleId {
-    fn into(self) -> String {
-        self.0
+impl From<RoleId> for String {
+    fn from(value: RoleId) -> Self {
+        value.0
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Role {
     id: RoleId,
+    tenant_id: TenantId,
     name: String,
     permissions: HashSet<PermissionId>,
+    approvers: HashSet<SubjectId>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
 
 impl Role {
-    pub fn new(name: &str) -> Role {
+    pub fn new(tenant_id: TenantId, name: &str) -> Role {
         Role {
             id: RoleId::default(),
+            tenant_id,
             name: name.to_string(),
             permissions: HashSet::new(),
+            approvers: HashSet::new(),
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
@@ -55,6 +61,10 @@ impl Role {
         self.id.clone()
     }
 
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
@@ -64,23 +74,50 @@ impl Role {
         self.updated_at = Utc::now();
     }
 
+    pub fn remove_permission(&mut self, permission_id: &PermissionId) {
+        self.permissions.remove(permission_id);
+        self.updated_at = Utc::now();
+    }
+
     pub fn get_permissions(&self) -> HashSet<PermissionId> {
         self.permissions.clone()
     }
 
+    // approvers are the subjects allowed to decide access requests for this role
+    pub fn add_approver(&mut self, subject_id: SubjectId) {
+        self.approvers.insert(subject_id);
+        self.updated_at = Utc::now();
+    }
+
+    pub fn remove_approver(&mut self, subject_id: &SubjectId) {
+        self.approvers.remove(subject_id);
+        self.updated_at = Utc::now();
+    }
+
+    pub fn get_approvers(&self) -> HashSet<SubjectId> {
+        self.approvers.clone()
+    }
+
+    pub fn is_approver(&self, subject_id: &SubjectId) -> bool {
+        self.approvers.contains(subject_id)
+    }
+
     pub fn get_created_at(&self) -> DateTime<Utc> {
-        self.created_at.clone()
+        self.created_at
     }
 
     pub fn get_updated_at(&self) -> DateTime<Utc> {
-        self.updated_at.clone()
+        self.updated_at
     }
 }
 
+#[derive(Default)]
 pub struct RoleBuilder {
     id: Option<RoleId>,
+    tenant_id: Option<TenantId>,
     name: Option<String>,
     permissions: Option<HashSet<PermissionId>>,
+    approvers: Option<HashSet<SubjectId>>,
     created_at: Option<DateTime<Utc>>,
     updated_at: Option<DateTime<Utc>>,
 }
@@ -89,8 +126,10 @@ impl RoleBuilder {
     pub fn new() -> RoleBuilder {
         Self {
             id: None,
+            tenant_id: None,
             name: None,
             permissions: None,
+            approvers: None,
             created_at: None,
             updated_at: None,
         }
@@ -101,6 +140,11 @@ impl RoleBuilder {
         self
     }
 
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> RoleBuilder {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
     pub fn name(mut self, name: String) -> RoleBuilder {
         self.name = Some(name);
         self
@@ -111,6 +155,11 @@ impl RoleBuilder {
         self
     }
 
+    pub fn approvers(mut self, approvers: HashSet<SubjectId>) -> RoleBuilder {
+        self.approvers = Some(approvers);
+        self
+    }
+
     pub fn created_at(mut self, created_at: DateTime<Utc>) -> RoleBuilder {
         self.created_at = Some(created_at);
         self
@@ -124,8 +173,10 @@ impl RoleBuilder {
     pub fn build(self) -> Role {
         Role {
             id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
             name: self.name.unwrap(),
             permissions: self.permissions.unwrap(),
+            approvers: self.approvers.unwrap_or_default(),
             created_at: self.created_at.unwrap(),
             updated_at: self.updated_at.unwrap(),
         }
@@ -0,0 +1,401 @@
+use std::cmp::Ordering;
+
+use serde_json::{Map, Value};
+
+use super::repositories::Error;
+
+// attributes whose values are compared as they are; every other string is compared without regard to case
+const CASE_EXACT: &[&str] = &["id", "externalId", "value", "$ref"];
+
+fn invalid(reason: &str, source: &str) -> Error {
+    Error::Simple(format!("{} in {:?}", reason, source))
+}
+
+// the key an attribute is stored under: attribute names are matched without regard to case
+pub fn attribute_key(object: &Map<String, Value>, name: &str) -> Option<String> {
+    object.keys().find(|key| key.eq_ignore_ascii_case(name)).cloned()
+}
+
+pub fn get_attribute<'a>(object: &'a Map<String, Value>, name: &str) -> Option<&'a Value> {
+    attribute_key(object, name).and_then(|key| object.get(&key))
+}
+
+// an attribute, or a sub-attribute of a complex one, e.g. `name.givenName`. a schema urn in front of
+// the name is dropped, so `urn:ietf:params:scim:schemas:core:2.0:User:userName` is `userName`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributePath {
+    name: String,
+    sub_attribute: Option<String>,
+}
+
+impl AttributePath {
+    pub fn parse(source: &str) -> Result<AttributePath, Error> {
+        let path = match source.starts_with("urn:") {
+            true => source.rsplit(':').next().unwrap_or_default(),
+            false => source,
+        };
+        let (name, sub_attribute) = match path.split_once('.') {
+            Some((name, sub_attribute)) => (name, Some(sub_attribute)),
+            None => (path, None),
+        };
+        let valid = |name: &str| {
+            name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '$')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '$')
+        };
+        if !valid(name) || sub_attribute.is_some_and(|sub_attribute| !valid(sub_attribute)) {
+            return Err(invalid("invalid attribute path", source));
+        }
+        Ok(AttributePath {
+            name: name.to_string(),
+            sub_attribute: sub_attribute.map(str::to_string),
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_sub_attribute(&self) -> Option<&str> {
+        self.sub_attribute.as_deref()
+    }
+
+    // the values the path points at. each value of a multi-valued attribute counts on its own, and a
+    // complex value without a sub-attribute stands for its `value`
+    fn resolve<'a>(&self, resource: &'a Value) -> Vec<&'a Value> {
+        let attribute = match resource.as_object().and_then(|object| get_attribute(object, &self.name)) {
+            Some(attribute) => attribute,
+            None => return Vec::new(),
+        };
+        let values: Vec<&Value> = match attribute {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        values.into_iter()
+            .filter_map(|value| match (&self.sub_attribute, value.as_object()) {
+                (Some(sub_attribute), Some(object)) => get_attribute(object, sub_attribute),
+                (Some(_), None) => None,
+                (None, Some(object)) => get_attribute(object, "value"),
+                (None, None) => Some(value),
+            })
+            .collect()
+    }
+
+    fn is_case_exact(&self) -> bool {
+        let name = self.sub_attribute.as_deref().unwrap_or(&self.name);
+        CASE_EXACT.iter().any(|exact| exact.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Co,
+    Sw,
+    Ew,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Comparison {
+    fn parse(operator: &str) -> Option<Comparison> {
+        let comparison = match operator.to_ascii_lowercase().as_str() {
+            "eq" => Comparison::Eq,
+            "ne" => Comparison::Ne,
+            "co" => Comparison::Co,
+            "sw" => Comparison::Sw,
+            "ew" => Comparison::Ew,
+            "gt" => Comparison::Gt,
+            "ge" => Comparison::Ge,
+            "lt" => Comparison::Lt,
+            "le" => Comparison::Le,
+            _ => return None,
+        };
+        Some(comparison)
+    }
+
+    fn holds(&self, actual: &Value, expected: &Value, case_exact: bool) -> bool {
+        let ordering = match (actual, expected) {
+            (Value::String(actual), Value::String(expected)) => {
+                let (actual, expected) = match case_exact {
+                    true => (actual.clone(), expected.clone()),
+                    false => (actual.to_lowercase(), expected.to_lowercase()),
+                };
+                match self {
+                    Comparison::Co => return actual.contains(&expected),
+                    Comparison::Sw => return actual.starts_with(&expected),
+                    Comparison::Ew => return actual.ends_with(&expected),
+                    _ => actual.cmp(&expected),
+                }
+            },
+            (Value::Number(actual), Value::Number(expected)) => {
+                match actual.as_f64().zip(expected.as_f64()).and_then(|(actual, expected)| actual.partial_cmp(&expected)) {
+                    Some(ordering) => ordering,
+                    None => return false,
+                }
+            },
+            // booleans are only ever equal or not
+            (Value::Bool(actual), Value::Bool(expected)) => match self {
+                Comparison::Eq | Comparison::Ne => actual.cmp(expected),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        match self {
+            Comparison::Eq => ordering == Ordering::Equal,
+            Comparison::Ne => ordering != Ordering::Equal,
+            Comparison::Gt => ordering == Ordering::Greater,
+            Comparison::Ge => ordering != Ordering::Less,
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::Le => ordering != Ordering::Greater,
+            Comparison::Co | Comparison::Sw | Comparison::Ew => false,
+        }
+    }
+}
+
+// a filter as RFC 7644 lays them out, e.g. `userName eq "bjensen" and not (emails[type eq "work"])`.
+// `not` binds tighter than `and`, which binds tighter than `or`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScimFilter {
+    Present(AttributePath),
+    Compare(AttributePath, Comparison, Value),
+    Not(Box<ScimFilter>),
+    And(Box<ScimFilter>, Box<ScimFilter>),
+    Or(Box<ScimFilter>, Box<ScimFilter>),
+    // some value of a multi-valued attribute matches the inner filter, e.g. `members[value eq "2819c223"]`
+    ValuePath(AttributePath, Box<ScimFilter>),
+}
+
+impl ScimFilter {
+    pub fn parse(source: &str) -> Result<ScimFilter, Error> {
+        let mut parser = Parser {
+            source,
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let filter = parser.parse_or()?;
+        if parser.position != parser.tokens.len() {
+            return Err(invalid("unexpected input after the filter", source));
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, resource: &Value) -> bool {
+        match self {
+            ScimFilter::Present(path) => path.resolve(resource)
+                .into_iter()
+                .any(|value| !value.is_null() && *value != "" && value.as_array().is_none_or(|values| !values.is_empty())),
+            ScimFilter::Compare(path, Comparison::Ne, expected) => {
+                !ScimFilter::Compare(path.clone(), Comparison::Eq, expected.clone()).matches(resource)
+            },
+            // only an attribute without a value equals null
+            ScimFilter::Compare(path, comparison, Value::Null) => {
+                *comparison == Comparison::Eq && path.resolve(resource).iter().all(|value| value.is_null())
+            },
+            ScimFilter::Compare(path, comparison, expected) => path.resolve(resource)
+                .into_iter()
+                .any(|value| comparison.holds(value, expected, path.is_case_exact())),
+            ScimFilter::Not(filter) => !filter.matches(resource),
+            ScimFilter::And(left, right) => left.matches(resource) && right.matches(resource),
+            ScimFilter::Or(left, right) => left.matches(resource) || right.matches(resource),
+            ScimFilter::ValuePath(path, filter) => resource.as_object()
+                .and_then(|object| get_attribute(object, path.get_name()))
+                .and_then(Value::as_array)
+                .is_some_and(|values| values.iter().any(|value| filter.matches(value))),
+        }
+    }
+}
+
+// where a PATCH operation applies: an attribute path, optionally narrowed down to the values of a
+// multi-valued attribute that match a filter, e.g. `members[value eq "2819c223"]` or
+// `emails[type eq "work"].value`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchPath {
+    attribute: AttributePath,
+    filter: Option<ScimFilter>,
+}
+
+impl PatchPath {
+    pub fn parse(source: &str) -> Result<PatchPath, Error> {
+        let (name, rest) = match source.split_once('[') {
+            Some((name, rest)) => (name, rest),
+            None => return Ok(PatchPath { attribute: AttributePath::parse(source)?, filter: None }),
+        };
+        let (filter, sub_attribute) = rest.rsplit_once(']').ok_or_else(|| invalid("unclosed [", source))?;
+        let attribute = AttributePath::parse(name)?;
+        if attribute.sub_attribute.is_some() {
+            return Err(invalid("a value filter applies to an attribute, not a sub-attribute", source));
+        }
+        let sub_attribute = match sub_attribute {
+            "" => None,
+            sub_attribute => {
+                let sub_attribute = sub_attribute.strip_prefix('.')
+                    .map(AttributePath::parse)
+                    .transpose()?
+                    .filter(|sub_attribute| sub_attribute.sub_attribute.is_none())
+                    .ok_or_else(|| invalid("invalid sub-attribute", source))?;
+                Some(sub_attribute.name)
+            },
+        };
+        Ok(PatchPath {
+            attribute: AttributePath {
+                name: attribute.name,
+                sub_attribute,
+            },
+            filter: Some(ScimFilter::parse(filter)?),
+        })
+    }
+
+    pub fn get_attribute(&self) -> &AttributePath {
+        &self.attribute
+    }
+
+    pub fn get_filter(&self) -> Option<&ScimFilter> {
+        self.filter.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Word(String),
+    Literal(Value),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {},
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '[' => tokens.push(Token::OpenBracket),
+            ']' => tokens.push(Token::CloseBracket),
+            '"' => {
+                let mut end = None;
+                while let Some((index, c)) = chars.next() {
+                    match c {
+                        '\\' => { chars.next(); },
+                        '"' => {
+                            end = Some(index);
+                            break;
+                        },
+                        _ => {},
+                    }
+                }
+                let end = end.ok_or_else(|| invalid("unterminated string", source))?;
+                let literal = serde_json::from_str(&source[start..=end]).map_err(|_| invalid("invalid string", source))?;
+                tokens.push(Token::Literal(literal));
+            },
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = chars.peek().copied() {
+                    if c.is_whitespace() || "()[]\"".contains(c) {
+                        break;
+                    }
+                    end = index + c.len_utf8();
+                    chars.next();
+                }
+                let word = &source[start..end];
+                let token = match word {
+                    "true" | "false" | "null" => Token::Literal(serde_json::from_str(word).unwrap_or_default()),
+                    _ if word.starts_with(|c: char| c.is_ascii_digit() || c == '-') => Token::Literal(
+                        serde_json::from_str::<serde_json::Number>(word)
+                            .map(Value::Number)
+                            .map_err(|_| invalid("invalid number", source))?,
+                    ),
+                    _ => Token::Word(word.to_string()),
+                };
+                tokens.push(token);
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), Error> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(invalid(&format!("expected {}", what), self.source)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<ScimFilter, Error> {
+        let mut filter = self.parse_and()?;
+        while self.next_is_keyword("or") {
+            self.position += 1;
+            filter = ScimFilter::Or(Box::new(filter), Box::new(self.parse_and()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<ScimFilter, Error> {
+        let mut filter = self.parse_term()?;
+        while self.next_is_keyword("and") {
+            self.position += 1;
+            filter = ScimFilter::And(Box::new(filter), Box::new(self.parse_term()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_term(&mut self) -> Result<ScimFilter, Error> {
+        if self.next_is_keyword("not") {
+            self.position += 1;
+            self.expect(Token::Open, "( after not")?;
+            let filter = self.parse_or()?;
+            self.expect(Token::Close, ")")?;
+            return Ok(ScimFilter::Not(Box::new(filter)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let filter = self.parse_or()?;
+                self.expect(Token::Close, ")")?;
+                Ok(filter)
+            },
+            Some(Token::Word(path)) => {
+                let path = AttributePath::parse(&path)?;
+                match self.next() {
+                    Some(Token::OpenBracket) => {
+                        let filter = self.parse_or()?;
+                        self.expect(Token::CloseBracket, "]")?;
+                        Ok(ScimFilter::ValuePath(path, Box::new(filter)))
+                    },
+                    Some(Token::Word(operator)) if operator.eq_ignore_ascii_case("pr") => Ok(ScimFilter::Present(path)),
+                    Some(Token::Word(operator)) => {
+                        let comparison = Comparison::parse(&operator)
+                            .ok_or_else(|| invalid(&format!("unknown operator {}", operator), self.source))?;
+                        match self.next() {
+                            Some(Token::Literal(value)) => Ok(ScimFilter::Compare(path, comparison, value)),
+                            _ => Err(invalid(&format!("expected a value after {}", operator), self.source)),
+                        }
+                    },
+                    _ => Err(invalid("expected an operator", self.source)),
+                }
+            },
+            _ => Err(invalid("expected an attribute", self.source)),
+        }
+    }
+}
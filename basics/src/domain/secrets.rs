@@ -0,0 +1,37 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+// random secrets handed out as bearer tokens read `<scheme>_<lookup>_<secret>`: the lookup part finds
+// the stored record and only a hash of the secret is kept. secrets are random enough that a plain
+// hash will do; passwords are not, see `credentials`
+
+pub(crate) fn random_hex(bytes: usize) -> String {
+    let mut buffer = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut buffer);
+    hex::encode(buffer)
+}
+
+pub(crate) fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+// compares every byte, so that how long it takes tells nothing about the secret
+pub(crate) fn verify_secret(secret: &str, hash: &str) -> bool {
+    let candidate = hash_secret(secret);
+    candidate.len() == hash.len() && candidate.bytes().zip(hash.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+pub(crate) fn format_token(scheme: &str, lookup: &str, secret: &str) -> String {
+    format!("{}_{}_{}", scheme, lookup, secret)
+}
+
+// the lookup part and the secret of a token of the given scheme
+pub(crate) fn parse_token(scheme: &str, token: &str) -> Option<(String, String)> {
+    let mut parts = token.splitn(3, '_');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(found), Some(lookup), Some(secret)) if found == scheme && !lookup.is_empty() && !secret.is_empty() => {
+            Some((lookup.to_string(), secret.to_string()))
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use super::conditions::{Attributes, Condition};
+use super::permissions::{Effect, Grantee};
+use super::repositories::Error;
+
+// the desired authorization state of a tenant, kept in a yaml or json file:
+//
+//   resources:
+//     - name: users
+//     - name: users/get_users
+//       parent: users
+//   permissions:
+//     - name: list users
+//       resource: users/get_users
+//   roles:
+//     - name: engineer
+//       permissions: [list users]
+//   subjects:
+//     - name: alec leamas
+//       attributes: {department: circus}
+//   groups:
+//     - name: employees
+//       subjects: [alec leamas]
+//       groups: [contractors]
+//       roles: [engineer]
+//
+// entries refer to each other, and to entities already stored, by name. a list or attribute map left
+// out of an entry is not managed, so e.g. roles granted through access requests survive the next apply
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Seed {
+    #[serde(default)]
+    pub resources: Vec<ResourceSeed>,
+    #[serde(default)]
+    pub permissions: Vec<PermissionSeed>,
+    #[serde(default)]
+    pub roles: Vec<RoleSeed>,
+    #[serde(default)]
+    pub subjects: Vec<SubjectSeed>,
+    #[serde(default)]
+    pub groups: Vec<GroupSeed>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceSeed {
+    pub name: String,
+    pub parent: Option<String>,
+    pub owner: Option<String>,
+    pub attributes: Option<Attributes>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionSeed {
+    pub name: String,
+    pub resource: String,
+    // `allow` unless given
+    pub effect: Option<String>,
+    // `role_holders` unless given
+    pub grantee: Option<String>,
+    pub condition: Option<String>,
+}
+
+impl PermissionSeed {
+    pub fn get_effect(&self) -> Result<Effect, Error> {
+        self.effect.clone().map_or(Ok(Effect::Allow), Effect::try_from)
+    }
+
+    pub fn get_grantee(&self) -> Result<Grantee, Error> {
+        self.grantee.clone().map_or(Ok(Grantee::RoleHolders), Grantee::try_from)
+    }
+
+    pub fn get_condition(&self) -> Result<Option<Condition>, Error> {
+        self.condition.as_deref().map(Condition::parse).transpose()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoleSeed {
+    pub name: String,
+    pub permissions: Option<Vec<String>>,
+    pub approvers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubjectSeed {
+    pub name: String,
+    pub roles: Option<Vec<String>>,
+    pub attributes: Option<Attributes>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupSeed {
+    pub name: String,
+    pub subjects: Option<Vec<String>>,
+    // member groups, whose members hold this group's roles too
+    pub groups: Option<Vec<String>>,
+    pub roles: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedFormat {
+    Yaml,
+    Json,
+}
+
+impl SeedFormat {
+    pub fn for_path(path: &Path) -> Result<SeedFormat, Error> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml") | Some("yml") => Ok(SeedFormat::Yaml),
+            Some("json") => Ok(SeedFormat::Json),
+            _ => Err(Error::Simple(format!("seed {} must be a .yaml, .yml or .json file", path.display()))),
+        }
+    }
+}
+
+impl Seed {
+    pub fn parse(source: &str, format: SeedFormat) -> Result<Seed, Error> {
+        let seed: Seed = match format {
+            SeedFormat::Yaml => serde_yaml::from_str(source)
+                .map_err(|error| Error::Simple(format!("invalid seed: {}", error)))?,
+            SeedFormat::Json => serde_json::from_str(source)
+                .map_err(|error| Error::Simple(format!("invalid seed: {}", error)))?,
+        };
+        seed.validate()?;
+        Ok(seed)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        ensure_unique_names("resource", self.resources.iter().map(|resource| &resource.name))?;
+        ensure_unique_names("permission", self.permissions.iter().map(|permission| &permission.name))?;
+        ensure_unique_names("role", self.roles.iter().map(|role| &role.name))?;
+        ensure_unique_names("subject", self.subjects.iter().map(|subject| &subject.name))?;
+        ensure_unique_names("group", self.groups.iter().map(|group| &group.name))?;
+
+        for permission in &self.permissions {
+            let in_permission = |error: Error| Error::Simple(format!("permission {}: {}", permission.name, error));
+            permission.get_effect().map_err(in_permission)?;
+            permission.get_grantee().map_err(in_permission)?;
+            permission.get_condition().map_err(in_permission)?;
+        }
+        Ok(())
+    }
+}
+
+fn ensure_unique_names<'a>(kind: &str, names: impl Iterator<Item = &'a String>) -> Result<(), Error> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            return Err(Error::Simple(format!("{} {} is declared more than once", kind, name)));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,392 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use super::groups::{containing, members_of, Group, GroupId};
+use super::repositories::Error;
+use super::roles::{Role, RoleId};
+use super::subjects::{Subject, SubjectId};
+use super::tenants::TenantId;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SodConstraintId(String);
+
+impl Default for SodConstraintId {
+    fn default() -> Self {
+        SodConstraintId(Uuid::new_v4().to_string())
+    }
+}
+
+impl From<String> for SodConstraintId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<SodConstraintId> for String {
+    fn from(value: SodConstraintId) -> Self {
+        value.0
+    }
+}
+
+// static constraints limit which roles a subject may hold at all, directly or through groups;
+// dynamic constraints let a subject hold the roles but not use them within the same session
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum SodKind {
+    Static,
+    Dynamic,
+}
+
+impl fmt::Display for SodKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            SodKind::Static => "static",
+            SodKind::Dynamic => "dynamic",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl TryFrom<String> for SodKind {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "static" => Ok(SodKind::Static),
+            "dynamic" => Ok(SodKind::Dynamic),
+            _ => Err(Error::Simple(format!("unknown separation of duties kind: {}", value))),
+        }
+    }
+}
+
+// the roles of a constraint are mutually exclusive: a subject may have at most one of them
+#[derive(Debug, Clone)]
+pub struct SodConstraint {
+    id: SodConstraintId,
+    tenant_id: TenantId,
+    name: String,
+    kind: SodKind,
+    roles: HashSet<RoleId>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl SodConstraint {
+    pub fn new(tenant_id: TenantId, name: &str, kind: SodKind, roles: HashSet<RoleId>) -> Result<SodConstraint, Error> {
+        if roles.len() < 2 {
+            return Err(Error::Simple(format!(
+                "separation of duties constraint {} needs at least two roles",
+                name,
+            )));
+        }
+        Ok(SodConstraint {
+            id: SodConstraintId::default(),
+            tenant_id,
+            name: name.to_string(),
+            kind,
+            roles,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+    }
+
+    pub fn builder() -> SodConstraintBuilder {
+        SodConstraintBuilder::new()
+    }
+
+    pub fn get_id(&self) -> SodConstraintId {
+        self.id.clone()
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn get_kind(&self) -> SodKind {
+        self.kind
+    }
+
+    pub fn get_roles(&self) -> HashSet<RoleId> {
+        self.roles.clone()
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn get_updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+#[derive(Default)]
+pub struct SodConstraintBuilder {
+    id: Option<SodConstraintId>,
+    tenant_id: Option<TenantId>,
+    name: Option<String>,
+    kind: Option<SodKind>,
+    roles: Option<HashSet<RoleId>>,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl SodConstraintBuilder {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            tenant_id: None,
+            name: None,
+            kind: None,
+            roles: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    pub fn id(mut self, id: SodConstraintId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn kind(mut self, kind: SodKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn roles(mut self, roles: HashSet<RoleId>) -> Self {
+        self.roles = Some(roles);
+        self
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn updated_at(mut self, updated_at: DateTime<Utc>) -> Self {
+        self.updated_at = Some(updated_at);
+        self
+    }
+
+    pub fn build(self) -> SodConstraint {
+        SodConstraint {
+            id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
+            name: self.name.unwrap(),
+            kind: self.kind.unwrap(),
+            roles: self.roles.unwrap(),
+            created_at: self.created_at.unwrap(),
+            updated_at: self.updated_at.unwrap(),
+        }
+    }
+}
+
+// a role a subject has, with where it comes from when that matters to the reader of an error
+type Holding = (RoleId, Option<String>);
+
+// the tenant's constraints together with who holds which role, as subjects and groups are about to
+// change. the default has no constraints and lets everything through
+#[derive(Debug, Clone, Default)]
+pub struct SeparationOfDuties {
+    constraints: Vec<SodConstraint>,
+    role_names: HashMap<RoleId, String>,
+    subject_names: HashMap<SubjectId, String>,
+    direct_roles: HashMap<SubjectId, HashSet<RoleId>>,
+    groups: Vec<Group>,
+}
+
+impl SeparationOfDuties {
+    // deleted subjects hold nothing, and neither do expired assignments. assignments that have not
+    // started yet count, since they will be held without anyone changing them again
+    pub fn new(constraints: Vec<SodConstraint>, roles: &[Role], subjects: &[Subject], groups: &[Group]) -> SeparationOfDuties {
+        let now = Utc::now();
+        let subjects: Vec<&Subject> = subjects.iter().filter(|subject| subject.get_deleted_at().is_none()).collect();
+        SeparationOfDuties {
+            constraints,
+            role_names: roles.iter().map(|role| (role.get_id(), role.get_name())).collect(),
+            subject_names: subjects.iter().map(|subject| (subject.get_id(), subject.get_name())).collect(),
+            direct_roles: subjects.iter().map(|subject| (subject.get_id(), held_roles(subject, now))).collect(),
+            groups: groups.to_vec(),
+        }
+    }
+
+    pub fn get_constraints(&self) -> &[SodConstraint] {
+        &self.constraints
+    }
+
+    pub fn ensure_subject_may_hold(&self, subject: &Subject, role_id: &RoleId) -> Result<(), Error> {
+        let mut holdings: Vec<Holding> = held_roles(subject, Utc::now()).into_iter()
+            .chain([role_id.clone()])
+            .map(|role_id| (role_id, Some("directly".to_string())))
+            .collect();
+        holdings.extend(self.group_holdings(&subject.get_id(), None));
+        self.ensure(SodKind::Static, &subject.get_name(), &holdings)
+    }
+
+    pub fn ensure_member_may_join(&self, group: &Group, subject_id: &SubjectId) -> Result<(), Error> {
+        let holdings = self.member_holdings(group, subject_id, group.get_roles());
+        self.ensure(SodKind::Static, &self.subject_name(subject_id), &holdings)
+    }
+
+    pub fn ensure_members_may_hold(&self, group: &Group, role_id: &RoleId) -> Result<(), Error> {
+        let mut group_roles = group.get_roles();
+        group_roles.insert(role_id.clone());
+        for subject_id in self.sorted_members(group) {
+            let holdings = self.member_holdings(group, &subject_id, group_roles.clone());
+            self.ensure(SodKind::Static, &self.subject_name(&subject_id), &holdings)?;
+        }
+        Ok(())
+    }
+
+    // the members of the joining group, its own member groups' included, would hold the roles of
+    // `group` and of every group above it
+    pub fn ensure_group_may_join(&self, group: &Group, group_id: &GroupId) -> Result<(), Error> {
+        let joining = match self.groups.iter().find(|candidate| &candidate.get_id() == group_id) {
+            Some(joining) => joining,
+            None => return Ok(()),
+        };
+        for subject_id in self.sorted_members(joining) {
+            let holdings = self.member_holdings(group, &subject_id, group.get_roles());
+            self.ensure(SodKind::Static, &self.subject_name(&subject_id), &holdings)?;
+        }
+        Ok(())
+    }
+
+    // `active` are the roles already in use in the session
+    pub fn ensure_may_activate(&self, subject_name: &str, active: &HashSet<RoleId>, role_id: &RoleId) -> Result<(), Error> {
+        let mut roles = active.clone();
+        roles.insert(role_id.clone());
+        self.ensure_session_roles(subject_name, &roles)
+    }
+
+    pub fn ensure_session_roles(&self, subject_name: &str, active: &HashSet<RoleId>) -> Result<(), Error> {
+        let holdings: Vec<Holding> = active.iter().map(|role_id| (role_id.clone(), None)).collect();
+        self.ensure(SodKind::Dynamic, subject_name, &holdings)
+    }
+
+    // checks every subject against every static constraint, for changes that are easier to judge as a
+    // whole than one role at a time
+    pub fn ensure_no_violations(&self) -> Result<(), Error> {
+        let mut subject_ids: Vec<&SubjectId> = self.subject_names.keys().collect();
+        subject_ids.sort_by_key(|subject_id| (self.subject_names[*subject_id].clone(), String::from((*subject_id).clone())));
+        for subject_id in subject_ids {
+            let mut holdings: Vec<Holding> = self.direct_roles[subject_id].iter()
+                .map(|role_id| (role_id.clone(), Some("directly".to_string())))
+                .collect();
+            holdings.extend(self.group_holdings(subject_id, None));
+            self.ensure(SodKind::Static, &self.subject_names[subject_id], &holdings)?;
+        }
+        Ok(())
+    }
+
+    // what a member of `group` would hold if the group granted `group_roles`, the roles of the groups
+    // above it included
+    fn member_holdings(&self, group: &Group, subject_id: &SubjectId, group_roles: HashSet<RoleId>) -> Vec<Holding> {
+        let mut holdings: Vec<Holding> = self.direct_roles.get(subject_id).cloned().unwrap_or_default().into_iter()
+            .map(|role_id| (role_id, Some("directly".to_string())))
+            .collect();
+        let mut joined = self.direct_groups(subject_id);
+        joined.insert(group.get_id());
+        holdings.extend(self.holdings_through(joined, Some(&group.get_id())));
+        holdings.extend(group_roles.into_iter().map(|role_id| (role_id, Some(format!("through group {}", group.get_name())))));
+        holdings
+    }
+
+    fn group_holdings(&self, subject_id: &SubjectId, except: Option<&GroupId>) -> Vec<Holding> {
+        self.holdings_through(self.direct_groups(subject_id), except)
+    }
+
+    fn direct_groups(&self, subject_id: &SubjectId) -> HashSet<GroupId> {
+        self.groups.iter()
+            .filter(|group| group.get_subjects().contains(subject_id))
+            .map(Group::get_id)
+            .collect()
+    }
+
+    // the roles of the given groups and of every group that contains one of them
+    fn holdings_through(&self, group_ids: HashSet<GroupId>, except: Option<&GroupId>) -> Vec<Holding> {
+        let reached = containing(&self.groups, group_ids);
+        self.groups.iter()
+            .filter(|group| reached.contains(&group.get_id()) && Some(&group.get_id()) != except)
+            .flat_map(|group| group.get_roles().into_iter().map(move |role_id| (role_id, Some(format!("through group {}", group.get_name())))))
+            .collect()
+    }
+
+    // the subjects of `group` and of its member groups, further down included, that are not deleted,
+    // in a stable order so that the first violation reported does not change from one run to the next
+    fn sorted_members(&self, group: &Group) -> Vec<SubjectId> {
+        let mut members: Vec<SubjectId> = members_of(&self.groups, group).into_iter().filter(|subject_id| self.subject_names.contains_key(subject_id)).collect();
+        members.sort_by_cached_key(|subject_id| (self.subject_name(subject_id), String::from(subject_id.clone())));
+        members
+    }
+
+    fn ensure(&self, kind: SodKind, subject_name: &str, holdings: &[Holding]) -> Result<(), Error> {
+        for constraint in self.constraints.iter().filter(|constraint| constraint.get_kind() == kind) {
+            let mut conflicting: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+            for (role_id, source) in holdings.iter().filter(|(role_id, _)| constraint.roles.contains(role_id)) {
+                let sources = conflicting.entry(self.get_role_name(role_id)).or_default();
+                sources.extend(source.clone());
+            }
+            if conflicting.len() < 2 {
+                continue;
+            }
+
+            let held: Vec<String> = conflicting.into_iter()
+                .map(|(role, sources)| if sources.is_empty() {
+                    role
+                } else {
+                    format!("{} ({})", role, sources.into_iter().collect::<Vec<_>>().join(", "))
+                })
+                .collect();
+            let mut roles: Vec<String> = constraint.roles.iter().map(|role_id| self.get_role_name(role_id)).collect();
+            roles.sort();
+            let (verb, setting) = match kind {
+                SodKind::Static => ("hold", ""),
+                SodKind::Dynamic => ("use", " in the same session"),
+            };
+            return Err(Error::Simple(format!(
+                "subject {} cannot {} {}{}: {} separation of duties constraint {} allows at most one of {}",
+                subject_name,
+                verb,
+                held.join(" and "),
+                setting,
+                kind,
+                constraint.get_name(),
+                roles.join(", "),
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn get_role_name(&self, role_id: &RoleId) -> String {
+        self.role_names.get(role_id).cloned().unwrap_or_else(|| role_id.clone().into())
+    }
+
+    fn subject_name(&self, subject_id: &SubjectId) -> String {
+        self.subject_names.get(subject_id).cloned().unwrap_or_else(|| subject_id.clone().into())
+    }
+}
+
+fn held_roles(subject: &Subject, at: DateTime<Utc>) -> HashSet<RoleId> {
+    subject.get_role_assignments().into_iter()
+        .filter(|assignment| !assignment.is_expired_at(at))
+        .map(|assignment| assignment.get_role_id())
+        .collect()
+}
@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use super::repositories::Error;
+use super::roles::RoleId;
+use super::secrets::{format_token, hash_secret, parse_token, random_hex, verify_secret};
+use super::separation_of_duties::SeparationOfDuties;
+use super::subjects::{Subject, SubjectId};
+use super::tenants::TenantId;
+
+// every sign-in token reads `bss_<session id>_<secret>`
+const TOKEN_SCHEME: &str = "bss";
+const SECRET_BYTES: usize = 32;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SessionId(String);
+
+impl Default for SessionId {
+    fn default() -> Self {
+        SessionId(Uuid::new_v4().to_string())
+    }
+}
+
+impl From<String> for SessionId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<SessionId> for String {
+    fn from(value: SessionId) -> Self {
+        value.0
+    }
+}
+
+// splits a sign-in token into the session it belongs to and its secret
+pub fn parse_session_token(token: &str) -> Option<(SessionId, String)> {
+    parse_token(TOKEN_SCHEME, token).map(|(session_id, secret)| (SessionId::from(session_id), secret))
+}
+
+// a subject acting with a chosen subset of the roles it holds. a session starts with no roles active.
+// sessions started by signing in carry a token, of which only a hash is kept, and an expiry; a session
+// that expired or was revoked is over for good
+#[derive(Debug, Clone)]
+pub struct Session {
+    id: SessionId,
+    tenant_id: TenantId,
+    subject_id: SubjectId,
+    active_roles: HashSet<RoleId>,
+    token_hash: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl Session {
+    pub fn new(tenant_id: TenantId, subject_id: SubjectId) -> Session {
+        Session {
+            id: SessionId::default(),
+            tenant_id,
+            subject_id,
+            active_roles: HashSet::new(),
+            token_hash: None,
+            expires_at: None,
+            revoked_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    // the session together with the token that stands for it
+    pub fn sign_in(subject: &Subject, expires_at: DateTime<Utc>) -> Result<(Session, String), Error> {
+        if subject.get_deleted_at().is_some() {
+            return Err(Error::Simple(format!("subject {} is deleted", subject.get_name())));
+        }
+        if expires_at <= Utc::now() {
+            return Err(Error::Simple(format!("a session cannot expire in the past ({})", expires_at)));
+        }
+
+        let secret = random_hex(SECRET_BYTES);
+        let mut session = Session::new(subject.get_tenant_id(), subject.get_id());
+        session.token_hash = Some(hash_secret(&secret));
+        session.expires_at = Some(expires_at);
+        let token = format_token(TOKEN_SCHEME, &String::from(session.get_id()), &secret);
+        Ok((session, token))
+    }
+
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::new()
+    }
+
+    pub fn get_id(&self) -> SessionId {
+        self.id.clone()
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
+    pub fn get_subject_id(&self) -> SubjectId {
+        self.subject_id.clone()
+    }
+
+    // only roles the subject holds right now can be activated, and never alongside a role that a
+    // dynamic separation of duties constraint keeps apart from it
+    pub fn activate_role(&mut self, subject: &Subject, role_id: RoleId, duties: &SeparationOfDuties) -> Result<(), Error> {
+        if !self.is_active_at(Utc::now()) {
+            return Err(Error::Simple(format!("session {} has ended", String::from(self.id.clone()))));
+        }
+        if subject.get_id() != self.subject_id {
+            return Err(Error::Simple(format!(
+                "session {} does not belong to subject {}",
+                String::from(self.id.clone()),
+                subject.get_name(),
+            )));
+        }
+        if !subject.get_active_roles(Utc::now()).contains(&role_id) {
+            return Err(Error::Simple(format!(
+                "subject {} does not hold role {}",
+                subject.get_name(),
+                duties.get_role_name(&role_id),
+            )));
+        }
+        duties.ensure_may_activate(&subject.get_name(), &self.active_roles, &role_id)?;
+        self.active_roles.insert(role_id);
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    pub fn deactivate_role(&mut self, role_id: &RoleId) {
+        self.active_roles.remove(role_id);
+        self.updated_at = Utc::now();
+    }
+
+    pub fn get_active_roles(&self) -> HashSet<RoleId> {
+        self.active_roles.clone()
+    }
+
+    pub fn get_token_hash(&self) -> Option<String> {
+        self.token_hash.clone()
+    }
+
+    pub fn get_expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    pub fn get_revoked_at(&self) -> Option<DateTime<Utc>> {
+        self.revoked_at
+    }
+
+    pub fn is_active_at(&self, at: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| at < expires_at)
+    }
+
+    // sessions without a token cannot be signed in to
+    pub fn verify(&self, secret: &str) -> bool {
+        self.token_hash.as_ref().is_some_and(|hash| verify_secret(secret, hash))
+    }
+
+    pub fn revoke(&mut self, at: DateTime<Utc>) -> Result<(), Error> {
+        if self.revoked_at.is_some() {
+            return Err(Error::Simple(format!("session {} is already revoked", String::from(self.id.clone()))));
+        }
+        self.revoked_at = Some(at);
+        self.updated_at = at;
+        Ok(())
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn get_updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+#[derive(Default)]
+pub struct SessionBuilder {
+    id: Option<SessionId>,
+    tenant_id: Option<TenantId>,
+    subject_id: Option<SubjectId>,
+    active_roles: Option<HashSet<RoleId>>,
+    token_hash: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl SessionBuilder {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            tenant_id: None,
+            subject_id: None,
+            active_roles: None,
+            token_hash: None,
+            expires_at: None,
+            revoked_at: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    pub fn id(mut self, id: SessionId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn subject_id(mut self, subject_id: SubjectId) -> Self {
+        self.subject_id = Some(subject_id);
+        self
+    }
+
+    pub fn active_roles(mut self, active_roles: HashSet<RoleId>) -> Self {
+        self.active_roles = Some(active_roles);
+        self
+    }
+
+    pub fn token_hash(mut self, token_hash: Option<String>) -> Self {
+        self.token_hash = token_hash;
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: Option<DateTime<Utc>>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    pub fn revoked_at(mut self, revoked_at: Option<DateTime<Utc>>) -> Self {
+        self.revoked_at = revoked_at;
+        self
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn updated_at(mut self, updated_at: DateTime<Utc>) -> Self {
+        self.updated_at = Some(updated_at);
+        self
+    }
+
+    pub fn build(self) -> Session {
+        Session {
+            id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
+            subject_id: self.subject_id.unwrap(),
+            active_roles: self.active_roles.unwrap_or_default(),
+            token_hash: self.token_hash,
+            expires_at: self.expires_at,
+            revoked_at: self.revoked_at,
+            created_at: self.created_at.unwrap(),
+            updated_at: self.updated_at.unwrap(),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use super::conditions::{AttributeValue, Attributes, Condition};
+use super::groups::{Group, GroupId};
+use super::operations::Operation;
+use super::permissions::{Effect, Grantee, Permission, PermissionId};
+use super::repositories::Error;
+use super::resources::{Resource, ResourceId};
+use super::roles::{Role, RoleId};
+use super::subjects::{RoleAssignment, Subject, SubjectId, SubjectKind};
+use super::tenants::TenantId;
+
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+// a full copy of one tenant's authorization store. ids are kept as they are, so importing a snapshot
+// into another database yields the same entities; every collection is sorted so that snapshots of
+// the same state compare equal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub format_version: u32,
+    pub tenant_id: TenantId,
+    pub exported_at: DateTime<Utc>,
+    pub resources: Vec<ResourceRecord>,
+    pub permissions: Vec<PermissionRecord>,
+    pub roles: Vec<RoleRecord>,
+    pub subjects: Vec<SubjectRecord>,
+    pub groups: Vec<GroupRecord>,
+}
+
+impl Snapshot {
+    pub fn new(
+        tenant_id: TenantId,
+        resources: Vec<Resource>,
+        permissions: Vec<Permission>,
+        roles: Vec<Role>,
+        subjects: Vec<Subject>,
+        groups: Vec<Group>,
+    ) -> Snapshot {
+        let mut snapshot = Snapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            tenant_id,
+            exported_at: Utc::now(),
+            resources: resources.into_iter().map(ResourceRecord::from).collect(),
+            permissions: permissions.into_iter().map(PermissionRecord::from).collect(),
+            roles: roles.into_iter().map(RoleRecord::from).collect(),
+            subjects: subjects.into_iter().map(SubjectRecord::from).collect(),
+            groups: groups.into_iter().map(GroupRecord::from).collect(),
+        };
+        snapshot.resources.sort_by_cached_key(|record| String::from(record.id.clone()));
+        snapshot.permissions.sort_by_cached_key(|record| String::from(record.id.clone()));
+        snapshot.roles.sort_by_cached_key(|record| String::from(record.id.clone()));
+        snapshot.subjects.sort_by_cached_key(|record| String::from(record.id.clone()));
+        snapshot.groups.sort_by_cached_key(|record| String::from(record.id.clone()));
+        snapshot
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|error| Error::Simple(format!("unable to write snapshot: {}", error)))
+    }
+
+    // the format version is checked before anything else, so older or newer snapshots fail with a
+    // clear message instead of a missing field
+    pub fn from_json(source: &str) -> Result<Snapshot, Error> {
+        let value: serde_json::Value = serde_json::from_str(source)
+            .map_err(|error| Error::Simple(format!("invalid snapshot: {}", error)))?;
+        match value.get("format_version").and_then(serde_json::Value::as_u64) {
+            Some(version) if version == SNAPSHOT_FORMAT_VERSION as u64 => {},
+            Some(version) => {
+                return Err(Error::Simple(format!(
+                    "snapshot format version {} is not supported, expected {}",
+                    version,
+                    SNAPSHOT_FORMAT_VERSION,
+                )));
+            },
+            None => return Err(Error::Simple("invalid snapshot: missing format_version".to_string())),
+        }
+        serde_json::from_value(value).map_err(|error| Error::Simple(format!("invalid snapshot: {}", error)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceRecord {
+    pub id: ResourceId,
+    pub name: String,
+    pub parent_id: Option<ResourceId>,
+    pub owner_id: Option<SubjectId>,
+    pub attributes: BTreeMap<String, AttributeValue>,
+}
+
+impl From<Resource> for ResourceRecord {
+    fn from(value: Resource) -> Self {
+        Self {
+            id: value.get_id(),
+            name: value.get_name(),
+            parent_id: value.get_parent_id(),
+            owner_id: value.get_owner_id(),
+            attributes: value.get_attributes().into_iter().collect(),
+        }
+    }
+}
+
+impl ResourceRecord {
+    pub fn to_entity(&self, tenant_id: &TenantId) -> Resource {
+        Resource::builder()
+            .id(self.id.clone())
+            .tenant_id(tenant_id.clone())
+            .name(self.name.clone())
+            .parent_id(self.parent_id.clone())
+            .owner_id(self.owner_id.clone())
+            .attributes(self.attributes.clone().into_iter().collect::<Attributes>())
+            .build()
+    }
+}
+
+// permissions embed a copy of their resource; the record keeps only its id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PermissionRecord {
+    pub id: PermissionId,
+    pub name: String,
+    pub resource_id: ResourceId,
+    pub effect: String,
+    pub grantee: String,
+    pub condition: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Permission> for PermissionRecord {
+    fn from(value: Permission) -> Self {
+        let resource_id = match value.get_operation() {
+            Operation::Invoke(resource) => resource.get_id(),
+        };
+        Self {
+            id: value.get_id(),
+            name: value.get_name(),
+            resource_id,
+            effect: value.get_effect().to_string(),
+            grantee: value.get_grantee().to_string(),
+            condition: value.get_condition().map(|condition| condition.get_source()),
+            created_at: value.get_created_at(),
+            updated_at: value.get_updated_at(),
+        }
+    }
+}
+
+impl PermissionRecord {
+    pub fn to_entity(&self, tenant_id: &TenantId, resource: Resource) -> Result<Permission, Error> {
+        let in_permission = |error: Error| Error::Simple(format!("permission {}: {}", String::from(self.id.clone()), error));
+        Ok(Permission::builder()
+            .id(self.id.clone())
+            .tenant_id(tenant_id.clone())
+            .name(self.name.clone())
+            .operation(Operation::Invoke(resource))
+            .effect(Effect::try_from(self.effect.clone()).map_err(in_permission)?)
+            .grantee(Grantee::try_from(self.grantee.clone()).map_err(in_permission)?)
+            .condition(self.condition.as_deref().map(Condition::parse).transpose().map_err(in_permission)?)
+            .created_at(self.created_at)
+            .updated_at(self.updated_at)
+            .build())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoleRecord {
+    pub id: RoleId,
+    pub name: String,
+    pub permissions: Vec<PermissionId>,
+    pub approvers: Vec<SubjectId>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Role> for RoleRecord {
+    fn from(value: Role) -> Self {
+        Self {
+            id: value.get_id(),
+            name: value.get_name(),
+            permissions: sorted(value.get_permissions()),
+            approvers: sorted(value.get_approvers()),
+            created_at: value.get_created_at(),
+            updated_at: value.get_updated_at(),
+        }
+    }
+}
+
+impl RoleRecord {
+    pub fn to_entity(&self, tenant_id: &TenantId) -> Role {
+        Role::builder()
+            .id(self.id.clone())
+            .tenant_id(tenant_id.clone())
+            .name(self.name.clone())
+            .permissions(self.permissions.iter().cloned().collect())
+            .approvers(self.approvers.iter().cloned().collect())
+            .created_at(self.created_at)
+            .updated_at(self.updated_at)
+            .build()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubjectRecord {
+    pub id: SubjectId,
+    pub version: i64,
+    pub name: String,
+    // snapshots exported before there were service accounts only have people
+    #[serde(default)]
+    pub kind: SubjectKind,
+    pub roles: Vec<RoleAssignment>,
+    pub attributes: BTreeMap<String, AttributeValue>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl From<Subject> for SubjectRecord {
+    fn from(value: Subject) -> Self {
+        let mut roles = value.get_role_assignments();
+        roles.sort_by_cached_key(|assignment| String::from(assignment.get_role_id()));
+        Self {
+            id: value.get_id(),
+            version: value.get_version(),
+            name: value.get_name(),
+            kind: value.get_kind(),
+            roles,
+            attributes: value.get_attributes().into_iter().collect(),
+            created_at: value.get_created_at(),
+            updated_at: value.get_updated_at(),
+            deleted_at: value.get_deleted_at(),
+        }
+    }
+}
+
+impl SubjectRecord {
+    pub fn to_entity(&self, tenant_id: &TenantId) -> Subject {
+        Subject::builder()
+            .id(self.id.clone())
+            .tenant_id(tenant_id.clone())
+            .version(self.version)
+            .name(self.name.clone())
+            .kind(self.kind)
+            .roles(self.roles.clone())
+            .attributes(self.attributes.clone().into_iter().collect())
+            .created_at(self.created_at)
+            .updated_at(self.updated_at)
+            .deleted_at(self.deleted_at)
+            .build()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupRecord {
+    pub id: GroupId,
+    pub name: String,
+    pub subjects: Vec<SubjectId>,
+    // snapshots taken before groups could nest have no member groups
+    #[serde(default)]
+    pub groups: Vec<GroupId>,
+    pub roles: Vec<RoleId>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Group> for GroupRecord {
+    fn from(value: Group) -> Self {
+        Self {
+            id: value.get_id(),
+            name: value.get_name(),
+            subjects: sorted(value.get_subjects().clone()),
+            groups: sorted(value.get_groups().clone()),
+            roles: sorted(value.get_roles()),
+            created_at: value.get_created_at(),
+            updated_at: value.get_updated_at(),
+        }
+    }
+}
+
+impl GroupRecord {
+    pub fn to_entity(&self, tenant_id: &TenantId) -> Group {
+        Group::builder()
+            .id(self.id.clone())
+            .tenant_id(tenant_id.clone())
+            .name(self.name.clone())
+            .subjects(self.subjects.iter().cloned().collect())
+            .groups(self.groups.iter().cloned().collect())
+            .roles(self.roles.iter().cloned().collect())
+            .created_at(self.created_at)
+            .updated_at(self.updated_at)
+            .build()
+    }
+}
+
+fn sorted<Id: Clone + Into<String>>(ids: impl IntoIterator<Item = Id>) -> Vec<Id> {
+    let mut ids: Vec<Id> = ids.into_iter().collect();
+    ids.sort_by_cached_key(|id| id.clone().into());
+    ids
+}
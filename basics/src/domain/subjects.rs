@@ -1,10 +1,17 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
+use super::conditions::{AttributeValue, Attributes};
+use super::repositories::Error;
 use super::roles::RoleId;
+use super::separation_of_duties::SeparationOfDuties;
+use super::tenants::TenantId;
+
+pub const ACTIVE_ATTRIBUTE: &str = "active";
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct SubjectId(String);
@@ -21,34 +28,285 @@ impl From<String> for SubjectId {
     }
 }
 
-impl Into<String> for SubjectId {
-    fn into(self) -> String {
-        self.0
+impl From<SubjectId> for String {
+    fn from(value: SubjectId) -> Self {
+        value.0
+    }
+}
+
+// people sign in themselves; service accounts are machines that authenticate with API keys
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum SubjectKind {
+    #[default]
+    Person,
+    ServiceAccount,
+}
+
+impl fmt::Display for SubjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            SubjectKind::Person => "person",
+            SubjectKind::ServiceAccount => "service_account",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl TryFrom<String> for SubjectKind {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "person" => Ok(SubjectKind::Person),
+            "service_account" => Ok(SubjectKind::ServiceAccount),
+            _ => Err(Error::Simple(format!("unknown subject kind: {}", value))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoleAssignment {
+    role_id: RoleId,
+    #[serde(with = "chrono::serde::ts_milliseconds_option")]
+    valid_from: Option<DateTime<Utc>>,
+    #[serde(with = "chrono::serde::ts_milliseconds_option")]
+    valid_until: Option<DateTime<Utc>>,
+}
+
+impl RoleAssignment {
+    pub fn new(role_id: RoleId) -> RoleAssignment {
+        RoleAssignment {
+            role_id,
+            valid_from: None,
+            valid_until: None,
+        }
+    }
+
+    pub fn bounded(
+        role_id: RoleId,
+        valid_from: Option<DateTime<Utc>>,
+        valid_until: Option<DateTime<Utc>>,
+    ) -> RoleAssignment {
+        RoleAssignment {
+            role_id,
+            valid_from,
+            valid_until,
+        }
+    }
+
+    pub fn get_role_id(&self) -> RoleId {
+        self.role_id.clone()
+    }
+
+    pub fn get_valid_from(&self) -> Option<DateTime<Utc>> {
+        self.valid_from
+    }
+
+    pub fn get_valid_until(&self) -> Option<DateTime<Utc>> {
+        self.valid_until
+    }
+
+    // the window is half-open: [valid_from, valid_until)
+    pub fn is_active_at(&self, at: DateTime<Utc>) -> bool {
+        let started = self.valid_from.is_none_or(|valid_from| valid_from <= at);
+        started && !self.is_expired_at(at)
+    }
+
+    pub fn is_expired_at(&self, at: DateTime<Utc>) -> bool {
+        self.valid_until.is_some_and(|valid_until| valid_until <= at)
+    }
+
+    // the smallest window covering both, or `None` when there would be a gap between them
+    pub fn spanning(&self, other: &RoleAssignment) -> Option<RoleAssignment> {
+        let starts_in_time = |first: &RoleAssignment, second: &RoleAssignment| match (first.valid_until, second.valid_from) {
+            (Some(valid_until), Some(valid_from)) => valid_from <= valid_until,
+            _ => true,
+        };
+        if !starts_in_time(self, other) || !starts_in_time(other, self) {
+            return None;
+        }
+        Some(RoleAssignment {
+            role_id: self.role_id.clone(),
+            valid_from: self.valid_from.zip(other.valid_from).map(|(first, second)| first.min(second)),
+            valid_until: self.valid_until.zip(other.valid_until).map(|(first, second)| first.max(second)),
+        })
+    }
+}
+
+// every change to a subject, in the order it happened. the stream of a subject starts with
+// `SubjectCreated` unless it starts from a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SubjectEvent {
+    // streams written before there were service accounts only have people
+    SubjectCreated {
+        name: String,
+        #[serde(default)]
+        kind: SubjectKind,
+    },
+    SubjectRenamed { name: String },
+    RoleGranted { assignment: RoleAssignment },
+    RoleRevoked { role_id: RoleId },
+    AttributeSet { name: String, value: AttributeValue },
+    AttributeRemoved { name: String },
+    SubjectDeleted,
+}
+
+// an event together with the subject it happened to and the version it brought the subject to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubjectEventEnvelope {
+    subject_id: SubjectId,
+    version: i64,
+    event: SubjectEvent,
+    occurred_at: DateTime<Utc>,
+}
+
+impl SubjectEventEnvelope {
+    pub fn new(subject_id: SubjectId, version: i64, event: SubjectEvent, occurred_at: DateTime<Utc>) -> SubjectEventEnvelope {
+        SubjectEventEnvelope {
+            subject_id,
+            version,
+            event,
+            occurred_at,
+        }
+    }
+
+    pub fn get_subject_id(&self) -> SubjectId {
+        self.subject_id.clone()
+    }
+
+    pub fn get_version(&self) -> i64 {
+        self.version
+    }
+
+    pub fn get_event(&self) -> SubjectEvent {
+        self.event.clone()
+    }
+
+    pub fn get_occurred_at(&self) -> DateTime<Utc> {
+        self.occurred_at
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Subject {
     id: SubjectId,
+    tenant_id: TenantId,
     version: i64,
     name: String,
-    roles: HashSet<RoleId>,
+    kind: SubjectKind,
+    roles: HashMap<RoleId, RoleAssignment>,
+    attributes: Attributes,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
     deleted_at: Option<DateTime<Utc>>,
+    // recorded by the mutators since the subject was loaded, oldest first
+    pending_events: Vec<SubjectEventEnvelope>,
 }
 
 impl Subject {
-    pub fn new(name: &str) -> Subject {
+    pub fn new(tenant_id: TenantId, name: &str) -> Subject {
+        Subject::with_id(SubjectId::default(), tenant_id, name)
+    }
+
+    pub fn with_id(id: SubjectId, tenant_id: TenantId, name: &str) -> Subject {
+        Subject::create(id, tenant_id, name, SubjectKind::Person)
+    }
+
+    pub fn service_account(tenant_id: TenantId, name: &str) -> Subject {
+        Subject::create(SubjectId::default(), tenant_id, name, SubjectKind::ServiceAccount)
+    }
+
+    fn create(id: SubjectId, tenant_id: TenantId, name: &str, kind: SubjectKind) -> Subject {
+        let created = SubjectEventEnvelope::new(
+            id.clone(),
+            0,
+            SubjectEvent::SubjectCreated { name: name.to_string(), kind },
+            Utc::now(),
+        );
+        let mut subject = Subject::empty(id, tenant_id);
+        subject.apply(&created);
+        subject.pending_events.push(created);
+        subject
+    }
+
+    fn empty(id: SubjectId, tenant_id: TenantId) -> Subject {
         Subject {
-            id: SubjectId::default(),
+            id,
+            tenant_id,
             version: 0,
-            name: name.to_string(),
-            roles: HashSet::new(),
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
+            name: String::new(),
+            kind: SubjectKind::default(),
+            roles: HashMap::new(),
+            attributes: Attributes::new(),
+            created_at: DateTime::<Utc>::default(),
+            updated_at: DateTime::<Utc>::default(),
             deleted_at: None,
+            pending_events: Vec::new(),
+        }
+    }
+
+    // rebuilds a subject from its stream, starting from a snapshot when there is one. the events
+    // must follow the snapshot, or `SubjectCreated` without one, with no version left out
+    pub fn replay(
+        tenant_id: TenantId,
+        snapshot: Option<Subject>,
+        events: &[SubjectEventEnvelope],
+    ) -> Result<Option<Subject>, Error> {
+        let mut subject = snapshot;
+        for envelope in events {
+            match (&mut subject, envelope.get_event()) {
+                (None, SubjectEvent::SubjectCreated { .. }) => {
+                    let mut created = Subject::empty(envelope.get_subject_id(), tenant_id.clone());
+                    created.apply(envelope);
+                    subject = Some(created);
+                },
+                (None, _) => return Err(Error::Simple(format!(
+                    "stream of subject {} does not start with its creation",
+                    String::from(envelope.get_subject_id()),
+                ))),
+                (Some(current), _) => {
+                    if envelope.get_version() != current.version + 1 {
+                        return Err(Error::Simple(format!(
+                            "stream of subject {} jumps from version {} to {}",
+                            String::from(current.get_id()),
+                            current.version,
+                            envelope.get_version(),
+                        )));
+                    }
+                    current.apply(envelope);
+                },
+            }
+        }
+        Ok(subject)
+    }
+
+    fn apply(&mut self, envelope: &SubjectEventEnvelope) {
+        match envelope.get_event() {
+            SubjectEvent::SubjectCreated { name, kind } => {
+                self.name = name;
+                self.kind = kind;
+                self.created_at = envelope.get_occurred_at();
+            },
+            SubjectEvent::SubjectRenamed { name } => self.name = name,
+            SubjectEvent::RoleGranted { assignment } => { self.roles.insert(assignment.get_role_id(), assignment); },
+            SubjectEvent::RoleRevoked { role_id } => { self.roles.remove(&role_id); },
+            SubjectEvent::AttributeSet { name, value } => { self.attributes.insert(name, value); },
+            SubjectEvent::AttributeRemoved { name } => { self.attributes.remove(&name); },
+            SubjectEvent::SubjectDeleted => self.deleted_at = Some(envelope.get_occurred_at()),
         }
+        self.version = envelope.get_version();
+        self.updated_at = envelope.get_occurred_at();
+    }
+
+    fn record(&mut self, event: SubjectEvent) {
+        let envelope = SubjectEventEnvelope::new(self.id.clone(), self.version + 1, event, Utc::now());
+        self.apply(&envelope);
+        self.pending_events.push(envelope);
+    }
+
+    pub fn get_pending_events(&self) -> Vec<SubjectEventEnvelope> {
+        self.pending_events.clone()
     }
 
     pub fn builder() -> SubjectBuilder {
@@ -59,6 +317,10 @@ impl Subject {
         self.id.clone()
     }
 
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tenant_id.clone()
+    }
+
     pub fn get_version(&self) -> i64 {
         self.version
     }
@@ -67,26 +329,71 @@ impl Subject {
         self.name.clone()
     }
 
+    pub fn get_kind(&self) -> SubjectKind {
+        self.kind
+    }
+
     pub fn rename(&mut self, name: &str) {
-        self.name = name.to_string();
-        self.updated_at = Utc::now();
-        self.version += 1;
+        self.record(SubjectEvent::SubjectRenamed { name: name.to_string() });
     }
 
-    pub fn add_role(&mut self, roles: RoleId) {
-        self.roles.insert(roles);
-        self.updated_at = Utc::now();
-        self.version += 1;
+    pub fn add_role(&mut self, role: RoleId, duties: &SeparationOfDuties) -> Result<(), Error> {
+        self.add_role_assignment(RoleAssignment::new(role), duties)
+    }
+
+    // rejected when the subject would end up holding roles that a static separation of duties
+    // constraint keeps apart, counting the roles it holds through groups
+    pub fn add_role_assignment(&mut self, assignment: RoleAssignment, duties: &SeparationOfDuties) -> Result<(), Error> {
+        duties.ensure_subject_may_hold(self, &assignment.get_role_id())?;
+        self.record(SubjectEvent::RoleGranted { assignment });
+        Ok(())
     }
 
     pub fn remove_role(&mut self, role: &RoleId) {
-        self.roles.remove(role);
-        self.updated_at = Utc::now();
-        self.version += 1;
+        self.record(SubjectEvent::RoleRevoked { role_id: role.clone() });
     }
 
     pub fn get_roles(&self) -> HashSet<RoleId> {
-        self.roles.clone()
+        self.roles.keys().cloned().collect()
+    }
+
+    pub fn get_active_roles(&self, at: DateTime<Utc>) -> HashSet<RoleId> {
+        self.roles.values()
+            .filter(|assignment| assignment.is_active_at(at))
+            .map(|assignment| assignment.get_role_id())
+            .collect()
+    }
+
+    pub fn get_role_assignment(&self, role_id: &RoleId) -> Option<RoleAssignment> {
+        self.roles.get(role_id).cloned()
+    }
+
+    pub fn get_role_assignments(&self) -> Vec<RoleAssignment> {
+        self.roles.values().cloned().collect()
+    }
+
+    pub fn remove_expired_roles(&mut self, at: DateTime<Utc>) -> Vec<RoleAssignment> {
+        let expired: Vec<RoleAssignment> = self.roles.values()
+            .filter(|assignment| assignment.is_expired_at(at))
+            .cloned()
+            .collect();
+
+        for assignment in &expired {
+            self.record(SubjectEvent::RoleRevoked { role_id: assignment.get_role_id() });
+        }
+        expired
+    }
+
+    pub fn get_attributes(&self) -> Attributes {
+        self.attributes.clone()
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: AttributeValue) {
+        self.record(SubjectEvent::AttributeSet { name: name.to_string(), value });
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) {
+        self.record(SubjectEvent::AttributeRemoved { name: name.to_string() });
     }
 
     pub fn get_created_at(&self) -> DateTime<Utc> {
@@ -98,24 +405,29 @@ impl Subject {
     }
 
     pub fn delete(&mut self) {
-        let now = Utc::now();
-        self.deleted_at = Some(now);
-        self.updated_at = now;
-        self.version += 1;
+        self.record(SubjectEvent::SubjectDeleted);
     }
 
     pub fn get_deleted_at(&self) -> Option<DateTime<Utc>> {
         self.deleted_at
     }
 
-   
+    // subjects are active until they are deleted or their `active` attribute is set to false, as the
+    // identity provider that provisions them does to suspend them
+    pub fn is_active(&self) -> bool {
+        self.deleted_at.is_none() && self.attributes.get(ACTIVE_ATTRIBUTE) != Some(&AttributeValue::Bool(false))
+    }
 }
 
+#[derive(Default)]
 pub struct SubjectBuilder {
     id: Option<SubjectId>,
+    tenant_id: Option<TenantId>,
     version: Option<i64>,
     name: Option<String>,
-    roles: Option<HashSet<RoleId>>,
+    kind: Option<SubjectKind>,
+    roles: Option<Vec<RoleAssignment>>,
+    attributes: Option<Attributes>,
     created_at: Option<DateTime<Utc>>,
     updated_at: Option<DateTime<Utc>>,
     deleted_at: Option<DateTime<Utc>>,
@@ -125,9 +437,12 @@ impl SubjectBuilder {
     pub fn new() -> Self {
         Self {
             id: None,
+            tenant_id: None,
             version: None,
             name: None,
+            kind: None,
             roles: None,
+            attributes: None,
             created_at: None,
             updated_at: None,
             deleted_at: None,
@@ -139,6 +454,11 @@ impl SubjectBuilder {
         self
     }
 
+    pub fn tenant_id(mut self, tenant_id: TenantId) -> SubjectBuilder {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
     pub fn version(mut self, version: i64) -> SubjectBuilder {
         self.version = Some(version);
         self
@@ -149,11 +469,21 @@ impl SubjectBuilder {
         self
     }
 
-    pub fn roles(mut self, roles: HashSet<RoleId>) -> SubjectBuilder {
+    pub fn kind(mut self, kind: SubjectKind) -> SubjectBuilder {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn roles(mut self, roles: Vec<RoleAssignment>) -> SubjectBuilder {
         self.roles = Some(roles);
         self
     }
 
+    pub fn attributes(mut self, attributes: Attributes) -> SubjectBuilder {
+        self.attributes = Some(attributes);
+        self
+    }
+
     pub fn created_at(mut self, created_at: DateTime<Utc>) -> SubjectBuilder {
         self.created_at = Some(created_at);
         self
@@ -172,12 +502,19 @@ impl SubjectBuilder {
     pub fn build(self) -> Subject {
         Subject {
             id: self.id.unwrap(),
+            tenant_id: self.tenant_id.unwrap(),
             version: self.version.unwrap(),
             name: self.name.unwrap(),
-            roles: self.roles.unwrap(),
+            kind: self.kind.unwrap_or_default(),
+            roles: self.roles.unwrap()
+                .into_iter()
+                .map(|assignment| (assignment.get_role_id(), assignment))
+                .collect(),
+            attributes: self.attributes.unwrap_or_default(),
             created_at: self.created_at.unwrap(),
             updated_at: self.updated_at.unwrap(),
             deleted_at: self.deleted_at,
+            pending_events: Vec::new(),
         }
     }
 }
\ No newline at end of file
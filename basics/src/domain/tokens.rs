@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use super::groups::{Group, GroupId};
+use super::jwks::{Jwk, Jwks};
+use super::repositories::Error;
+use super::roles::RoleId;
+use super::subjects::{Subject, SubjectId};
+use super::tenants::TenantId;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+// what an access token says about its subject. `roles` are the roles the subject held when the token
+// was issued, those it holds through `groups` included, so a token never outlives a role assignment:
+// it expires when the first of them ends. `groups` are all the groups the subject belongs to, the
+// groups that contain its groups included
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessTokenClaims {
+    iss: String,
+    sub: SubjectId,
+    tid: TenantId,
+    jti: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    iat: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    exp: DateTime<Utc>,
+    roles: Vec<RoleId>,
+    groups: Vec<GroupId>,
+}
+
+impl AccessTokenClaims {
+    pub fn new(issuer: &str, subject: &Subject, groups: &[Group], lifetime: Duration, at: DateTime<Utc>) -> AccessTokenClaims {
+        let mut roles = subject.get_active_roles(at);
+        roles.extend(groups.iter().flat_map(|group| group.get_roles()));
+        let expires_at = subject.get_role_assignments()
+            .iter()
+            .filter(|assignment| roles.contains(&assignment.get_role_id()))
+            .filter_map(|assignment| assignment.get_valid_until())
+            .filter(|valid_until| *valid_until > at)
+            .fold(at + lifetime, DateTime::min);
+
+        let mut roles: Vec<RoleId> = roles.into_iter().collect();
+        roles.sort_by_key(|role_id| String::from(role_id.clone()));
+        let mut groups: Vec<GroupId> = groups.iter().map(|group| group.get_id()).collect::<HashSet<_>>().into_iter().collect();
+        groups.sort_by_key(|group_id| String::from(group_id.clone()));
+
+        AccessTokenClaims {
+            iss: issuer.to_string(),
+            sub: subject.get_id(),
+            tid: subject.get_tenant_id(),
+            jti: Uuid::new_v4().to_string(),
+            // seconds are all a token can tell
+            iat: DateTime::from_timestamp(at.timestamp(), 0).unwrap_or(at),
+            exp: DateTime::from_timestamp(expires_at.timestamp(), 0).unwrap_or(expires_at),
+            roles,
+            groups,
+        }
+    }
+
+    pub fn get_issuer(&self) -> String {
+        self.iss.clone()
+    }
+
+    pub fn get_subject_id(&self) -> SubjectId {
+        self.sub.clone()
+    }
+
+    pub fn get_tenant_id(&self) -> TenantId {
+        self.tid.clone()
+    }
+
+    pub fn get_token_id(&self) -> String {
+        self.jti.clone()
+    }
+
+    pub fn get_issued_at(&self) -> DateTime<Utc> {
+        self.iat
+    }
+
+    pub fn get_expires_at(&self) -> DateTime<Utc> {
+        self.exp
+    }
+
+    pub fn get_roles(&self) -> HashSet<RoleId> {
+        self.roles.iter().cloned().collect()
+    }
+
+    pub fn get_groups(&self) -> HashSet<GroupId> {
+        self.groups.iter().cloned().collect()
+    }
+
+    // a compact JWS signed with the given key
+    pub fn sign(&self, key: &Jwk) -> Result<String, Error> {
+        let header = Header {
+            alg: key.get_algorithm()?.to_string(),
+            typ: "JWT".to_string(),
+            kid: key.get_kid(),
+        };
+        let signing_input = format!("{}.{}", encode_json(&header)?, encode_json(self)?);
+        let signature = key.sign(signing_input.as_bytes())?;
+        Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature)))
+    }
+
+    // the claims of a token signed by one of the keys in the set, from the given issuer and not expired
+    // at the given time. the key named in the header must have the algorithm the header claims, so that
+    // a token cannot pick how it is verified
+    pub fn verify(token: &str, key_set: &Jwks, issuer: &str, at: DateTime<Utc>) -> Result<AccessTokenClaims, Error> {
+        let invalid = |reason: &str| Error::Simple(format!("invalid access token: {}", reason));
+        let (signing_input, signature) = token.rsplit_once('.').ok_or_else(|| invalid("malformed"))?;
+        let (header, claims) = signing_input.split_once('.').ok_or_else(|| invalid("malformed"))?;
+
+        let header: Header = decode_json(header).ok_or_else(|| invalid("malformed header"))?;
+        let key = key_set.find(&header.kid).ok_or_else(|| invalid("unknown key"))?;
+        if key.get_algorithm()?.to_string() != header.alg {
+            return Err(invalid("algorithm does not match the key"));
+        }
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid("malformed signature"))?;
+        if !key.verify(signing_input.as_bytes(), &signature) {
+            return Err(invalid("bad signature"));
+        }
+
+        let claims: AccessTokenClaims = decode_json(claims).ok_or_else(|| invalid("malformed claims"))?;
+        if claims.iss != issuer {
+            return Err(invalid("unexpected issuer"));
+        }
+        if claims.exp <= at {
+            return Err(invalid("expired"));
+        }
+        Ok(claims)
+    }
+}
+
+fn encode_json<T: Serialize>(value: &T) -> Result<String, Error> {
+    serde_json::to_vec(value)
+        .map(|json| URL_SAFE_NO_PAD.encode(json))
+        .map_err(|error| Error::Simple(format!("unable to encode access token: {}", error)))
+}
+
+fn decode_json<T: for<'de> Deserialize<'de>>(value: &str) -> Option<T> {
+    URL_SAFE_NO_PAD.decode(value).ok().and_then(|json| serde_json::from_slice(&json).ok())
+}
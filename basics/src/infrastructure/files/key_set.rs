@@ -0,0 +1,64 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use async_std::fs::OpenOptions;
+use async_std::io::WriteExt;
+#[cfg(unix)]
+use async_std::os::unix::fs::OpenOptionsExt;
+use async_trait::async_trait;
+
+use crate::domain::jwks::Jwks;
+use crate::domain::repositories::{Error, KeySetRepository};
+
+// keeps the key set in a local JWKS document. it holds private keys, so it is only readable by its owner,
+// and it is replaced in one go so that readers never see half a key set
+pub struct FileKeySetRepository {
+    path: PathBuf,
+}
+
+impl FileKeySetRepository {
+    pub fn new(path: impl Into<PathBuf>) -> FileKeySetRepository {
+        FileKeySetRepository {
+            path: path.into(),
+        }
+    }
+
+    fn error(&self, what: &str, error: std::io::Error) -> Error {
+        Error::Simple(format!("unable to {} key set {}: {}", what, self.path.display(), error))
+    }
+}
+
+#[async_trait]
+impl KeySetRepository for FileKeySetRepository {
+    async fn load(&self) -> Result<Jwks, Error> {
+        match async_std::fs::read_to_string(&self.path).await {
+            Ok(source) => Jwks::parse(&source),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Jwks::new()),
+            Err(error) => Err(self.error("read", error)),
+        }
+    }
+
+    async fn save(&self, key_set: &Jwks) -> Result<(), Error> {
+        if let Some(directory) = self.path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            async_std::fs::create_dir_all(directory).await.map_err(|error| self.error("create the directory of", error))?;
+        }
+
+        let mut staged = self.path.clone().into_os_string();
+        staged.push(".tmp");
+        let staged = PathBuf::from(staged);
+        // a save that failed halfway may have left its staged copy behind
+        match async_std::fs::remove_file(&staged).await {
+            Err(error) if error.kind() != ErrorKind::NotFound => return Err(self.error("clean up", error)),
+            _ => {},
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&staged).await.map_err(|error| self.error("write", error))?;
+        file.write_all(key_set.to_json()?.as_bytes()).await.map_err(|error| self.error("write", error))?;
+        file.sync_all().await.map_err(|error| self.error("write", error))?;
+        async_std::fs::rename(&staged, &self.path).await.map_err(|error| self.error("replace", error))
+    }
+}
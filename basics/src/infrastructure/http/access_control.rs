@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use http::request::Parts;
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use serde::{Serialize, Deserialize};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::application::access_checker::AccessChecker;
+use crate::application::api_keys::ApiKeyService;
+use crate::application::authentication::AuthenticationService;
+use crate::domain::conditions::RequestContext;
+use crate::domain::repositories::{Error, SubjectRepository};
+use crate::domain::subjects::{Subject, SubjectId};
+
+pub const SUBJECT_HEADER: &str = "x-subject-id";
+
+// tells who sent a request. requests without credentials, or whose credentials name nobody, have no
+// subject; an error means the credentials could not be checked at all
+#[async_trait]
+pub trait Authenticator {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<Option<SubjectId>, Error>;
+
+    // the authentication scheme a 401 challenges the client with in `WWW-Authenticate`, if it has one
+    fn scheme(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+// trusts the subject id a gateway in front of the service put in the `x-subject-id` header. only
+// subjects that exist and are active are let through. a header is no authentication scheme, so
+// its 401s challenge nobody
+pub struct SubjectHeader {
+    subject_repository: Box<dyn SubjectRepository + Send + Sync>,
+}
+
+impl SubjectHeader {
+    pub fn new(subject_repository: Box<dyn SubjectRepository + Send + Sync>) -> SubjectHeader {
+        SubjectHeader {
+            subject_repository,
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for SubjectHeader {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<Option<SubjectId>, Error> {
+        let subject_id = match headers.get(SUBJECT_HEADER).and_then(|value| value.to_str().ok()) {
+            Some(subject_id) => SubjectId::from(subject_id.to_string()),
+            None => return Ok(None),
+        };
+        let subject = self.subject_repository.get_by_id(subject_id).await?;
+        Ok(subject.filter(Subject::is_active).map(|subject| subject.get_id()))
+    }
+}
+
+// takes the service account from an `Authorization: Bearer <api key>` header. a key that cannot be
+// checked counts as one that is refused, so a store that cannot be reached shows up as 401s
+pub struct ApiKeyAuthenticator {
+    api_key_service: Box<dyn ApiKeyService + Send + Sync>,
+}
+
+impl ApiKeyAuthenticator {
+    pub fn new(api_key_service: Box<dyn ApiKeyService + Send + Sync>) -> ApiKeyAuthenticator {
+        ApiKeyAuthenticator {
+            api_key_service,
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for ApiKeyAuthenticator {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<Option<SubjectId>, Error> {
+        let api_key = match bearer_token(headers) {
+            Some(api_key) => api_key,
+            None => return Ok(None),
+        };
+        match self.api_key_service.authenticate(api_key).await {
+            Ok(subject_id) => Ok(Some(subject_id)),
+            Err(Error::Simple(cause)) => {
+                tracing::debug!(cause, "api key refused");
+                Ok(None)
+            },
+        }
+    }
+
+    fn scheme(&self) -> Option<&'static str> {
+        Some("Bearer")
+    }
+}
+
+// takes the person from an `Authorization: Bearer <session token>` header, as handed out by logging in.
+// like API keys, tokens that cannot be checked count as refused
+pub struct SessionAuthenticator {
+    authentication_service: Box<dyn AuthenticationService + Send + Sync>,
+}
+
+impl SessionAuthenticator {
+    pub fn new(authentication_service: Box<dyn AuthenticationService + Send + Sync>) -> SessionAuthenticator {
+        SessionAuthenticator {
+            authentication_service,
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for SessionAuthenticator {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<Option<SubjectId>, Error> {
+        let token = match bearer_token(headers) {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        match self.authentication_service.authenticate(token).await {
+            Ok(session) => Ok(Some(session.get_subject_id())),
+            Err(Error::Simple(cause)) => {
+                tracing::debug!(cause, "session token refused");
+                Ok(None)
+            },
+        }
+    }
+
+    fn scheme(&self) -> Option<&'static str> {
+        Some("Bearer")
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+// the body of every refused request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessError {
+    pub error: String,
+    pub message: String,
+    // the resource the route maps to, once known
+    pub resource: Option<String>,
+    #[serde(skip)]
+    pub status: u16,
+}
+
+impl AccessError {
+    fn unauthenticated() -> AccessError {
+        AccessError {
+            error: "unauthenticated".to_string(),
+            message: "the request carries no valid credentials".to_string(),
+            resource: None,
+            status: StatusCode::UNAUTHORIZED.as_u16(),
+        }
+    }
+
+    fn forbidden(message: String, resource: Option<String>) -> AccessError {
+        AccessError {
+            error: "forbidden".to_string(),
+            message,
+            resource,
+            status: StatusCode::FORBIDDEN.as_u16(),
+        }
+    }
+
+    // the cause is logged rather than handed to the caller
+    fn internal(error: Error) -> AccessError {
+        let Error::Simple(cause) = error;
+        tracing::error!(cause, "unable to check access");
+        AccessError {
+            error: "internal".to_string(),
+            message: "access could not be checked".to_string(),
+            resource: None,
+            status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+        }
+    }
+
+    // a 401 names the scheme it challenges with, when there is one
+    fn into_response<B: From<String>>(self, scheme: Option<&'static str>) -> Response<B> {
+        let body = serde_json::to_string(&self).expect("access errors always serialize");
+        let mut response = Response::new(B::from(body));
+        *response.status_mut() = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if let Some(scheme) = scheme.filter(|_| response.status() == StatusCode::UNAUTHORIZED) {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static(scheme));
+        }
+        response
+    }
+}
+
+// the request context keys the layer sets itself, which path parameters may not take over
+const CONTEXT_KEYS: [&str; 2] = ["method", "path"];
+
+#[derive(Debug, Clone)]
+enum Access {
+    Public,
+    // a resource name in which `{name}` stands for the path segment of the same name
+    Resource(String),
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    method: Method,
+    segments: Vec<String>,
+    access: Access,
+}
+
+impl Route {
+    fn new(method: Method, path: &str, access: Access) -> Route {
+        let segments: Vec<String> = path.split('/').filter(|segment| !segment.is_empty()).map(str::to_string).collect();
+        for name in segments.iter().filter_map(|segment| parameter_name(segment)) {
+            assert!(!CONTEXT_KEYS.contains(&name), "path parameter {{{}}} of {} would take the place of the request's {}", name, path, name);
+        }
+        Route {
+            method,
+            segments,
+            access,
+        }
+    }
+
+    // the path parameters when the route matches
+    fn matches(&self, method: &Method, path: &str) -> Option<HashMap<String, String>> {
+        let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+        if *method != self.method || segments.len() != self.segments.len() {
+            return None;
+        }
+
+        let mut parameters = HashMap::new();
+        for (pattern, segment) in self.segments.iter().zip(segments) {
+            match parameter_name(pattern) {
+                Some(name) => { parameters.insert(name.to_string(), segment.to_string()); },
+                None if pattern == segment => {},
+                None => return None,
+            }
+        }
+        Some(parameters)
+    }
+}
+
+fn parameter_name(segment: &str) -> Option<&str> {
+    segment.strip_prefix('{').and_then(|segment| segment.strip_suffix('}'))
+}
+
+// puts the path parameters in place of their `{name}` in one pass over the template, so a value that
+// itself reads `{name}` is taken as it is. names without a parameter are left as they were
+fn expand(template: &str, parameters: &HashMap<String, String>) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        match placeholder.find('}').and_then(|end| parameters.get(&placeholder[1..end]).map(|value| (end, value))) {
+            Some((end, value)) => {
+                expanded.push_str(value);
+                rest = &placeholder[end + 1..];
+            },
+            None => {
+                expanded.push('{');
+                rest = &placeholder[1..];
+            },
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+struct AccessPolicy {
+    access_checker: AccessChecker,
+    authenticator: Box<dyn Authenticator + Send + Sync>,
+    routes: Vec<Route>,
+}
+
+impl AccessPolicy {
+    // the subject the request is let through for, if it needs one
+    async fn authorize(&self, parts: &Parts) -> Result<Option<SubjectId>, AccessError> {
+        let path = parts.uri.path();
+        let (route, parameters) = self.routes.iter()
+            .find_map(|route| route.matches(&parts.method, path).map(|parameters| (route, parameters)))
+            .ok_or_else(|| AccessError::forbidden(format!("{} {} is not mapped to a resource", parts.method, path), None))?;
+
+        let template = match &route.access {
+            Access::Public => return Ok(None),
+            Access::Resource(template) => template,
+        };
+        let subject_id = self.authenticator.authenticate(&parts.headers)
+            .await
+            .map_err(AccessError::internal)?
+            .ok_or_else(AccessError::unauthenticated)?;
+
+        let resource_name = expand(template, &parameters);
+        let mut context = RequestContext::new()
+            .with("method", parts.method.as_str())
+            .with("path", path);
+        for (name, value) in parameters {
+            context = context.with(&name, value);
+        }
+
+        let decision = self.access_checker.check_by_name_in_context(subject_id.clone(), &resource_name, &context)
+            .await
+            .map_err(AccessError::internal)?;
+        if !decision.allowed {
+            return Err(AccessError::forbidden(format!("not allowed to invoke {}", resource_name), Some(resource_name)));
+        }
+        Ok(Some(subject_id))
+    }
+}
+
+// refuses requests the access checker does not let through: 401 when the request has no known subject
+// and 403 when the subject may not invoke the resource its route maps to. routes nobody declared are
+// refused too. the operation is always invoking, the only one there is. the subject of a request that
+// gets through is added to its extensions
+#[derive(Clone)]
+pub struct AccessControlLayer {
+    policy: Arc<AccessPolicy>,
+}
+
+impl AccessControlLayer {
+    pub fn new(access_checker: AccessChecker, authenticator: Box<dyn Authenticator + Send + Sync>) -> AccessControlLayer {
+        AccessControlLayer {
+            policy: Arc::new(AccessPolicy {
+                access_checker,
+                authenticator,
+                routes: Vec::new(),
+            }),
+        }
+    }
+
+    // routes are matched in the order they were declared; `{name}` in the path matches any segment
+    // and may be used in the resource name. parameters reach conditions as `context.name`, so they
+    // cannot be named `method` or `path`
+    pub fn route(self, method: Method, path: &str, resource_name: &str) -> AccessControlLayer {
+        self.with_route(Route::new(method, path, Access::Resource(resource_name.to_string())))
+    }
+
+    pub fn public(self, method: Method, path: &str) -> AccessControlLayer {
+        self.with_route(Route::new(method, path, Access::Public))
+    }
+
+    fn with_route(mut self, route: Route) -> AccessControlLayer {
+        Arc::get_mut(&mut self.policy)
+            .expect("routes are declared before the layer is used")
+            .routes
+            .push(route);
+        self
+    }
+}
+
+impl<S> Layer<S> for AccessControlLayer {
+    type Service = AccessControl<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessControl {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AccessControl<S> {
+    inner: S,
+    policy: Arc<AccessPolicy>,
+}
+
+impl<S, RequestBody, ResponseBody> Service<Request<RequestBody>> for AccessControl<S>
+where
+    S: Service<Request<RequestBody>, Response = Response<ResponseBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    RequestBody: Send + 'static,
+    ResponseBody: From<String>,
+{
+    type Response = Response<ResponseBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, context: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(context)
+    }
+
+    fn call(&mut self, request: Request<RequestBody>) -> Self::Future {
+        // the clone that was polled ready handles the request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let (mut parts, body) = request.into_parts();
+            match policy.authorize(&parts).await {
+                Ok(subject_id) => {
+                    if let Some(subject_id) = subject_id {
+                        parts.extensions.insert(subject_id);
+                    }
+                    inner.call(Request::from_parts(parts, body)).await
+                },
+                Err(error) => Ok(error.into_response(policy.authenticator.scheme())),
+            }
+        })
+    }
+}
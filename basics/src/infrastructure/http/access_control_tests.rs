@@ -0,0 +1,221 @@
+use std::convert::Infallible;
+
+use http::{header, Method, Request, Response, StatusCode};
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+use tower::util::BoxCloneService;
+use tower::{service_fn, ServiceBuilder, ServiceExt};
+
+use crate::domain::subjects::SubjectId;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::metrics;
+use crate::test_support::{access_checker, seeded_database, subject_id, tenant_id};
+
+use super::access_control::{AccessControlLayer, AccessError, SubjectHeader, SUBJECT_HEADER};
+use super::metrics::{MetricsEndpoint, METRICS_CONTENT_TYPE, METRICS_PATH};
+
+const SEED: &str = "
+    resources:
+      - name: users
+      - name: users/get_users
+        parent: users
+      - name: users/update_user
+        parent: users
+      - name: reports
+      - name: reports/karla
+        parent: reports
+    permissions:
+      - name: list users
+        resource: users/get_users
+      - name: update user
+        resource: users/update_user
+      - name: read reports
+        resource: reports
+    roles:
+      - name: engineer
+        permissions: [list users, update user]
+      - name: analyst
+        permissions: [read reports]
+    subjects:
+      - name: alec leamas
+        roles: [engineer]
+      - name: george smiley
+        roles: [analyst]
+      - name: bill haydon
+        roles: [engineer]
+        attributes: {active: false}
+";
+
+type ExampleService = BoxCloneService<Request<String>, Response<String>, Infallible>;
+
+// a users and reports service that answers with the subject it was called for. it does no access
+// checks of its own: the layer in front of it does them all
+fn example_service(connection_pool: &Pool<Sqlite>) -> ExampleService {
+    let access_control = AccessControlLayer::new(
+        access_checker(connection_pool),
+        Box::new(SubjectHeader::new(Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())))),
+    )
+        .route(Method::GET, "/users", "users/get_users")
+        .route(Method::PUT, "/users/{id}", "users/update_user")
+        .route(Method::GET, "/reports/{name}", "reports/{name}")
+        .route(Method::GET, "/reports/{name}/{section}", "reports/{name}")
+        .public(Method::GET, METRICS_PATH);
+
+    let handler = service_fn(|request: Request<String>| async move {
+        if request.uri().path() == METRICS_PATH {
+            return MetricsEndpoint::new(metrics::global()).oneshot(request).await;
+        }
+        let subject_id = request.extensions().get::<SubjectId>().cloned().map(String::from).unwrap_or_default();
+        Ok::<_, Infallible>(Response::new(format!("{} {} for {}", request.method(), request.uri().path(), subject_id)))
+    });
+    BoxCloneService::new(ServiceBuilder::new().layer(access_control).service(handler))
+}
+
+async fn send(service: &ExampleService, method: Method, path: &str, subject_id: Option<&SubjectId>) -> Response<String> {
+    let mut request = Request::builder().method(method).uri(path);
+    if let Some(subject_id) = subject_id {
+        request = request.header(SUBJECT_HEADER, String::from(subject_id.clone()));
+    }
+    service.clone().oneshot(request.body(String::new()).unwrap()).await.unwrap()
+}
+
+fn access_error(response: &Response<String>) -> AccessError {
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    serde_json::from_str(response.body()).unwrap()
+}
+
+#[async_std::test]
+async fn test_allowed_requests_reach_the_service_with_their_subject() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = example_service(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+
+    let response = send(&service, Method::GET, "/users", Some(&alec_leamas_id)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body(), &format!("GET /users for {}", String::from(alec_leamas_id.clone())));
+
+    let response = send(&service, Method::PUT, "/users/42", Some(&alec_leamas_id)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[async_std::test]
+async fn test_requests_without_a_known_subject_are_unauthenticated() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = example_service(&connection_pool);
+
+    for subject_id in [None, Some(SubjectId::default())] {
+        let response = send(&service, Method::GET, "/users", subject_id.as_ref()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // a header is no scheme to challenge the client with
+        assert!(!response.headers().contains_key(header::WWW_AUTHENTICATE));
+        assert_eq!(access_error(&response).error, "unauthenticated");
+    }
+}
+
+#[async_std::test]
+async fn test_deactivated_subjects_are_unauthenticated() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = example_service(&connection_pool);
+    let bill_haydon_id = subject_id(&connection_pool, "bill haydon").await;
+
+    let response = send(&service, Method::GET, "/users", Some(&bill_haydon_id)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(access_error(&response).error, "unauthenticated");
+}
+
+#[async_std::test]
+async fn test_subjects_without_permission_are_forbidden() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = example_service(&connection_pool);
+    let george_smiley_id = subject_id(&connection_pool, "george smiley").await;
+
+    let response = send(&service, Method::PUT, "/users/42", Some(&george_smiley_id)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let error = access_error(&response);
+    assert_eq!(error.error, "forbidden");
+    assert_eq!(error.resource.as_deref(), Some("users/update_user"));
+}
+
+#[async_std::test]
+async fn test_path_parameters_pick_the_resource() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = example_service(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    let george_smiley_id = subject_id(&connection_pool, "george smiley").await;
+
+    // the permission on reports covers the reports under it
+    assert_eq!(send(&service, Method::GET, "/reports/karla", Some(&george_smiley_id)).await.status(), StatusCode::OK);
+    assert_eq!(send(&service, Method::GET, "/reports/karla", Some(&alec_leamas_id)).await.status(), StatusCode::FORBIDDEN);
+
+    // resources that were never registered cannot be invoked by anyone
+    let response = send(&service, Method::GET, "/reports/control", Some(&george_smiley_id)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(access_error(&response).resource.as_deref(), Some("reports/control"));
+}
+
+#[async_std::test]
+async fn test_path_parameters_are_put_in_place_once() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = example_service(&connection_pool);
+    let george_smiley_id = subject_id(&connection_pool, "george smiley").await;
+
+    // the name reads like the other parameter, but stays what it is
+    let response = send(&service, Method::GET, "/reports/{section}/karla", Some(&george_smiley_id)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(access_error(&response).resource.as_deref(), Some("reports/{section}"));
+}
+
+#[test]
+#[should_panic(expected = "path parameter {path} of /files/{path} would take the place of the request's path")]
+fn test_path_parameters_cannot_take_the_place_of_the_request_context() {
+    async_std::task::block_on(async {
+        let connection_pool = seeded_database(SEED).await;
+        AccessControlLayer::new(
+            access_checker(&connection_pool),
+            Box::new(SubjectHeader::new(Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())))),
+        )
+            .route(Method::GET, "/files/{path}", "files/{path}");
+    });
+}
+
+#[async_std::test]
+async fn test_checks_that_fail_are_internal_errors() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = example_service(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+
+    // the role alec holds goes missing behind the store's back
+    sqlx::query("DELETE FROM roles WHERE name = 'engineer'").execute(&connection_pool).await.unwrap();
+
+    let response = send(&service, Method::GET, "/users", Some(&alec_leamas_id)).await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let error = access_error(&response);
+    assert_eq!(error.error, "internal");
+    assert_eq!(error.resource, None);
+}
+
+#[async_std::test]
+async fn test_undeclared_routes_are_forbidden() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = example_service(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+
+    for (method, path) in [(Method::DELETE, "/users/42"), (Method::GET, "/payments")] {
+        let response = send(&service, method, path, Some(&alec_leamas_id)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(access_error(&response).resource, None);
+    }
+}
+
+#[async_std::test]
+async fn test_public_routes_need_no_subject() {
+    let connection_pool = seeded_database(SEED).await;
+    let service = example_service(&connection_pool);
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+    send(&service, Method::GET, "/users", Some(&alec_leamas_id)).await;
+
+    let response = send(&service, Method::GET, METRICS_PATH, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], METRICS_CONTENT_TYPE);
+    assert!(response.body().contains("basics_access_decisions_total{outcome=\"allow\"}"));
+}
@@ -0,0 +1,53 @@
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use std::task::{Context, Poll};
+
+use http::{header, Method, Request, Response, StatusCode};
+use tower_service::Service;
+
+use crate::metrics::Metrics;
+
+pub const METRICS_PATH: &str = "/metrics";
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// serves the metrics in the Prometheus text exposition format on a GET of `/metrics`. scrapers send no
+// subject, so the route is usually declared public on the `AccessControlLayer` in front of it
+#[derive(Clone)]
+pub struct MetricsEndpoint {
+    metrics: &'static Metrics,
+}
+
+impl MetricsEndpoint {
+    pub fn new(metrics: &'static Metrics) -> MetricsEndpoint {
+        MetricsEndpoint {
+            metrics,
+        }
+    }
+}
+
+impl<RequestBody> Service<Request<RequestBody>> for MetricsEndpoint {
+    type Response = Response<String>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _context: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<RequestBody>) -> Self::Future {
+        let response = if request.uri().path() != METRICS_PATH {
+            Response::builder().status(StatusCode::NOT_FOUND).body(String::new())
+        } else if request.method() != Method::GET {
+            Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, Method::GET.as_str())
+                .body(String::new())
+        } else {
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)
+                .body(self.metrics.render())
+        };
+        ready(Ok(response.expect("metrics responses are always valid")))
+    }
+}
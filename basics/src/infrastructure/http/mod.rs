@@ -0,0 +1,7 @@
+pub mod access_control;
+#[cfg(test)]
+mod access_control_tests;
+pub mod metrics;
+pub mod scim;
+#[cfg(test)]
+mod scim_tests;
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::request::Parts;
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tower_service::Service;
+
+use crate::application::scim::{
+    ScimCreateRequest, ScimDeleteRequest, ScimError, ScimGetRequest, ScimListRequest, ScimPatchRequest,
+    ScimReplaceRequest, ScimService, MAX_RESULTS,
+};
+use crate::domain::scim_filters::attribute_key;
+
+pub const SCIM_PATH: &str = "/scim/v2";
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+
+const SERVICE_PROVIDER_CONFIG_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+
+// attributes a response always has, whatever the request excludes
+const ALWAYS_RETURNED: &[&str] = &["id", "schemas", "meta"];
+
+struct Reply {
+    status: StatusCode,
+    body: Option<Value>,
+    etag: Option<String>,
+    location: Option<String>,
+}
+
+impl Reply {
+    // a resource's ETag and location come from its `meta`
+    fn resource<T: Serialize>(status: StatusCode, resource: &T) -> Reply {
+        let body = serde_json::to_value(resource).expect("scim resources always serialize");
+        let meta = |name: &str| body.get("meta").and_then(|meta| meta.get(name)).and_then(Value::as_str).map(str::to_string);
+        Reply {
+            status,
+            etag: meta("version"),
+            location: (status == StatusCode::CREATED).then(|| meta("location")).flatten(),
+            body: Some(body),
+        }
+    }
+
+    fn empty(status: StatusCode) -> Reply {
+        Reply {
+            status,
+            body: None,
+            etag: None,
+            location: None,
+        }
+    }
+
+    fn exclude(&mut self, excluded: &[String]) {
+        let resources = match &mut self.body {
+            Some(Value::Object(body)) if body.contains_key("Resources") => match body.get_mut("Resources") {
+                Some(Value::Array(resources)) => resources.iter_mut().collect(),
+                _ => Vec::new(),
+            },
+            Some(body) => vec![body],
+            None => Vec::new(),
+        };
+        for resource in resources.into_iter().filter_map(Value::as_object_mut) {
+            for name in excluded.iter().filter(|name| !ALWAYS_RETURNED.iter().any(|kept| kept.eq_ignore_ascii_case(name))) {
+                if let Some(key) = attribute_key(resource, name) {
+                    resource.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn into_response(self) -> Response<String> {
+        let body = self.body.map(|body| body.to_string()).unwrap_or_default();
+        let mut response = Response::new(body);
+        *response.status_mut() = self.status;
+        if response.status() != StatusCode::NO_CONTENT && response.status() != StatusCode::NOT_MODIFIED {
+            response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(SCIM_CONTENT_TYPE));
+        }
+        for (name, value) in [(header::ETAG, self.etag), (header::LOCATION, self.location)] {
+            if let Some(value) = value.and_then(|value| HeaderValue::from_str(&value).ok()) {
+                response.headers_mut().insert(name, value);
+            }
+        }
+        response
+    }
+}
+
+impl From<ScimError> for Reply {
+    fn from(error: ScimError) -> Self {
+        Reply {
+            status: StatusCode::from_u16(error.get_status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            body: Some(serde_json::to_value(&error).expect("scim errors always serialize")),
+            etag: None,
+            location: None,
+        }
+    }
+}
+
+fn header_value(parts: &Parts, name: header::HeaderName) -> Option<String> {
+    parts.headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+fn json_body(body: &str) -> Result<Value, ScimError> {
+    serde_json::from_str(body).map_err(|error| ScimError::invalid_syntax(format!("the body is not json: {}", error)))
+}
+
+fn list_request(query: &HashMap<String, String>) -> Result<ScimListRequest, ScimError> {
+    let number = |name: &str| query.get(name)
+        .map(|value| value.parse::<i64>().map_err(|_| ScimError::invalid_value(format!("{} is not a number", name))))
+        .transpose();
+    Ok(ScimListRequest {
+        filter: query.get("filter").cloned(),
+        start_index: number("startIndex")?,
+        count: number("count")?,
+    })
+}
+
+// what the endpoints support, for identity providers that ask
+fn service_provider_config() -> Value {
+    json!({
+        "schemas": [SERVICE_PROVIDER_CONFIG_SCHEMA],
+        "patch": {"supported": true},
+        "bulk": {"supported": false, "maxOperations": 0, "maxPayloadSize": 0},
+        "filter": {"supported": true, "maxResults": MAX_RESULTS},
+        "changePassword": {"supported": false},
+        "sort": {"supported": false},
+        "etag": {"supported": true},
+        "authenticationSchemes": [],
+    })
+}
+
+async fn route(scim_service: &(dyn ScimService + Send + Sync), parts: &Parts, body: &str) -> Result<Reply, ScimError> {
+    let path = parts.uri.path();
+    let segments: Vec<&str> = path.strip_prefix(SCIM_PATH)
+        .map(|path| path.split('/').filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+    let query: HashMap<String, String> = form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect();
+    let if_match = header_value(parts, header::IF_MATCH);
+    let id = |id: &str| id.to_string();
+
+    let mut reply = match (&parts.method, segments.as_slice()) {
+        (&Method::GET, ["ServiceProviderConfig"]) => Reply::resource(StatusCode::OK, &service_provider_config()),
+        (&Method::GET, ["Users"]) => Reply::resource(StatusCode::OK, &scim_service.list_users(list_request(&query)?).await?),
+        (&Method::POST, ["Users"]) => Reply::resource(
+            StatusCode::CREATED,
+            &scim_service.create_user(ScimCreateRequest { resource: json_body(body)? }).await?,
+        ),
+        (&Method::GET, ["Users", user_id]) => Reply::resource(
+            StatusCode::OK,
+            &scim_service.get_user(ScimGetRequest { id: id(user_id) }).await?,
+        ),
+        (&Method::PUT, ["Users", user_id]) => Reply::resource(
+            StatusCode::OK,
+            &scim_service.replace_user(ScimReplaceRequest { id: id(user_id), resource: json_body(body)?, if_match }).await?,
+        ),
+        (&Method::PATCH, ["Users", user_id]) => Reply::resource(
+            StatusCode::OK,
+            &scim_service.patch_user(ScimPatchRequest { id: id(user_id), patch: json_body(body)?, if_match }).await?,
+        ),
+        (&Method::DELETE, ["Users", user_id]) => {
+            scim_service.delete_user(ScimDeleteRequest { id: id(user_id), if_match }).await?;
+            Reply::empty(StatusCode::NO_CONTENT)
+        },
+        (&Method::GET, ["Groups"]) => Reply::resource(StatusCode::OK, &scim_service.list_groups(list_request(&query)?).await?),
+        (&Method::POST, ["Groups"]) => Reply::resource(
+            StatusCode::CREATED,
+            &scim_service.create_group(ScimCreateRequest { resource: json_body(body)? }).await?,
+        ),
+        (&Method::GET, ["Groups", group_id]) => Reply::resource(
+            StatusCode::OK,
+            &scim_service.get_group(ScimGetRequest { id: id(group_id) }).await?,
+        ),
+        (&Method::PUT, ["Groups", group_id]) => Reply::resource(
+            StatusCode::OK,
+            &scim_service.replace_group(ScimReplaceRequest { id: id(group_id), resource: json_body(body)?, if_match }).await?,
+        ),
+        (&Method::PATCH, ["Groups", group_id]) => Reply::resource(
+            StatusCode::OK,
+            &scim_service.patch_group(ScimPatchRequest { id: id(group_id), patch: json_body(body)?, if_match }).await?,
+        ),
+        (&Method::DELETE, ["Groups", group_id]) => {
+            scim_service.delete_group(ScimDeleteRequest { id: id(group_id), if_match }).await?;
+            Reply::empty(StatusCode::NO_CONTENT)
+        },
+        (method, _) => return Err(ScimError::not_found(format!("{} {} is not a scim endpoint", method, path))),
+    };
+
+    if let Some(excluded) = query.get("excludedAttributes") {
+        reply.exclude(&excluded.split(',').map(|name| name.trim().to_string()).collect::<Vec<_>>());
+    }
+    Ok(reply)
+}
+
+// serves SCIM 2.0 users and groups under `/scim/v2`. a GET whose If-None-Match names the resource's
+// current ETag is answered with 304. If-Match on a user holds up to the write, since the store refuses
+// to save a user that changed since it was read. groups have no version in the store, so If-Match on
+// a group is only compared as the group is read and does not guard against a change committed between
+// that read and the write. the endpoint does not authenticate the identity provider itself: it belongs
+// behind an `AccessControlLayer`, e.g. with the API key of a service account
+#[derive(Clone)]
+pub struct ScimEndpoint {
+    scim_service: Arc<dyn ScimService + Send + Sync>,
+}
+
+impl ScimEndpoint {
+    pub fn new(scim_service: Box<dyn ScimService + Send + Sync>) -> ScimEndpoint {
+        ScimEndpoint {
+            scim_service: Arc::from(scim_service),
+        }
+    }
+}
+
+impl<RequestBody> Service<Request<RequestBody>> for ScimEndpoint
+where
+    RequestBody: Into<String>,
+{
+    type Response = Response<String>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _context: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<RequestBody>) -> Self::Future {
+        let scim_service = self.scim_service.clone();
+        let (parts, body) = request.into_parts();
+        let body: String = body.into();
+        Box::pin(async move {
+            let reply = match route(scim_service.as_ref(), &parts, &body).await {
+                Ok(reply) => reply,
+                Err(error) => Reply::from(error),
+            };
+            let if_none_match = header_value(&parts, header::IF_NONE_MATCH);
+            let unchanged = parts.method == Method::GET
+                && reply.status == StatusCode::OK
+                && reply.etag.as_ref().is_some_and(|etag| {
+                    if_none_match.is_some_and(|if_none_match| if_none_match.split(',').any(|candidate| candidate.trim() == etag))
+                });
+            if unchanged {
+                return Ok(Reply { etag: reply.etag, ..Reply::empty(StatusCode::NOT_MODIFIED) }.into_response());
+            }
+            Ok(reply.into_response())
+        })
+    }
+}
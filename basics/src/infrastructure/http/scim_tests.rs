@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use http::{header, Request, Response, StatusCode};
+use serde_json::{json, Map, Value};
+use sqlx::pool::Pool;
+use sqlx::Sqlite;
+use tower::ServiceExt;
+
+use crate::application::scim::ScimServiceImpl;
+use crate::application::separation_of_duties::DutiesLoader;
+use crate::domain::groups::Group;
+use crate::domain::repositories::{GroupRepository, Repository};
+use crate::domain::separation_of_duties::SeparationOfDuties;
+use crate::domain::subjects::SubjectId;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+use crate::test_support::{access_checker, seeded_database, tenant_id};
+
+use super::scim::ScimEndpoint;
+
+const SEED: &str = "
+    resources:
+      - name: users
+      - name: users/get_users
+        parent: users
+    permissions:
+      - name: list users
+        resource: users/get_users
+    roles:
+      - name: engineer
+        permissions: [list users]
+    subjects:
+      - name: alec leamas
+    groups:
+      - name: engineers
+        roles: [engineer]
+";
+
+// the fixtures are exchanges recorded from identity providers. each one is a request, the parts of the
+// response that matter and, optionally, values to capture from the response for the exchanges after it.
+// `{{name}}` in a request or an expected response stands for a captured value
+const OKTA_USERS: &str = include_str!("../../../fixtures/scim/okta_users.json");
+const ENTRA_USERS: &str = include_str!("../../../fixtures/scim/entra_users.json");
+const GROUPS: &str = include_str!("../../../fixtures/scim/groups.json");
+const ERRORS: &str = include_str!("../../../fixtures/scim/errors.json");
+const OKTA_DEACTIVATION: &str = include_str!("../../../fixtures/scim/okta_deactivation.json");
+
+fn scim_endpoint(connection_pool: &Pool<Sqlite>) -> ScimEndpoint {
+    let duties_loader = DutiesLoader::new(
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+    );
+    ScimEndpoint::new(Box::new(ScimServiceImpl::new(
+        tenant_id(),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())),
+        duties_loader,
+        "https://basics.example.com/scim/v2",
+    )))
+}
+
+fn substitute(value: &Value, captures: &HashMap<String, String>) -> Value {
+    match value {
+        Value::String(text) => Value::String(captures.iter().fold(text.clone(), |text, (name, captured)| {
+            text.replace(&format!("{{{{{}}}}}", name), captured)
+        })),
+        Value::Array(items) => Value::Array(items.iter().map(|item| substitute(item, captures)).collect()),
+        Value::Object(fields) => Value::Object(fields.iter().map(|(name, field)| (name.clone(), substitute(field, captures))).collect()),
+        value => value.clone(),
+    }
+}
+
+fn recorded(response: Response<String>) -> Value {
+    let headers: Map<String, Value> = response.headers()
+        .iter()
+        .map(|(name, value)| (name.as_str().to_lowercase(), json!(value.to_str().unwrap())))
+        .collect();
+    json!({
+        "status": response.status().as_u16(),
+        "headers": headers,
+        "body": serde_json::from_str::<Value>(response.body()).unwrap_or(Value::Null),
+    })
+}
+
+// whether the actual value has everything the expected one has. objects may have more fields, an
+// expected `null` stands for a field that is absent, and arrays match in any order
+fn contains(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => expected.iter().all(|(name, expected)| {
+            match actual.get(name) {
+                Some(actual) => contains(actual, expected),
+                None => expected.is_null(),
+            }
+        }),
+        (Value::Array(actual), Value::Array(expected)) => {
+            actual.len() == expected.len() && expected.iter().all(|expected| actual.iter().any(|actual| contains(actual, expected)))
+        },
+        (actual, expected) => actual == expected,
+    }
+}
+
+async fn replay_exchanges(endpoint: &ScimEndpoint, exchanges: &[Value], captures: &mut HashMap<String, String>) {
+    for exchange in exchanges {
+        let exchange = substitute(exchange, captures);
+        let description = exchange["description"].as_str().unwrap_or_default();
+        let request = &exchange["request"];
+
+        let mut builder = Request::builder()
+            .method(request["method"].as_str().unwrap())
+            .uri(request["path"].as_str().unwrap());
+        for (name, value) in request["headers"].as_object().into_iter().flatten() {
+            builder = builder.header(name, value.as_str().unwrap());
+        }
+        let body = match (&request["body"], &request["raw_body"]) {
+            (Value::Null, Value::String(raw_body)) => raw_body.clone(),
+            (Value::Null, _) => String::new(),
+            (body, _) => body.to_string(),
+        };
+
+        let response = endpoint.clone().oneshot(builder.body(body).unwrap()).await.unwrap();
+        let actual = recorded(response);
+        assert!(
+            contains(&actual, &exchange["response"]),
+            "{}: expected {:#} in {:#}",
+            description,
+            exchange["response"],
+            actual,
+        );
+
+        for (name, pointer) in exchange["capture"].as_object().into_iter().flatten() {
+            let captured = actual.pointer(pointer.as_str().unwrap())
+                .and_then(Value::as_str)
+                .unwrap_or_else(|| panic!("{}: nothing to capture at {}", description, pointer));
+            captures.insert(name.clone(), captured.to_string());
+        }
+    }
+}
+
+async fn replay(endpoint: &ScimEndpoint, fixture: &str) -> HashMap<String, String> {
+    let fixture: Value = serde_json::from_str(fixture).unwrap();
+    let mut captures = HashMap::new();
+    replay_exchanges(endpoint, fixture["exchanges"].as_array().unwrap(), &mut captures).await;
+    captures
+}
+
+#[async_std::test]
+async fn test_okta_provisions_and_deprovisions_users() {
+    let connection_pool = seeded_database(SEED).await;
+    let captures = replay(&scim_endpoint(&connection_pool), OKTA_USERS).await;
+
+    // the location is the user's url under the base url
+    assert_eq!(captures["location"], format!("https://basics.example.com/scim/v2/Users/{}", captures["user_id"]));
+}
+
+#[async_std::test]
+async fn test_entra_provisions_and_deprovisions_users() {
+    let connection_pool = seeded_database(SEED).await;
+    replay(&scim_endpoint(&connection_pool), ENTRA_USERS).await;
+}
+
+#[async_std::test]
+async fn test_group_members_are_added_removed_and_replaced() {
+    let connection_pool = seeded_database(SEED).await;
+    replay(&scim_endpoint(&connection_pool), GROUPS).await;
+}
+
+#[async_std::test]
+async fn test_refused_requests_answer_with_scim_errors() {
+    let connection_pool = seeded_database(SEED).await;
+    replay(&scim_endpoint(&connection_pool), ERRORS).await;
+}
+
+#[async_std::test]
+async fn test_suspended_users_lose_the_access_their_groups_grant() {
+    let connection_pool = seeded_database(SEED).await;
+    let endpoint = scim_endpoint(&connection_pool);
+    let access_checker = access_checker(&connection_pool);
+    let fixture: Value = serde_json::from_str(OKTA_DEACTIVATION).unwrap();
+    let (suspension, provisioning) = fixture["exchanges"].as_array().unwrap().split_last().unwrap();
+
+    let mut captures = HashMap::new();
+    replay_exchanges(&endpoint, provisioning, &mut captures).await;
+    let user_id = SubjectId::from(captures["user_id"].clone());
+    assert!(access_checker.can_invoke_by_name(user_id.clone(), "users/get_users").await.unwrap());
+
+    replay_exchanges(&endpoint, std::slice::from_ref(suspension), &mut captures).await;
+    assert!(!access_checker.can_invoke_by_name(user_id, "users/get_users").await.unwrap());
+}
+
+#[async_std::test]
+async fn test_group_etags_change_with_their_member_groups() {
+    let connection_pool = seeded_database(SEED).await;
+    let endpoint = scim_endpoint(&connection_pool);
+    let group_repository = SqliteGroupRepository::new(connection_pool.clone(), tenant_id());
+    let mut engineers = group_repository.find_all().await.unwrap().remove(0);
+    let path = format!("/scim/v2/Groups/{}", String::from(engineers.get_id()));
+    let get = || Request::get(path.as_str()).body(String::new()).unwrap();
+
+    let before = endpoint.clone().oneshot(get()).await.unwrap();
+    let interns = Group::new(tenant_id(), "interns");
+    group_repository.save(interns.clone()).await.unwrap();
+    engineers.add_group(interns.get_id(), &[], &SeparationOfDuties::default()).unwrap();
+    group_repository.save(engineers).await.unwrap();
+    let after = endpoint.clone().oneshot(get()).await.unwrap();
+    assert_ne!(before.headers()[header::ETAG], after.headers()[header::ETAG]);
+
+    let stale = Request::delete(path.as_str())
+        .header(header::IF_MATCH, before.headers()[header::ETAG].clone())
+        .body(String::new())
+        .unwrap();
+    assert_eq!(endpoint.clone().oneshot(stale).await.unwrap().status(), StatusCode::PRECONDITION_FAILED);
+}
@@ -1 +1,3 @@
-pub mod sqlite;
\ No newline at end of file
+pub mod files;
+pub mod http;
+pub mod sqlite;
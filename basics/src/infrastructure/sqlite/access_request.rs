@@ -0,0 +1,165 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, SqliteConnection, FromRow};
+use sqlx::pool::Pool;
+
+use chrono::{DateTime, Utc, TimeZone};
+
+use crate::domain::access_requests::{AccessRequestId, AccessRequest, AccessRequestState};
+use crate::domain::tenants::TenantId;
+use crate::domain::repositories::{AccessRequestRepository, Error, Repository};
+
+use super::tenant::{ensure_all_references, ensure_same_tenant, References};
+use super::instrumentation::observed;
+
+#[derive(Debug, FromRow)]
+struct SqliteAccessRequestModel {
+    tenant_id: String,
+    id: String,
+    subject_id: String,
+    role_id: String,
+    state: String,
+    valid_until: Option<i64>,
+    decided_by: Option<String>,
+    decided_at: Option<i64>,
+    expires_at: i64,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl From<AccessRequest> for SqliteAccessRequestModel {
+    fn from(value: AccessRequest) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            id: value.get_id().into(),
+            subject_id: value.get_subject_id().into(),
+            role_id: value.get_role_id().into(),
+            state: value.get_state().to_string(),
+            valid_until: value.get_valid_until().map(|utc| utc.timestamp_millis()),
+            decided_by: value.get_decided_by().map(String::from),
+            decided_at: value.get_decided_at().map(|utc| utc.timestamp_millis()),
+            expires_at: value.get_expires_at().timestamp_millis(),
+            created_at: value.get_created_at().timestamp_millis(),
+            updated_at: value.get_updated_at().timestamp_millis(),
+        }
+    }
+}
+
+impl TryFrom<SqliteAccessRequestModel> for AccessRequest {
+    type Error = Error;
+
+    fn try_from(value: SqliteAccessRequestModel) -> Result<Self, Self::Error> {
+        Ok(AccessRequest::builder()
+            .id(AccessRequestId::from(value.id))
+            .tenant_id(value.tenant_id.into())
+            .subject_id(value.subject_id.into())
+            .role_id(value.role_id.into())
+            .state(AccessRequestState::try_from(value.state)?)
+            .valid_until(value.valid_until.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .decided_by(value.decided_by.map(|id| id.into()))
+            .decided_at(value.decided_at.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .expires_at(Utc.timestamp_millis_opt(value.expires_at).single().unwrap_or_default())
+            .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
+            .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
+            .build())
+    }
+}
+
+#[derive(Debug)]
+pub struct SqliteAccessRequestRepository {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteAccessRequestRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteAccessRequestRepository {
+        SqliteAccessRequestRepository {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Repository<AccessRequestId, AccessRequest> for SqliteAccessRequestRepository {
+    async fn get_by_id(&self, id: AccessRequestId) -> Result<Option<AccessRequest>, Error> {
+        observed("access_request", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM access_requests WHERE tenant_id = ? AND id = ?;";
+            sqlx::query_as::<_, SqliteAccessRequestModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into())
+                .fetch_optional(&mut *connection).await?
+                .map(AccessRequest::try_from)
+                .transpose()
+        }).await
+    }
+
+    async fn save(&self, entity: AccessRequest) -> Result<(), Error> {
+        observed("access_request", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let mut connection = self.connection_pool.acquire().await?;
+            ensure_all_references(&mut connection, &self.tenant_id, access_request_references(&entity)).await?;
+            upsert_access_request(&mut connection, entity).await
+        }).await
+    }
+}
+
+pub(crate) fn access_request_references(access_request: &AccessRequest) -> References {
+    vec![
+        ("subjects", vec![access_request.get_subject_id().into()]),
+        ("roles", vec![access_request.get_role_id().into()]),
+    ]
+}
+
+pub(crate) async fn upsert_access_request(connection: &mut SqliteConnection, access_request: AccessRequest) -> Result<(), Error> {
+    let model = SqliteAccessRequestModel::from(access_request);
+    let query = "
+        INSERT INTO access_requests (
+            tenant_id, id, subject_id, role_id, state, valid_until,
+            decided_by, decided_at, expires_at, created_at, updated_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (tenant_id, id) DO UPDATE SET
+        state=?, valid_until=?, decided_by=?, decided_at=?, expires_at=?, updated_at=?;
+    ";
+    sqlx::query(query)
+        // insert
+        .bind(model.tenant_id)
+        .bind(model.id)
+        .bind(model.subject_id)
+        .bind(model.role_id)
+        .bind(model.state.clone())
+        .bind(model.valid_until)
+        .bind(model.decided_by.clone())
+        .bind(model.decided_at)
+        .bind(model.expires_at)
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        // update
+        .bind(model.state)
+        .bind(model.valid_until)
+        .bind(model.decided_by)
+        .bind(model.decided_at)
+        .bind(model.expires_at)
+        .bind(model.updated_at)
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
+#[async_trait]
+impl AccessRequestRepository for SqliteAccessRequestRepository {
+    async fn find_expired_pending(&self, at: DateTime<Utc>) -> Result<Vec<AccessRequest>, Error> {
+        observed("access_request", "find_expired_pending", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM access_requests WHERE tenant_id = ? AND state = ? AND expires_at <= ?";
+            sqlx::query_as::<_, SqliteAccessRequestModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(AccessRequestState::Pending.to_string())
+                .bind(at.timestamp_millis())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(AccessRequest::try_from)
+                .collect()
+        }).await
+    }
+}
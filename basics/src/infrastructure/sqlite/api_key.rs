@@ -0,0 +1,208 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, SqliteConnection, FromRow};
+use sqlx::pool::Pool;
+
+use chrono::{DateTime, Utc, TimeZone};
+
+use crate::domain::api_keys::{ApiKey, ApiKeyId};
+use crate::domain::repositories::{ApiKeyRepository, Error, Repository};
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+use super::tenant::{ensure_references, ensure_same_tenant};
+use super::instrumentation::observed;
+
+#[derive(Debug, FromRow)]
+struct SqliteApiKeyModel {
+    tenant_id: String,
+    id: String,
+    subject_id: String,
+    name: String,
+    prefix: String,
+    hash: String,
+    expires_at: Option<i64>,
+    last_used_at: Option<i64>,
+    revoked_at: Option<i64>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl From<ApiKey> for SqliteApiKeyModel {
+    fn from(value: ApiKey) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            id: value.get_id().into(),
+            subject_id: value.get_subject_id().into(),
+            name: value.get_name(),
+            prefix: value.get_prefix(),
+            hash: value.get_hash(),
+            expires_at: value.get_expires_at().map(|utc| utc.timestamp_millis()),
+            last_used_at: value.get_last_used_at().map(|utc| utc.timestamp_millis()),
+            revoked_at: value.get_revoked_at().map(|utc| utc.timestamp_millis()),
+            created_at: value.get_created_at().timestamp_millis(),
+            updated_at: value.get_updated_at().timestamp_millis(),
+        }
+    }
+}
+
+impl From<SqliteApiKeyModel> for ApiKey {
+    fn from(value: SqliteApiKeyModel) -> Self {
+        ApiKey::builder()
+            .id(value.id.into())
+            .tenant_id(value.tenant_id.into())
+            .subject_id(value.subject_id.into())
+            .name(value.name)
+            .prefix(value.prefix)
+            .hash(value.hash)
+            .expires_at(value.expires_at.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .last_used_at(value.last_used_at.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .revoked_at(value.revoked_at.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
+            .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
+            .build()
+    }
+}
+
+pub struct SqliteApiKeyRepository {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteApiKeyRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteApiKeyRepository {
+        SqliteApiKeyRepository {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Repository<ApiKeyId, ApiKey> for SqliteApiKeyRepository {
+    async fn get_by_id(&self, id: ApiKeyId) -> Result<Option<ApiKey>, Error> {
+        observed("api_key", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            get_api_key(&mut connection, &self.tenant_id, id).await
+        }).await
+    }
+
+    async fn save(&self, entity: ApiKey) -> Result<(), Error> {
+        observed("api_key", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let mut connection = self.connection_pool.acquire().await?;
+            upsert_api_key(&mut connection, &self.tenant_id, entity).await
+        }).await
+    }
+}
+
+async fn get_api_key(connection: &mut SqliteConnection, tenant_id: &TenantId, id: ApiKeyId) -> Result<Option<ApiKey>, Error> {
+    let query = "SELECT * FROM api_keys WHERE tenant_id = ? AND id = ?;";
+    let api_key = sqlx::query_as::<_, SqliteApiKeyModel>(query)
+        .bind::<String>(tenant_id.clone().into())
+        .bind::<String>(id.into())
+        .fetch_optional(&mut *connection).await?
+        .map(ApiKey::from);
+    Ok(api_key)
+}
+
+// the last use is left alone, see `record_use`. a revocation is never undone and an expiry never pushed
+// back, whatever copy of the key is saved afterwards
+async fn upsert_api_key(connection: &mut SqliteConnection, tenant_id: &TenantId, entity: ApiKey) -> Result<(), Error> {
+    let model = SqliteApiKeyModel::from(entity);
+    ensure_references(connection, tenant_id, "subjects", vec![model.subject_id.clone()]).await?;
+    let query = "
+        INSERT INTO api_keys (
+            tenant_id, id, subject_id, name, prefix, hash, expires_at,
+            last_used_at, revoked_at, created_at, updated_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (tenant_id, id) DO UPDATE SET
+        name=?,
+        expires_at=COALESCE(MIN(api_keys.expires_at, excluded.expires_at), api_keys.expires_at, excluded.expires_at),
+        revoked_at=COALESCE(api_keys.revoked_at, excluded.revoked_at),
+        updated_at=?;
+    ";
+    sqlx::query(query)
+        // insert
+        .bind(model.tenant_id)
+        .bind(model.id)
+        .bind(model.subject_id)
+        .bind(model.name.clone())
+        .bind(model.prefix)
+        .bind(model.hash)
+        .bind(model.expires_at)
+        .bind(model.last_used_at)
+        .bind(model.revoked_at)
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        // update
+        .bind(model.name)
+        .bind(model.updated_at)
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
+#[async_trait]
+impl ApiKeyRepository for SqliteApiKeyRepository {
+    async fn get_by_prefix(&self, prefix: &str) -> Result<Option<ApiKey>, Error> {
+        observed("api_key", "get_by_prefix", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM api_keys WHERE tenant_id = ? AND prefix = ?;";
+            let api_key = sqlx::query_as::<_, SqliteApiKeyModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(prefix)
+                .fetch_optional(&mut *connection).await?
+                .map(ApiKey::from);
+            Ok(api_key)
+        }).await
+    }
+
+    async fn find_by_subject(&self, subject_id: SubjectId) -> Result<Vec<ApiKey>, Error> {
+        observed("api_key", "find_by_subject", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM api_keys WHERE tenant_id = ? AND subject_id = ? ORDER BY created_at;";
+            let api_keys = sqlx::query_as::<_, SqliteApiKeyModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(subject_id.into())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(ApiKey::from)
+                .collect();
+            Ok(api_keys)
+        }).await
+    }
+
+    async fn record_use(&self, id: ApiKeyId, at: DateTime<Utc>) -> Result<(), Error> {
+        observed("api_key", "record_use", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "UPDATE api_keys SET last_used_at = MAX(COALESCE(last_used_at, 0), ?) WHERE tenant_id = ? AND id = ?;";
+            sqlx::query(query)
+                .bind(at.timestamp_millis())
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into())
+                .execute(&mut *connection).await?;
+            Ok(())
+        }).await
+    }
+
+    async fn rotate(&self, replaced_id: ApiKeyId, issued: ApiKey, grace_until: Option<DateTime<Utc>>, at: DateTime<Utc>) -> Result<(), Error> {
+        observed("api_key", "rotate", async move {
+            ensure_same_tenant(&self.tenant_id, &issued.get_tenant_id())?;
+            let mut transaction = self.connection_pool.begin().await?;
+            let mut replaced = get_api_key(&mut transaction, &self.tenant_id, replaced_id.clone())
+                .await?
+                .ok_or_else(|| Error::Simple(format!("api key {} not found", String::from(replaced_id))))?;
+            if !replaced.is_usable_at(at) {
+                return Err(Error::Simple(format!("api key {} is revoked or expired", replaced.get_name())));
+            }
+            match grace_until {
+                Some(until) => replaced.expire(until),
+                None => replaced.revoke(at)?,
+            }
+            upsert_api_key(&mut transaction, &self.tenant_id, issued).await?;
+            upsert_api_key(&mut transaction, &self.tenant_id, replaced).await?;
+            transaction.commit().await?;
+            Ok(())
+        }).await
+    }
+}
@@ -0,0 +1,94 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, SqliteConnection, FromRow};
+use sqlx::pool::Pool;
+
+use chrono::{Utc, TimeZone};
+
+use crate::domain::audit::{AuditRecordId, AuditRecord};
+use crate::domain::tenants::TenantId;
+use crate::domain::repositories::{Error, Repository};
+
+use super::tenant::ensure_same_tenant;
+use super::instrumentation::observed;
+
+#[derive(Debug, FromRow)]
+struct SqliteAuditRecordModel {
+    tenant_id: String,
+    id: String,
+    event: String,
+    created_at: i64,
+}
+
+impl From<AuditRecord> for SqliteAuditRecordModel {
+    fn from(value: AuditRecord) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            id: value.get_id().into(),
+            event: serde_json::to_string(&value.get_event()).unwrap(),
+            created_at: value.get_created_at().timestamp_millis(),
+        }
+    }
+}
+
+impl From<SqliteAuditRecordModel> for AuditRecord {
+    fn from(value: SqliteAuditRecordModel) -> Self {
+        AuditRecord::builder()
+            .id(AuditRecordId::from(value.id))
+            .tenant_id(value.tenant_id.into())
+            .event(serde_json::from_str(&value.event).unwrap())
+            .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
+            .build()
+    }
+}
+
+#[derive(Debug)]
+pub struct SqliteAuditRepository {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteAuditRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteAuditRepository {
+        SqliteAuditRepository {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Repository<AuditRecordId, AuditRecord> for SqliteAuditRepository {
+    async fn get_by_id(&self, id: AuditRecordId) -> Result<Option<AuditRecord>, Error> {
+        observed("audit", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM audit_log WHERE tenant_id = ? AND id = ?;";
+            let record = sqlx::query_as::<_, SqliteAuditRecordModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into())
+                .fetch_optional(&mut *connection).await?
+                .map(AuditRecord::from);
+            Ok(record)
+        }).await
+    }
+
+    // audit records are append-only
+    async fn save(&self, entity: AuditRecord) -> Result<(), Error> {
+        observed("audit", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let mut connection = self.connection_pool.acquire().await?;
+            insert_audit_record(&mut connection, entity).await
+        }).await
+    }
+}
+
+pub(crate) async fn insert_audit_record(connection: &mut SqliteConnection, record: AuditRecord) -> Result<(), Error> {
+    let model = SqliteAuditRecordModel::from(record);
+    let query = "INSERT INTO audit_log (tenant_id, id, event, created_at) VALUES(?, ?, ?, ?);";
+    sqlx::query(query)
+        .bind(model.tenant_id)
+        .bind(model.id)
+        .bind(model.event)
+        .bind(model.created_at)
+        .execute(&mut *connection).await?;
+    Ok(())
+}
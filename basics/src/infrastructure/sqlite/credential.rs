@@ -0,0 +1,202 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, FromRow};
+use sqlx::pool::Pool;
+
+use chrono::{DateTime, Utc, TimeZone};
+
+use crate::domain::credentials::{LockoutPolicy, PasswordCredential};
+use crate::domain::repositories::{CredentialRepository, Error, Repository};
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+use super::tenant::{ensure_references, ensure_same_tenant};
+use super::instrumentation::observed;
+
+#[derive(Debug, FromRow)]
+struct SqlitePasswordCredentialModel {
+    tenant_id: String,
+    subject_id: String,
+    login: String,
+    hash: String,
+    failed_attempts: i64,
+    locked_until: Option<i64>,
+    last_login_at: Option<i64>,
+    changed_at: i64,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl From<PasswordCredential> for SqlitePasswordCredentialModel {
+    fn from(value: PasswordCredential) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            subject_id: value.get_subject_id().into(),
+            login: value.get_login(),
+            hash: value.get_hash(),
+            failed_attempts: value.get_failed_attempts().into(),
+            locked_until: value.get_locked_until().map(|utc| utc.timestamp_millis()),
+            last_login_at: value.get_last_login_at().map(|utc| utc.timestamp_millis()),
+            changed_at: value.get_changed_at().timestamp_millis(),
+            created_at: value.get_created_at().timestamp_millis(),
+            updated_at: value.get_updated_at().timestamp_millis(),
+        }
+    }
+}
+
+impl From<SqlitePasswordCredentialModel> for PasswordCredential {
+    fn from(value: SqlitePasswordCredentialModel) -> Self {
+        PasswordCredential::builder()
+            .tenant_id(value.tenant_id.into())
+            .subject_id(value.subject_id.into())
+            .login(value.login)
+            .hash(value.hash)
+            .failed_attempts(value.failed_attempts.try_into().unwrap_or_default())
+            .locked_until(value.locked_until.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .last_login_at(value.last_login_at.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .changed_at(Utc.timestamp_millis_opt(value.changed_at).single().unwrap_or_default())
+            .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
+            .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
+            .build()
+    }
+}
+
+pub struct SqliteCredentialRepository {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteCredentialRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteCredentialRepository {
+        SqliteCredentialRepository {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Repository<SubjectId, PasswordCredential> for SqliteCredentialRepository {
+    async fn get_by_id(&self, id: SubjectId) -> Result<Option<PasswordCredential>, Error> {
+        observed("credential", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM password_credentials WHERE tenant_id = ? AND subject_id = ?;";
+            let credential = sqlx::query_as::<_, SqlitePasswordCredentialModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into())
+                .fetch_optional(&mut *connection).await?
+                .map(PasswordCredential::from);
+            Ok(credential)
+        }).await
+    }
+
+    // the last login is left alone, see `record_successful_login`
+    async fn save(&self, entity: PasswordCredential) -> Result<(), Error> {
+        observed("credential", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let model = SqlitePasswordCredentialModel::from(entity);
+            let mut connection = self.connection_pool.acquire().await?;
+            ensure_references(&mut connection, &self.tenant_id, "subjects", vec![model.subject_id.clone()]).await?;
+            // the unique index would refuse it too, just not as clearly
+            let query = "SELECT subject_id FROM password_credentials WHERE tenant_id = ? AND login = ? AND subject_id != ?;";
+            let taken = sqlx::query_scalar::<_, String>(query)
+                .bind(model.tenant_id.clone())
+                .bind(model.login.clone())
+                .bind(model.subject_id.clone())
+                .fetch_optional(&mut *connection).await?;
+            if taken.is_some() {
+                return Err(Error::Simple(format!("login {} is taken", model.login)));
+            }
+            let query = "
+                INSERT INTO password_credentials (
+                    tenant_id, subject_id, login, hash, failed_attempts, locked_until,
+                    last_login_at, changed_at, created_at, updated_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (tenant_id, subject_id) DO UPDATE SET
+                login=?, hash=?, failed_attempts=?, locked_until=?, changed_at=?, updated_at=?;
+            ";
+            sqlx::query(query)
+                // insert
+                .bind(model.tenant_id)
+                .bind(model.subject_id)
+                .bind(model.login.clone())
+                .bind(model.hash.clone())
+                .bind(model.failed_attempts)
+                .bind(model.locked_until)
+                .bind(model.last_login_at)
+                .bind(model.changed_at)
+                .bind(model.created_at)
+                .bind(model.updated_at)
+                // update
+                .bind(model.login)
+                .bind(model.hash)
+                .bind(model.failed_attempts)
+                .bind(model.locked_until)
+                .bind(model.changed_at)
+                .bind(model.updated_at)
+                .execute(&mut *connection).await?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl CredentialRepository for SqliteCredentialRepository {
+    async fn get_by_login(&self, login: &str) -> Result<Option<PasswordCredential>, Error> {
+        observed("credential", "get_by_login", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM password_credentials WHERE tenant_id = ? AND login = ?;";
+            let credential = sqlx::query_as::<_, SqlitePasswordCredentialModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(login)
+                .fetch_optional(&mut *connection).await?
+                .map(PasswordCredential::from);
+            Ok(credential)
+        }).await
+    }
+
+    async fn record_failed_login(&self, subject_id: SubjectId, policy: &LockoutPolicy, at: DateTime<Utc>) -> Result<(), Error> {
+        let max_failed_attempts = i64::from(policy.get_max_failed_attempts());
+        let locked_until = (at + policy.get_lockout()).timestamp_millis();
+        observed("credential", "record_failed_login", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            // every expression sees the row as it was before the update
+            let query = "
+                UPDATE password_credentials SET
+                locked_until = CASE WHEN failed_attempts + 1 >= ? THEN ? ELSE locked_until END,
+                failed_attempts = CASE WHEN failed_attempts + 1 >= ? THEN 0 ELSE failed_attempts + 1 END,
+                updated_at = ?
+                WHERE tenant_id = ? AND subject_id = ? AND (locked_until IS NULL OR locked_until <= ?);
+            ";
+            sqlx::query(query)
+                .bind(max_failed_attempts)
+                .bind(locked_until)
+                .bind(max_failed_attempts)
+                .bind(at.timestamp_millis())
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(subject_id.into())
+                .bind(at.timestamp_millis())
+                .execute(&mut *connection).await?;
+            Ok(())
+        }).await
+    }
+
+    async fn record_successful_login(&self, subject_id: SubjectId, at: DateTime<Utc>) -> Result<bool, Error> {
+        observed("credential", "record_successful_login", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                UPDATE password_credentials SET
+                failed_attempts = 0, locked_until = NULL, last_login_at = ?, updated_at = ?
+                WHERE tenant_id = ? AND subject_id = ? AND (locked_until IS NULL OR locked_until <= ?);
+            ";
+            let result = sqlx::query(query)
+                .bind(at.timestamp_millis())
+                .bind(at.timestamp_millis())
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(subject_id.into())
+                .bind(at.timestamp_millis())
+                .execute(&mut *connection).await?;
+            Ok(result.rows_affected() == 1)
+        }).await
+    }
+}
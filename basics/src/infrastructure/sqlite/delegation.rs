@@ -0,0 +1,156 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, FromRow};
+use sqlx::pool::Pool;
+
+use chrono::{DateTime, Utc, TimeZone};
+
+use crate::domain::delegations::{Delegation, DelegationId};
+use crate::domain::repositories::{DelegationRepository, Error, Repository};
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+use super::tenant::{ensure_references, ensure_same_tenant};
+use super::instrumentation::observed;
+
+#[derive(Debug, FromRow)]
+struct SqliteDelegationModel {
+    tenant_id: String,
+    id: String,
+    delegator_id: String,
+    delegate_id: String,
+    permissions: String,
+    valid_until: i64,
+    revoked_by: Option<String>,
+    revoked_at: Option<i64>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl From<Delegation> for SqliteDelegationModel {
+    fn from(value: Delegation) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            id: value.get_id().into(),
+            delegator_id: value.get_delegator_id().into(),
+            delegate_id: value.get_delegate_id().into(),
+            permissions: serde_json::to_string(&value.get_permissions()).unwrap(),
+            valid_until: value.get_valid_until().timestamp_millis(),
+            revoked_by: value.get_revoked_by().map(String::from),
+            revoked_at: value.get_revoked_at().map(|utc| utc.timestamp_millis()),
+            created_at: value.get_created_at().timestamp_millis(),
+            updated_at: value.get_updated_at().timestamp_millis(),
+        }
+    }
+}
+
+impl From<SqliteDelegationModel> for Delegation {
+    fn from(value: SqliteDelegationModel) -> Self {
+        Delegation::builder()
+            .id(value.id.into())
+            .tenant_id(value.tenant_id.into())
+            .delegator_id(value.delegator_id.into())
+            .delegate_id(value.delegate_id.into())
+            .permissions(serde_json::from_str(&value.permissions).unwrap())
+            .valid_until(Utc.timestamp_millis_opt(value.valid_until).single().unwrap_or_default())
+            .revoked_by(value.revoked_by.map(|id| id.into()))
+            .revoked_at(value.revoked_at.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
+            .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
+            .build()
+    }
+}
+
+pub struct SqliteDelegationRepository {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteDelegationRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteDelegationRepository {
+        SqliteDelegationRepository {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Repository<DelegationId, Delegation> for SqliteDelegationRepository {
+    async fn get_by_id(&self, id: DelegationId) -> Result<Option<Delegation>, Error> {
+        observed("delegation", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM delegations WHERE tenant_id = ? AND id = ?;";
+            let delegation = sqlx::query_as::<_, SqliteDelegationModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into())
+                .fetch_optional(&mut *connection).await?
+                .map(Delegation::from);
+            Ok(delegation)
+        }).await
+    }
+
+    async fn save(&self, entity: Delegation) -> Result<(), Error> {
+        observed("delegation", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let subject_ids = vec![entity.get_delegator_id().into(), entity.get_delegate_id().into()];
+            let permission_ids = entity.get_permissions().into_iter().map(String::from).collect();
+            let model = SqliteDelegationModel::from(entity);
+            let mut connection = self.connection_pool.acquire().await?;
+            ensure_references(&mut connection, &self.tenant_id, "subjects", subject_ids).await?;
+            ensure_references(&mut connection, &self.tenant_id, "permissions", permission_ids).await?;
+            let query = "
+                INSERT INTO delegations (
+                    tenant_id, id, delegator_id, delegate_id, permissions, valid_until,
+                    revoked_by, revoked_at, created_at, updated_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (tenant_id, id) DO UPDATE SET
+                permissions=?, valid_until=?, revoked_by=?, revoked_at=?, updated_at=?;
+            ";
+            sqlx::query(query)
+                // insert
+                .bind(model.tenant_id)
+                .bind(model.id)
+                .bind(model.delegator_id)
+                .bind(model.delegate_id)
+                .bind(model.permissions.clone())
+                .bind(model.valid_until)
+                .bind(model.revoked_by.clone())
+                .bind(model.revoked_at)
+                .bind(model.created_at)
+                .bind(model.updated_at)
+                // update
+                .bind(model.permissions)
+                .bind(model.valid_until)
+                .bind(model.revoked_by)
+                .bind(model.revoked_at)
+                .bind(model.updated_at)
+                .execute(&mut *connection).await?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl DelegationRepository for SqliteDelegationRepository {
+    async fn find_active_for_delegate(&self, delegate_id: SubjectId, at: DateTime<Utc>) -> Result<Vec<Delegation>, Error> {
+        observed("delegation", "find_active_for_delegate", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                SELECT * FROM delegations
+                WHERE tenant_id = ? AND delegate_id = ? AND revoked_at IS NULL AND created_at <= ? AND valid_until > ?
+                ORDER BY created_at;
+            ";
+            let delegations = sqlx::query_as::<_, SqliteDelegationModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(delegate_id.into())
+                .bind(at.timestamp_millis())
+                .bind(at.timestamp_millis())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(Delegation::from)
+                .collect();
+            Ok(delegations)
+        }).await
+    }
+}
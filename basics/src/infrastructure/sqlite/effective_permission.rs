@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use sqlx::{Sqlite, SqliteConnection, FromRow};
+use sqlx::pool::Pool;
+
+use chrono::{DateTime, Utc, TimeZone};
+
+use crate::domain::effective_permissions::EffectivePermissions;
+use crate::domain::groups::GroupId;
+use crate::domain::repositories::{EffectivePermissionIndex, Error};
+use crate::domain::resources::ResourceId;
+use crate::domain::roles::RoleId;
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+use super::tenant::ensure_same_tenant;
+use super::instrumentation::observed;
+
+// invoking is the only operation there is; the column leaves room for more
+const INVOKE: &str = "invoke";
+
+#[derive(Debug, FromRow)]
+struct SqliteEffectivePermissionSubjectModel {
+    tenant_id: String,
+    subject_id: String,
+    roles: String,
+    groups: String,
+    indexable: bool,
+    valid_until: Option<i64>,
+    computed_at: i64,
+}
+
+impl From<EffectivePermissions> for SqliteEffectivePermissionSubjectModel {
+    fn from(value: EffectivePermissions) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            subject_id: value.get_subject_id().into(),
+            roles: serde_json::to_string(&value.get_roles()).unwrap(),
+            groups: serde_json::to_string(&value.get_groups()).unwrap(),
+            indexable: value.is_indexable(),
+            valid_until: value.get_valid_until().map(|utc| utc.timestamp_millis()),
+            computed_at: value.get_computed_at().timestamp_millis(),
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct SqliteEffectivePermissionModel {
+    resource_id: String,
+    allowed: bool,
+}
+
+pub struct SqliteEffectivePermissionIndex {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteEffectivePermissionIndex {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteEffectivePermissionIndex {
+        SqliteEffectivePermissionIndex {
+            connection_pool,
+            tenant_id,
+        }
+    }
+
+    async fn delete_subject(&self, connection: &mut SqliteConnection, subject_id: &SubjectId) -> Result<(), Error> {
+        for query in [
+            "DELETE FROM effective_permissions WHERE tenant_id = ? AND subject_id = ?;",
+            "DELETE FROM effective_permission_subjects WHERE tenant_id = ? AND subject_id = ?;",
+        ] {
+            sqlx::query(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(subject_id.clone().into())
+                .execute(&mut *connection).await?;
+        }
+        Ok(())
+    }
+
+    async fn find_subjects_by_source(&self, column: &str, id: String) -> Result<Vec<SubjectId>, Error> {
+        let mut connection = self.connection_pool.acquire().await?;
+        let query = format!("
+            SELECT subject_id FROM effective_permission_subjects
+            WHERE tenant_id = ? AND EXISTS (SELECT 1 FROM json_each(effective_permission_subjects.{}) WHERE value = ?);
+        ", column);
+        let subject_ids = sqlx::query_scalar::<_, String>(&query)
+            .bind::<String>(self.tenant_id.clone().into())
+            .bind(id)
+            .fetch_all(&mut *connection).await?
+            .into_iter()
+            .map(SubjectId::from)
+            .collect();
+        Ok(subject_ids)
+    }
+}
+
+#[async_trait]
+impl EffectivePermissionIndex for SqliteEffectivePermissionIndex {
+    async fn store(&self, entry: EffectivePermissions) -> Result<(), Error> {
+        observed("effective_permission", "store", async move {
+            ensure_same_tenant(&self.tenant_id, &entry.get_tenant_id())?;
+            let decisions = entry.get_decisions();
+            let model = SqliteEffectivePermissionSubjectModel::from(entry);
+
+            let mut transaction = self.connection_pool.begin().await?;
+            self.delete_subject(&mut transaction, &model.subject_id.clone().into()).await?;
+
+            let query = "
+                INSERT INTO effective_permission_subjects (tenant_id, subject_id, roles, groups, indexable, valid_until, computed_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?);
+            ";
+            sqlx::query(query)
+                .bind(model.tenant_id.clone())
+                .bind(model.subject_id.clone())
+                .bind(model.roles)
+                .bind(model.groups)
+                .bind(model.indexable)
+                .bind(model.valid_until)
+                .bind(model.computed_at)
+                .execute(&mut *transaction).await?;
+
+            let query = "INSERT INTO effective_permissions (tenant_id, subject_id, operation, resource_id, allowed) VALUES (?, ?, ?, ?, ?);";
+            for (resource_id, allowed) in decisions {
+                sqlx::query(query)
+                    .bind(model.tenant_id.clone())
+                    .bind(model.subject_id.clone())
+                    .bind(INVOKE)
+                    .bind::<String>(resource_id.into())
+                    .bind(allowed)
+                    .execute(&mut *transaction).await?;
+            }
+
+            transaction.commit().await?;
+            Ok(())
+        }).await
+    }
+
+    async fn remove(&self, subject_id: SubjectId) -> Result<(), Error> {
+        observed("effective_permission", "remove", async move {
+            let mut transaction = self.connection_pool.begin().await?;
+            self.delete_subject(&mut transaction, &subject_id).await?;
+            transaction.commit().await?;
+            Ok(())
+        }).await
+    }
+
+    async fn clear(&self) -> Result<(), Error> {
+        observed("effective_permission", "clear", async move {
+            let mut transaction = self.connection_pool.begin().await?;
+            for query in [
+                "DELETE FROM effective_permissions WHERE tenant_id = ?;",
+                "DELETE FROM effective_permission_subjects WHERE tenant_id = ?;",
+                "DELETE FROM effective_permission_positions WHERE tenant_id = ?;",
+            ] {
+                sqlx::query(query)
+                    .bind::<String>(self.tenant_id.clone().into())
+                    .execute(&mut *transaction).await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        }).await
+    }
+
+    async fn find_subjects_with_role(&self, role_id: RoleId) -> Result<Vec<SubjectId>, Error> {
+        observed("effective_permission", "find_subjects_with_role", async move {
+            self.find_subjects_by_source("roles", role_id.into()).await
+        }).await
+    }
+
+    async fn find_subjects_in_group(&self, group_id: GroupId) -> Result<Vec<SubjectId>, Error> {
+        observed("effective_permission", "find_subjects_in_group", async move {
+            self.find_subjects_by_source("groups", group_id.into()).await
+        }).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<EffectivePermissions>, Error> {
+        observed("effective_permission", "find_all", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM effective_permission_subjects WHERE tenant_id = ? ORDER BY subject_id;";
+            let subjects = sqlx::query_as::<_, SqliteEffectivePermissionSubjectModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .fetch_all(&mut *connection).await?;
+
+            let mut entries = Vec::new();
+            for model in subjects {
+                let query = "SELECT resource_id, allowed FROM effective_permissions WHERE tenant_id = ? AND subject_id = ? AND operation = ?;";
+                let decisions: HashMap<ResourceId, bool> = sqlx::query_as::<_, SqliteEffectivePermissionModel>(query)
+                    .bind::<String>(self.tenant_id.clone().into())
+                    .bind(model.subject_id.clone())
+                    .bind(INVOKE)
+                    .fetch_all(&mut *connection).await?
+                    .into_iter()
+                    .map(|decision| (decision.resource_id.into(), decision.allowed))
+                    .collect();
+
+                entries.push(EffectivePermissions::builder()
+                    .tenant_id(model.tenant_id.into())
+                    .subject_id(model.subject_id.into())
+                    .roles(serde_json::from_str(&model.roles).unwrap())
+                    .groups(serde_json::from_str(&model.groups).unwrap())
+                    .decisions(decisions)
+                    .indexable(model.indexable)
+                    .valid_until(model.valid_until.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+                    .computed_at(Utc.timestamp_millis_opt(model.computed_at).single().unwrap_or_default())
+                    .build());
+            }
+            Ok(entries)
+        }).await
+    }
+
+    async fn advance_to(&self, position: i64) -> Result<(), Error> {
+        observed("effective_permission", "advance_to", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                INSERT INTO effective_permission_positions (tenant_id, position)
+                VALUES (?, ?)
+                ON CONFLICT (tenant_id) DO UPDATE SET
+                position=MAX(position, excluded.position);
+            ";
+            sqlx::query(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(position)
+                .execute(&mut *connection).await?;
+            Ok(())
+        }).await
+    }
+
+    // a single lookup by primary key; the freshness checks ride along in the same query
+    async fn lookup(&self, subject_id: SubjectId, resource_id: ResourceId, at: DateTime<Utc>) -> Result<Option<bool>, Error> {
+        observed("effective_permission", "lookup", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                SELECT permissions.allowed FROM effective_permissions AS permissions
+                JOIN effective_permission_subjects AS subjects
+                    ON subjects.tenant_id = permissions.tenant_id AND subjects.subject_id = permissions.subject_id
+                WHERE permissions.tenant_id = ? AND permissions.subject_id = ? AND permissions.operation = ? AND permissions.resource_id = ?
+                    AND subjects.indexable AND (subjects.valid_until IS NULL OR subjects.valid_until > ?)
+                    AND (SELECT position FROM effective_permission_positions WHERE tenant_id = permissions.tenant_id)
+                        >= (SELECT COALESCE(MAX(position), 0) FROM outbox WHERE tenant_id = permissions.tenant_id);
+            ";
+            let allowed = sqlx::query_scalar::<_, bool>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(subject_id.into())
+                .bind(INVOKE)
+                .bind::<String>(resource_id.into())
+                .bind(at.timestamp_millis())
+                .fetch_optional(&mut *connection).await?;
+            Ok(allowed)
+        }).await
+    }
+}
@@ -1,17 +1,27 @@
 use async_trait::async_trait;
-use sqlx::{Sqlite, FromRow};
+use sqlx::{Sqlite, SqliteConnection, FromRow};
 use sqlx::pool::Pool;
 
 use chrono::{Utc, TimeZone};
 
-use crate::domain::repositories::{Error, Repository};
+use crate::domain::tenants::TenantId;
+use crate::domain::outbox::ChangeEvent;
+use crate::domain::repositories::{Error, GroupRepository, Repository};
 use crate::domain::groups::{GroupId, Group};
+use crate::domain::subjects::SubjectId;
+
+use super::outbox::append_event;
+use super::sod_constraint::ensure_separation_of_duties;
+use super::tenant::{ensure_all_references, ensure_same_tenant, References};
+use super::instrumentation::observed;
 
 #[derive(Debug, FromRow)]
 struct SqliteGroupModel {
+    tenant_id: String,
     id: String,
     name: String,
     subjects: String,
+    groups: String,
     roles: String,
     created_at: i64,
     updated_at: i64,
@@ -20,9 +30,11 @@ struct SqliteGroupModel {
 impl From<Group> for SqliteGroupModel {
     fn from(value: Group) -> Self {
         Self {
+            tenant_id: value.get_tenant_id().into(),
             id: value.get_id().into(),
             name: value.get_name(),
             subjects: serde_json::to_string(&value.get_subjects()).unwrap(),
+            groups: serde_json::to_string(&value.get_groups()).unwrap(),
             roles: serde_json::to_string(&value.get_roles()).unwrap(),
             created_at: value.get_created_at().timestamp_millis(),
             updated_at: value.get_updated_at().timestamp_millis()
@@ -34,8 +46,10 @@ impl From<SqliteGroupModel> for Group {
     fn from(value: SqliteGroupModel) -> Self {
         Group::builder()
             .id(value.id.into())
+            .tenant_id(value.tenant_id.into())
             .name(value.name)
             .subjects(serde_json::from_str(&value.subjects).unwrap())
+            .groups(serde_json::from_str(&value.groups).unwrap())
             .roles(serde_json::from_str(&value.roles).unwrap())
             .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
             .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
@@ -44,52 +58,270 @@ impl From<SqliteGroupModel> for Group {
 }
 
 pub struct SqliteGroupRepository {
-    connection_pool: Pool<Sqlite>
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
 }
 
 impl SqliteGroupRepository {
-    pub fn new(connection_pool: Pool<Sqlite>) -> SqliteGroupRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteGroupRepository {
         SqliteGroupRepository {
-            connection_pool
+            connection_pool,
+            tenant_id,
         }
     }
 }
 
+pub(crate) fn group_references(entity: &Group) -> References {
+    vec![
+        ("subjects", entity.get_subjects().iter().cloned().map(String::from).collect()),
+        ("groups", entity.get_groups().iter().cloned().map(String::from).collect()),
+        ("roles", entity.get_roles().into_iter().map(String::from).collect()),
+    ]
+}
+
+pub(crate) async fn find_all_groups(connection: &mut SqliteConnection, tenant_id: &TenantId) -> Result<Vec<Group>, Error> {
+    let query = "SELECT * FROM groups WHERE tenant_id = ? ORDER BY created_at;";
+    let groups = sqlx::query_as::<_, SqliteGroupModel>(query)
+        .bind(String::from(tenant_id.clone()))
+        .fetch_all(&mut *connection).await?
+        .into_iter()
+        .map(Group::from)
+        .collect();
+    Ok(groups)
+}
+
+pub(crate) async fn upsert_group(connection: &mut SqliteConnection, entity: Group) -> Result<(), Error> {
+    let model = SqliteGroupModel::from(entity);
+    let query = "
+        INSERT INTO groups (tenant_id, id, name, subjects, groups, roles, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (tenant_id, id) DO UPDATE SET
+        name=?, subjects=?, groups=?, roles=?, created_at=?, updated_at=?;
+    ";
+    sqlx::query(query)
+        // insert
+        .bind(model.tenant_id.clone())
+        .bind(model.id.clone())
+        .bind(model.name.clone())
+        .bind(model.subjects.clone())
+        .bind(model.groups.clone())
+        .bind(model.roles.clone())
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        // update
+        .bind(model.name.clone())
+        .bind(model.subjects.clone())
+        .bind(model.groups.clone())
+        .bind(model.roles.clone())
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        .execute(&mut *connection).await?;
+
+    delete_group_members(connection, &model.tenant_id, &model.id).await?;
+    let query = "
+        INSERT INTO group_members (tenant_id, group_id, member_kind, member_id)
+        SELECT ?, ?, 'subject', value FROM json_each(?)
+        UNION
+        SELECT ?, ?, 'group', value FROM json_each(?);
+    ";
+    sqlx::query(query)
+        .bind(model.tenant_id.clone())
+        .bind(model.id.clone())
+        .bind(model.subjects)
+        .bind(model.tenant_id.clone())
+        .bind(model.id.clone())
+        .bind(model.groups)
+        .execute(&mut *connection).await?;
+    ensure_no_cycle(connection, &model.tenant_id, &model.id, &model.name).await
+}
+
+// the domain refuses to let a group contain one of the groups above it, but only as far as it could
+// see them when the change was made. walking down from the group as stored, membership rows written
+// in the same transaction included, catches the cycles that changes made side by side close
+async fn ensure_no_cycle(connection: &mut SqliteConnection, tenant_id: &str, group_id: &str, name: &str) -> Result<(), Error> {
+    let query = "
+        WITH RECURSIVE contained(id) AS (
+            SELECT member_id FROM group_members WHERE tenant_id = ?1 AND group_id = ?2 AND member_kind = 'group'
+            UNION
+            SELECT group_members.member_id FROM group_members JOIN contained ON group_members.group_id = contained.id
+            WHERE group_members.tenant_id = ?1 AND group_members.member_kind = 'group'
+        )
+        SELECT EXISTS (SELECT 1 FROM contained WHERE id = ?2);
+    ";
+    let cycle = sqlx::query_scalar::<_, bool>(query)
+        .bind(tenant_id)
+        .bind(group_id)
+        .fetch_one(&mut *connection).await?;
+    if cycle {
+        return Err(Error::Simple(format!("group {} would end up containing itself through its member groups", name)));
+    }
+    Ok(())
+}
+
+// takes a deleted group out of the groups that contained it, both their json lists and their rows
+pub(crate) async fn remove_from_parents(connection: &mut SqliteConnection, tenant_id: &str, group_id: &str) -> Result<(), Error> {
+    let query = "
+        UPDATE groups SET
+        groups = (SELECT json_group_array(value) FROM json_each(groups.groups) WHERE value != ?2),
+        updated_at = ?3
+        WHERE tenant_id = ?1 AND EXISTS (SELECT 1 FROM json_each(groups.groups) WHERE value = ?2);
+    ";
+    sqlx::query(query)
+        .bind(tenant_id)
+        .bind(group_id)
+        .bind(Utc::now().timestamp_millis())
+        .execute(&mut *connection).await?;
+    sqlx::query("DELETE FROM group_members WHERE tenant_id = ? AND member_kind = 'group' AND member_id = ?;")
+        .bind(tenant_id)
+        .bind(group_id)
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
+// the rows of the groups the group contains stay until those groups leave it, as the json list does
+pub(crate) async fn delete_group_members(connection: &mut SqliteConnection, tenant_id: &str, group_id: &str) -> Result<(), Error> {
+    sqlx::query("DELETE FROM group_members WHERE tenant_id = ? AND group_id = ?;")
+        .bind(tenant_id)
+        .bind(group_id)
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
 #[async_trait]
 impl Repository<GroupId, Group> for SqliteGroupRepository {
     async fn save(&self, entity: Group) -> Result<(), Error> {
-        let model = SqliteGroupModel::from(entity);
-        let mut connection = self.connection_pool.acquire().await?;
-        let query = "
-            INSERT INTO groups VALUES (?, ?, ?, ?, ?, ?)
-            ON CONFLICT (id) DO UPDATE SET
-            name=?, subjects=?, roles=?, created_at=?, updated_at=?;
-        ";
-        sqlx::query(query)
-            // insert
-            .bind(model.id)
-            .bind(model.name.clone())
-            .bind(model.subjects.clone())
-            .bind(model.roles.clone())
-            .bind(model.created_at)
-            .bind(model.updated_at)
-            // update
-            .bind(model.name.clone())
-            .bind(model.subjects)
-            .bind(model.roles.clone())
-            .bind(model.created_at)
-            .bind(model.updated_at)
-            .execute(&mut *connection).await?;
-        Ok(())
+        observed("group", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let group_id = entity.get_id();
+            let mut transaction = self.connection_pool.begin().await?;
+            ensure_all_references(&mut transaction, &self.tenant_id, group_references(&entity)).await?;
+            upsert_group(&mut transaction, entity).await?;
+            ensure_separation_of_duties(&mut transaction, &self.tenant_id).await?;
+            append_event(&mut transaction, &self.tenant_id, ChangeEvent::GroupSaved { group_id }).await?;
+            transaction.commit().await?;
+            Ok(())
+        }).await
     }
     
     async fn get_by_id(&self, id: GroupId) -> Result<Option<Group>, Error> {
-        let mut connection = self.connection_pool.acquire().await?;
-        let query = "SELECT * FROM groups WHERE id = ?;";
-        let Group = sqlx::query_as::<_, SqliteGroupModel>(query)
-            .bind::<String>(id.into()) // todo: map to model PK first
-            .fetch_optional(&mut *connection).await?
-            .map(Group::from);
-        Ok(Group)
+        observed("group", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM groups WHERE tenant_id = ? AND id = ?;";
+            let group = sqlx::query_as::<_, SqliteGroupModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into()) // todo: map to model PK first
+                .fetch_optional(&mut *connection).await?
+                .map(Group::from);
+            Ok(group)
+        }).await
+    }
+}
+
+#[async_trait]
+impl GroupRepository for SqliteGroupRepository {
+    async fn find_all(&self) -> Result<Vec<Group>, Error> {
+        observed("group", "find_all", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            find_all_groups(&mut connection, &self.tenant_id).await
+        }).await
+    }
+
+    async fn find_by_subject(&self, subject_id: SubjectId) -> Result<Vec<Group>, Error> {
+        observed("group", "find_by_subject", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                SELECT * FROM groups
+                WHERE tenant_id = ? AND EXISTS (SELECT 1 FROM json_each(groups.subjects) WHERE value = ?)
+                ORDER BY created_at;
+            ";
+            let groups = sqlx::query_as::<_, SqliteGroupModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(subject_id.into())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(Group::from)
+                .collect();
+            Ok(groups)
+        }).await
+    }
+
+    // walks up from the subject's own groups through `group_members_by_member`. UNION rather than
+    // UNION ALL drops groups already reached, which also ends the walk should a cycle ever be stored
+    async fn find_by_subject_transitively(&self, subject_id: SubjectId) -> Result<Vec<Group>, Error> {
+        observed("group", "find_by_subject_transitively", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                WITH RECURSIVE containing(id) AS (
+                    SELECT group_id FROM group_members
+                    WHERE tenant_id = ?1 AND member_kind = 'subject' AND member_id = ?2
+                    UNION
+                    SELECT group_members.group_id FROM group_members JOIN containing ON group_members.member_id = containing.id
+                    WHERE group_members.tenant_id = ?1 AND group_members.member_kind = 'group'
+                )
+                SELECT groups.* FROM groups JOIN containing ON groups.id = containing.id
+                WHERE groups.tenant_id = ?1
+                ORDER BY groups.created_at;
+            ";
+            let groups = sqlx::query_as::<_, SqliteGroupModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(subject_id.into())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(Group::from)
+                .collect();
+            Ok(groups)
+        }).await
+    }
+
+    async fn find_ancestors(&self, group_id: GroupId) -> Result<Vec<Group>, Error> {
+        observed("group", "find_ancestors", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                WITH RECURSIVE containing(id) AS (
+                    SELECT group_id FROM group_members
+                    WHERE tenant_id = ?1 AND member_kind = 'group' AND member_id = ?2
+                    UNION
+                    SELECT group_members.group_id FROM group_members JOIN containing ON group_members.member_id = containing.id
+                    WHERE group_members.tenant_id = ?1 AND group_members.member_kind = 'group'
+                )
+                SELECT groups.* FROM groups JOIN containing ON groups.id = containing.id
+                WHERE groups.tenant_id = ?1
+                ORDER BY groups.created_at;
+            ";
+            let groups = sqlx::query_as::<_, SqliteGroupModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(group_id.into())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(Group::from)
+                .collect();
+            Ok(groups)
+        }).await
+    }
+
+    // walks down from the group through the primary key of `group_members`
+    async fn find_members(&self, group_id: GroupId) -> Result<Vec<SubjectId>, Error> {
+        observed("group", "find_members", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                WITH RECURSIVE contained(id) AS (
+                    SELECT ?2
+                    UNION
+                    SELECT group_members.member_id FROM group_members JOIN contained ON group_members.group_id = contained.id
+                    WHERE group_members.tenant_id = ?1 AND group_members.member_kind = 'group'
+                )
+                SELECT DISTINCT group_members.member_id FROM group_members JOIN contained ON group_members.group_id = contained.id
+                WHERE group_members.tenant_id = ?1 AND group_members.member_kind = 'subject'
+                ORDER BY group_members.member_id;
+            ";
+            let subject_ids = sqlx::query_scalar::<_, String>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(group_id.into())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(SubjectId::from)
+                .collect();
+            Ok(subject_ids)
+        }).await
     }
 }
@@ -0,0 +1,25 @@
+use std::future::Future;
+use std::time::Instant;
+
+use tracing::{field, info_span, Instrument};
+
+use crate::domain::repositories::Error;
+use crate::metrics::{self, REPOSITORY_DURATION};
+
+// runs a repository call inside a `repository` span and records how long it took, both in the span
+// and in the repository latency histogram
+pub(crate) async fn observed<T, F>(repository: &'static str, operation: &'static str, call: F) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    let span = info_span!("repository", repository, operation, outcome = field::Empty, latency_ms = field::Empty);
+    let started = Instant::now();
+    let result = call.instrument(span.clone()).await;
+    let elapsed = started.elapsed();
+
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+    span.record("outcome", outcome);
+    span.record("latency_ms", elapsed.as_secs_f64() * 1000.0);
+    metrics::global().observe(REPOSITORY_DURATION, &[("repository", repository), ("operation", operation), ("outcome", outcome)], elapsed);
+    result
+}
@@ -1,5 +1,21 @@
+pub mod access_request;
+pub mod api_key;
+pub mod audit;
+pub mod credential;
+pub mod delegation;
+pub mod effective_permission;
 pub mod error;
 pub mod group;
+pub(crate) mod instrumentation;
 pub mod role;
 pub mod permission;
-pub mod subject;
\ No newline at end of file
+pub mod outbox;
+pub mod relation_tuple;
+pub mod resource;
+pub mod session;
+pub mod sod_constraint;
+pub mod subject;
+pub mod tenant;
+#[cfg(test)]
+mod tenant_tests;
+pub mod unit_of_work;
@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, SqliteConnection, FromRow};
+use sqlx::pool::Pool;
+
+use chrono::{Utc, TimeZone};
+
+use crate::domain::outbox::{ChangeEvent, OutboxEntry};
+use crate::domain::repositories::{ChangeFeed, Error};
+use crate::domain::tenants::TenantId;
+
+use super::instrumentation::observed;
+
+#[derive(Debug, FromRow)]
+struct SqliteOutboxEntryModel {
+    position: i64,
+    tenant_id: String,
+    event: String,
+    created_at: i64,
+}
+
+impl From<SqliteOutboxEntryModel> for OutboxEntry {
+    fn from(value: SqliteOutboxEntryModel) -> Self {
+        OutboxEntry::builder()
+            .position(value.position)
+            .tenant_id(value.tenant_id.into())
+            .event(serde_json::from_str(&value.event).unwrap())
+            .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
+            .build()
+    }
+}
+
+// called with the transaction of the change itself, so the event is stored if and only if the change is.
+// sqlite runs one writer at a time, hence positions are handed out in commit order
+pub(crate) async fn append_event(connection: &mut SqliteConnection, tenant_id: &TenantId, event: ChangeEvent) -> Result<(), Error> {
+    let query = "INSERT INTO outbox (tenant_id, event, created_at) VALUES (?, ?, ?);";
+    sqlx::query(query)
+        .bind(String::from(tenant_id.clone()))
+        .bind(serde_json::to_string(&event).unwrap())
+        .bind(Utc::now().timestamp_millis())
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
+pub struct SqliteChangeFeed {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteChangeFeed {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteChangeFeed {
+        SqliteChangeFeed {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl ChangeFeed for SqliteChangeFeed {
+    async fn read_after(&self, position: i64, limit: u32) -> Result<Vec<OutboxEntry>, Error> {
+        observed("outbox", "read_after", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM outbox WHERE tenant_id = ? AND position > ? ORDER BY position LIMIT ?;";
+            let entries = sqlx::query_as::<_, SqliteOutboxEntryModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(position)
+                .bind(limit)
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(OutboxEntry::from)
+                .collect();
+            Ok(entries)
+        }).await
+    }
+
+    async fn get_head_position(&self) -> Result<i64, Error> {
+        observed("outbox", "get_head_position", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT COALESCE(MAX(position), 0) FROM outbox WHERE tenant_id = ?;";
+            let position = sqlx::query_scalar::<_, i64>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .fetch_one(&mut *connection).await?;
+            Ok(position)
+        }).await
+    }
+
+    async fn get_offset(&self, consumer: &str) -> Result<i64, Error> {
+        observed("outbox", "get_offset", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT position FROM outbox_offsets WHERE tenant_id = ? AND consumer = ?;";
+            let position = sqlx::query_scalar::<_, i64>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(consumer)
+                .fetch_optional(&mut *connection).await?
+                .unwrap_or(0);
+            Ok(position)
+        }).await
+    }
+
+    async fn commit_offset(&self, consumer: &str, position: i64) -> Result<(), Error> {
+        observed("outbox", "commit_offset", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                INSERT INTO outbox_offsets (tenant_id, consumer, position, updated_at)
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT (tenant_id, consumer) DO UPDATE SET
+                position=?, updated_at=?;
+            ";
+            let updated_at = Utc::now().timestamp_millis();
+            sqlx::query(query)
+                // insert
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(consumer)
+                .bind(position)
+                .bind(updated_at)
+                // update
+                .bind(position)
+                .bind(updated_at)
+                .execute(&mut *connection).await?;
+            Ok(())
+        }).await
+    }
+}
@@ -1,17 +1,29 @@
 use async_trait::async_trait;
-use sqlx::{Sqlite, FromRow};
+use sqlx::{Sqlite, SqliteConnection, FromRow};
 use sqlx::pool::Pool;
 
 use chrono::{Utc, TimeZone};
 
-use crate::domain::permissions::{PermissionId, Permission};
-use crate::domain::repositories::{Error, Repository};
+use crate::domain::conditions::Condition;
+use crate::domain::operations::Operation;
+use crate::domain::permissions::{Effect, Grantee, PermissionId, Permission};
+use crate::domain::tenants::TenantId;
+use crate::domain::outbox::ChangeEvent;
+use crate::domain::repositories::{Error, PermissionRepository, Repository};
+
+use super::outbox::append_event;
+use super::tenant::{ensure_all_references, ensure_same_tenant, References};
+use super::instrumentation::observed;
 
 #[derive(Debug, FromRow)]
 struct SqlitePermissionRepositoryModel {
+    tenant_id: String,
     id: String,
     name: String,
     operation: String,
+    effect: String,
+    grantee: String,
+    condition: Option<String>,
     created_at: i64,
     updated_at: i64,
 }
@@ -19,9 +31,13 @@ struct SqlitePermissionRepositoryModel {
 impl From<Permission> for SqlitePermissionRepositoryModel {
     fn from(value: Permission) -> Self {      
         Self {
+            tenant_id: value.get_tenant_id().into(),
             id: value.get_id().into(),
             name: value.get_name(),
             operation: serde_json::to_string(&value.get_operation()).unwrap(),
+            effect: value.get_effect().to_string(),
+            grantee: value.get_grantee().to_string(),
+            condition: value.get_condition().map(|condition| condition.get_source()),
             created_at: value.get_created_at().timestamp_millis(),
             updated_at: value.get_updated_at().timestamp_millis(),
         }
@@ -32,8 +48,12 @@ impl From<SqlitePermissionRepositoryModel> for Permission {
     fn from(value: SqlitePermissionRepositoryModel) -> Self {
         Permission::builder()
             .id(PermissionId::from(value.id))
+            .tenant_id(value.tenant_id.into())
             .name(value.name)
             .operation(serde_json::from_str(&value.operation).unwrap())
+            .effect(Effect::try_from(value.effect).unwrap())
+            .grantee(Grantee::try_from(value.grantee).unwrap())
+            .condition(value.condition.map(|condition| Condition::parse(&condition).unwrap()))
             .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
             .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
             .build()
@@ -42,40 +62,131 @@ impl From<SqlitePermissionRepositoryModel> for Permission {
 
 #[derive(Debug)]
 pub struct SqlitePermissionRepository {
-    connection_pool: Pool<Sqlite>
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
 }
 
 impl SqlitePermissionRepository {
-    pub fn new(connection_pool: Pool<Sqlite>) -> SqlitePermissionRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqlitePermissionRepository {
         SqlitePermissionRepository {
-            connection_pool
+            connection_pool,
+            tenant_id,
         }
     }
 }
 
+// the operation embeds a copy of its resource, which has to belong to the same tenant as well
+pub(crate) fn permission_references(tenant_id: &TenantId, entity: &Permission) -> Result<References, Error> {
+    let resource_ids = match entity.get_operation() {
+        Operation::Invoke(resource) => {
+            ensure_same_tenant(tenant_id, &resource.get_tenant_id())?;
+            vec![resource.get_id().into()]
+        },
+    };
+    Ok(vec![("resources", resource_ids)])
+}
+
+pub(crate) async fn upsert_permission(connection: &mut SqliteConnection, entity: Permission) -> Result<(), Error> {
+    let model = SqlitePermissionRepositoryModel::from(entity);
+    let query = "
+        INSERT INTO permissions (tenant_id, id, name, operation, effect, grantee, condition, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (tenant_id, id) DO UPDATE SET
+        name=?, operation=?, effect=?, grantee=?, condition=?, created_at=?, updated_at=?;
+    ";
+    sqlx::query(query)
+        // insert
+        .bind(model.tenant_id)
+        .bind(model.id)
+        .bind(model.name.clone())
+        .bind(model.operation.clone())
+        .bind(model.effect.clone())
+        .bind(model.grantee.clone())
+        .bind(model.condition.clone())
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        // update
+        .bind(model.name)
+        .bind(model.operation)
+        .bind(model.effect)
+        .bind(model.grantee)
+        .bind(model.condition)
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
 #[async_trait]
 impl Repository<PermissionId, Permission> for SqlitePermissionRepository {
     async fn get_by_id(&self, id: PermissionId) -> Result<Option<Permission>, Error> {
-        let mut connection = self.connection_pool.acquire().await?;
-        let query = "SELECT * FROM permissions WHERE id = ?";
-        let permission = sqlx::query_as::<_, SqlitePermissionRepositoryModel>(query)
-            .bind::<String>(id.into()) // todo: map to model PK first
-            .fetch_optional(&mut *connection).await?
-            .map(Permission::from);
-        Ok(permission)
+        observed("permission", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM permissions WHERE tenant_id = ? AND id = ?;";
+            let permission = sqlx::query_as::<_, SqlitePermissionRepositoryModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into()) // todo: map to model PK first
+                .fetch_optional(&mut *connection).await?
+                .map(Permission::from);
+            Ok(permission)
+        }).await
     }
 
     async fn save(&self, entity: Permission) -> Result<(), Error> {
-        let model = SqlitePermissionRepositoryModel::from(entity);
-        let mut connection = self.connection_pool.acquire().await?;
-        let query = "INSERT INTO permissions VALUES(?, ?, ?, ?, ?);";
-        sqlx::query(query)
-            .bind(model.id)
-            .bind(model.name)
-            .bind(model.operation)
-            .bind(model.created_at)
-            .bind(model.updated_at)
-            .execute(&mut *connection).await?;
-        Ok(())
+        observed("permission", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let references = permission_references(&self.tenant_id, &entity)?;
+            let permission_id = entity.get_id();
+            let mut transaction = self.connection_pool.begin().await?;
+            ensure_all_references(&mut transaction, &self.tenant_id, references).await?;
+            upsert_permission(&mut transaction, entity).await?;
+            append_event(&mut transaction, &self.tenant_id, ChangeEvent::PermissionSaved { permission_id }).await?;
+            transaction.commit().await?;
+            Ok(())
+        }).await
     }
-}
\ No newline at end of file
+}
+#[async_trait]
+impl PermissionRepository for SqlitePermissionRepository {
+    async fn find_owner_permissions(&self) -> Result<Vec<Permission>, Error> {
+        observed("permission", "find_owner_permissions", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM permissions WHERE tenant_id = ? AND grantee = ?;";
+            let permissions = sqlx::query_as::<_, SqlitePermissionRepositoryModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(Grantee::Owner.to_string())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(Permission::from)
+                .collect();
+            Ok(permissions)
+        }).await
+    }
+
+    async fn get_by_name(&self, name: &str) -> Result<Option<Permission>, Error> {
+        observed("permission", "get_by_name", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM permissions WHERE tenant_id = ? AND name = ? ORDER BY created_at LIMIT 1;";
+            let permission = sqlx::query_as::<_, SqlitePermissionRepositoryModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(name)
+                .fetch_optional(&mut *connection).await?
+                .map(Permission::from);
+            Ok(permission)
+        }).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Permission>, Error> {
+        observed("permission", "find_all", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM permissions WHERE tenant_id = ? ORDER BY created_at;";
+            let permissions = sqlx::query_as::<_, SqlitePermissionRepositoryModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(Permission::from)
+                .collect();
+            Ok(permissions)
+        }).await
+    }
+}
@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, FromRow};
+use sqlx::pool::Pool;
+
+use crate::domain::relationships::{ObjectRef, RelationTuple, TupleSubject};
+use crate::domain::repositories::{Error, RelationTupleRepository};
+use crate::domain::tenants::TenantId;
+
+use super::tenant::ensure_same_tenant;
+use super::instrumentation::observed;
+
+// objects and subjects are stored in their `namespace:object_id` and `subject` / `namespace:object_id#relation` text forms
+#[derive(Debug, FromRow)]
+struct SqliteRelationTupleModel {
+    tenant_id: String,
+    object: String,
+    relation: String,
+    subject: String,
+}
+
+impl From<RelationTuple> for SqliteRelationTupleModel {
+    fn from(value: RelationTuple) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            object: value.get_object().to_string(),
+            relation: value.get_relation(),
+            subject: value.get_subject().to_string(),
+        }
+    }
+}
+
+impl TryFrom<SqliteRelationTupleModel> for RelationTuple {
+    type Error = Error;
+
+    fn try_from(value: SqliteRelationTupleModel) -> Result<Self, Self::Error> {
+        Ok(RelationTuple::new(
+            value.tenant_id.into(),
+            ObjectRef::try_from(value.object.as_str())?,
+            &value.relation,
+            TupleSubject::try_from(value.subject.as_str())?,
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct SqliteRelationTupleRepository {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteRelationTupleRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteRelationTupleRepository {
+        SqliteRelationTupleRepository {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl RelationTupleRepository for SqliteRelationTupleRepository {
+    async fn write(&self, tuple: RelationTuple) -> Result<(), Error> {
+        observed("relation_tuple", "write", async move {
+            ensure_same_tenant(&self.tenant_id, &tuple.get_tenant_id())?;
+            let model = SqliteRelationTupleModel::from(tuple);
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                INSERT INTO relation_tuples (tenant_id, object, relation, subject)
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT (tenant_id, object, relation, subject) DO NOTHING;
+            ";
+            sqlx::query(query)
+                .bind(model.tenant_id)
+                .bind(model.object)
+                .bind(model.relation)
+                .bind(model.subject)
+                .execute(&mut *connection).await?;
+            Ok(())
+        }).await
+    }
+
+    async fn delete(&self, tuple: RelationTuple) -> Result<(), Error> {
+        observed("relation_tuple", "delete", async move {
+            ensure_same_tenant(&self.tenant_id, &tuple.get_tenant_id())?;
+            let model = SqliteRelationTupleModel::from(tuple);
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "DELETE FROM relation_tuples WHERE tenant_id = ? AND object = ? AND relation = ? AND subject = ?;";
+            sqlx::query(query)
+                .bind(model.tenant_id)
+                .bind(model.object)
+                .bind(model.relation)
+                .bind(model.subject)
+                .execute(&mut *connection).await?;
+            Ok(())
+        }).await
+    }
+
+    async fn find(&self, object: ObjectRef, relation: &str) -> Result<Vec<RelationTuple>, Error> {
+        observed("relation_tuple", "find", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM relation_tuples WHERE tenant_id = ? AND object = ? AND relation = ?;";
+            sqlx::query_as::<_, SqliteRelationTupleModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(object.to_string())
+                .bind(relation)
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(RelationTuple::try_from)
+                .collect()
+        }).await
+    }
+}
@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, SqliteConnection, FromRow};
+use sqlx::pool::Pool;
+
+use crate::domain::outbox::ChangeEvent;
+use crate::domain::repositories::{Error, Repository, ResourceRepository};
+use crate::domain::resources::{ResourceId, Resource};
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+use super::outbox::append_event;
+use super::tenant::{ensure_all_references, ensure_same_tenant, References};
+use super::instrumentation::observed;
+
+#[derive(Debug, FromRow)]
+struct SqliteResourceModel {
+    tenant_id: String,
+    id: String,
+    name: String,
+    parent_id: Option<String>,
+    owner_id: Option<String>,
+    attributes: String,
+}
+
+impl From<Resource> for SqliteResourceModel {
+    fn from(value: Resource) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            id: value.get_id().into(),
+            name: value.get_name(),
+            parent_id: value.get_parent_id().map(String::from),
+            owner_id: value.get_owner_id().map(String::from),
+            attributes: serde_json::to_string(&value.get_attributes()).unwrap(),
+        }
+    }
+}
+
+impl From<SqliteResourceModel> for Resource {
+    fn from(value: SqliteResourceModel) -> Self {
+        Resource::builder()
+            .id(ResourceId::from(value.id))
+            .tenant_id(value.tenant_id.into())
+            .name(value.name)
+            .parent_id(value.parent_id.map(ResourceId::from))
+            .owner_id(value.owner_id.map(SubjectId::from))
+            .attributes(serde_json::from_str(&value.attributes).unwrap())
+            .build()
+    }
+}
+
+#[derive(Debug)]
+pub struct SqliteResourceRepository {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteResourceRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteResourceRepository {
+        SqliteResourceRepository {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+pub(crate) fn resource_references(entity: &Resource) -> References {
+    vec![
+        ("resources", entity.get_parent_id().into_iter().map(String::from).collect()),
+        ("subjects", entity.get_owner_id().into_iter().map(String::from).collect()),
+    ]
+}
+
+pub(crate) async fn upsert_resource(connection: &mut SqliteConnection, entity: Resource) -> Result<(), Error> {
+    let model = SqliteResourceModel::from(entity);
+    let query = "
+        INSERT INTO resources (tenant_id, id, name, parent_id, owner_id, attributes)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT (tenant_id, id) DO UPDATE SET
+        name=?, parent_id=?, owner_id=?, attributes=?;
+    ";
+    sqlx::query(query)
+        // insert
+        .bind(model.tenant_id)
+        .bind(model.id)
+        .bind(model.name.clone())
+        .bind(model.parent_id.clone())
+        .bind(model.owner_id.clone())
+        .bind(model.attributes.clone())
+        // update
+        .bind(model.name)
+        .bind(model.parent_id)
+        .bind(model.owner_id)
+        .bind(model.attributes)
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
+#[async_trait]
+impl Repository<ResourceId, Resource> for SqliteResourceRepository {
+    async fn get_by_id(&self, id: ResourceId) -> Result<Option<Resource>, Error> {
+        observed("resource", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM resources WHERE tenant_id = ? AND id = ?;";
+            let resource = sqlx::query_as::<_, SqliteResourceModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into())
+                .fetch_optional(&mut *connection).await?
+                .map(Resource::from);
+            Ok(resource)
+        }).await
+    }
+
+    async fn save(&self, entity: Resource) -> Result<(), Error> {
+        observed("resource", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let resource_id = entity.get_id();
+            let mut transaction = self.connection_pool.begin().await?;
+            ensure_all_references(&mut transaction, &self.tenant_id, resource_references(&entity)).await?;
+            upsert_resource(&mut transaction, entity).await?;
+            append_event(&mut transaction, &self.tenant_id, ChangeEvent::ResourceSaved { resource_id }).await?;
+            transaction.commit().await?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl ResourceRepository for SqliteResourceRepository {
+    async fn get_by_name(&self, name: &str) -> Result<Option<Resource>, Error> {
+        observed("resource", "get_by_name", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM resources WHERE tenant_id = ? AND name = ?;";
+            let resource = sqlx::query_as::<_, SqliteResourceModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(name)
+                .fetch_optional(&mut *connection).await?
+                .map(Resource::from);
+            Ok(resource)
+        }).await
+    }
+
+    async fn register(&self, resource: Resource) -> Result<Resource, Error> {
+        observed("resource", "register", async move {
+            ensure_same_tenant(&self.tenant_id, &resource.get_tenant_id())?;
+            let name = resource.get_name();
+            let resource_id = resource.get_id();
+            let mut transaction = self.connection_pool.begin().await?;
+            ensure_all_references(&mut transaction, &self.tenant_id, resource_references(&resource)).await?;
+            let model = SqliteResourceModel::from(resource);
+            let query = "
+                INSERT INTO resources (tenant_id, id, name, parent_id, owner_id, attributes)
+                VALUES (?, ?, ?, ?, ?, ?)
+                ON CONFLICT (tenant_id, name) DO NOTHING;
+            ";
+            let inserted = sqlx::query(query)
+                .bind(model.tenant_id)
+                .bind(model.id)
+                .bind(model.name)
+                .bind(model.parent_id)
+                .bind(model.owner_id)
+                .bind(model.attributes)
+                .execute(&mut *transaction).await?;
+            if inserted.rows_affected() > 0 {
+                append_event(&mut transaction, &self.tenant_id, ChangeEvent::ResourceSaved { resource_id }).await?;
+            }
+
+            let query = "SELECT * FROM resources WHERE tenant_id = ? AND name = ?;";
+            let registered = sqlx::query_as::<_, SqliteResourceModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(&name)
+                .fetch_optional(&mut *transaction).await?
+                .map(Resource::from)
+                .ok_or_else(|| Error::Simple(format!("resource {} was not registered", name)))?;
+            transaction.commit().await?;
+            Ok(registered)
+        }).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Resource>, Error> {
+        observed("resource", "find_all", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM resources WHERE tenant_id = ? ORDER BY name;";
+            let resources = sqlx::query_as::<_, SqliteResourceModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(Resource::from)
+                .collect();
+            Ok(resources)
+        }).await
+    }
+}
@@ -1,17 +1,25 @@
 use async_trait::async_trait;
-use sqlx::{Sqlite, FromRow};
+use sqlx::{Sqlite, SqliteConnection, FromRow};
 use sqlx::pool::Pool;
 
 use chrono::{Utc, TimeZone};
 
 use crate::domain::roles::{RoleId, Role};
-use crate::domain::repositories::{Error, Repository};
+use crate::domain::tenants::TenantId;
+use crate::domain::outbox::ChangeEvent;
+use crate::domain::repositories::{Error, Repository, RoleRepository};
+
+use super::outbox::append_event;
+use super::tenant::{ensure_all_references, ensure_same_tenant, References};
+use super::instrumentation::observed;
 
 #[derive(Debug, FromRow)]
 struct SqliteRoleRepositoryModel {
+    tenant_id: String,
     id: String,
     name: String,
     permissions: String,
+    approvers: String,
     created_at: i64,
     updated_at: i64,
 }
@@ -19,9 +27,11 @@ struct SqliteRoleRepositoryModel {
 impl From<Role> for SqliteRoleRepositoryModel {
     fn from(value: Role) -> Self {      
         Self {
+            tenant_id: value.get_tenant_id().into(),
             id: value.get_id().into(),
             name: value.get_name(),
             permissions: serde_json::to_string(&value.get_permissions()).unwrap(),
+            approvers: serde_json::to_string(&value.get_approvers()).unwrap(),
             created_at: value.get_created_at().timestamp_millis(),
             updated_at: value.get_updated_at().timestamp_millis(),
         }
@@ -32,8 +42,10 @@ impl From<SqliteRoleRepositoryModel> for Role {
     fn from(value: SqliteRoleRepositoryModel) -> Self {
         Role::builder()
             .id(RoleId::from(value.id))
+            .tenant_id(value.tenant_id.into())
             .name(value.name)
             .permissions(serde_json::from_str(&value.permissions).unwrap())
+            .approvers(serde_json::from_str(&value.approvers).unwrap())
             .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
             .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
             .build()
@@ -42,40 +54,111 @@ impl From<SqliteRoleRepositoryModel> for Role {
 
 #[derive(Debug)]
 pub struct SqliteRoleRepository {
-    connection_pool: Pool<Sqlite>
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
 }
 
 impl SqliteRoleRepository {
-    pub fn new(connection_pool: Pool<Sqlite>) -> SqliteRoleRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteRoleRepository {
         SqliteRoleRepository {
-            connection_pool
+            connection_pool,
+            tenant_id,
         }
     }
 }
 
+pub(crate) fn role_references(entity: &Role) -> References {
+    vec![
+        ("permissions", entity.get_permissions().into_iter().map(String::from).collect()),
+        ("subjects", entity.get_approvers().into_iter().map(String::from).collect()),
+    ]
+}
+
+pub(crate) async fn find_all_roles(connection: &mut SqliteConnection, tenant_id: &TenantId) -> Result<Vec<Role>, Error> {
+    let query = "SELECT * FROM roles WHERE tenant_id = ? ORDER BY created_at;";
+    let roles = sqlx::query_as::<_, SqliteRoleRepositoryModel>(query)
+        .bind(String::from(tenant_id.clone()))
+        .fetch_all(&mut *connection).await?
+        .into_iter()
+        .map(Role::from)
+        .collect();
+    Ok(roles)
+}
+
+pub(crate) async fn upsert_role(connection: &mut SqliteConnection, entity: Role) -> Result<(), Error> {
+    let model = SqliteRoleRepositoryModel::from(entity);
+    let query = "
+        INSERT INTO roles (tenant_id, id, name, permissions, approvers, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (tenant_id, id) DO UPDATE SET
+        name=?, permissions=?, approvers=?, created_at=?, updated_at=?;
+    ";
+    sqlx::query(query)
+        // insert
+        .bind(model.tenant_id)
+        .bind(model.id)
+        .bind(model.name.clone())
+        .bind(model.permissions.clone())
+        .bind(model.approvers.clone())
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        // update
+        .bind(model.name)
+        .bind(model.permissions)
+        .bind(model.approvers)
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
 #[async_trait]
 impl Repository<RoleId, Role> for SqliteRoleRepository {
     async fn get_by_id(&self, id: RoleId) -> Result<Option<Role>, Error> {
-        let mut connection = self.connection_pool.acquire().await?;
-        let query = "SELECT * FROM roles WHERE id = ?";
-        let role = sqlx::query_as::<_, SqliteRoleRepositoryModel>(query)
-            .bind::<String>(id.into()) // todo: map to model PK first
-            .fetch_optional(&mut *connection).await?
-            .map(Role::from);
-        Ok(role)
+        observed("role", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM roles WHERE tenant_id = ? AND id = ?;";
+            let role = sqlx::query_as::<_, SqliteRoleRepositoryModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into()) // todo: map to model PK first
+                .fetch_optional(&mut *connection).await?
+                .map(Role::from);
+            Ok(role)
+        }).await
     }
 
     async fn save(&self, entity: Role) -> Result<(), Error> {
-        let model = SqliteRoleRepositoryModel::from(entity);
-        let mut connection = self.connection_pool.acquire().await?;
-        let query = "INSERT INTO roles VALUES(?, ?, ?, ?, ?);";
-        sqlx::query(query)
-            .bind(model.id)
-            .bind(model.name)
-            .bind(model.permissions)
-            .bind(model.created_at)
-            .bind(model.updated_at)
-            .execute(&mut *connection).await?;
-        Ok(())
+        observed("role", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let role_id = entity.get_id();
+            let mut transaction = self.connection_pool.begin().await?;
+            ensure_all_references(&mut transaction, &self.tenant_id, role_references(&entity)).await?;
+            upsert_role(&mut transaction, entity).await?;
+            append_event(&mut transaction, &self.tenant_id, ChangeEvent::RoleSaved { role_id }).await?;
+            transaction.commit().await?;
+            Ok(())
+        }).await
     }
-}
\ No newline at end of file
+}
+#[async_trait]
+impl RoleRepository for SqliteRoleRepository {
+    async fn get_by_name(&self, name: &str) -> Result<Option<Role>, Error> {
+        observed("role", "get_by_name", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM roles WHERE tenant_id = ? AND name = ? ORDER BY created_at LIMIT 1;";
+            let role = sqlx::query_as::<_, SqliteRoleRepositoryModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(name)
+                .fetch_optional(&mut *connection).await?
+                .map(Role::from);
+            Ok(role)
+        }).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Role>, Error> {
+        observed("role", "find_all", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            find_all_roles(&mut connection, &self.tenant_id).await
+        }).await
+    }
+}
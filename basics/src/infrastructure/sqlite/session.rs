@@ -0,0 +1,168 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, FromRow};
+use sqlx::pool::Pool;
+
+use chrono::{DateTime, Utc, TimeZone};
+
+use crate::domain::repositories::{Error, Repository, SessionRepository};
+use crate::domain::sessions::{Session, SessionId};
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+
+use super::tenant::{ensure_references, ensure_same_tenant};
+use super::instrumentation::observed;
+
+#[derive(Debug, FromRow)]
+struct SqliteSessionModel {
+    tenant_id: String,
+    id: String,
+    subject_id: String,
+    active_roles: String,
+    token_hash: Option<String>,
+    expires_at: Option<i64>,
+    revoked_at: Option<i64>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl From<Session> for SqliteSessionModel {
+    fn from(value: Session) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            id: value.get_id().into(),
+            subject_id: value.get_subject_id().into(),
+            active_roles: serde_json::to_string(&value.get_active_roles()).unwrap(),
+            token_hash: value.get_token_hash(),
+            expires_at: value.get_expires_at().map(|utc| utc.timestamp_millis()),
+            revoked_at: value.get_revoked_at().map(|utc| utc.timestamp_millis()),
+            created_at: value.get_created_at().timestamp_millis(),
+            updated_at: value.get_updated_at().timestamp_millis(),
+        }
+    }
+}
+
+impl From<SqliteSessionModel> for Session {
+    fn from(value: SqliteSessionModel) -> Self {
+        Session::builder()
+            .id(value.id.into())
+            .tenant_id(value.tenant_id.into())
+            .subject_id(value.subject_id.into())
+            .active_roles(serde_json::from_str(&value.active_roles).unwrap())
+            .token_hash(value.token_hash)
+            .expires_at(value.expires_at.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .revoked_at(value.revoked_at.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
+            .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
+            .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
+            .build()
+    }
+}
+
+pub struct SqliteSessionRepository {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteSessionRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteSessionRepository {
+        SqliteSessionRepository {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Repository<SessionId, Session> for SqliteSessionRepository {
+    async fn get_by_id(&self, id: SessionId) -> Result<Option<Session>, Error> {
+        observed("session", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM sessions WHERE tenant_id = ? AND id = ?;";
+            let session = sqlx::query_as::<_, SqliteSessionModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into())
+                .fetch_optional(&mut *connection).await?
+                .map(Session::from);
+            Ok(session)
+        }).await
+    }
+
+    // the token and the expiry never change, and a revocation is never undone
+    async fn save(&self, entity: Session) -> Result<(), Error> {
+        observed("session", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let subject_ids = vec![entity.get_subject_id().into()];
+            let role_ids = entity.get_active_roles().into_iter().map(String::from).collect();
+            let model = SqliteSessionModel::from(entity);
+            let mut connection = self.connection_pool.acquire().await?;
+            ensure_references(&mut connection, &self.tenant_id, "subjects", subject_ids).await?;
+            ensure_references(&mut connection, &self.tenant_id, "roles", role_ids).await?;
+            let query = "
+                INSERT INTO sessions (
+                    tenant_id, id, subject_id, active_roles, token_hash,
+                    expires_at, revoked_at, created_at, updated_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (tenant_id, id) DO UPDATE SET
+                active_roles=?, revoked_at=COALESCE(revoked_at, ?), updated_at=?;
+            ";
+            sqlx::query(query)
+                // insert
+                .bind(model.tenant_id)
+                .bind(model.id)
+                .bind(model.subject_id)
+                .bind(model.active_roles.clone())
+                .bind(model.token_hash)
+                .bind(model.expires_at)
+                .bind(model.revoked_at)
+                .bind(model.created_at)
+                .bind(model.updated_at)
+                // update
+                .bind(model.active_roles)
+                .bind(model.revoked_at)
+                .bind(model.updated_at)
+                .execute(&mut *connection).await?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl SessionRepository for SqliteSessionRepository {
+    async fn find_active_by_subject(&self, subject_id: SubjectId, at: DateTime<Utc>) -> Result<Vec<Session>, Error> {
+        observed("session", "find_active_by_subject", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                SELECT * FROM sessions
+                WHERE tenant_id = ? AND subject_id = ? AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > ?)
+                ORDER BY created_at;
+            ";
+            let sessions = sqlx::query_as::<_, SqliteSessionModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(subject_id.into())
+                .bind(at.timestamp_millis())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(Session::from)
+                .collect();
+            Ok(sessions)
+        }).await
+    }
+
+    async fn revoke_all_by_subject(&self, subject_id: SubjectId, at: DateTime<Utc>) -> Result<u64, Error> {
+        observed("session", "revoke_all_by_subject", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                UPDATE sessions SET revoked_at = ?, updated_at = ?
+                WHERE tenant_id = ? AND subject_id = ? AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > ?);
+            ";
+            let result = sqlx::query(query)
+                .bind(at.timestamp_millis())
+                .bind(at.timestamp_millis())
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(subject_id.into())
+                .bind(at.timestamp_millis())
+                .execute(&mut *connection).await?;
+            Ok(result.rows_affected())
+        }).await
+    }
+}
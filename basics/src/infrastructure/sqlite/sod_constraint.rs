@@ -0,0 +1,169 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, SqliteConnection, FromRow};
+use sqlx::pool::Pool;
+
+use chrono::{Utc, TimeZone};
+
+use crate::domain::repositories::{Error, Repository, SodConstraintRepository};
+use crate::domain::separation_of_duties::{SeparationOfDuties, SodConstraint, SodConstraintId, SodKind};
+use crate::domain::tenants::TenantId;
+
+use super::group::find_all_groups;
+use super::role::find_all_roles;
+use super::subject::find_all_subjects;
+use super::tenant::{ensure_references, ensure_same_tenant};
+use super::instrumentation::observed;
+
+#[derive(Debug, FromRow)]
+struct SqliteSodConstraintModel {
+    tenant_id: String,
+    id: String,
+    name: String,
+    kind: String,
+    roles: String,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl From<SodConstraint> for SqliteSodConstraintModel {
+    fn from(value: SodConstraint) -> Self {
+        Self {
+            tenant_id: value.get_tenant_id().into(),
+            id: value.get_id().into(),
+            name: value.get_name(),
+            kind: value.get_kind().to_string(),
+            roles: serde_json::to_string(&value.get_roles()).unwrap(),
+            created_at: value.get_created_at().timestamp_millis(),
+            updated_at: value.get_updated_at().timestamp_millis(),
+        }
+    }
+}
+
+impl TryFrom<SqliteSodConstraintModel> for SodConstraint {
+    type Error = Error;
+
+    fn try_from(value: SqliteSodConstraintModel) -> Result<Self, Self::Error> {
+        Ok(SodConstraint::builder()
+            .id(value.id.into())
+            .tenant_id(value.tenant_id.into())
+            .name(value.name)
+            .kind(SodKind::try_from(value.kind)?)
+            .roles(serde_json::from_str(&value.roles).unwrap())
+            .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
+            .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
+            .build())
+    }
+}
+
+async fn find_all_constraints(connection: &mut SqliteConnection, tenant_id: &TenantId) -> Result<Vec<SodConstraint>, Error> {
+    let query = "SELECT * FROM sod_constraints WHERE tenant_id = ? ORDER BY created_at;";
+    sqlx::query_as::<_, SqliteSodConstraintModel>(query)
+        .bind(String::from(tenant_id.clone()))
+        .fetch_all(&mut *connection).await?
+        .into_iter()
+        .map(SodConstraint::try_from)
+        .collect()
+}
+
+// judges what the connection sees by the static constraints, writes not yet committed included. the
+// services check each change before they make it, but only this check, run in the transaction that
+// writes the change, sees changes that other services committed in the meantime
+pub(crate) async fn ensure_separation_of_duties(connection: &mut SqliteConnection, tenant_id: &TenantId) -> Result<(), Error> {
+    let constraints = find_all_constraints(connection, tenant_id).await?;
+    if constraints.iter().all(|constraint| constraint.get_kind() != SodKind::Static) {
+        return Ok(());
+    }
+    SeparationOfDuties::new(
+        constraints,
+        &find_all_roles(connection, tenant_id).await?,
+        &find_all_subjects(connection, tenant_id).await?,
+        &find_all_groups(connection, tenant_id).await?,
+    ).ensure_no_violations()
+}
+
+pub struct SqliteSodConstraintRepository {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteSodConstraintRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteSodConstraintRepository {
+        SqliteSodConstraintRepository {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Repository<SodConstraintId, SodConstraint> for SqliteSodConstraintRepository {
+    async fn get_by_id(&self, id: SodConstraintId) -> Result<Option<SodConstraint>, Error> {
+        observed("sod_constraint", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM sod_constraints WHERE tenant_id = ? AND id = ?;";
+            sqlx::query_as::<_, SqliteSodConstraintModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind::<String>(id.into())
+                .fetch_optional(&mut *connection).await?
+                .map(SodConstraint::try_from)
+                .transpose()
+        }).await
+    }
+
+    async fn save(&self, entity: SodConstraint) -> Result<(), Error> {
+        observed("sod_constraint", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let role_ids = entity.get_roles().into_iter().map(String::from).collect();
+            let model = SqliteSodConstraintModel::from(entity);
+            let mut transaction = self.connection_pool.begin().await?;
+            ensure_references(&mut transaction, &self.tenant_id, "roles", role_ids).await?;
+            let query = "
+                INSERT INTO sod_constraints (tenant_id, id, name, kind, roles, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (tenant_id, id) DO UPDATE SET
+                name=?, kind=?, roles=?, updated_at=?;
+            ";
+            sqlx::query(query)
+                // insert
+                .bind(model.tenant_id)
+                .bind(model.id)
+                .bind(model.name.clone())
+                .bind(model.kind.clone())
+                .bind(model.roles.clone())
+                .bind(model.created_at)
+                .bind(model.updated_at)
+                // update
+                .bind(model.name)
+                .bind(model.kind)
+                .bind(model.roles)
+                .bind(model.updated_at)
+                .execute(&mut *transaction).await?;
+            ensure_separation_of_duties(&mut transaction, &self.tenant_id).await?;
+            transaction.commit().await?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl SodConstraintRepository for SqliteSodConstraintRepository {
+    async fn get_by_name(&self, name: &str) -> Result<Option<SodConstraint>, Error> {
+        observed("sod_constraint", "get_by_name", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM sod_constraints WHERE tenant_id = ? AND name = ?;";
+            sqlx::query_as::<_, SqliteSodConstraintModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(name)
+                .fetch_optional(&mut *connection).await?
+                .map(SodConstraint::try_from)
+                .transpose()
+        }).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<SodConstraint>, Error> {
+        observed("sod_constraint", "find_all", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            find_all_constraints(&mut connection, &self.tenant_id).await
+        }).await
+    }
+}
@@ -1,18 +1,28 @@
 use async_trait::async_trait;
-use sqlx::{Sqlite, FromRow};
+use sqlx::{Sqlite, SqliteConnection, FromRow};
 use sqlx::pool::Pool;
 
-use chrono::{Utc, TimeZone};
+use chrono::{DateTime, Utc, TimeZone};
 
-use crate::domain::repositories::{Error, Repository};
-use crate::domain::subjects::{SubjectId, Subject};
+use crate::domain::outbox::ChangeEvent;
+use crate::domain::repositories::{Error, Repository, SubjectRepository};
+use crate::domain::subjects::{SubjectEventEnvelope, SubjectId, SubjectKind, Subject};
+use crate::domain::tenants::TenantId;
 
-#[derive(Debug, FromRow)]
+use super::outbox::append_event;
+use super::sod_constraint::ensure_separation_of_duties;
+use super::tenant::{ensure_all_references, ensure_same_tenant, References};
+use super::instrumentation::observed;
+
+#[derive(Debug, Clone, FromRow)]
 struct SqliteSubjectModel {
+    tenant_id: String,
     id: String,
     version: i64,
     name: String,
+    kind: String,
     roles: String,
+    attributes: String,
     created_at: i64,
     updated_at: i64,
     deleted_at: Option<i64>,
@@ -21,10 +31,13 @@ struct SqliteSubjectModel {
 impl From<Subject> for SqliteSubjectModel {
     fn from(value: Subject) -> Self {
         Self {
+            tenant_id: value.get_tenant_id().into(),
             id: value.get_id().into(),
             version: value.get_version(),
             name: value.get_name(),
-            roles: serde_json::to_string(&value.get_roles()).unwrap(),
+            kind: value.get_kind().to_string(),
+            roles: serde_json::to_string(&value.get_role_assignments()).unwrap(),
+            attributes: serde_json::to_string(&value.get_attributes()).unwrap(),
             created_at: value.get_created_at().timestamp_millis(),
             updated_at: value.get_updated_at().timestamp_millis(),
             deleted_at: value.get_deleted_at().map(|utc| utc.timestamp_millis()),
@@ -36,9 +49,12 @@ impl From<SqliteSubjectModel> for Subject {
     fn from(value: SqliteSubjectModel) -> Self {
         Subject::builder()
             .id(value.id.into())
+            .tenant_id(value.tenant_id.into())
             .version(value.version)
             .name(value.name)
+            .kind(SubjectKind::try_from(value.kind).unwrap())
             .roles(serde_json::from_str(&value.roles).unwrap())
+            .attributes(serde_json::from_str(&value.attributes).unwrap())
             .created_at(Utc.timestamp_millis_opt(value.created_at).single().unwrap_or_default())
             .updated_at(Utc.timestamp_millis_opt(value.updated_at).single().unwrap_or_default())
             .deleted_at(value.deleted_at.map(|ms| Utc.timestamp_millis_opt(ms).single().unwrap_or_default()))
@@ -46,55 +62,307 @@ impl From<SqliteSubjectModel> for Subject {
     }
 }
 
+#[derive(Debug, FromRow)]
+struct SqliteSubjectEventModel {
+    subject_id: String,
+    version: i64,
+    event: String,
+    occurred_at: i64,
+}
+
+impl From<SqliteSubjectEventModel> for SubjectEventEnvelope {
+    fn from(value: SqliteSubjectEventModel) -> Self {
+        SubjectEventEnvelope::new(
+            value.subject_id.into(),
+            value.version,
+            serde_json::from_str(&value.event).unwrap(),
+            Utc.timestamp_millis_opt(value.occurred_at).single().unwrap_or_default(),
+        )
+    }
+}
+
+// a snapshot is taken once a stream has grown this many events past the last one
+const SNAPSHOT_INTERVAL: i64 = 50;
+
 pub struct SqliteSubjectRepository {
-    connection_pool: Pool<Sqlite>
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
 }
 
 impl SqliteSubjectRepository {
-    pub fn new(connection_pool: Pool<Sqlite>) -> SqliteSubjectRepository {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteSubjectRepository {
         SqliteSubjectRepository {
-            connection_pool
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+pub(crate) fn subject_references(entity: &Subject) -> References {
+    vec![("roles", entity.get_roles().into_iter().map(String::from).collect())]
+}
+
+// the projection and the snapshots share their columns
+async fn upsert_subject_row(connection: &mut SqliteConnection, table: &str, entity: Subject) -> Result<(), Error> {
+    upsert_subject_model(connection, table, SqliteSubjectModel::from(entity)).await
+}
+
+async fn upsert_subject_model(connection: &mut SqliteConnection, table: &str, model: SqliteSubjectModel) -> Result<(), Error> {
+    let query = format!("
+        INSERT INTO {} (tenant_id, id, version, name, kind, roles, attributes, created_at, updated_at, deleted_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT DO UPDATE SET
+        version=?, name=?, kind=?, roles=?, attributes=?, created_at=?, updated_at=?, deleted_at=?;
+    ", table);
+    sqlx::query(&query)
+        // insert
+        .bind(model.tenant_id)
+        .bind(model.id)
+        .bind(model.version)
+        .bind(model.name.clone())
+        .bind(model.kind.clone())
+        .bind(model.roles.clone())
+        .bind(model.attributes.clone())
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        .bind(model.deleted_at)
+        // update
+        .bind(model.version)
+        .bind(model.name.clone())
+        .bind(model.kind.clone())
+        .bind(model.roles.clone())
+        .bind(model.attributes.clone())
+        .bind(model.created_at)
+        .bind(model.updated_at)
+        .bind(model.deleted_at)
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
+async fn get_head_version(connection: &mut SqliteConnection, tenant_id: &TenantId, id: &SubjectId) -> Result<Option<i64>, Error> {
+    let query = "
+        SELECT MAX(version) FROM (
+            SELECT version FROM subject_events WHERE tenant_id = ? AND subject_id = ?
+            UNION ALL
+            SELECT version FROM subject_snapshots WHERE tenant_id = ? AND id = ?
+        );
+    ";
+    let version = sqlx::query_scalar::<_, Option<i64>>(query)
+        .bind(String::from(tenant_id.clone()))
+        .bind(String::from(id.clone()))
+        .bind(String::from(tenant_id.clone()))
+        .bind(String::from(id.clone()))
+        .fetch_one(&mut *connection).await?;
+    Ok(version)
+}
+
+async fn find_events_after(
+    connection: &mut SqliteConnection,
+    tenant_id: &TenantId,
+    id: &SubjectId,
+    version: i64,
+) -> Result<Vec<SubjectEventEnvelope>, Error> {
+    let query = "SELECT * FROM subject_events WHERE tenant_id = ? AND subject_id = ? AND version > ? ORDER BY version;";
+    let events = sqlx::query_as::<_, SqliteSubjectEventModel>(query)
+        .bind(String::from(tenant_id.clone()))
+        .bind(String::from(id.clone()))
+        .bind(version)
+        .fetch_all(&mut *connection).await?
+        .into_iter()
+        .map(SubjectEventEnvelope::from)
+        .collect();
+    Ok(events)
+}
+
+async fn append_subject_event(connection: &mut SqliteConnection, tenant_id: &TenantId, envelope: SubjectEventEnvelope) -> Result<(), Error> {
+    let query = "INSERT INTO subject_events (tenant_id, subject_id, version, event, occurred_at) VALUES (?, ?, ?, ?, ?);";
+    sqlx::query(query)
+        .bind(String::from(tenant_id.clone()))
+        .bind(String::from(envelope.get_subject_id()))
+        .bind(envelope.get_version())
+        .bind(serde_json::to_string(&envelope.get_event()).unwrap())
+        .bind(envelope.get_occurred_at().timestamp_millis())
+        .execute(&mut *connection).await?;
+    Ok(())
+}
+
+// the latest snapshot with the events that follow it replayed on top
+async fn load_subject(connection: &mut SqliteConnection, tenant_id: &TenantId, id: &SubjectId) -> Result<Option<Subject>, Error> {
+    let query = "SELECT * FROM subject_snapshots WHERE tenant_id = ? AND id = ? ORDER BY version DESC LIMIT 1;";
+    let snapshot = sqlx::query_as::<_, SqliteSubjectModel>(query)
+        .bind(String::from(tenant_id.clone()))
+        .bind(String::from(id.clone()))
+        .fetch_optional(&mut *connection).await?
+        .map(Subject::from);
+    let after = snapshot.as_ref().map_or(-1, Subject::get_version);
+    let events = find_events_after(connection, tenant_id, id, after).await?;
+    Subject::replay(tenant_id.clone(), snapshot, &events)
+}
+
+// as the projection has them
+pub(crate) async fn find_all_subjects(connection: &mut SqliteConnection, tenant_id: &TenantId) -> Result<Vec<Subject>, Error> {
+    let query = "SELECT * FROM subjects WHERE tenant_id = ? ORDER BY created_at;";
+    let subjects = sqlx::query_as::<_, SqliteSubjectModel>(query)
+        .bind(String::from(tenant_id.clone()))
+        .fetch_all(&mut *connection).await?
+        .into_iter()
+        .map(Subject::from)
+        .collect();
+    Ok(subjects)
+}
+
+pub(crate) async fn delete_subject_stream(connection: &mut SqliteConnection, tenant_id: &TenantId, id: &SubjectId) -> Result<(), Error> {
+    for query in [
+        "DELETE FROM subject_events WHERE tenant_id = ? AND subject_id = ?;",
+        "DELETE FROM subject_snapshots WHERE tenant_id = ? AND id = ?;",
+    ] {
+        sqlx::query(query)
+            .bind(String::from(tenant_id.clone()))
+            .bind(String::from(id.clone()))
+            .execute(&mut *connection).await?;
+    }
+    Ok(())
+}
+
+// appends the pending events of the subject to its stream and refreshes the `subjects` projection.
+// pending events the stream already holds are skipped, so a subject saved again after further changes
+// stores each change once; a pending event that differs from the stored one at its version means the
+// subject was changed by someone else in the meantime, as does a subject without pending events that
+// is not at the head of its stream
+pub(crate) async fn store_subject(connection: &mut SqliteConnection, entity: Subject) -> Result<(), Error> {
+    let tenant_id = entity.get_tenant_id();
+    let id = entity.get_id();
+    let head = get_head_version(connection, &tenant_id, &id).await?;
+    let pending = entity.get_pending_events();
+
+    if pending.is_empty() {
+        if head != Some(entity.get_version()) {
+            return Err(concurrent_change(&entity, head.unwrap_or(-1)));
+        }
+        return upsert_subject_row(connection, "subjects", entity).await;
+    }
+
+    let head = head.unwrap_or(-1);
+    let (known, new): (Vec<_>, Vec<_>) = pending.into_iter().partition(|envelope| envelope.get_version() <= head);
+    if let Some(first) = known.first() {
+        let stored = find_events_after(connection, &tenant_id, &id, first.get_version() - 1).await?;
+        let unchanged = known.iter().all(|envelope| stored.iter().any(|event| {
+            event.get_version() == envelope.get_version() && event.get_event() == envelope.get_event()
+        }));
+        if !unchanged {
+            return Err(concurrent_change(&entity, head));
         }
     }
+    if new.first().is_some_and(|envelope| envelope.get_version() != head + 1) {
+        return Err(concurrent_change(&entity, head));
+    }
+    for envelope in new {
+        append_subject_event(connection, &tenant_id, envelope).await?;
+    }
+
+    let query = "SELECT COALESCE(MAX(version), -1) FROM subject_snapshots WHERE tenant_id = ? AND id = ?;";
+    let snapshot_version = sqlx::query_scalar::<_, i64>(query)
+        .bind(String::from(tenant_id.clone()))
+        .bind(String::from(id.clone()))
+        .fetch_one(&mut *connection).await?;
+    if entity.get_version() - snapshot_version >= SNAPSHOT_INTERVAL {
+        upsert_subject_row(connection, "subject_snapshots", entity.clone()).await?;
+    }
+    upsert_subject_row(connection, "subjects", entity).await
+}
+
+// puts back a copy of the subject, e.g. from a snapshot import, as the latest snapshot of its stream.
+// the events before it stay as its history. a copy that is behind the stream is stored one version past
+// its head, so the stream's versions keep going up and the copy still replays last
+pub(crate) async fn restore_subject(connection: &mut SqliteConnection, entity: Subject) -> Result<(), Error> {
+    let head = get_head_version(connection, &entity.get_tenant_id(), &entity.get_id()).await?;
+    let mut model = SqliteSubjectModel::from(entity);
+    if let Some(head) = head.filter(|head| *head > model.version) {
+        model.version = head + 1;
+    }
+    upsert_subject_model(connection, "subject_snapshots", model.clone()).await?;
+    upsert_subject_model(connection, "subjects", model).await
+}
+
+fn concurrent_change(entity: &Subject, head: i64) -> Error {
+    Error::Simple(format!(
+        "subject {} was changed concurrently: it is at version {} in the store",
+        entity.get_name(),
+        head,
+    ))
 }
 
 #[async_trait]
 impl Repository<SubjectId, Subject> for SqliteSubjectRepository {
     async fn save(&self, entity: Subject) -> Result<(), Error> {
-        let model = SqliteSubjectModel::from(entity);
-        let mut connection = self.connection_pool.acquire().await?;
-        let query = "
-            INSERT INTO subjects VALUES (?, ?, ?, ?, ?, ?, ?)
-            ON CONFLICT (id) DO UPDATE SET
-            version=?, name=?, roles=?, created_at=?, updated_at=?, deleted_at=?;
-        ";
-        sqlx::query(query)
-            // insert
-            .bind(model.id)
-            .bind(model.version)
-            .bind(model.name.clone())
-            .bind(model.roles.clone())
-            .bind(model.created_at)
-            .bind(model.updated_at)
-            .bind(model.deleted_at)
-            // update
-            .bind(model.version)
-            .bind(model.name.clone())
-            .bind(model.roles.clone())
-            .bind(model.created_at)
-            .bind(model.updated_at)
-            .bind(model.deleted_at)
-            .execute(&mut *connection).await?;
-        Ok(())
+        observed("subject", "save", async move {
+            ensure_same_tenant(&self.tenant_id, &entity.get_tenant_id())?;
+            let subject_id = entity.get_id();
+            let mut transaction = self.connection_pool.begin().await?;
+            ensure_all_references(&mut transaction, &self.tenant_id, subject_references(&entity)).await?;
+            store_subject(&mut transaction, entity).await?;
+            ensure_separation_of_duties(&mut transaction, &self.tenant_id).await?;
+            append_event(&mut transaction, &self.tenant_id, ChangeEvent::SubjectSaved { subject_id }).await?;
+            transaction.commit().await?;
+            Ok(())
+        }).await
     }
-    
+
     async fn get_by_id(&self, id: SubjectId) -> Result<Option<Subject>, Error> {
-        let mut connection = self.connection_pool.acquire().await?;
-        let query = "SELECT * FROM subjects WHERE id = ? ORDER BY version DESC LIMIT 1;";
-        let subject = sqlx::query_as::<_, SqliteSubjectModel>(query)
-            .bind::<String>(id.into()) // todo: map to model PK first
-            .fetch_optional(&mut *connection).await?
-            .map(Subject::from);
-        Ok(subject)
+        observed("subject", "get_by_id", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            load_subject(&mut connection, &self.tenant_id, &id).await
+        }).await
+    }
+}
+
+#[async_trait]
+impl SubjectRepository for SqliteSubjectRepository {
+    async fn get_history(&self, id: SubjectId) -> Result<Vec<SubjectEventEnvelope>, Error> {
+        observed("subject", "get_history", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            find_events_after(&mut connection, &self.tenant_id, &id, -1).await
+        }).await
+    }
+
+    async fn find_with_expired_roles(&self, at: DateTime<Utc>) -> Result<Vec<Subject>, Error> {
+        observed("subject", "find_with_expired_roles", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "
+                SELECT * FROM subjects
+                WHERE tenant_id = ? AND EXISTS (
+                    SELECT 1 FROM json_each(subjects.roles)
+                    WHERE json_extract(value, '$.valid_until') <= ?
+                );
+            ";
+            let subjects = sqlx::query_as::<_, SqliteSubjectModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(at.timestamp_millis())
+                .fetch_all(&mut *connection).await?
+                .into_iter()
+                .map(Subject::from)
+                .collect();
+            Ok(subjects)
+        }).await
+    }
+
+    async fn get_by_name(&self, name: &str) -> Result<Option<Subject>, Error> {
+        observed("subject", "get_by_name", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            let query = "SELECT * FROM subjects WHERE tenant_id = ? AND name = ? AND deleted_at IS NULL ORDER BY created_at LIMIT 1;";
+            let subject = sqlx::query_as::<_, SqliteSubjectModel>(query)
+                .bind::<String>(self.tenant_id.clone().into())
+                .bind(name)
+                .fetch_optional(&mut *connection).await?
+                .map(Subject::from);
+            Ok(subject)
+        }).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Subject>, Error> {
+        observed("subject", "find_all", async move {
+            let mut connection = self.connection_pool.acquire().await?;
+            find_all_subjects(&mut connection, &self.tenant_id).await
+        }).await
     }
 }
@@ -0,0 +1,128 @@
+use sqlx::SqliteConnection;
+
+use crate::domain::repositories::Error;
+use crate::domain::tenants::TenantId;
+
+pub(crate) fn ensure_same_tenant(repository_tenant_id: &TenantId, entity_tenant_id: &TenantId) -> Result<(), Error> {
+    if repository_tenant_id != entity_tenant_id {
+        return Err(Error::Simple(format!(
+            "entity belongs to tenant {} but the repository is scoped to tenant {}",
+            entity_tenant_id,
+            repository_tenant_id,
+        )));
+    }
+    Ok(())
+}
+
+// the ids an entity refers to, keyed by the table they live in
+pub(crate) type References = Vec<(&'static str, Vec<String>)>;
+
+pub(crate) async fn ensure_all_references(
+    connection: &mut SqliteConnection,
+    tenant_id: &TenantId,
+    references: References,
+) -> Result<(), Error> {
+    for (table, ids) in references {
+        ensure_references(connection, tenant_id, table, ids).await?;
+    }
+    Ok(())
+}
+
+// rejects ids that do not exist in `table` under the given tenant, which covers references into other tenants
+pub(crate) async fn ensure_references(
+    connection: &mut SqliteConnection,
+    tenant_id: &TenantId,
+    table: &str,
+    ids: Vec<String>,
+) -> Result<(), Error> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let query = format!(
+        "SELECT value FROM json_each(?) WHERE value NOT IN (SELECT id FROM {} WHERE tenant_id = ?);",
+        table,
+    );
+    let missing: Vec<String> = sqlx::query_scalar(&query)
+        .bind(serde_json::to_string(&ids).unwrap())
+        .bind(String::from(tenant_id.clone()))
+        .fetch_all(&mut *connection).await?;
+
+    if !missing.is_empty() {
+        return Err(Error::Simple(format!(
+            "references to {} {:?} are not part of tenant {}",
+            table,
+            missing,
+            tenant_id,
+        )));
+    }
+    Ok(())
+}
+
+// what may refer to an entity of a table: the kind of the referring entity and a query that names every
+// one of them in the tenant (?1) that refers to the id (?2). groups are missing, their referrers are
+// cleaned up as they are deleted, and so is what belongs to a deleted entity, see `DEPENDENTS`
+const REFERRERS: [(&str, &str, &str); 12] = [
+    ("roles", "subject", "SELECT name FROM subjects WHERE tenant_id = ?1 AND EXISTS (SELECT 1 FROM json_each(subjects.roles) WHERE json_extract(value, '$.role_id') = ?2)"),
+    ("roles", "group", "SELECT name FROM groups WHERE tenant_id = ?1 AND EXISTS (SELECT 1 FROM json_each(groups.roles) WHERE value = ?2)"),
+    ("roles", "separation of duties constraint", "SELECT name FROM sod_constraints WHERE tenant_id = ?1 AND EXISTS (SELECT 1 FROM json_each(sod_constraints.roles) WHERE value = ?2)"),
+    ("permissions", "role", "SELECT name FROM roles WHERE tenant_id = ?1 AND EXISTS (SELECT 1 FROM json_each(roles.permissions) WHERE value = ?2)"),
+    ("permissions", "delegation", "SELECT id FROM delegations WHERE tenant_id = ?1 AND revoked_at IS NULL AND EXISTS (SELECT 1 FROM json_each(delegations.permissions) WHERE value = ?2)"),
+    ("resources", "permission", "SELECT name FROM permissions WHERE tenant_id = ?1 AND json_extract(operation, '$.Invoke.id') = ?2"),
+    ("resources", "resource", "SELECT name FROM resources WHERE tenant_id = ?1 AND parent_id = ?2"),
+    ("subjects", "group", "SELECT name FROM groups WHERE tenant_id = ?1 AND EXISTS (SELECT 1 FROM json_each(groups.subjects) WHERE value = ?2)"),
+    ("subjects", "role", "SELECT name FROM roles WHERE tenant_id = ?1 AND EXISTS (SELECT 1 FROM json_each(roles.approvers) WHERE value = ?2)"),
+    ("subjects", "resource", "SELECT name FROM resources WHERE tenant_id = ?1 AND owner_id = ?2"),
+    ("subjects", "delegation", "SELECT id FROM delegations WHERE tenant_id = ?1 AND revoked_at IS NULL AND (delegator_id = ?2 OR delegate_id = ?2)"),
+    ("subjects", "relation tuple", "SELECT object || '#' || relation || '@' || subject FROM relation_tuples WHERE tenant_id = ?1 AND subject = ?2"),
+];
+
+// what goes along with an entity of a table when it is deleted, as it means nothing without it: statements
+// that remove or update every row of the tenant (?1) that depends on the id (?2)
+const DEPENDENTS: [(&str, &str); 6] = [
+    ("subjects", "DELETE FROM sessions WHERE tenant_id = ?1 AND subject_id = ?2"),
+    ("subjects", "DELETE FROM api_keys WHERE tenant_id = ?1 AND subject_id = ?2"),
+    ("subjects", "DELETE FROM password_credentials WHERE tenant_id = ?1 AND subject_id = ?2"),
+    ("subjects", "DELETE FROM access_requests WHERE tenant_id = ?1 AND subject_id = ?2"),
+    ("roles", "DELETE FROM access_requests WHERE tenant_id = ?1 AND role_id = ?2"),
+    ("roles", "UPDATE sessions SET active_roles = (SELECT json_group_array(value) FROM json_each(sessions.active_roles) WHERE value != ?2) WHERE tenant_id = ?1 AND EXISTS (SELECT 1 FROM json_each(sessions.active_roles) WHERE value = ?2)"),
+];
+
+pub(crate) async fn delete_dependents(connection: &mut SqliteConnection, tenant_id: &TenantId, table: &str, id: &str) -> Result<(), Error> {
+    for (_, query) in DEPENDENTS.iter().filter(|(referred, _)| *referred == table) {
+        sqlx::query(query)
+            .bind(String::from(tenant_id.clone()))
+            .bind(id)
+            .execute(&mut *connection).await?;
+    }
+    Ok(())
+}
+
+// rejects deletes that would leave something referring to the deleted entities. meant to run once every
+// change of a batch is written, so that a batch may delete an entity along with whatever referred to it
+pub(crate) async fn ensure_no_referrers(
+    connection: &mut SqliteConnection,
+    tenant_id: &TenantId,
+    deleted: References,
+) -> Result<(), Error> {
+    for (table, ids) in deleted {
+        for id in ids {
+            for (_, kind, query) in REFERRERS.iter().filter(|(referred, _, _)| *referred == table) {
+                let referrers: Vec<String> = sqlx::query_scalar(query)
+                    .bind(String::from(tenant_id.clone()))
+                    .bind(&id)
+                    .fetch_all(&mut *connection).await?;
+                if let Some(referrer) = referrers.first() {
+                    return Err(Error::Simple(format!(
+                        "{} {} cannot be deleted: {} {} still refers to it",
+                        table.trim_end_matches('s'),
+                        id,
+                        kind,
+                        referrer,
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use crate::domain::repositories::{
+    EntityChange, PermissionRepository, Repository, ResourceRepository, RoleRepository, SubjectRepository, UnitOfWork,
+};
+use crate::domain::roles::Role;
+use crate::domain::subjects::Subject;
+use crate::domain::tenants::TenantId;
+use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use crate::infrastructure::sqlite::resource::SqliteResourceRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+use crate::test_support::{seeded_database, subject_id, tenant_id};
+
+const SEED: &str = "
+    resources:
+      - name: reports
+    permissions:
+      - name: read reports
+        resource: reports
+    roles:
+      - name: analyst
+        permissions: [read reports]
+    subjects:
+      - name: alec leamas
+        roles: [analyst]
+";
+
+fn moscow_centre() -> TenantId {
+    TenantId::from("moscow centre")
+}
+
+#[async_std::test]
+async fn test_repositories_refuse_entities_of_another_tenant() {
+    let connection_pool = seeded_database(SEED).await;
+
+    let error = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())
+        .save(Subject::new(moscow_centre(), "karla"))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("belongs to tenant moscow centre"), "{}", error);
+
+    let error = SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())
+        .commit(vec![EntityChange::SaveRole(Role::new(moscow_centre(), "handler"))])
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("belongs to tenant moscow centre"), "{}", error);
+}
+
+#[async_std::test]
+async fn test_entities_cannot_refer_to_those_of_another_tenant() {
+    let connection_pool = seeded_database(SEED).await;
+    let read_reports = SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())
+        .get_by_name("read reports")
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut handler = Role::new(moscow_centre(), "handler");
+    handler.add_permission(read_reports.get_id());
+    let error = SqliteRoleRepository::new(connection_pool.clone(), moscow_centre()).save(handler).await.unwrap_err();
+    assert!(error.to_string().contains("are not part of tenant moscow centre"), "{}", error);
+    assert!(SqliteRoleRepository::new(connection_pool.clone(), moscow_centre()).find_all().await.unwrap().is_empty());
+}
+
+#[async_std::test]
+async fn test_reads_see_only_the_tenant_of_the_repository() {
+    let connection_pool = seeded_database(SEED).await;
+    let alec_leamas_id = subject_id(&connection_pool, "alec leamas").await;
+
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), moscow_centre());
+    assert!(subject_repository.get_by_id(alec_leamas_id).await.unwrap().is_none());
+    assert!(subject_repository.get_by_name("alec leamas").await.unwrap().is_none());
+    assert!(subject_repository.find_all().await.unwrap().is_empty());
+    assert!(SqliteResourceRepository::new(connection_pool.clone(), moscow_centre()).get_by_name("reports").await.unwrap().is_none());
+
+    subject_repository.save(Subject::new(moscow_centre(), "karla")).await.unwrap();
+    assert_eq!(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).find_all().await.unwrap().len(), 1);
+}
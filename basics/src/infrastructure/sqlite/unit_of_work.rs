@@ -0,0 +1,150 @@
+use async_trait::async_trait;
+use sqlx::{Sqlite, SqliteConnection};
+use sqlx::pool::Pool;
+
+use crate::domain::outbox::ChangeEvent;
+use crate::domain::repositories::{EntityChange, Error, UnitOfWork};
+use crate::domain::tenants::TenantId;
+
+use super::access_request::{access_request_references, upsert_access_request};
+use super::audit::insert_audit_record;
+use super::group::{delete_group_members, group_references, remove_from_parents, upsert_group};
+use super::outbox::append_event;
+use super::permission::{permission_references, upsert_permission};
+use super::resource::{resource_references, upsert_resource};
+use super::role::{role_references, upsert_role};
+use super::sod_constraint::ensure_separation_of_duties;
+use super::subject::{delete_subject_stream, restore_subject, store_subject, subject_references};
+use super::tenant::{delete_dependents, ensure_all_references, ensure_no_referrers, ensure_same_tenant, References};
+use super::instrumentation::observed;
+
+pub struct SqliteUnitOfWork {
+    connection_pool: Pool<Sqlite>,
+    tenant_id: TenantId,
+}
+
+impl SqliteUnitOfWork {
+    pub fn new(connection_pool: Pool<Sqlite>, tenant_id: TenantId) -> SqliteUnitOfWork {
+        SqliteUnitOfWork {
+            connection_pool,
+            tenant_id,
+        }
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for SqliteUnitOfWork {
+    // dropping the transaction on an early return rolls it back
+    async fn commit(&self, changes: Vec<EntityChange>) -> Result<(), Error> {
+        observed("unit_of_work", "commit", async move {
+            let mut transaction = self.connection_pool.begin().await?;
+            let mut references = Vec::new();
+            let mut deleted: References = Vec::new();
+            // whether who holds which role may have changed
+            let mut holdings_changed = false;
+
+            for change in changes {
+                match change {
+                    EntityChange::SaveResource(resource) => {
+                        ensure_same_tenant(&self.tenant_id, &resource.get_tenant_id())?;
+                        references.extend(resource_references(&resource));
+                        let resource_id = resource.get_id();
+                        upsert_resource(&mut transaction, resource).await?;
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::ResourceSaved { resource_id }).await?;
+                    },
+                    EntityChange::SavePermission(permission) => {
+                        ensure_same_tenant(&self.tenant_id, &permission.get_tenant_id())?;
+                        references.extend(permission_references(&self.tenant_id, &permission)?);
+                        let permission_id = permission.get_id();
+                        upsert_permission(&mut transaction, permission).await?;
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::PermissionSaved { permission_id }).await?;
+                    },
+                    EntityChange::SaveRole(role) => {
+                        ensure_same_tenant(&self.tenant_id, &role.get_tenant_id())?;
+                        references.extend(role_references(&role));
+                        let role_id = role.get_id();
+                        upsert_role(&mut transaction, role).await?;
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::RoleSaved { role_id }).await?;
+                    },
+                    EntityChange::SaveSubject(subject) => {
+                        ensure_same_tenant(&self.tenant_id, &subject.get_tenant_id())?;
+                        references.extend(subject_references(&subject));
+                        let subject_id = subject.get_id();
+                        store_subject(&mut transaction, subject).await?;
+                        holdings_changed = true;
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::SubjectSaved { subject_id }).await?;
+                    },
+                    EntityChange::RestoreSubject(subject) => {
+                        ensure_same_tenant(&self.tenant_id, &subject.get_tenant_id())?;
+                        references.extend(subject_references(&subject));
+                        let subject_id = subject.get_id();
+                        restore_subject(&mut transaction, subject).await?;
+                        holdings_changed = true;
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::SubjectSaved { subject_id }).await?;
+                    },
+                    EntityChange::SaveGroup(group) => {
+                        ensure_same_tenant(&self.tenant_id, &group.get_tenant_id())?;
+                        references.extend(group_references(&group));
+                        let group_id = group.get_id();
+                        upsert_group(&mut transaction, group).await?;
+                        holdings_changed = true;
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::GroupSaved { group_id }).await?;
+                    },
+                    EntityChange::DeleteResource(id) => {
+                        delete(&mut transaction, &self.tenant_id, "resources", id.clone().into()).await?;
+                        deleted.push(("resources", vec![id.clone().into()]));
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::ResourceDeleted { resource_id: id }).await?;
+                    },
+                    EntityChange::DeletePermission(id) => {
+                        delete(&mut transaction, &self.tenant_id, "permissions", id.clone().into()).await?;
+                        deleted.push(("permissions", vec![id.clone().into()]));
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::PermissionDeleted { permission_id: id }).await?;
+                    },
+                    EntityChange::DeleteRole(id) => {
+                        delete(&mut transaction, &self.tenant_id, "roles", id.clone().into()).await?;
+                        deleted.push(("roles", vec![id.clone().into()]));
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::RoleDeleted { role_id: id }).await?;
+                    },
+                    EntityChange::DeleteSubject(id) => {
+                        delete(&mut transaction, &self.tenant_id, "subjects", id.clone().into()).await?;
+                        deleted.push(("subjects", vec![id.clone().into()]));
+                        delete_subject_stream(&mut transaction, &self.tenant_id, &id).await?;
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::SubjectDeleted { subject_id: id }).await?;
+                    },
+                    EntityChange::DeleteGroup(id) => {
+                        delete(&mut transaction, &self.tenant_id, "groups", id.clone().into()).await?;
+                        delete_group_members(&mut transaction, &String::from(self.tenant_id.clone()), &String::from(id.clone())).await?;
+                        remove_from_parents(&mut transaction, &String::from(self.tenant_id.clone()), &String::from(id.clone())).await?;
+                        append_event(&mut transaction, &self.tenant_id, ChangeEvent::GroupDeleted { group_id: id }).await?;
+                    },
+                    EntityChange::SaveAccessRequest(access_request) => {
+                        ensure_same_tenant(&self.tenant_id, &access_request.get_tenant_id())?;
+                        references.extend(access_request_references(&access_request));
+                        upsert_access_request(&mut transaction, access_request).await?;
+                    },
+                    EntityChange::AppendAuditRecord(record) => {
+                        ensure_same_tenant(&self.tenant_id, &record.get_tenant_id())?;
+                        insert_audit_record(&mut transaction, record).await?;
+                    },
+                }
+            }
+
+            ensure_no_referrers(&mut transaction, &self.tenant_id, deleted).await?;
+            ensure_all_references(&mut transaction, &self.tenant_id, references).await?;
+            if holdings_changed {
+                ensure_separation_of_duties(&mut transaction, &self.tenant_id).await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        }).await
+    }
+}
+
+async fn delete(connection: &mut SqliteConnection, tenant_id: &TenantId, table: &str, id: String) -> Result<(), Error> {
+    let query = format!("DELETE FROM {} WHERE tenant_id = ? AND id = ?;", table);
+    sqlx::query(&query)
+        .bind(String::from(tenant_id.clone()))
+        .bind(&id)
+        .execute(&mut *connection).await?;
+    delete_dependents(connection, tenant_id, table, &id).await
+}
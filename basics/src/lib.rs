@@ -0,0 +1,6 @@
+pub mod domain;
+pub mod application;
+pub mod infrastructure;
+pub mod metrics;
+#[cfg(test)]
+mod test_support;
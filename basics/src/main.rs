@@ -1,64 +1,246 @@
+use chrono::{Duration, Utc};
+use http::{header, Method, Request};
+use serde_json::json;
 use sqlx::sqlite::SqlitePool;
+use tower_service::Service;
 use tracing::info;
 
-use crate::domain::repositories::{Repository, Error};
-use crate::domain::operations::Operation;
-use crate::domain::permissions::Permission;
-use crate::domain::resources::Resource;
-use crate::domain::roles::Role;
-use crate::domain::groups::Group;
+use basics::domain::conditions::RequestContext;
+use basics::domain::credentials::LockoutPolicy;
+use basics::domain::jwks::TokenAlgorithm;
+use basics::domain::namespaces::NamespaceConfig;
+use basics::domain::relationships::ObjectRef;
+use basics::domain::repositories::{EntityChange, GroupRepository, PermissionRepository, Repository, ResourceRepository, RoleRepository, SodConstraintRepository, SubjectRepository, Error};
+use basics::domain::separation_of_duties::SodKind;
+use basics::domain::snapshots::Snapshot;
+use basics::domain::tenants::TenantId;
 
-use crate::infrastructure::sqlite::group::SqliteGroupRepository;
-use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
-use crate::infrastructure::sqlite::role::SqliteRoleRepository;
-use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use basics::infrastructure::files::key_set::FileKeySetRepository;
+use basics::infrastructure::http::metrics::{MetricsEndpoint, METRICS_PATH};
+use basics::infrastructure::http::scim::{ScimEndpoint, SCIM_CONTENT_TYPE};
+use basics::infrastructure::sqlite::access_request::SqliteAccessRequestRepository;
+use basics::infrastructure::sqlite::api_key::SqliteApiKeyRepository;
+use basics::infrastructure::sqlite::audit::SqliteAuditRepository;
+use basics::infrastructure::sqlite::credential::SqliteCredentialRepository;
+use basics::infrastructure::sqlite::delegation::SqliteDelegationRepository;
+use basics::infrastructure::sqlite::effective_permission::SqliteEffectivePermissionIndex;
+use basics::infrastructure::sqlite::group::SqliteGroupRepository;
+use basics::infrastructure::sqlite::outbox::SqliteChangeFeed;
+use basics::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use basics::infrastructure::sqlite::relation_tuple::SqliteRelationTupleRepository;
+use basics::infrastructure::sqlite::resource::SqliteResourceRepository;
+use basics::infrastructure::sqlite::role::SqliteRoleRepository;
+use basics::infrastructure::sqlite::session::SqliteSessionRepository;
+use basics::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use basics::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use basics::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
 
-use crate::application::subjects::{SubjectService, SubjectServiceImpl, CreateSubjectRequest, DeleteSubjectRequest};
+use basics::application::access_requests::{AccessRequestService, AccessRequestServiceImpl, RequestRoleRequest, DecideAccessRequestRequest};
+use basics::application::access_checker::AccessChecker;
+use basics::application::api_keys::{ApiKeyService, ApiKeyServiceImpl, IssueApiKeyRequest, ListApiKeysRequest, RevokeApiKeyRequest, RotateApiKeyRequest};
+use basics::application::authentication::{AuthenticationService, AuthenticationServiceImpl, ListSessionsRequest, LoginRequest, LogoutEverywhereRequest, SetPasswordRequest};
+use basics::application::analysis::{PolicyAnalyzer, Severity, DEFAULT_PRIVILEGED_RESOURCES};
+use basics::application::change_feed::{ChangeFeedConsumer, DecisionCache};
+use basics::application::delegations::{DelegateRequest, DelegationService, DelegationServiceImpl, RevokeDelegationRequest};
+use basics::application::effective_permissions::EffectivePermissionIndexer;
+use basics::application::groups::{GroupMemberGroupRequest, GroupService, GroupServiceImpl, ListGroupMembersRequest, ListSubjectGroupsRequest};
+use basics::application::policies::PolicyLoader;
+use basics::application::relationships::{RelationshipChecker, RelationshipService, RelationshipServiceImpl, RelationTupleRequest};
+use basics::application::resources::{ResourceService, ResourceServiceImpl, TransferOwnershipRequest};
+use basics::application::role_assignments::{AssignRoleRequest, RoleAssignmentService, RoleAssignmentServiceImpl, RoleAssignmentSweeper};
+use basics::application::scim::{ScimServiceImpl, USER_SCHEMA};
+use basics::application::seeds::{read_seed, SeedPlanner};
+use basics::application::separation_of_duties::{CreateSodConstraintRequest, DutiesLoader, SodConstraintService, SodConstraintServiceImpl};
+use basics::application::sessions::{SessionRoleRequest, SessionService, SessionServiceImpl, StartSessionRequest};
+use basics::application::simulation::{SimulateRequest, SimulationService, SimulationServiceImpl};
+use basics::application::snapshots::{ImportMode, SnapshotExporter, SnapshotImporter};
+use basics::application::subjects::{SubjectService, SubjectServiceImpl, CreateServiceAccountRequest, CreateSubjectRequest, DeleteSubjectRequest};
+use basics::application::tokens::{IssueTokenRequest, RolePermissionLoader, RotateKeysRequest, TokenService, TokenServiceImpl, TokenVerifier};
 
-mod domain;
-mod application;
-mod infrastructure;
+use basics::metrics;
+
+const DEFAULT_SEED: &str = "seeds/default.yaml";
+
+const EFFECTIVE_PERMISSION_CONSUMER: &str = "effective-permission-index";
+
+const KEY_SET: &str = "datastore/keys/data/jwks.json";
+const TOKEN_ISSUER: &str = "basics";
+
+const NAMESPACE_CONFIG: &str = "
+    namespace team {
+        relation member
+    }
+    namespace folder {
+        relation owner
+    }
+    namespace document {
+        relation parent
+        relation editor = this | parent->owner
+        relation banned
+        relation viewer = (this | editor) - banned
+    }
+";
 
 #[async_std::main]
 async fn main() -> Result<(), Error> {
     let subscriber = tracing_subscriber::FmtSubscriber::default();
     tracing::subscriber::set_global_default(subscriber).expect("unable to set global tracing subscriber");
 
-    let list_users_resource = Resource::new("users/get_users");
-    let list_users_operation = Operation::Invoke(list_users_resource.clone());
-    let list_users_permission = Permission::new(
-        "list users",
-        list_users_operation.clone(),
-    );
-
-    let update_user_resource = Resource::new("users/update_user");
-    let update_user_operation = Operation::Invoke(update_user_resource);
-    let update_user_permission = Permission::new(
-        "update user",
-        update_user_operation.clone(),
-    );
-
-    let mut engineer_role = Role::new("engineer");
-    engineer_role.add_permission(list_users_permission.get_id());
-    engineer_role.add_permission(update_user_permission.get_id());
+    let tenant_id = TenantId::from("default");
 
     let connection_pool = SqlitePool::connect("datastore/memory").await?;
     let mut connection = connection_pool.acquire().await?;
     sqlx::migrate!("./datastore/sqlite").run(&mut connection).await.expect("unable to migrate");
 
-    let role_repository = SqliteRoleRepository::new(connection_pool.clone());
-    role_repository.save(engineer_role.clone()).await?;
-    let role = role_repository.get_by_id(engineer_role.get_id()).await?.unwrap();
-    info!("{:?}", role);
+    let seed_planner = SeedPlanner::new(
+        tenant_id.clone(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id.clone())),
+    );
+
+    // `basics plan|apply [seed]` manage the store from a seed, `basics export [file]` and
+    // `basics import <file> [merge|replace]` move snapshots in and out of it and `basics analyze` reports
+    // dead or risky configuration. `basics index rebuild|check` recompute the effective permission index or
+    // compare it with live evaluation. `basics keys rotate [hs256|eddsa]` starts signing access tokens with
+    // a new key and `basics keys jwks` prints the keys verifiers need. `basics simulate [seed]` tells whose
+    // access applying a seed would change, without applying it. without a command the demo runs
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(command) = args.get(1) {
+        match command.as_str() {
+            "plan" | "apply" => {
+                let seed = read_seed(args.get(2).map_or(DEFAULT_SEED, String::as_str)).await?;
+                let plan = if command == "plan" {
+                    seed_planner.plan(&seed).await?
+                } else {
+                    seed_planner.apply(&seed).await?
+                };
+                print!("{}", plan);
+            },
+            "export" => {
+                let snapshot = snapshot_exporter(connection_pool.clone(), tenant_id.clone()).export().await?.to_json()?;
+                match args.get(2) {
+                    Some(path) => async_std::fs::write(path, snapshot)
+                        .await
+                        .map_err(|error| Error::Simple(format!("unable to write snapshot {}: {}", path, error)))?,
+                    None => println!("{}", snapshot),
+                }
+            },
+            "import" => {
+                let path = args.get(2).ok_or_else(|| Error::Simple("import expects a snapshot file".to_string()))?;
+                let mode = match args.get(3).map(String::as_str) {
+                    None | Some("merge") => ImportMode::Merge,
+                    Some("replace") => ImportMode::Replace,
+                    Some(other) => return Err(Error::Simple(format!("unknown import mode {}, expected merge or replace", other))),
+                };
+                let source = async_std::fs::read_to_string(path)
+                    .await
+                    .map_err(|error| Error::Simple(format!("unable to read snapshot {}: {}", path, error)))?;
+                let imported = snapshot_importer(connection_pool.clone(), tenant_id.clone())
+                    .import(&Snapshot::from_json(&source)?, mode)
+                    .await?;
+                println!("{:?}", imported);
+            },
+            "analyze" => {
+                let policy_analyzer = PolicyAnalyzer::new(
+                    tenant_id.clone(),
+                    Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())),
+                    Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone())),
+                    Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+                    Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+                    Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone())),
+                    DEFAULT_PRIVILEGED_RESOURCES.iter().map(|name| name.to_string()).collect(),
+                );
+                let report = policy_analyzer.analyze().await?;
+                println!("{}", report.to_json()?);
+                // errors fail the command so that CI can gate on it
+                let errors = report.count_at_least(Severity::Error);
+                if errors > 0 {
+                    return Err(Error::Simple(format!("analysis found {} errors", errors)));
+                }
+            },
+            "index" => {
+                let indexer = effective_permission_indexer(connection_pool.clone(), tenant_id.clone());
+                match args.get(2).map(String::as_str) {
+                    Some("rebuild") => {
+                        let change_feed = SqliteChangeFeed::new(connection_pool.clone(), tenant_id.clone());
+                        println!("indexed {} subjects", indexer.rebuild(&change_feed).await?);
+                    },
+                    Some("check") => {
+                        let report = indexer.check().await?;
+                        println!("{}", report.to_json()?);
+                        if !report.mismatches.is_empty() {
+                            return Err(Error::Simple(format!("index disagrees with live evaluation in {} places", report.mismatches.len())));
+                        }
+                    },
+                    _ => return Err(Error::Simple("index expects rebuild or check".to_string())),
+                }
+            },
+            "keys" => {
+                let token_service = token_service(connection_pool.clone(), tenant_id.clone());
+                match args.get(2).map(String::as_str) {
+                    Some("rotate") => {
+                        let algorithm = match args.get(3).map(String::as_str) {
+                            None | Some("eddsa") => TokenAlgorithm::EdDsa,
+                            Some("hs256") => TokenAlgorithm::Hs256,
+                            Some(other) => return Err(Error::Simple(format!("unknown algorithm {}, expected hs256 or eddsa", other))),
+                        };
+                        println!("{:?}", token_service.rotate_keys(RotateKeysRequest { algorithm }).await?);
+                    },
+                    Some("jwks") => println!("{}", token_service.get_published_keys().await?.to_json()?),
+                    _ => return Err(Error::Simple("keys expects rotate or jwks".to_string())),
+                }
+            },
+            "simulate" => {
+                let seed = read_seed(args.get(2).map_or(DEFAULT_SEED, String::as_str)).await?;
+                let plan = seed_planner.plan(&seed).await?;
+                let simulation = simulation_service(connection_pool.clone(), tenant_id.clone())
+                    .simulate(SimulateRequest {
+                        changes: plan.get_writes(),
+                        context: RequestContext::default(),
+                        subject_ids: None,
+                        resource_ids: None,
+                    })
+                    .await?;
+                for flip in &simulation.flips {
+                    let change = if flip.after { "gains" } else { "loses" };
+                    println!("{} {} {} on {}", flip.subject_name, change, flip.operation, flip.resource_name);
+                }
+                println!("{} decisions checked, {} would flip", simulation.checked, simulation.flips.len());
+            },
+            other => return Err(Error::Simple(format!("unknown command {}, expected plan, apply, export, import, analyze, index, keys or simulate", other))),
+        }
+        return Ok(());
+    }
+
+    let applied_seed = seed_planner.apply(&read_seed(DEFAULT_SEED).await?).await?;
+    info!("\n{}", applied_seed);
+
+    let policy_loader = PolicyLoader::new(
+        tenant_id.clone(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone())),
+    );
+    let loaded_policy = policy_loader.load_file("policies/default.policy").await?;
+    info!("{:?}", loaded_policy);
+
+    let resource_repository = SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone());
+    let list_users_resource = resource_repository.get_by_name("users/get_users").await?.unwrap();
+    let karla_report_id = resource_repository.get_by_name("reports/karla").await?.unwrap().get_id();
 
-    let permission_repository = SqlitePermissionRepository::new(connection_pool.clone());
-    permission_repository.save(list_users_permission.clone()).await?;
-    permission_repository.save(update_user_permission.clone()).await?;
+    let role_repository = SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone());
+    let on_call_role = role_repository.get_by_name("on-call").await?.unwrap();
 
-    info!("{:?}", permission_repository.get_by_id(list_users_permission.get_id()).await?.unwrap());
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone());
+    let alec_leamas_id = subject_repository.get_by_name("alec leamas").await?.unwrap().get_id();
+    let george_smiley_id = subject_repository.get_by_name("george smiley").await?.unwrap().get_id();
 
-    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone());
-    let subject_service = SubjectServiceImpl::new(Box::new(subject_repository));
+    let subject_service = SubjectServiceImpl::new(tenant_id.clone(), Box::new(subject_repository));
     let john_wick_id = subject_service.create_subject(
         CreateSubjectRequest { name: "john wick".to_string() }
     ).await?.subject_id;
@@ -67,30 +249,462 @@ async fn main() -> Result<(), Error> {
         DeleteSubjectRequest { subject_id: john_wick_id.clone() }
     ).await?;
 
-    let alec_leamas_id = subject_service.create_subject(
-        CreateSubjectRequest { name: "alec leamas".to_string() }
-    ).await?.subject_id;
+    let access_request_service = AccessRequestServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteAccessRequestRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id.clone())),
+        duties_loader(connection_pool.clone(), tenant_id.clone()),
+        Duration::days(1),
+    );
+    let on_call_request_id = access_request_service.request_role(RequestRoleRequest {
+        subject_id: alec_leamas_id.clone(),
+        role_id: on_call_role.get_id(),
+        valid_until: Some(Utc::now() + Duration::hours(8)),
+    }).await?.access_request_id;
 
-    let mut employees_group = Group::new("employees");
-    employees_group.add_subject(john_wick_id.clone());
-    employees_group.add_subject(alec_leamas_id.clone());
-    employees_group.add_role(engineer_role.get_id());
+    let on_call_decision = access_request_service.approve(DecideAccessRequestRequest {
+        access_request_id: on_call_request_id,
+        approver_id: george_smiley_id.clone(),
+    }).await?;
+    info!("{:?}", on_call_decision);
 
-    let group_repository = SqliteGroupRepository::new(connection_pool.clone());
-    group_repository.save(employees_group).await.expect("failed to save employees group");
+    // the index only answers once it caught up with the change feed; until then checks are evaluated
+    let effective_permission_indexer = effective_permission_indexer(connection_pool.clone(), tenant_id.clone());
+    let effective_permission_consumer = ChangeFeedConsumer::new(
+        EFFECTIVE_PERMISSION_CONSUMER,
+        Box::new(SqliteChangeFeed::new(connection_pool.clone(), tenant_id.clone())),
+    );
+    effective_permission_consumer.poll(&effective_permission_indexer).await?;
 
-    let subject_repository_2 = SqliteSubjectRepository::new(connection_pool.clone());
-    let access_checker = application::access_checker::AccessChecker::new(
-        Box::new(subject_repository_2),
-        Box::new(role_repository),
+    let access_checker = access_checker(connection_pool.clone(), tenant_id.clone())
+        .with_index(Box::new(SqliteEffectivePermissionIndex::new(connection_pool.clone(), tenant_id.clone())));
+    let can_invoke = access_checker.can_invoke(john_wick_id.clone(), list_users_resource.get_id())
+        .await?;
+    info!("{:?}", can_invoke);
+
+    let can_invoke_by_name = access_checker.can_invoke_by_name(alec_leamas_id.clone(), "users/update_user")
+        .await?;
+    info!("{:?}", can_invoke_by_name);
+
+    let payments_initiator_id = SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())
+        .get_by_name("payments-initiator").await?.unwrap().get_id();
+    let payments_approver_id = SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())
+        .get_by_name("payments-approver").await?.unwrap().get_id();
+
+    let sod_constraint_service = SodConstraintServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id.clone())),
+        duties_loader(connection_pool.clone(), tenant_id.clone()),
+    );
+    let sod_constraint_repository = SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id.clone());
+    for (name, kind, role_ids) in [
+        ("payments", SodKind::Static, [payments_initiator_id.clone(), payments_approver_id.clone()]),
+        ("payments on call", SodKind::Dynamic, [payments_initiator_id.clone(), on_call_role.get_id()]),
+    ] {
+        if sod_constraint_repository.get_by_name(name).await?.is_none() {
+            sod_constraint_service.create_constraint(CreateSodConstraintRequest {
+                name: name.to_string(),
+                kind,
+                role_ids: role_ids.into_iter().collect(),
+            }).await?;
+        }
+    }
+
+    let role_assignment_service = RoleAssignmentServiceImpl::new(
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        duties_loader(connection_pool.clone(), tenant_id.clone()),
+    );
+    role_assignment_service.assign_role(AssignRoleRequest {
+        subject_id: alec_leamas_id.clone(),
+        role_id: payments_initiator_id.clone(),
+        valid_from: None,
+        valid_until: None,
+    }).await?;
+    // alec initiates payments, so he cannot approve them as well
+    let approver_assignment = role_assignment_service.assign_role(AssignRoleRequest {
+        subject_id: alec_leamas_id.clone(),
+        role_id: payments_approver_id,
+        valid_from: None,
+        valid_until: None,
+    }).await;
+    info!("{:?}", approver_assignment);
+
+    let session_service = SessionServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteSessionRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        duties_loader(connection_pool.clone(), tenant_id.clone()),
+    );
+    let session_id = session_service.start_session(StartSessionRequest { subject_id: alec_leamas_id.clone() }).await?.session_id;
+    session_service.activate_role(SessionRoleRequest { session_id: session_id.clone(), role_id: payments_initiator_id }).await?;
+    // he holds on-call as well, just not in the session that initiates payments
+    let on_call_activation = session_service.activate_role(SessionRoleRequest {
+        session_id: session_id.clone(),
+        role_id: on_call_role.get_id(),
+    }).await;
+    info!("{:?}", on_call_activation);
+
+    let session = SqliteSessionRepository::new(connection_pool.clone(), tenant_id.clone()).get_by_id(session_id).await?.unwrap();
+    let payments_initiate_id = SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())
+        .get_by_name("payments/initiate").await?.unwrap().get_id();
+    let can_initiate_payment = access_checker.can_invoke_in_session(&session, payments_initiate_id.clone(), &RequestContext::default()).await?;
+    info!("{:?}", can_initiate_payment);
+
+    let permission_repository = SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone());
+    let initiate_payments_id = permission_repository.get_by_name("initiate payments").await?.unwrap().get_id();
+    let approve_payments_id = permission_repository.get_by_name("approve payments").await?.unwrap().get_id();
+    let peter_guillam_id = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())
+        .get_by_name("peter guillam").await?.unwrap().get_id();
+
+    let delegation_service = DelegationServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteDelegationRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
         Box::new(permission_repository),
     );
-    let can_invoke = access_checker.can_invoke(john_wick_id.clone(), list_users_resource.get_id())
+    // alec covers payments with peter while away, but cannot lend what he does not hold
+    let delegation_id = delegation_service.delegate(DelegateRequest {
+        delegator_id: alec_leamas_id.clone(),
+        delegate_id: peter_guillam_id.clone(),
+        permission_ids: [initiate_payments_id].into_iter().collect(),
+        valid_until: Utc::now() + Duration::days(7),
+    }).await?.delegation_id;
+    let approve_delegation = delegation_service.delegate(DelegateRequest {
+        delegator_id: alec_leamas_id.clone(),
+        delegate_id: peter_guillam_id.clone(),
+        permission_ids: [approve_payments_id].into_iter().collect(),
+        valid_until: Utc::now() + Duration::days(7),
+    }).await;
+    info!("{:?}", approve_delegation);
+
+    let delegated_decision = access_checker.check_in_context(peter_guillam_id.clone(), payments_initiate_id.clone(), &RequestContext::default()).await?;
+    info!("{:?}", delegated_decision);
+    delegation_service.revoke(RevokeDelegationRequest {
+        delegation_id,
+        revoked_by: alec_leamas_id.clone(),
+    }).await?;
+    let revoked_decision = access_checker.check_in_context(peter_guillam_id, payments_initiate_id, &RequestContext::default()).await?;
+    info!("{:?}", revoked_decision);
+
+    let namespace_config = NamespaceConfig::parse(NAMESPACE_CONFIG)?;
+    let relationship_service = RelationshipServiceImpl::new(
+        tenant_id.clone(),
+        namespace_config.clone(),
+        Box::new(SqliteRelationTupleRepository::new(connection_pool.clone(), tenant_id.clone())),
+    );
+    for tuple in [
+        format!("team:circus#member@{}", String::from(alec_leamas_id.clone())),
+        "folder:operations#owner@team:circus#member".to_string(),
+        "document:windfall#parent@folder:operations#...".to_string(),
+    ] {
+        relationship_service.write_tuple(RelationTupleRequest { tuple }).await?;
+    }
+
+    let relationship_checker = RelationshipChecker::new(
+        namespace_config,
+        Box::new(SqliteRelationTupleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        16,
+    );
+    let windfall = ObjectRef::new("document", "windfall");
+    let can_edit = relationship_checker.check(windfall.clone(), "editor", alec_leamas_id.clone()).await?;
+    info!("{:?}", can_edit);
+    let viewers = relationship_checker.expand(windfall, "viewer").await?;
+    info!("{:?}", viewers.resolve());
+
+    let mfa_context = RequestContext::new().with("mfa", true);
+    let can_edit_report = access_checker.can_invoke_in_context(alec_leamas_id.clone(), karla_report_id.clone(), &mfa_context)
+        .await?;
+    info!("{:?}", can_edit_report);
+
+    let resource_service = ResourceServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteAuditRepository::new(connection_pool.clone(), tenant_id.clone())),
+    );
+    let ownership_transfer = resource_service.transfer_ownership(TransferOwnershipRequest {
+        resource_id: karla_report_id.clone(),
+        owner_id: george_smiley_id.clone(),
+        transferred_by: alec_leamas_id.clone(),
+    }).await?;
+    info!("{:?}", ownership_transfer);
+
+    let can_edit_report = access_checker.can_invoke_in_context(alec_leamas_id.clone(), karla_report_id, &mfa_context)
+        .await?;
+    info!("{:?}", can_edit_report);
+
+    let role_assignment_sweeper = RoleAssignmentSweeper::new(
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id.clone())),
+    );
+    let expired_assignments = role_assignment_sweeper.sweep(Utc::now()).await?;
+    info!("{:?}", expired_assignments);
+
+    // the cache first catches up with the feed, then drops alec's decisions once alec is saved again
+    let decision_cache = DecisionCache::new();
+    let decision_cache_consumer = ChangeFeedConsumer::new(
+        "decision-cache",
+        Box::new(SqliteChangeFeed::new(connection_pool.clone(), tenant_id.clone())),
+    );
+    decision_cache_consumer.poll(&decision_cache).await?;
+    let can_list_users = access_checker.can_invoke(alec_leamas_id.clone(), list_users_resource.get_id())
+        .await?;
+    decision_cache.put(alec_leamas_id.clone(), list_users_resource.get_id(), can_list_users);
+
+    let subject_repository = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone());
+    let alec_leamas = subject_repository.get_by_id(alec_leamas_id.clone()).await?.unwrap();
+    subject_repository.save(alec_leamas).await?;
+    let handled_changes = decision_cache_consumer.poll(&decision_cache).await?;
+    info!("{:?} {:?}", handled_changes, decision_cache.get(&alec_leamas_id, &list_users_resource.get_id()));
+
+    let alec_leamas_history = subject_repository.get_history(alec_leamas_id.clone()).await?;
+    info!("{:?}", alec_leamas_history.iter().map(|envelope| (envelope.get_version(), envelope.get_event())).collect::<Vec<_>>());
+
+    effective_permission_consumer.poll(&effective_permission_indexer).await?;
+    let indexed_decision = access_checker.check_in_context(alec_leamas_id.clone(), list_users_resource.get_id(), &RequestContext::default()).await?;
+    let consistency_report = effective_permission_indexer.check().await?;
+    info!("{:?} {:?}", indexed_decision, consistency_report.mismatches);
+
+    let deploy_bot_id = subject_service.create_service_account(
+        CreateServiceAccountRequest { name: "deploy bot".to_string() }
+    ).await?.subject_id;
+    let api_key_service = ApiKeyServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteApiKeyRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+    );
+    let issued_api_key = api_key_service.issue_api_key(IssueApiKeyRequest {
+        subject_id: deploy_bot_id.clone(),
+        name: "ci".to_string(),
+        expires_at: Some(Utc::now() + Duration::days(90)),
+    }).await?;
+    let authenticated_id = api_key_service.authenticate(&issued_api_key.api_key).await?;
+    let bot_can_invoke = access_checker.can_invoke(authenticated_id, list_users_resource.get_id())
+        .await?;
+    let rotated_api_key = api_key_service.rotate_api_key(RotateApiKeyRequest {
+        api_key_id: issued_api_key.api_key_id,
+        expires_at: Some(Utc::now() + Duration::days(90)),
+        grace_period_seconds: None,
+    }).await?;
+    let replaced_key_refused = api_key_service.authenticate(&issued_api_key.api_key).await.is_err();
+    api_key_service.revoke_api_key(RevokeApiKeyRequest { api_key_id: rotated_api_key.api_key_id }).await?;
+    let api_keys = api_key_service.list_api_keys(ListApiKeysRequest { subject_id: deploy_bot_id }).await?;
+    info!("{:?} {:?} {:?}", bot_can_invoke, replaced_key_refused, api_keys);
+
+    let authentication_service = AuthenticationServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteCredentialRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSessionRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        LockoutPolicy::new(3, Duration::minutes(15)),
+        Duration::hours(8),
+    );
+    authentication_service.set_password(SetPasswordRequest {
+        subject_id: alec_leamas_id.clone(),
+        login: None,
+        password: "the spy who came in".to_string(),
+    }).await?;
+    let login = authentication_service.login(LoginRequest {
+        login: "alec leamas".to_string(),
+        password: "the spy who came in".to_string(),
+    }).await?;
+    let signed_in_subject_id = authentication_service.authenticate(&login.token).await?.get_subject_id();
+    let sessions = authentication_service.list_sessions(ListSessionsRequest { subject_id: alec_leamas_id.clone() }).await?;
+    let logout = authentication_service.logout_everywhere(LogoutEverywhereRequest { subject_id: alec_leamas_id.clone() }).await?;
+    let token_refused = authentication_service.authenticate(&login.token).await.is_err();
+    info!("{:?} {:?} {:?} {:?}", signed_in_subject_id, sessions, logout, token_refused);
+    // three wrong passwords in a row lock him out, right one included
+    for password in ["control", "karla", "smiley", "the spy who came in"] {
+        let login = authentication_service.login(LoginRequest { login: "alec leamas".to_string(), password: password.to_string() }).await;
+        info!("{:?}", login.map(|login| login.session_id));
+    }
+
+    let token_service = token_service(connection_pool.clone(), tenant_id.clone());
+    token_service.rotate_keys(RotateKeysRequest { algorithm: TokenAlgorithm::EdDsa }).await?;
+    let access_token = token_service.issue_token(IssueTokenRequest { subject_id: alec_leamas_id.clone() }).await?;
+    let role_permission_loader = RolePermissionLoader::new(
+        tenant_id.clone(),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())),
+    );
+    // the verifier only sees the published keys and the permission map from here on
+    let token_verifier = TokenVerifier::new(
+        tenant_id.clone(),
+        TOKEN_ISSUER,
+        token_service.get_published_keys().await?,
+        role_permission_loader.load().await?,
+    );
+    let offline_decision = token_verifier.check_by_name(&access_token.token, "users/get_users", &RequestContext::default())?;
+    // after a rotation tokens signed with the retired key still verify, as long as the verifier has the new set
+    token_service.rotate_keys(RotateKeysRequest { algorithm: TokenAlgorithm::EdDsa }).await?;
+    token_verifier.replace_key_set(token_service.get_published_keys().await?);
+    let verified_after_rotation = token_verifier.verify(&access_token.token).map(|claims| claims.get_roles().len());
+    info!("{:?} {:?} {:?}", access_token.expires_at, offline_decision, verified_after_rotation);
+
+    // groups nest: bill holds engineer through london station, which is a member of employees
+    let group_service = GroupServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone())),
+        duties_loader(connection_pool.clone(), tenant_id.clone()),
+    );
+    let bill_haydon_id = SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())
+        .get_by_name("bill haydon").await?.unwrap().get_id();
+    let groups = SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone()).find_all().await?;
+    let group_id = |name: &str| groups.iter().find(|group| group.get_name() == name).unwrap().get_id();
+    let employees_id = group_id("employees");
+    let london_station_id = group_id("london station");
+    let nested_decision = access_checker.can_invoke_by_name(bill_haydon_id.clone(), "users/get_users").await;
+    let employees = group_service.list_members(ListGroupMembersRequest { group_id: employees_id.clone() }).await?;
+    let bill_haydon_groups = group_service.list_subject_groups(ListSubjectGroupsRequest { subject_id: bill_haydon_id.clone() }).await?;
+    // employees already contains london station, so london station cannot contain employees
+    let cycle = group_service.add_group(GroupMemberGroupRequest {
+        group_id: london_station_id.clone(),
+        member_group_id: employees_id.clone(),
+    }).await;
+    info!("{:?} {:?} {:?} {:?}", nested_decision, employees.subject_ids, bill_haydon_groups.group_ids, cycle);
+
+    // taking london station out of employees would cost bill what engineer grants him; nothing is saved
+    let mut employees = SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone())
+        .get_by_id(employees_id).await?.unwrap();
+    employees.remove_group(&london_station_id);
+    let simulation = simulation_service(connection_pool.clone(), tenant_id.clone())
+        .simulate(SimulateRequest {
+            changes: vec![EntityChange::SaveGroup(employees)],
+            context: RequestContext::default(),
+            subject_ids: None,
+            resource_ids: None,
+        })
+        .await?;
+    let still_allowed = access_checker.can_invoke_by_name(bill_haydon_id, "users/get_users").await;
+    info!("{} {:?} {:?}", simulation.checked, simulation.flips, still_allowed);
+
+    // an identity provider provisions a user over SCIM, then suspends it
+    let mut scim_endpoint = ScimEndpoint::new(Box::new(ScimServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id.clone())),
+        duties_loader(connection_pool.clone(), tenant_id.clone()),
+        "https://basics.example.com/scim/v2",
+    )));
+    let created = scim_endpoint.call(scim_request(Method::POST, "/scim/v2/Users", json!({
+        "schemas": [USER_SCHEMA],
+        "userName": "jim prideaux",
+        "externalId": "00u1xk9d6ePLhZwiS5f3",
+    }))).await.unwrap_or_else(|never| match never {});
+    let scim_user_id = serde_json::from_str::<serde_json::Value>(created.body()).ok()
+        .and_then(|user| user["id"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    let suspended = scim_endpoint.call(scim_request(Method::PATCH, &format!("/scim/v2/Users/{}", scim_user_id), json!({
+        "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+        "Operations": [{"op": "replace", "path": "active", "value": false}],
+    }))).await.unwrap_or_else(|never| match never {});
+    let suspended_users = scim_endpoint.call(scim_request(Method::GET, "/scim/v2/Users?filter=active%20eq%20false", json!(null)))
         .await
-        .expect("failed to check if can invoke");
-    info!("{:?}", can_invoke);
+        .unwrap_or_else(|never| match never {});
+    info!("{} {} {} {}", created.status(), suspended.status(), suspended.headers()[header::ETAG].to_str().unwrap_or_default(), suspended_users.body());
 
-    
+    let mut metrics_endpoint = MetricsEndpoint::new(metrics::global());
+    let scraped = metrics_endpoint.call(Request::get(METRICS_PATH).body(String::new()).unwrap())
+        .await
+        .unwrap_or_else(|never| match never {});
+    info!("{} {}\n{}", scraped.status(), scraped.headers()[header::CONTENT_TYPE].to_str().unwrap_or_default(), scraped.body());
 
     Ok(())
-}
\ No newline at end of file
+}
+
+fn scim_request(method: Method, path: &str, body: serde_json::Value) -> Request<String> {
+    let body = if body.is_null() { String::new() } else { body.to_string() };
+    Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::CONTENT_TYPE, SCIM_CONTENT_TYPE)
+        .body(body)
+        .expect("scim requests are well formed")
+}
+
+fn token_service(connection_pool: SqlitePool, tenant_id: TenantId) -> TokenServiceImpl {
+    TokenServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteGroupRepository::new(connection_pool, tenant_id)),
+        Box::new(FileKeySetRepository::new(KEY_SET)),
+        TOKEN_ISSUER,
+        Duration::minutes(5),
+    )
+}
+
+fn access_checker(connection_pool: SqlitePool, tenant_id: TenantId) -> AccessChecker {
+    AccessChecker::new(
+        tenant_id.clone(),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteDelegationRepository::new(connection_pool, tenant_id)),
+    )
+}
+
+fn simulation_service(connection_pool: SqlitePool, tenant_id: TenantId) -> SimulationServiceImpl {
+    SimulationServiceImpl::new(
+        tenant_id.clone(),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteDelegationRepository::new(connection_pool, tenant_id)),
+    )
+}
+
+fn effective_permission_indexer(connection_pool: SqlitePool, tenant_id: TenantId) -> EffectivePermissionIndexer {
+    EffectivePermissionIndexer::new(
+        tenant_id.clone(),
+        Box::new(SqliteEffectivePermissionIndex::new(connection_pool.clone(), tenant_id.clone())),
+        access_checker(connection_pool.clone(), tenant_id.clone()),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteResourceRepository::new(connection_pool, tenant_id)),
+    )
+}
+
+fn duties_loader(connection_pool: SqlitePool, tenant_id: TenantId) -> DutiesLoader {
+    DutiesLoader::new(
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteGroupRepository::new(connection_pool, tenant_id)),
+    )
+}
+
+fn snapshot_exporter(connection_pool: SqlitePool, tenant_id: TenantId) -> SnapshotExporter {
+    SnapshotExporter::new(
+        tenant_id.clone(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteGroupRepository::new(connection_pool, tenant_id)),
+    )
+}
+
+fn snapshot_importer(connection_pool: SqlitePool, tenant_id: TenantId) -> SnapshotImporter {
+    SnapshotImporter::new(
+        tenant_id.clone(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id.clone())),
+        Box::new(SqliteUnitOfWork::new(connection_pool, tenant_id)),
+    )
+}
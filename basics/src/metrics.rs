@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+pub const ACCESS_DECISIONS: &str = "basics_access_decisions_total";
+pub const ACCESS_CHECK_DURATION: &str = "basics_access_check_duration_seconds";
+pub const REPOSITORY_DURATION: &str = "basics_repository_duration_seconds";
+pub const CACHE_LOOKUPS: &str = "basics_cache_lookups_total";
+
+// name, type and help of every metric, in the order they are rendered
+const DESCRIPTIONS: [(&str, &str, &str); 4] = [
+    (ACCESS_DECISIONS, "counter", "Access checks by outcome."),
+    (ACCESS_CHECK_DURATION, "histogram", "Time taken by access checks."),
+    (REPOSITORY_DURATION, "histogram", "Time taken by repository calls by repository, operation and outcome."),
+    (CACHE_LOOKUPS, "counter", "Lookups in decision caches and the effective permission index by result."),
+];
+
+// upper bounds in seconds; sqlite calls on a local file mostly land in the lower half
+const BUCKETS: [f64; 11] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+type Labels = Vec<(&'static str, String)>;
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    // one count per bucket, not cumulative; rendering adds them up
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+// counters and histograms rendered in the Prometheus text exposition format. metrics are keyed by
+// name and labels and spring into existence the first time they are recorded
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<(&'static str, Labels), u64>>,
+    histograms: Mutex<BTreeMap<(&'static str, Labels), Histogram>>,
+}
+
+// the registry the checker and the repositories record into
+pub fn global() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+fn to_labels(labels: &[(&'static str, &str)]) -> Labels {
+    labels.iter().map(|(name, value)| (*name, value.to_string())).collect()
+}
+
+fn format_labels(labels: &Labels, extra: Option<(&str, String)>) -> String {
+    let pairs: Vec<String> = labels.iter()
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .chain(extra.map(|(name, value)| (name.to_string(), value)))
+        .map(|(name, value)| format!("{}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+        .collect();
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        *self.counters.lock().unwrap().entry((name, to_labels(labels))).or_default() += 1;
+    }
+
+    pub fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms.entry((name, to_labels(labels))).or_default();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            histogram.counts[bucket] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+
+    pub fn get_counter(&self, name: &'static str, labels: &[(&'static str, &str)]) -> u64 {
+        self.counters.lock().unwrap().get(&(name, to_labels(labels))).copied().unwrap_or_default()
+    }
+
+    pub fn get_histogram_count(&self, name: &'static str, labels: &[(&'static str, &str)]) -> u64 {
+        self.histograms.lock().unwrap().get(&(name, to_labels(labels))).map_or(0, |histogram| histogram.count)
+    }
+
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        let histograms = self.histograms.lock().unwrap();
+        let mut text = String::new();
+
+        for (name, kind, help) in DESCRIPTIONS {
+            writeln!(text, "# HELP {} {}", name, help).unwrap();
+            writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+            for ((_, labels), value) in counters.range((name, Vec::new())..).take_while(|((key, _), _)| *key == name) {
+                writeln!(text, "{}{} {}", name, format_labels(labels, None), value).unwrap();
+            }
+            for ((_, labels), histogram) in histograms.range((name, Vec::new())..).take_while(|((key, _), _)| *key == name) {
+                let mut cumulative = 0;
+                for (bound, count) in BUCKETS.iter().zip(histogram.counts) {
+                    cumulative += count;
+                    writeln!(text, "{}_bucket{} {}", name, format_labels(labels, Some(("le", bound.to_string()))), cumulative).unwrap();
+                }
+                writeln!(text, "{}_bucket{} {}", name, format_labels(labels, Some(("le", "+Inf".to_string()))), histogram.count).unwrap();
+                writeln!(text, "{}_sum{} {}", name, format_labels(labels, None), histogram.sum).unwrap();
+                writeln!(text, "{}_count{} {}", name, format_labels(labels, None), histogram.count).unwrap();
+            }
+        }
+        text
+    }
+}
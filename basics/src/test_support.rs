@@ -0,0 +1,76 @@
+// fixtures the tests of every layer share: one tenant, and a store of its own per test
+use sqlx::pool::Pool;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::Sqlite;
+
+use crate::application::access_checker::AccessChecker;
+use crate::application::seeds::SeedPlanner;
+use crate::domain::repositories::{ResourceRepository, SubjectRepository};
+use crate::domain::resources::ResourceId;
+use crate::domain::seeds::{Seed, SeedFormat};
+use crate::domain::subjects::SubjectId;
+use crate::domain::tenants::TenantId;
+use crate::infrastructure::sqlite::delegation::SqliteDelegationRepository;
+use crate::infrastructure::sqlite::group::SqliteGroupRepository;
+use crate::infrastructure::sqlite::permission::SqlitePermissionRepository;
+use crate::infrastructure::sqlite::resource::SqliteResourceRepository;
+use crate::infrastructure::sqlite::role::SqliteRoleRepository;
+use crate::infrastructure::sqlite::sod_constraint::SqliteSodConstraintRepository;
+use crate::infrastructure::sqlite::subject::SqliteSubjectRepository;
+use crate::infrastructure::sqlite::unit_of_work::SqliteUnitOfWork;
+
+pub fn tenant_id() -> TenantId {
+    TenantId::from("circus")
+}
+
+// an in-memory store with every migration run. it has a single connection, as each connection to
+// `sqlite::memory:` would open a database of its own
+pub async fn empty_database() -> Pool<Sqlite> {
+    let connection_pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("./datastore/sqlite").run(&connection_pool).await.unwrap();
+    connection_pool
+}
+
+// an in-memory store holding what the yaml seed describes
+pub async fn seeded_database(seed: &str) -> Pool<Sqlite> {
+    let connection_pool = empty_database().await;
+    let seed_planner = SeedPlanner::new(
+        tenant_id(),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteUnitOfWork::new(connection_pool.clone(), tenant_id())),
+    );
+    seed_planner.apply(&Seed::parse(seed, SeedFormat::Yaml).unwrap()).await.unwrap();
+    connection_pool
+}
+
+pub fn access_checker(connection_pool: &Pool<Sqlite>) -> AccessChecker {
+    AccessChecker::new(
+        tenant_id(),
+        Box::new(SqliteSubjectRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteGroupRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteRoleRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqlitePermissionRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteResourceRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteSodConstraintRepository::new(connection_pool.clone(), tenant_id())),
+        Box::new(SqliteDelegationRepository::new(connection_pool.clone(), tenant_id())),
+    )
+}
+
+pub async fn subject_id(connection_pool: &Pool<Sqlite>, name: &str) -> SubjectId {
+    SqliteSubjectRepository::new(connection_pool.clone(), tenant_id()).get_by_name(name).await.unwrap().unwrap().get_id()
+}
+
+pub async fn resource_id(connection_pool: &Pool<Sqlite>, name: &str) -> ResourceId {
+    SqliteResourceRepository::new(connection_pool.clone(), tenant_id()).get_by_name(name).await.unwrap().unwrap().get_id()
+}